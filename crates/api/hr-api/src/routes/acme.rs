@@ -4,6 +4,7 @@ use axum::{
     routing::{get, post},
 };
 use hr_acme::WildcardType;
+use hr_ipc::edge::EdgeRequest;
 use serde_json::{Value, json};
 use tracing::{error, info};
 
@@ -25,6 +26,12 @@ async fn status(State(state): State<ApiState>) -> Json<Value> {
         .iter()
         .find(|c| c.wildcard_type == WildcardType::Global);
 
+    // OCSP stapling state, best-effort (hr-edge may be down)
+    let stapling = match state.edge.request(&EdgeRequest::GetOcspStatus).await {
+        Ok(r) if r.ok => r.data.unwrap_or_default(),
+        _ => json!({}),
+    };
+
     Json(json!({
         "success": true,
         "initialized": state.acme.is_initialized(),
@@ -39,7 +46,8 @@ async fn status(State(state): State<ApiState>) -> Json<Value> {
                 "days_until_expiry": c.days_until_expiry(),
                 "needs_renewal": c.needs_renewal(state.acme.renewal_threshold_days())
             }))
-        }
+        },
+        "stapling": stapling
     }))
 }
 
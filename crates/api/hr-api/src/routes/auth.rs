@@ -6,6 +6,8 @@ use axum::{
     routing::{delete, get, post},
 };
 use axum_extra::extract::CookieJar;
+use hr_auth::credentials::NewCredential;
+use hr_auth::users::UserInfo;
 use serde::Deserialize;
 use serde_json::{Value, json};
 
@@ -21,6 +23,11 @@ pub fn router() -> Router<ApiState> {
         .route("/sessions", get(list_sessions))
         .route("/sessions/{id}", delete(revoke_session))
         .route("/change-code", post(change_code))
+        .route(
+            "/credentials",
+            get(list_credentials).post(create_credential),
+        )
+        .route("/credentials/{id}", delete(revoke_credential))
 }
 
 #[derive(Deserialize)]
@@ -409,11 +416,15 @@ struct ForwardCheckQuery {
     host: Option<String>,
     #[serde(default)]
     uri: Option<String>,
+    /// Comma-separated allowed groups; empty means any authenticated user.
+    #[serde(default)]
+    groups: Option<String>,
 }
 
 /// Forward-auth endpoint for agent reverse proxies.
-/// Accepts query params: host, uri — or X-Forwarded-* headers.
-/// Returns 200 + user on success, 401 + login_url on unauthenticated.
+/// Accepts query params: host, uri, groups — or X-Forwarded-* headers.
+/// Returns 200 + user on success, 401 + login_url on unauthenticated,
+/// 403 when the user is not in any of the allowed groups.
 async fn forward_check(
     State(state): State<ApiState>,
     axum::extract::Query(query): axum::extract::Query<ForwardCheckQuery>,
@@ -451,21 +462,36 @@ async fn forward_check(
             })
         });
 
+    let allowed_groups: Vec<String> = query
+        .groups
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|g| !g.is_empty())
+        .map(String::from)
+        .collect();
+
     match check_forward_auth(
         &state.auth,
         cookie_value,
         forwarded_host,
         forwarded_uri,
         forwarded_proto,
+        &allowed_groups,
     ) {
         ForwardAuthResult::Success { user } => (
             axum::http::StatusCode::OK,
-            Json(json!({"user": user.username})),
+            Json(json!({"user": user.username, "groups": user.groups})),
         ),
         ForwardAuthResult::Unauthorized { login_url } => (
             axum::http::StatusCode::UNAUTHORIZED,
             Json(json!({"login_url": login_url})),
         ),
+        ForwardAuthResult::Forbidden { user, reason } => (
+            axum::http::StatusCode::FORBIDDEN,
+            Json(json!({"error": reason, "user": user.username})),
+        ),
     }
 }
 
@@ -511,3 +537,118 @@ async fn change_code(
         ),
     }
 }
+
+/// Utilisateur de la session ; rejette les requêtes sans session valide
+fn require_session(
+    state: &ApiState,
+    jar: &CookieJar,
+) -> Result<UserInfo, (axum::http::StatusCode, Json<Value>)> {
+    let Some(session_id) = jar.get("auth_session").map(|c| c.value().to_string()) else {
+        return Err((
+            axum::http::StatusCode::UNAUTHORIZED,
+            Json(json!({"success": false, "error": "Non authentifie"})),
+        ));
+    };
+    let Ok(Some(session)) = state.auth.sessions.validate(&session_id) else {
+        return Err((
+            axum::http::StatusCode::UNAUTHORIZED,
+            Json(json!({"success": false, "error": "Session expiree"})),
+        ));
+    };
+    state.auth.users.get(&session.user_id).ok_or((
+        axum::http::StatusCode::UNAUTHORIZED,
+        Json(json!({"success": false, "error": "Utilisateur inconnu"})),
+    ))
+}
+
+/// Clés d'API et identifiants basic (sans secrets) que l'utilisateur gère
+async fn list_credentials(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match require_session(&state, &jar) {
+        Ok(user) => user,
+        Err(rejection) => return rejection,
+    };
+    match state.auth.credentials.list() {
+        Ok(mut credentials) => {
+            credentials.retain(|c| c.managed_by(&user));
+            (
+                axum::http::StatusCode::OK,
+                Json(json!({"success": true, "credentials": credentials})),
+            )
+        }
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
+
+/// Crée un identifiant ; la clé d'API n'est renvoyée qu'ici
+async fn create_credential(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(mut body): Json<NewCredential>,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match require_session(&state, &jar) {
+        Ok(user) => user,
+        Err(rejection) => return rejection,
+    };
+    if let Some(reason) = body.denied_for(&user) {
+        return (
+            axum::http::StatusCode::FORBIDDEN,
+            Json(json!({"success": false, "error": reason})),
+        );
+    }
+    body.created_by = Some(user.username);
+    match state.auth.create_credential(body) {
+        Ok(created) => (
+            axum::http::StatusCode::OK,
+            Json(json!({"success": true, "credential": created})),
+        ),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
+
+async fn revoke_credential(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match require_session(&state, &jar) {
+        Ok(user) => user,
+        Err(rejection) => return rejection,
+    };
+    let managed = match state.auth.credentials.list() {
+        Ok(credentials) => credentials
+            .iter()
+            .any(|c| c.id == id && c.managed_by(&user)),
+        Err(e) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"success": false, "error": e.to_string()})),
+            );
+        }
+    };
+    if !managed {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "error": "Identifiant non trouve"})),
+        );
+    }
+    match state.auth.credentials.revoke(&id) {
+        Ok(true) => (axum::http::StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "error": "Identifiant non trouve"})),
+        ),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
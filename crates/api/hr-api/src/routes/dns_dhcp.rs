@@ -1,8 +1,9 @@
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Query, State},
     routing::{get, post},
 };
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 use crate::state::ApiState;
@@ -13,6 +14,9 @@ pub fn router() -> Router<ApiState> {
         .route("/reload", post(reload))
         .route("/config", get(get_config).put(update_config))
         .route("/leases", get(get_leases))
+        .route("/failover", get(get_failover))
+        .route("/reservations/import", post(import_reservations))
+        .route("/reservations/export", get(export_reservations))
 }
 
 async fn status(State(state): State<ApiState>) -> Json<Value> {
@@ -111,3 +115,68 @@ async fn get_leases(State(state): State<ApiState>) -> Json<Value> {
         Err(_) => Json(json!({"success": false, "error": "Network core unavailable"})),
     }
 }
+
+async fn get_failover(State(state): State<ApiState>) -> Json<Value> {
+    match state.netcore.dhcp_failover_status().await {
+        Ok(status) => Json(json!({"success": true, "failover": status})),
+        Err(_) => Json(json!({"success": false, "error": "Network core unavailable"})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ImportReservationsRequest {
+    format: String,
+    content: String,
+    /// false (default) = dry run: only return the report.
+    #[serde(default)]
+    apply: bool,
+    #[serde(default)]
+    skip_conflicts: bool,
+}
+
+async fn import_reservations(
+    State(state): State<ApiState>,
+    Json(body): Json<ImportReservationsRequest>,
+) -> Json<Value> {
+    match state
+        .netcore
+        .dhcp_import_reservations(&body.format, body.content, body.apply, body.skip_conflicts)
+        .await
+    {
+        Ok(resp) if resp.ok => {
+            let mut result = resp.data.unwrap_or_else(|| json!({}));
+            result["success"] = json!(true);
+            Json(result)
+        }
+        Ok(resp) => Json(json!({
+            "success": false,
+            "error": resp.error.unwrap_or_else(|| "Unknown error".into())
+        })),
+        Err(_) => Json(json!({"success": false, "error": "Network core unavailable"})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportReservationsQuery {
+    #[serde(default = "default_export_format")]
+    format: String,
+}
+
+fn default_export_format() -> String {
+    "dnsmasq".to_string()
+}
+
+async fn export_reservations(
+    State(state): State<ApiState>,
+    Query(query): Query<ExportReservationsQuery>,
+) -> Json<Value> {
+    match state.netcore.dhcp_export_reservations(&query.format).await {
+        Ok(export) => Json(json!({
+            "success": true,
+            "format": export.format,
+            "count": export.count,
+            "content": export.content,
+        })),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
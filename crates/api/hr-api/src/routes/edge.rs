@@ -13,7 +13,7 @@ use serde::Deserialize;
 use serde_json::json;
 use tracing::{error, info, warn};
 
-use hr_ipc::edge::EdgeRequest;
+use hr_ipc::edge::{AppRouteSpec, EdgeRequest};
 
 use crate::state::ApiState;
 
@@ -23,6 +23,9 @@ pub fn router() -> Router<ApiState> {
         .route("/{domain}", delete(remove_route))
 }
 
+/// Body of `POST /api/edge/routes`. The JSON route settings are handed to
+/// hr-edge as-is; it validates them and refuses the route when one doesn't
+/// parse. Settings left out keep the ones the route already has.
 #[derive(Debug, Deserialize)]
 struct SetRouteRequest {
     /// Public domain hr-edge will route from (e.g. "studio.mynetwk.biz").
@@ -42,6 +45,32 @@ struct SetRouteRequest {
     /// Optional host id; defaults to "manual".
     #[serde(default)]
     host_id: Option<String>,
+    /// Optional backend pool (declared in the proxy config) used instead of `target`.
+    #[serde(default)]
+    pool: Option<String>,
+    /// Optional per-client request rate and connection caps.
+    #[serde(default)]
+    rate_limit: Option<serde_json::Value>,
+    /// Optional header rules: named policies, then request/response set,
+    /// append and remove.
+    #[serde(default)]
+    headers: Option<serde_json::Value>,
+    /// Optional client address allow/deny lists (CIDRs, IPs or list names).
+    #[serde(default)]
+    acl: Option<serde_json::Value>,
+    /// Optional client certificate mode ("off", "optional", "required").
+    #[serde(default)]
+    client_cert: Option<String>,
+    /// Optional backend certificate check ("system", "ca", "fingerprint" or
+    /// "insecure") for HTTPS targets.
+    #[serde(default)]
+    backend_tls: Option<serde_json::Value>,
+    /// Optional API keys / basic credentials accepted besides the session.
+    #[serde(default)]
+    machine_auth: Option<serde_json::Value>,
+    /// Optional backend timeouts, retries and circuit breaker.
+    #[serde(default)]
+    upstream: Option<serde_json::Value>,
 }
 
 /// Parse "host:port" or "ip:port" into its parts. Only IPv4 + bare numeric
@@ -102,7 +131,7 @@ async fn set_route(
         body.target
     );
 
-    let req = EdgeRequest::SetAppRoute {
+    let req = EdgeRequest::SetAppRoute(Box::new(AppRouteSpec {
         domain: body.domain.clone(),
         app_id,
         host_id,
@@ -111,7 +140,15 @@ async fn set_route(
         auth_required: body.auth_required,
         allowed_groups: body.allowed_groups.clone(),
         local_only: body.local_only,
-    };
+        pool: body.pool.clone(),
+        rate_limit: body.rate_limit.clone(),
+        headers: body.headers.clone(),
+        acl: body.acl.clone(),
+        client_cert: body.client_cert.clone(),
+        backend_tls: body.backend_tls.clone(),
+        machine_auth: body.machine_auth.clone(),
+        upstream: body.upstream.clone(),
+    }));
 
     match state.edge.request(&req).await {
         Ok(resp) if resp.ok => Json(json!({
@@ -4,6 +4,8 @@ use axum::{
     routing::get,
 };
 use hr_common::logging::LogQuery;
+use hr_ipc::edge::EdgeRequest;
+use hr_proxy::AccessLogQuery;
 
 use crate::state::ApiState;
 
@@ -11,6 +13,8 @@ pub fn router() -> Router<ApiState> {
     Router::new()
         .route("/", get(get_logs))
         .route("/stats", get(get_stats))
+        .route("/access", get(get_access_logs))
+        .route("/access/stats", get(get_access_stats))
 }
 
 async fn get_logs(
@@ -37,3 +41,38 @@ async fn get_stats(
         )),
     }
 }
+
+/// Send an access log request to hr-edge and return its data.
+async fn edge_access_log(
+    state: &ApiState,
+    req: EdgeRequest,
+) -> Result<Json<serde_json::Value>, (axum::http::StatusCode, String)> {
+    let resp = state.edge.request(&req).await.map_err(|e| {
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to query access logs: {e}"),
+        )
+    })?;
+    if !resp.ok {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            resp.error.unwrap_or_default(),
+        ));
+    }
+    Ok(Json(resp.data.unwrap_or_default()))
+}
+
+async fn get_access_logs(
+    State(state): State<ApiState>,
+    Query(filter): Query<AccessLogQuery>,
+) -> Result<Json<serde_json::Value>, (axum::http::StatusCode, String)> {
+    let filter = serde_json::to_value(&filter).unwrap_or_default();
+    let Json(entries) = edge_access_log(&state, EdgeRequest::AccessLogQuery { filter }).await?;
+    Ok(Json(serde_json::json!({ "logs": entries })))
+}
+
+async fn get_access_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, (axum::http::StatusCode, String)> {
+    edge_access_log(&state, EdgeRequest::AccessLogStats).await
+}
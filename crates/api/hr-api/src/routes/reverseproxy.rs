@@ -91,6 +91,7 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
             "target_port": host.get("targetPort").unwrap_or(&json!(80)),
             "local_only": host.get("localOnly").unwrap_or(&json!(false)),
             "require_auth": host.get("requireAuth").unwrap_or(&json!(false)),
+            "rules": host.get("rules").unwrap_or(&json!([])),
             "enabled": true
         }));
     }
@@ -106,20 +107,13 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
     proxy_config["routes"] = json!(routes);
     proxy_config["base_domain"] = json!(base_domain);
 
-    let content =
-        serde_json::to_string_pretty(&proxy_config).map_err(|e| format!("Serialize: {}", e))?;
-    let tmp = proxy_config_path.with_extension("json.tmp");
-    tokio::fs::write(&tmp, &content)
-        .await
-        .map_err(|e| format!("Write: {}", e))?;
-    tokio::fs::rename(&tmp, proxy_config_path)
-        .await
-        .map_err(|e| format!("Rename: {}", e))?;
-
-    // Tell hr-edge to reload config (it will reload TLS certs + proxy config internally)
+    // hr-edge validates the new config, writes it and applies it (the
+    // running config and file stay as they were if it is rejected)
     let resp = state
         .edge
-        .request(&EdgeRequest::ReloadConfig)
+        .request(&EdgeRequest::SaveProxyConfig {
+            config: proxy_config,
+        })
         .await
         .map_err(|e| format!("Edge IPC error: {}", e))?;
     if !resp.ok {
@@ -394,7 +388,10 @@ async fn system_routes(State(state): State<ApiState>) -> impl IntoResponse {
                 .map(|app| {
                     let domain = app.get("domain").and_then(|v| v.as_str()).unwrap_or("");
                     let port = app.get("port").and_then(|v| v.as_u64()).unwrap_or(0);
-                    let vis = app.get("visibility").and_then(|v| v.as_str()).unwrap_or("private");
+                    let vis = app
+                        .get("visibility")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("private");
                     json!({
                         "domain": domain,
                         "target": format!("127.0.0.1:{}", port),
@@ -409,7 +406,10 @@ async fn system_routes(State(state): State<ApiState>) -> impl IntoResponse {
                     serde_json::from_str(&content).unwrap_or_default();
                 for (domain, cfg) in &map {
                     // Skip app domains (already from registry)
-                    if routes.iter().any(|r| r.get("domain").and_then(|v| v.as_str()) == Some(domain)) {
+                    if routes
+                        .iter()
+                        .any(|r| r.get("domain").and_then(|v| v.as_str()) == Some(domain))
+                    {
                         continue;
                     }
                     routes.push(json!({
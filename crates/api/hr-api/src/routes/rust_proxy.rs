@@ -1,11 +1,13 @@
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Path, Query, State},
     http::StatusCode,
-    routing::{get, post},
+    routing::{delete, get, post, put},
 };
 use hr_ipc::edge::EdgeRequest;
+use serde::Deserialize;
 use serde_json::{Value, json};
+use std::collections::HashMap;
 
 use crate::state::ApiState;
 
@@ -14,6 +16,25 @@ pub fn router() -> Router<ApiState> {
         .route("/status", get(status))
         .route("/routes", get(routes))
         .route("/reload", post(reload))
+        .route("/config", put(apply_config))
+        .route("/config/validate", post(validate_config))
+        .route("/config/revisions", get(list_config_revisions))
+        .route("/config/rollback", post(rollback_config))
+        .route("/devices", get(list_devices).post(issue_device))
+        .route("/devices/{serial}", delete(revoke_device))
+        .route("/maintenance", get(list_maintenance))
+        .route(
+            "/maintenance/{domain}",
+            post(set_maintenance).delete(clear_maintenance),
+        )
+        .route("/streams", get(stream_status))
+        .route("/redirects/test", get(test_redirect))
+        .route(
+            "/apps/{domain}/split",
+            put(set_app_split).delete(clear_app_split),
+        )
+        .route("/apps/{domain}/split/weights", post(set_app_weights))
+        .route("/apps/{domain}/split/promote", post(promote_app_target))
 }
 
 async fn status(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCode, String)> {
@@ -30,6 +51,12 @@ async fn status(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCod
     }
     let config: Value = resp.data.unwrap_or_default();
 
+    // Pool member state is best-effort: an older edge may not know the command
+    let pools = match state.edge.request(&EdgeRequest::GetPoolStatus).await {
+        Ok(r) if r.ok => r.data.unwrap_or_default(),
+        _ => json!({}),
+    };
+
     Ok(Json(json!({
         "success": true,
         "running": true,
@@ -40,7 +67,11 @@ async fn status(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCod
         "routeCount": config.get("routes").and_then(|r| r.as_array()).map(|a| a.len()).unwrap_or(0),
         "activeRoutes": config.get("routes").and_then(|r| r.as_array())
             .map(|routes| routes.iter().filter(|r| r.get("enabled").and_then(|e| e.as_bool()).unwrap_or(true)).count())
-            .unwrap_or(0)
+            .unwrap_or(0),
+        "pools": pools.get("pools").cloned().unwrap_or(json!([])),
+        "backends": pools.get("backends").cloned().unwrap_or(json!([])),
+        "backendTls": pools.get("backend_tls").cloned().unwrap_or(json!([])),
+        "circuitBreakers": pools.get("circuit_breakers").cloned().unwrap_or(json!([]))
     })))
 }
 
@@ -76,3 +107,258 @@ async fn reload(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCod
     }
     Ok(Json(json!({"success": true})))
 }
+
+/// Validate and apply a full proxy config; rejected (400) when validation
+/// finds errors. Returns the recorded revision, the diff and the warnings.
+async fn apply_config(
+    State(state): State<ApiState>,
+    Json(config): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let data = edge_command(&state, EdgeRequest::SaveProxyConfig { config }).await?;
+    Ok(Json(json!({
+        "success": true,
+        "revision": data.get("revision").cloned().unwrap_or(Value::Null),
+        "diff": data.get("diff").cloned().unwrap_or(json!({})),
+        "issues": data.get("issues").cloned().unwrap_or(json!([])),
+    })))
+}
+
+/// Validation report of a candidate config and its diff against the
+/// running one, without applying it.
+async fn validate_config(
+    State(state): State<ApiState>,
+    Json(config): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let data = edge_command(&state, EdgeRequest::ValidateProxyConfig { config }).await?;
+    Ok(Json(json!({
+        "success": true,
+        "valid": data.get("valid").cloned().unwrap_or(json!(false)),
+        "issues": data.get("issues").cloned().unwrap_or(json!([])),
+        "diff": data.get("diff").cloned().unwrap_or(json!({})),
+    })))
+}
+
+async fn list_config_revisions(
+    State(state): State<ApiState>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let data = edge_command(&state, EdgeRequest::ListConfigRevisions).await?;
+    Ok(Json(json!({
+        "success": true,
+        "revisions": data.get("revisions").cloned().unwrap_or(json!([])),
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RollbackRequest {
+    /// Revision to restore (default: the one before the current).
+    #[serde(default)]
+    revision: Option<u64>,
+}
+
+async fn rollback_config(
+    State(state): State<ApiState>,
+    body: Option<Json<RollbackRequest>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let revision = body.map(|Json(b)| b).unwrap_or_default().revision;
+    let data = edge_command(&state, EdgeRequest::RollbackProxyConfig { revision }).await?;
+    Ok(Json(json!({
+        "success": true,
+        "restored": data.get("restored").cloned().unwrap_or(Value::Null),
+        "revision": data.get("revision").cloned().unwrap_or(Value::Null),
+        "diff": data.get("diff").cloned().unwrap_or(json!({})),
+    })))
+}
+
+/// Send a command to hr-edge and return its data.
+async fn edge_command(state: &ApiState, req: EdgeRequest) -> Result<Value, (StatusCode, String)> {
+    let resp = state
+        .edge
+        .request(&req)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !resp.ok {
+        return Err((StatusCode::BAD_REQUEST, resp.error.unwrap_or_default()));
+    }
+    Ok(resp.data.unwrap_or_default())
+}
+
+async fn list_devices(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCode, String)> {
+    let data = edge_command(&state, EdgeRequest::DeviceCertList).await?;
+    Ok(Json(json!({
+        "success": true,
+        "devices": data.get("devices").cloned().unwrap_or(json!([])),
+        "caPem": data.get("ca_pem").cloned().unwrap_or(json!("")),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueDeviceRequest {
+    name: String,
+    #[serde(default = "default_device_validity_days")]
+    validity_days: u32,
+}
+
+fn default_device_validity_days() -> u32 {
+    365
+}
+
+/// Issue a device certificate. The private key is only returned here.
+async fn issue_device(
+    State(state): State<ApiState>,
+    Json(body): Json<IssueDeviceRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let device = edge_command(
+        &state,
+        EdgeRequest::DeviceCertIssue {
+            name: body.name,
+            validity_days: body.validity_days,
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true, "device": device})))
+}
+
+async fn revoke_device(
+    State(state): State<ApiState>,
+    Path(serial): Path<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(&state, EdgeRequest::DeviceCertRevoke { serial }).await?;
+    Ok(Json(json!({"success": true})))
+}
+
+async fn list_maintenance(
+    State(state): State<ApiState>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let domains = edge_command(&state, EdgeRequest::MaintenanceList).await?;
+    Ok(Json(json!({"success": true, "domains": domains})))
+}
+
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    #[serde(default)]
+    message: Option<String>,
+    /// CIDRs, IPs or address list names still reaching the backend.
+    #[serde(default)]
+    allow: Vec<String>,
+}
+
+/// Put a domain in maintenance mode (or update its message / allow list).
+async fn set_maintenance(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+    Json(body): Json<MaintenanceRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(
+        &state,
+        EdgeRequest::MaintenanceSet {
+            domain,
+            message: body.message,
+            allow: body.allow,
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true})))
+}
+
+async fn clear_maintenance(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(&state, EdgeRequest::MaintenanceClear { domain }).await?;
+    Ok(Json(json!({"success": true})))
+}
+
+/// Split an app route's traffic (body: `hr_proxy::TrafficSplit`).
+async fn set_app_split(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+    Json(split): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(
+        &state,
+        EdgeRequest::SetAppSplit {
+            domain,
+            split: Some(split),
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true})))
+}
+
+async fn clear_app_split(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(
+        &state,
+        EdgeRequest::SetAppSplit {
+            domain,
+            split: None,
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+struct AppWeightsRequest {
+    /// Target name → new weight; unlisted targets keep theirs.
+    weights: HashMap<String, u32>,
+}
+
+/// Shift traffic between split targets, e.g. `{"weights": {"canary": 25}}`.
+async fn set_app_weights(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+    Json(body): Json<AppWeightsRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let split = edge_command(
+        &state,
+        EdgeRequest::SetAppWeights {
+            domain,
+            weights: body.weights,
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true, "split": split})))
+}
+
+#[derive(Debug, Deserialize)]
+struct PromoteRequest {
+    target: String,
+}
+
+async fn promote_app_target(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+    Json(body): Json<PromoteRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    edge_command(
+        &state,
+        EdgeRequest::PromoteAppTarget {
+            domain,
+            target: body.target,
+        },
+    )
+    .await?;
+    Ok(Json(json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+struct RedirectTestQuery {
+    url: String,
+}
+
+/// Which domain alias or redirect rule `url` would hit (`rule: null` if none).
+async fn test_redirect(
+    State(state): State<ApiState>,
+    Query(query): Query<RedirectTestQuery>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let rule = edge_command(&state, EdgeRequest::RedirectTest { url: query.url }).await?;
+    Ok(Json(json!({"success": true, "rule": rule})))
+}
+
+async fn stream_status(State(state): State<ApiState>) -> Result<Json<Value>, (StatusCode, String)> {
+    let streams = edge_command(&state, EdgeRequest::StreamStatus).await?;
+    Ok(Json(json!({"success": true, "streams": streams})))
+}
@@ -0,0 +1,738 @@
+//! Identifiants machine : clés d'API et comptes basic-auth.
+//!
+//! Les scripts, appareils et webhooks ne peuvent pas suivre le login par
+//! cookie. Une route peut donc accepter une clé d'API (`hrk_<id>_<secret>`)
+//! ou des identifiants `Authorization: Basic`. Chaque identifiant appartient
+//! à un utilisateur de `users.yml` ou à un compte de service, et porte des
+//! portées (`scopes`) que la route peut exiger.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use rand_core::RngCore;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::users::{UserInfo, verify_password};
+
+/// Préfixe des clés d'API
+pub const API_KEY_PREFIX: &str = "hrk_";
+/// Portée accordant toutes les autres
+pub const WILDCARD_SCOPE: &str = "*";
+/// Durée pendant laquelle un mot de passe basic vérifié évite un nouveau hachage
+const BASIC_CACHE_TTL_MS: i64 = 5 * 60 * 1000;
+/// Échecs tolérés par client et par fenêtre avant de refuser sans vérifier
+const MAX_FAILURES: u32 = 10;
+/// Fenêtre de comptage des échecs
+const FAILURE_WINDOW_MS: i64 = 60 * 1000;
+/// Intervalle minimal entre deux écritures de `last_used_at` d'un identifiant
+const TOUCH_INTERVAL_MS: i64 = 60 * 1000;
+
+/// Type d'identifiant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialKind {
+    ApiKey,
+    Basic,
+}
+
+impl CredentialKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CredentialKind::ApiKey => "api_key",
+            CredentialKind::Basic => "basic",
+        }
+    }
+}
+
+/// Identité au nom de laquelle un identifiant agit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CredentialOwner {
+    /// Utilisateur de `users.yml` (uuid et groupes repris de son compte)
+    User { username: String },
+    /// Compte de service, avec un uuid stable propre à son nom
+    Service {
+        name: String,
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+}
+
+/// Identifiant machine (sans secret)
+#[derive(Debug, Clone, Serialize)]
+pub struct Credential {
+    pub id: String,
+    pub kind: CredentialKind,
+    /// Libellé (clé d'API) ou identifiant de connexion (basic)
+    pub name: String,
+    pub owner: CredentialOwner,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub revoked: bool,
+    /// Utilisateur ayant créé l'identifiant
+    pub created_by: Option<String>,
+}
+
+impl Credential {
+    /// Vrai si l'identifiant couvre une des portées demandées (liste vide = toutes)
+    pub fn allows(&self, required: &[String]) -> bool {
+        required.is_empty()
+            || self
+                .scopes
+                .iter()
+                .any(|s| s == WILDCARD_SCOPE || required.contains(s))
+    }
+
+    /// Vrai si `caller` peut voir et révoquer l'identifiant : le sien, celui
+    /// qu'il a créé pour un service, ou n'importe lequel s'il est administrateur
+    pub fn managed_by(&self, caller: &UserInfo) -> bool {
+        caller.is_admin()
+            || self.created_by.as_deref() == Some(caller.username.as_str())
+            || matches!(&self.owner, CredentialOwner::User { username } if *username == caller.username)
+    }
+
+    fn is_active(&self, now: i64) -> bool {
+        !self.revoked && self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+/// Demande de création d'un identifiant
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewCredential {
+    pub kind: CredentialKind,
+    pub name: String,
+    pub owner: CredentialOwner,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Basic : mot de passe en clair, haché en Argon2id
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Basic : hash existant importé tel quel (Argon2 ou bcrypt `$2a$`/`$2b$`/`$2y$`)
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    /// Utilisateur qui crée l'identifiant (renseigné par l'API, pas par le client)
+    #[serde(skip)]
+    pub created_by: Option<String>,
+}
+
+impl NewCredential {
+    /// Raison du refus si `caller` ne peut pas créer cet identifiant : hors
+    /// administrateurs, il doit appartenir à `caller` ou à un service qui
+    /// n'a que des groupes de `caller`.
+    pub fn denied_for(&self, caller: &UserInfo) -> Option<String> {
+        if caller.is_admin() {
+            return None;
+        }
+        match &self.owner {
+            CredentialOwner::User { username } if *username != caller.username => Some(format!(
+                "Identifiant réservé à '{}' ou à un administrateur",
+                username
+            )),
+            CredentialOwner::Service { groups, .. } => groups
+                .iter()
+                .find(|g| !caller.groups.contains(g))
+                .map(|g| format!("Groupe '{}' non détenu", g)),
+            _ => None,
+        }
+    }
+}
+
+/// Identifiant créé ; la clé d'API n'est visible qu'à ce moment
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedCredential {
+    #[serde(flatten)]
+    pub credential: Credential,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// Mot de passe basic récemment vérifié pour un hash donné
+struct VerifiedBasic {
+    secret_hash: String,
+    digest: String,
+    until: i64,
+}
+
+/// Échecs d'un client dans la fenêtre en cours
+struct FailureWindow {
+    count: u32,
+    since: i64,
+}
+
+/// Store des identifiants machine (tables de `auth.db`)
+pub struct CredentialStore {
+    conn: Mutex<Connection>,
+    /// Les hachages Argon2/bcrypt sont volontairement lents : un client basic
+    /// qui s'authentifie à chaque requête ne les paie qu'une fois par période.
+    verified: Mutex<HashMap<String, VerifiedBasic>>,
+    /// Échecs récents par client, pour borner le coût des essais en rafale
+    failures: Mutex<HashMap<String, FailureWindow>>,
+    /// Dernière écriture de `last_used_at` par identifiant
+    touched: Mutex<HashMap<String, i64>>,
+}
+
+const CREDENTIAL_COLUMNS: &str = "id, kind, name, owner, scopes, created_at, expires_at, last_used_at, revoked, secret_hash, created_by";
+
+impl CredentialStore {
+    pub fn new(data_dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(data_dir)?;
+        let conn = Connection::open(data_dir.join("auth.db"))?;
+
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS credentials (
+                id TEXT PRIMARY KEY,
+                kind TEXT NOT NULL,
+                name TEXT NOT NULL,
+                owner TEXT NOT NULL,
+                scopes TEXT NOT NULL,
+                secret_hash TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER,
+                last_used_at INTEGER,
+                revoked INTEGER DEFAULT 0,
+                created_by TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_credentials_name ON credentials(kind, name);
+            CREATE TABLE IF NOT EXISTS service_accounts (
+                name TEXT PRIMARY KEY,
+                uuid TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );",
+        )?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            verified: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            touched: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Crée un identifiant. Pour une clé d'API, la clé complète est renvoyée
+    /// une seule fois ; seul le SHA-256 de son secret est conservé.
+    pub fn create(&self, new: NewCredential) -> anyhow::Result<CreatedCredential> {
+        let name = new.name.trim().to_string();
+        if name.is_empty() {
+            bail!("Nom requis");
+        }
+        let scopes: Vec<String> = new.scopes.iter().map(|s| s.trim().to_string()).collect();
+        if let Some(scope) = scopes
+            .iter()
+            .find(|s| s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == ','))
+        {
+            bail!("Portée invalide '{}'", scope);
+        }
+        match &new.owner {
+            CredentialOwner::User { username } if username.trim().is_empty() => {
+                bail!("Utilisateur propriétaire requis")
+            }
+            CredentialOwner::Service { name, .. } if !valid_service_name(name) => {
+                bail!("Nom de service invalide '{}'", name)
+            }
+            _ => {}
+        }
+
+        let id = random_hex(6);
+        let (secret_hash, api_key) = match new.kind {
+            CredentialKind::ApiKey => {
+                if new.password.is_some() || new.password_hash.is_some() {
+                    bail!("Une clé d'API n'a pas de mot de passe");
+                }
+                let secret = random_hex(32);
+                (
+                    sha256_hex(&secret),
+                    Some(format!("{}{}_{}", API_KEY_PREFIX, id, secret)),
+                )
+            }
+            CredentialKind::Basic => {
+                if name.contains(':') {
+                    bail!("L'identifiant basic ne peut pas contenir ':'");
+                }
+                let hash = match (new.password, new.password_hash) {
+                    (Some(password), None) if !password.is_empty() => {
+                        crate::users::hash_password(&password)?
+                    }
+                    (None, Some(hash)) if is_supported_hash(&hash) => hash,
+                    (None, Some(_)) => bail!("Hash non supporté (Argon2 ou bcrypt attendu)"),
+                    _ => bail!("Fournir soit password, soit password_hash"),
+                };
+                if self.find_active(CredentialKind::Basic, &name)?.is_some() {
+                    bail!("L'identifiant basic '{}' existe déjà", name);
+                }
+                (hash, None)
+            }
+        };
+
+        let credential = Credential {
+            id,
+            kind: new.kind,
+            name,
+            owner: new.owner,
+            scopes,
+            created_at: now_ms(),
+            expires_at: new.expires_at,
+            last_used_at: None,
+            revoked: false,
+            created_by: new.created_by,
+        };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO credentials (id, kind, name, owner, scopes, secret_hash, created_at, expires_at, created_by)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                credential.id,
+                credential.kind.as_str(),
+                credential.name,
+                serde_json::to_string(&credential.owner)?,
+                serde_json::to_string(&credential.scopes)?,
+                secret_hash,
+                credential.created_at,
+                credential.expires_at,
+                credential.created_by,
+            ],
+        )?;
+
+        Ok(CreatedCredential {
+            credential,
+            api_key,
+        })
+    }
+
+    /// Liste tous les identifiants, révoqués compris
+    pub fn list(&self) -> anyhow::Result<Vec<Credential>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM credentials ORDER BY created_at",
+            CREDENTIAL_COLUMNS
+        ))?;
+        let credentials = stmt
+            .query_map([], |row| Ok(credential_from_row(row)?.0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(credentials)
+    }
+
+    /// Révoque un identifiant ; faux s'il n'existe pas
+    pub fn revoke(&self, id: &str) -> anyhow::Result<bool> {
+        self.verified.lock().unwrap().remove(id);
+        let conn = self.conn.lock().unwrap();
+        let changed = conn.execute(
+            "UPDATE credentials SET revoked = 1 WHERE id = ?1",
+            params![id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Vérifie une clé d'API complète (`hrk_<id>_<secret>`)
+    pub fn verify_api_key(&self, key: &str) -> anyhow::Result<Option<Credential>> {
+        let Some((id, secret)) = key
+            .strip_prefix(API_KEY_PREFIX)
+            .and_then(|rest| rest.split_once('_'))
+        else {
+            return Ok(None);
+        };
+        let Some((credential, hash)) = self.get(id)? else {
+            return Ok(None);
+        };
+        if credential.kind != CredentialKind::ApiKey
+            || !credential.is_active(now_ms())
+            || !constant_time_eq(sha256_hex(secret).as_bytes(), hash.as_bytes())
+        {
+            return Ok(None);
+        }
+        self.touch(&credential.id)?;
+        Ok(Some(credential))
+    }
+
+    /// Vérifie un couple identifiant / mot de passe basic
+    pub fn verify_basic(&self, login: &str, password: &str) -> anyhow::Result<Option<Credential>> {
+        let Some((credential, hash)) = self.find_active(CredentialKind::Basic, login)? else {
+            return Ok(None);
+        };
+        let now = now_ms();
+        let digest = sha256_hex(password);
+        let cached = self
+            .verified
+            .lock()
+            .unwrap()
+            .get(&credential.id)
+            .is_some_and(|v| {
+                v.secret_hash == hash
+                    && v.until > now
+                    && constant_time_eq(v.digest.as_bytes(), digest.as_bytes())
+            });
+        if !cached {
+            if !verify_secret(password, &hash) {
+                return Ok(None);
+            }
+            self.verified.lock().unwrap().insert(
+                credential.id.clone(),
+                VerifiedBasic {
+                    secret_hash: hash,
+                    digest,
+                    until: now + BASIC_CACHE_TTL_MS,
+                },
+            );
+        }
+        self.touch(&credential.id)?;
+        Ok(Some(credential))
+    }
+
+    /// Secondes avant que `client` puisse réessayer, s'il a épuisé ses
+    /// échecs sur la fenêtre en cours
+    pub fn throttled(&self, client: &str) -> Option<u64> {
+        let now = now_ms();
+        let failures = self.failures.lock().unwrap();
+        let window = failures.get(client)?;
+        let remaining = window.since + FAILURE_WINDOW_MS - now;
+        (window.count >= MAX_FAILURES && remaining > 0).then(|| (remaining as u64).div_ceil(1000))
+    }
+
+    /// Compte un échec d'authentification de `client`
+    pub fn record_failure(&self, client: &str) {
+        let now = now_ms();
+        let mut failures = self.failures.lock().unwrap();
+        let window = failures.entry(client.to_string()).or_insert(FailureWindow {
+            count: 0,
+            since: now,
+        });
+        if now - window.since >= FAILURE_WINDOW_MS {
+            *window = FailureWindow {
+                count: 0,
+                since: now,
+            };
+        }
+        window.count += 1;
+        // Chemin d'échec uniquement : les fenêtres closes sont purgées ici
+        if failures.len() > 1024 {
+            failures.retain(|_, w| now - w.since < FAILURE_WINDOW_MS);
+        }
+    }
+
+    /// Oublie les échecs de `client` après une authentification réussie
+    pub fn clear_failures(&self, client: &str) {
+        self.failures.lock().unwrap().remove(client);
+    }
+
+    /// Uuid stable d'un compte de service, créé à sa première utilisation
+    pub fn service_uuid(&self, name: &str) -> anyhow::Result<Uuid> {
+        let conn = self.conn.lock().unwrap();
+        let existing: Option<String> = conn
+            .query_row(
+                "SELECT uuid FROM service_accounts WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(uuid) = existing {
+            return Ok(Uuid::parse_str(&uuid)?);
+        }
+        let uuid = Uuid::new_v4();
+        conn.execute(
+            "INSERT INTO service_accounts (name, uuid, created_at) VALUES (?1, ?2, ?3)",
+            params![name, uuid.to_string(), now_ms()],
+        )?;
+        Ok(uuid)
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<Option<(Credential, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let found = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM credentials WHERE id = ?1",
+                    CREDENTIAL_COLUMNS
+                ),
+                params![id],
+                credential_from_row,
+            )
+            .optional()?;
+        Ok(found)
+    }
+
+    /// Identifiant actif (non révoqué, non expiré) de ce type et de ce nom
+    fn find_active(
+        &self,
+        kind: CredentialKind,
+        name: &str,
+    ) -> anyhow::Result<Option<(Credential, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM credentials WHERE kind = ?1 AND name = ?2 AND revoked = 0",
+            CREDENTIAL_COLUMNS
+        ))?;
+        let now = now_ms();
+        let found = stmt
+            .query_map(params![kind.as_str(), name], credential_from_row)?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .find(|(c, _)| c.is_active(now));
+        Ok(found)
+    }
+
+    /// Met à jour `last_used_at`, au plus une fois par `TOUCH_INTERVAL_MS`
+    /// et par identifiant : pas d'écriture SQLite à chaque requête.
+    fn touch(&self, id: &str) -> anyhow::Result<()> {
+        let now = now_ms();
+        {
+            let mut touched = self.touched.lock().unwrap();
+            if touched.get(id).is_some_and(|t| now - t < TOUCH_INTERVAL_MS) {
+                return Ok(());
+            }
+            touched.insert(id.to_string(), now);
+        }
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE credentials SET last_used_at = ?1 WHERE id = ?2",
+            params![now, id],
+        )?;
+        Ok(())
+    }
+}
+
+/// Ligne de `credentials` avec le hash de son secret
+fn credential_from_row(row: &Row) -> rusqlite::Result<(Credential, String)> {
+    let kind = match row.get::<_, String>(1)?.as_str() {
+        "basic" => CredentialKind::Basic,
+        _ => CredentialKind::ApiKey,
+    };
+    let owner = serde_json::from_str(&row.get::<_, String>(3)?).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, e.into())
+    })?;
+    let scopes = serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default();
+    Ok((
+        Credential {
+            id: row.get(0)?,
+            kind,
+            name: row.get(2)?,
+            owner,
+            scopes,
+            created_at: row.get(5)?,
+            expires_at: row.get(6)?,
+            last_used_at: row.get(7)?,
+            revoked: row.get::<_, i32>(8)? == 1,
+            created_by: row.get(10)?,
+        },
+        row.get(9)?,
+    ))
+}
+
+/// Vérifie un mot de passe contre un hash bcrypt ou Argon2
+fn verify_secret(password: &str, hash: &str) -> bool {
+    if is_bcrypt_hash(hash) {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else {
+        verify_password(password, hash)
+    }
+}
+
+fn is_bcrypt_hash(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2y$"].iter().any(|p| hash.starts_with(p))
+}
+
+fn is_supported_hash(hash: &str) -> bool {
+    is_bcrypt_hash(hash) || hash.starts_with("$argon2")
+}
+
+fn valid_service_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand_core::OsRng.fill_bytes(&mut buf);
+    hex::encode(buf)
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_credential(kind: CredentialKind, name: &str) -> NewCredential {
+        NewCredential {
+            kind,
+            name: name.to_string(),
+            owner: CredentialOwner::Service {
+                name: "sensors".to_string(),
+                groups: vec![],
+            },
+            scopes: vec!["metrics".to_string()],
+            expires_at: None,
+            password: None,
+            password_hash: None,
+            created_by: None,
+        }
+    }
+
+    fn user(name: &str, groups: &[&str]) -> UserInfo {
+        UserInfo {
+            username: name.to_string(),
+            uuid: Uuid::nil(),
+            displayname: name.to_string(),
+            email: String::new(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            created: None,
+            last_login: None,
+        }
+    }
+
+    #[test]
+    fn test_api_key_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path()).unwrap();
+
+        let created = store
+            .create(new_credential(CredentialKind::ApiKey, "thermostat"))
+            .unwrap();
+        let key = created.api_key.unwrap();
+        assert!(key.starts_with("hrk_"));
+
+        let found = store.verify_api_key(&key).unwrap().unwrap();
+        assert_eq!(found.id, created.credential.id);
+        assert!(found.allows(&["metrics".to_string()]));
+        assert!(!found.allows(&["admin".to_string()]));
+        assert!(found.allows(&[]));
+
+        let last = if key.ends_with('0') { '1' } else { '0' };
+        let forged = format!("{}{last}", &key[..key.len() - 1]);
+        assert!(store.verify_api_key(&forged).unwrap().is_none());
+        assert!(store.verify_api_key("not-a-key").unwrap().is_none());
+
+        assert!(store.revoke(&found.id).unwrap());
+        assert!(store.verify_api_key(&key).unwrap().is_none());
+        assert!(store.list().unwrap()[0].revoked);
+    }
+
+    #[test]
+    fn test_basic_argon2_and_bcrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path()).unwrap();
+
+        let mut webhook = new_credential(CredentialKind::Basic, "webhook");
+        webhook.password = Some("s3cret".to_string());
+        store.create(webhook.clone()).unwrap();
+        assert!(store.create(webhook).is_err());
+
+        let mut legacy = new_credential(CredentialKind::Basic, "legacy");
+        legacy.password_hash = Some(bcrypt::hash("hunter2", 4).unwrap());
+        store.create(legacy).unwrap();
+
+        assert!(store.verify_basic("webhook", "s3cret").unwrap().is_some());
+        // La deuxième vérification passe par le cache
+        assert!(store.verify_basic("webhook", "s3cret").unwrap().is_some());
+        assert!(store.verify_basic("webhook", "wrong").unwrap().is_none());
+        assert!(store.verify_basic("legacy", "hunter2").unwrap().is_some());
+        assert!(store.verify_basic("legacy", "s3cret").unwrap().is_none());
+        assert!(store.verify_basic("nobody", "s3cret").unwrap().is_none());
+
+        let mut bad = new_credential(CredentialKind::Basic, "bad");
+        bad.password_hash = Some("plaintext".to_string());
+        assert!(store.create(bad).is_err());
+
+        let uuid = store.service_uuid("sensors").unwrap();
+        assert_eq!(store.service_uuid("sensors").unwrap(), uuid);
+    }
+
+    #[test]
+    fn test_failure_throttling_and_touch() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path()).unwrap();
+
+        for _ in 0..MAX_FAILURES - 1 {
+            store.record_failure("203.0.113.7");
+        }
+        assert!(store.throttled("203.0.113.7").is_none());
+        store.record_failure("203.0.113.7");
+        let retry = store.throttled("203.0.113.7").unwrap();
+        assert!((1..=60).contains(&retry));
+        assert!(store.throttled("203.0.113.8").is_none());
+        store.clear_failures("203.0.113.7");
+        assert!(store.throttled("203.0.113.7").is_none());
+
+        let created = store
+            .create(new_credential(CredentialKind::ApiKey, "thermostat"))
+            .unwrap();
+        let key = created.api_key.unwrap();
+        store.verify_api_key(&key).unwrap().unwrap();
+        let first = store.list().unwrap()[0].last_used_at.unwrap();
+        // Une seconde utilisation rapprochée n'écrit pas
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.verify_api_key(&key).unwrap().unwrap();
+        assert_eq!(store.list().unwrap()[0].last_used_at, Some(first));
+    }
+
+    #[test]
+    fn test_credential_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path()).unwrap();
+        let alice = user("alice", &["family"]);
+        let bob = user("bob", &[]);
+        let admin = user("carol", &["admins"]);
+
+        let mut own = new_credential(CredentialKind::ApiKey, "laptop");
+        own.owner = CredentialOwner::User {
+            username: "alice".to_string(),
+        };
+        assert!(own.denied_for(&alice).is_none());
+        assert!(own.denied_for(&bob).is_some());
+        assert!(own.denied_for(&admin).is_none());
+
+        let mut service = new_credential(CredentialKind::ApiKey, "backup");
+        service.owner = CredentialOwner::Service {
+            name: "backup".to_string(),
+            groups: vec!["family".to_string()],
+        };
+        assert!(service.denied_for(&alice).is_none());
+        service.owner = CredentialOwner::Service {
+            name: "backup".to_string(),
+            groups: vec!["family".to_string(), "admins".to_string()],
+        };
+        assert!(service.denied_for(&alice).is_some());
+        assert!(service.denied_for(&admin).is_none());
+
+        service.created_by = Some("alice".to_string());
+        store.create(own).unwrap();
+        store.create(service).unwrap();
+        store
+            .create(new_credential(CredentialKind::ApiKey, "thermostat"))
+            .unwrap();
+        let managed = |caller: &UserInfo| {
+            store
+                .list()
+                .unwrap()
+                .into_iter()
+                .filter(|c| c.managed_by(caller))
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(managed(&alice), vec!["laptop", "backup"]);
+        assert!(managed(&bob).is_empty());
+        assert_eq!(managed(&admin).len(), 3);
+        assert_eq!(managed(&user("admin", &[])).len(), 3);
+    }
+}
@@ -1,8 +1,10 @@
+pub mod credentials;
 pub mod forward_auth;
 pub mod middleware;
 pub mod sessions;
 pub mod users;
 
+use crate::credentials::{CreatedCredential, CredentialOwner, CredentialStore, NewCredential};
 use crate::sessions::SessionStore;
 use crate::users::UserStore;
 use std::path::Path;
@@ -10,11 +12,13 @@ use std::sync::Arc;
 
 /// Service d'authentification unifié
 ///
-/// Encapsule le store de sessions (SQLite) et le store d'utilisateurs (YAML).
-/// Thread-safe via Arc, utilisable depuis le proxy (forward-auth) et l'API.
+/// Encapsule le store de sessions et d'identifiants machine (SQLite) et le
+/// store d'utilisateurs (YAML). Thread-safe via Arc, utilisable depuis le
+/// proxy (forward-auth) et l'API.
 pub struct AuthService {
     pub sessions: SessionStore,
     pub users: UserStore,
+    pub credentials: CredentialStore,
     pub base_domain: String,
 }
 
@@ -23,14 +27,27 @@ impl AuthService {
     pub fn new(data_dir: &Path, base_domain: &str) -> anyhow::Result<Arc<Self>> {
         let sessions = SessionStore::new(data_dir)?;
         let users = UserStore::new(data_dir);
+        let credentials = CredentialStore::new(data_dir)?;
 
         Ok(Arc::new(Self {
             sessions,
             users,
+            credentials,
             base_domain: base_domain.to_string(),
         }))
     }
 
+    /// Crée un identifiant machine après avoir vérifié que l'utilisateur
+    /// propriétaire existe
+    pub fn create_credential(&self, new: NewCredential) -> anyhow::Result<CreatedCredential> {
+        if let CredentialOwner::User { username } = &new.owner
+            && self.users.get(username).is_none()
+        {
+            anyhow::bail!("Utilisateur inconnu '{}'", username);
+        }
+        self.credentials.create(new)
+    }
+
     /// Démarre le nettoyage périodique des sessions expirées
     pub fn start_cleanup_task(self: &Arc<Self>) {
         let this = Arc::clone(self);
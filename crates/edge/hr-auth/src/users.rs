@@ -47,10 +47,25 @@ pub struct UserInfo {
     pub uuid: Uuid,
     pub displayname: String,
     pub email: String,
+    /// Groupes de l'utilisateur (autorisation par `allowed_groups`)
+    #[serde(default)]
+    pub groups: Vec<String>,
     pub created: Option<String>,
     pub last_login: Option<String>,
 }
 
+/// Compte du tableau de bord, administrateur d'office
+pub const ADMIN_USER: &str = "admin";
+/// Groupe donnant les mêmes droits que `ADMIN_USER`
+pub const ADMIN_GROUP: &str = "admins";
+
+impl UserInfo {
+    /// Vrai pour le compte du tableau de bord et les membres de `admins`
+    pub fn is_admin(&self) -> bool {
+        self.username == ADMIN_USER || self.groups.iter().any(|g| g == ADMIN_GROUP)
+    }
+}
+
 /// Informations utilisateur avec hash du mot de passe (pour l'auth)
 #[derive(Debug, Clone)]
 pub struct UserWithPassword {
@@ -103,7 +118,7 @@ impl UserStore {
     /// read of a missing entry.
     pub fn get(&self, username: &str) -> Option<UserInfo> {
         let mut data = self.load();
-        let (uuid, displayname, email, created, last_login, needs_save);
+        let (uuid, displayname, email, groups, created, last_login, needs_save);
         {
             let user = data.users.get_mut(username)?;
             (uuid, needs_save) = match user.uuid {
@@ -119,6 +134,7 @@ impl UserStore {
                 .clone()
                 .unwrap_or_else(|| username.to_string());
             email = user.email.clone().unwrap_or_default();
+            groups = user.groups.clone();
             created = user.created.clone();
             last_login = user.last_login.clone();
         }
@@ -133,6 +149,7 @@ impl UserStore {
             uuid,
             displayname,
             email,
+            groups,
             created,
             last_login,
         })
@@ -262,6 +279,22 @@ mod tests {
         assert!(yaml.contains(&first_uuid.to_string()));
     }
 
+    #[test]
+    fn get_returns_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("users.yml"),
+            "users:\n  alice:\n    groups: [adults]\n  bob: {}\n",
+        )
+        .unwrap();
+        let store = UserStore::new(dir.path());
+
+        let alice = store.get("alice").expect("user exists");
+        assert_eq!(alice.groups, vec!["adults".to_string()]);
+        let bob = store.get("bob").expect("user exists");
+        assert!(bob.groups.is_empty());
+    }
+
     #[test]
     fn get_returns_none_for_missing_user() {
         let dir = tempfile::tempdir().unwrap();
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use hr_ipc::edge::EdgeRequest;
+use hr_ipc::edge::{AppRouteSpec, EdgeRequest};
 use hr_ipc::server::IpcHandler;
 use hr_ipc::types::IpcResponse;
 use tracing::{info, warn};
@@ -14,6 +14,10 @@ pub struct EdgeHandler {
     pub tls_manager: Arc<hr_proxy::TlsManager>,
     pub env: Arc<hr_common::config::EnvConfig>,
     pub dns_route_sync: Arc<DnsRouteSync>,
+    pub config_history: Arc<hr_proxy::ConfigHistory>,
+    /// Held from validation to activation so saves, rollbacks and reloads
+    /// of the proxy config don't interleave.
+    pub config_lock: tokio::sync::Mutex<()>,
 }
 
 impl EdgeHandler {
@@ -52,26 +56,138 @@ impl EdgeHandler {
         }
         info!("Re-loaded {} ACME certificates after config reload", loaded);
     }
+
+    /// Make `config` the running config: certificates, OCSP, proxy state
+    /// and DNS records.
+    fn activate(&self, config: hr_proxy::ProxyConfig) -> Result<(), String> {
+        self.tls_manager
+            .reload_certificates(&config.routes)
+            .map_err(|e| format!("TLS reload failed: {}", e))?;
+        self.reload_acme_certs();
+        self.tls_manager.ocsp.configure(&config.ocsp);
+        self.proxy.reload_config(config);
+        self.dns_route_sync.request_sync();
+        Ok(())
+    }
+
+    /// Activate `config`; if that fails partway, activate `running` again
+    /// so certificates and proxy state don't mix both configs.
+    fn activate_or_revert(
+        &self,
+        config: hr_proxy::ProxyConfig,
+        running: hr_proxy::ProxyConfig,
+    ) -> Result<(), String> {
+        self.activate(config).inspect_err(|_| {
+            if let Err(e) = self.activate(running) {
+                warn!("Failed to re-activate the running proxy config: {}", e);
+            }
+        })
+    }
+
+    /// Validate `candidate`, write it over the config file and apply it,
+    /// recording it in the history. The previous file and running config
+    /// are put back if the config can't be activated.
+    async fn apply_config(
+        &self,
+        candidate: hr_proxy::ProxyConfig,
+        source: &str,
+    ) -> Result<serde_json::Value, String> {
+        let _guard = self.config_lock.lock().await;
+        let report = hr_proxy::transaction::validate(&candidate, &self.tls_manager).await;
+        if !report.is_valid() {
+            return Err(format!("Invalid config: {}", report.error_summary()));
+        }
+        let running = self.proxy.config();
+        let diff = hr_proxy::transaction::diff(&running, &candidate);
+
+        let path = &self.env.proxy_config_path;
+        let previous = std::fs::read(path).ok();
+        candidate
+            .save_to_file(path)
+            .map_err(|e| format!("Failed to write config: {}", e))?;
+        let keep = candidate.history_size;
+        if let Err(e) = self.activate_or_revert(candidate.clone(), running) {
+            if let Some(previous) = previous
+                && let Err(restore) = std::fs::write(path, previous)
+            {
+                warn!("Failed to restore previous proxy config: {}", restore);
+            }
+            return Err(e);
+        }
+        let revision = match self.config_history.record(&candidate, source, keep) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                warn!("Failed to record proxy config revision: {:#}", e);
+                None
+            }
+        };
+        info!(source, changes = diff.changes.len(), "Proxy config applied");
+        Ok(serde_json::json!({
+            "revision": revision,
+            "diff": diff,
+            "issues": report.issues,
+        }))
+    }
 }
 
 impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
     async fn handle(&self, request: EdgeRequest) -> IpcResponse {
         match request {
             // ── Route management ──────────────────────────────────
-            EdgeRequest::SetAppRoute {
-                domain,
-                app_id,
-                host_id,
-                target_ip,
-                target_port,
-                auth_required,
-                allowed_groups,
-                local_only,
-            } => {
+            EdgeRequest::SetAppRoute(spec) => {
+                let AppRouteSpec {
+                    domain,
+                    app_id,
+                    host_id,
+                    target_ip,
+                    target_port,
+                    auth_required,
+                    allowed_groups,
+                    local_only,
+                    pool,
+                    rate_limit,
+                    headers,
+                    acl,
+                    client_cert,
+                    backend_tls,
+                    machine_auth,
+                    upstream,
+                } = *spec;
                 let ip: std::net::Ipv4Addr = match target_ip.parse() {
                     Ok(ip) => ip,
                     Err(e) => return IpcResponse::err(format!("Invalid IP: {}", e)),
                 };
+                let rate_limit = match rate_limit.map(serde_json::from_value).transpose() {
+                    Ok(limit) => limit,
+                    Err(e) => return IpcResponse::err(format!("Invalid rate_limit: {}", e)),
+                };
+                let headers = match headers.map(serde_json::from_value).transpose() {
+                    Ok(h) => h.unwrap_or_default(),
+                    Err(e) => return IpcResponse::err(format!("Invalid headers: {}", e)),
+                };
+                let acl = match acl.map(serde_json::from_value).transpose() {
+                    Ok(a) => a.unwrap_or_default(),
+                    Err(e) => return IpcResponse::err(format!("Invalid acl: {}", e)),
+                };
+                let client_cert = match client_cert
+                    .map(|mode| serde_json::from_value(serde_json::Value::String(mode)))
+                    .transpose()
+                {
+                    Ok(mode) => mode.unwrap_or_default(),
+                    Err(e) => return IpcResponse::err(format!("Invalid client_cert: {}", e)),
+                };
+                let backend_tls = match backend_tls.map(serde_json::from_value).transpose() {
+                    Ok(tls) => tls.unwrap_or_default(),
+                    Err(e) => return IpcResponse::err(format!("Invalid backend_tls: {}", e)),
+                };
+                let machine_auth = match machine_auth.map(serde_json::from_value).transpose() {
+                    Ok(config) => config,
+                    Err(e) => return IpcResponse::err(format!("Invalid machine_auth: {}", e)),
+                };
+                let upstream = match upstream.map(serde_json::from_value).transpose() {
+                    Ok(config) => config,
+                    Err(e) => return IpcResponse::err(format!("Invalid upstream: {}", e)),
+                };
                 self.proxy.set_app_route(
                     domain,
                     hr_proxy::AppRoute {
@@ -82,6 +198,15 @@ impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
                         auth_required,
                         allowed_groups,
                         local_only,
+                        acl,
+                        client_cert,
+                        backend_tls,
+                        pool,
+                        rate_limit,
+                        headers,
+                        split: None,
+                        machine_auth,
+                        upstream,
                     },
                 );
                 self.dns_route_sync.request_sync();
@@ -93,17 +218,107 @@ impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
                 IpcResponse::ok_empty()
             }
             EdgeRequest::ListAppRoutes => IpcResponse::ok_data(self.proxy.list_app_routes()),
+            EdgeRequest::SetAppState { domain, state } => {
+                self.proxy.set_app_state(&domain, &state);
+                IpcResponse::ok_empty()
+            }
+            EdgeRequest::SetAppSplit { domain, split } => {
+                let split = match split.map(serde_json::from_value).transpose() {
+                    Ok(s) => s,
+                    Err(e) => return IpcResponse::err(format!("Invalid split: {}", e)),
+                };
+                match self.proxy.set_app_split(&domain, split) {
+                    Ok(()) => IpcResponse::ok_empty(),
+                    Err(e) => IpcResponse::err(e.to_string()),
+                }
+            }
+            EdgeRequest::SetAppWeights { domain, weights } => {
+                match self.proxy.set_app_weights(&domain, &weights) {
+                    Ok(split) => IpcResponse::ok_data(split),
+                    Err(e) => IpcResponse::err(e.to_string()),
+                }
+            }
+            EdgeRequest::PromoteAppTarget { domain, target } => {
+                match self.proxy.promote_app_target(&domain, &target) {
+                    Ok(()) => IpcResponse::ok_empty(),
+                    Err(e) => IpcResponse::err(e.to_string()),
+                }
+            }
+
+            // ── Maintenance mode ──────────────────────────────────
+            EdgeRequest::MaintenanceList => IpcResponse::ok_data(self.proxy.maintenance.list()),
+            EdgeRequest::MaintenanceSet {
+                domain,
+                message,
+                allow,
+            } => {
+                self.proxy.maintenance.set(domain, message, allow);
+                IpcResponse::ok_empty()
+            }
+            EdgeRequest::MaintenanceClear { domain } => {
+                if self.proxy.maintenance.clear(&domain) {
+                    IpcResponse::ok_empty()
+                } else {
+                    IpcResponse::err(format!("{} is not in maintenance", domain))
+                }
+            }
+
+            // ── Redirect rules ────────────────────────────────────
+            EdgeRequest::RedirectTest { url } => match self.proxy.test_redirect(&url) {
+                Ok(hit) => IpcResponse::ok_data(hit),
+                Err(e) => IpcResponse::err(format!("Invalid URL: {}", e)),
+            },
+
+            // ── Stream routes ─────────────────────────────────────
+            EdgeRequest::StreamStatus => IpcResponse::ok_data(self.proxy.streams.stats()),
+
+            // ── Access log index ──────────────────────────────────
+            EdgeRequest::AccessLogQuery { filter } => {
+                let Some(ref index) = self.proxy.access_index else {
+                    return IpcResponse::err("Access log index is disabled");
+                };
+                let filter: hr_proxy::AccessLogQuery = match serde_json::from_value(filter) {
+                    Ok(f) => f,
+                    Err(e) => return IpcResponse::err(format!("Invalid filter: {}", e)),
+                };
+                match index.query(&filter).await {
+                    Ok(entries) => IpcResponse::ok_data(entries),
+                    Err(e) => IpcResponse::err(e.to_string()),
+                }
+            }
+            EdgeRequest::AccessLogStats => {
+                let Some(ref index) = self.proxy.access_index else {
+                    return IpcResponse::err("Access log index is disabled");
+                };
+                match index.stats().await {
+                    Ok(stats) => IpcResponse::ok_data(stats),
+                    Err(e) => IpcResponse::err(e.to_string()),
+                }
+            }
 
             // ── Proxy config ──────────────────────────────────────
             EdgeRequest::ReloadConfig => {
+                let _guard = self.config_lock.lock().await;
                 match hr_proxy::ProxyConfig::load_from_file(&self.env.proxy_config_path) {
                     Ok(new_config) => {
-                        if let Err(e) = self.tls_manager.reload_certificates(&new_config.routes) {
-                            return IpcResponse::err(format!("TLS reload failed: {}", e));
+                        // The file was edited in place: keep the running
+                        // config rather than activate a broken one
+                        let report = hr_proxy::transaction::check(&new_config);
+                        if !report.is_valid() {
+                            return IpcResponse::err(format!(
+                                "Config reload refused: {}",
+                                report.error_summary()
+                            ));
+                        }
+                        let keep = new_config.history_size;
+                        if let Err(e) =
+                            self.activate_or_revert(new_config.clone(), self.proxy.config())
+                        {
+                            return IpcResponse::err(e);
+                        }
+                        if let Err(e) = self.config_history.record(&new_config, "reload", keep) {
+                            warn!("Failed to record proxy config revision: {:#}", e);
                         }
-                        self.reload_acme_certs();
-                        self.proxy.reload_config(new_config);
-                        self.dns_route_sync.request_sync();
                         IpcResponse::ok_empty()
                     }
                     Err(e) => IpcResponse::err(format!("Config reload failed: {}", e)),
@@ -111,28 +326,51 @@ impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
             }
             EdgeRequest::GetProxyConfig => IpcResponse::ok_data(self.proxy.config()),
             EdgeRequest::SaveProxyConfig { config } => {
-                match serde_json::to_string_pretty(&config) {
-                    Ok(json) => {
-                        if let Err(e) = std::fs::write(&self.env.proxy_config_path, &json) {
-                            return IpcResponse::err(format!("Failed to write config: {}", e));
-                        }
-                        // Trigger reload after save
-                        match hr_proxy::ProxyConfig::load_from_file(&self.env.proxy_config_path) {
-                            Ok(new_config) => {
-                                if let Err(e) =
-                                    self.tls_manager.reload_certificates(&new_config.routes)
-                                {
-                                    return IpcResponse::err(format!("TLS reload failed: {}", e));
-                                }
-                                self.reload_acme_certs();
-                                self.proxy.reload_config(new_config);
-                                self.dns_route_sync.request_sync();
-                                IpcResponse::ok_empty()
+                match serde_json::from_value::<hr_proxy::ProxyConfig>(config) {
+                    Ok(candidate) => match self.apply_config(candidate, "save").await {
+                        Ok(data) => IpcResponse::ok_data(data),
+                        Err(e) => IpcResponse::err(e),
+                    },
+                    Err(e) => IpcResponse::err(format!("Invalid config JSON: {}", e)),
+                }
+            }
+            EdgeRequest::ValidateProxyConfig { config } => {
+                match serde_json::from_value::<hr_proxy::ProxyConfig>(config) {
+                    Ok(candidate) => {
+                        let report =
+                            hr_proxy::transaction::validate(&candidate, &self.tls_manager).await;
+                        let diff = hr_proxy::transaction::diff(&self.proxy.config(), &candidate);
+                        IpcResponse::ok_data(serde_json::json!({
+                            "valid": report.is_valid(),
+                            "issues": report.issues,
+                            "diff": diff,
+                        }))
+                    }
+                    Err(e) => IpcResponse::err(format!("Invalid config JSON: {}", e)),
+                }
+            }
+            EdgeRequest::ListConfigRevisions => IpcResponse::ok_data(serde_json::json!({
+                "revisions": self.config_history.list(),
+            })),
+            EdgeRequest::RollbackProxyConfig { revision } => {
+                let Some(id) = revision.or_else(|| self.config_history.previous()) else {
+                    return IpcResponse::err("No previous config revision");
+                };
+                match self.config_history.get(id) {
+                    Ok(rev) => {
+                        info!(revision = id, "Rolling back proxy config");
+                        match self
+                            .apply_config(rev.config, &format!("rollback:{}", id))
+                            .await
+                        {
+                            Ok(mut data) => {
+                                data["restored"] = serde_json::json!(id);
+                                IpcResponse::ok_data(data)
                             }
-                            Err(e) => IpcResponse::err(format!("Config reload failed: {}", e)),
+                            Err(e) => IpcResponse::err(e),
                         }
                     }
-                    Err(e) => IpcResponse::err(format!("Invalid config JSON: {}", e)),
+                    Err(e) => IpcResponse::err(format!("{:#}", e)),
                 }
             }
 
@@ -152,6 +390,29 @@ impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
             ),
             EdgeRequest::AcmeRenewAll => IpcResponse::ok_data("renewal triggered"),
 
+            // ── Device certificates ───────────────────────────────
+            EdgeRequest::DeviceCertList => match self.tls_manager.list_device_certs() {
+                Ok((devices, ca_pem)) => IpcResponse::ok_data(serde_json::json!({
+                    "devices": devices,
+                    "ca_pem": ca_pem,
+                })),
+                Err(e) => IpcResponse::err(format!("{}", e)),
+            },
+            EdgeRequest::DeviceCertIssue {
+                name,
+                validity_days,
+            } => match self.tls_manager.issue_device_cert(&name, validity_days) {
+                Ok(issued) => IpcResponse::ok_data(issued),
+                Err(e) => IpcResponse::err(format!("{}", e)),
+            },
+            EdgeRequest::DeviceCertRevoke { serial } => {
+                match self.tls_manager.revoke_device_cert(&serial) {
+                    Ok(true) => IpcResponse::ok_empty(),
+                    Ok(false) => IpcResponse::err(format!("Unknown device certificate {}", serial)),
+                    Err(e) => IpcResponse::err(format!("{}", e)),
+                }
+            }
+
             // ── Auth ──────────────────────────────────────────────
             EdgeRequest::AuthLogin {
                 username,
@@ -247,9 +508,21 @@ impl IpcHandler<EdgeRequest, IpcResponse> for EdgeHandler {
                 IpcResponse::ok_data(serde_json::json!({
                     "global": global,
                     "domains": domain_stats,
+                    "backends": self.proxy.metrics.backend_snapshot(),
+                    "cache": self.proxy.cache.stats(),
                     "certificates": cert_expiry,
                 }))
             }
+            EdgeRequest::GetPoolStatus => IpcResponse::ok_data(serde_json::json!({
+                "pools": self.proxy.pools.status(),
+                "backends": self.proxy.metrics.backend_snapshot(),
+                "backend_tls": self.proxy.backend_tls.status(),
+                "circuit_breakers": self.proxy.breakers.status(),
+            })),
+            EdgeRequest::GetOcspStatus => IpcResponse::ok_data(serde_json::json!({
+                "enabled": self.proxy.config().ocsp.enabled,
+                "certificates": self.tls_manager.ocsp.status(),
+            })),
         }
     }
 }
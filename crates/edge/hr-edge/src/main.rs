@@ -13,7 +13,7 @@ use hr_proxy::{ProxyConfig, ProxyState, TlsManager};
 use crate::dns_route_sync::{DnsRouteSync, resolve_server_ip};
 use signal_hook::consts::SIGHUP;
 use signal_hook_tokio::Signals;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio_stream::StreamExt;
@@ -165,6 +165,11 @@ async fn main() -> anyhow::Result<()> {
     // Restrict fallback cert to local domain only
     tls_manager.resolver.set_local_domain(&env.base_domain);
 
+    // Client certificates for routes with `client_cert` (device CA)
+    if let Err(e) = tls_manager.load_device_ca() {
+        warn!("Failed to load device CA: {}", e);
+    }
+
     let tls_config = tls_manager.build_server_config()?;
 
     // ── Proxy ────────────────────────────────────────────────────────
@@ -183,12 +188,33 @@ async fn main() -> anyhow::Result<()> {
     let proxy_state = Arc::new(
         ProxyState::new(proxy_config.clone(), env.api_port, orchestrator_port)
             .with_auth(auth.clone())
-            .with_app_routes_path(env.data_dir.join("app-routes.json")),
+            .with_app_routes_path(env.data_dir.join("app-routes.json"))
+            .with_maintenance_path(env.data_dir.join("maintenance.json"))
+            .with_access_index(env.data_dir.join("access-log.db")),
     );
+    proxy_state.start_streams();
 
     let https_port = proxy_config.https_port;
     let http_port = proxy_config.http_port;
 
+    // Active health checks for backend pools
+    tokio::spawn(hr_proxy::pool::run_health_checks(proxy_state.pools.clone()));
+    tokio::spawn(hr_proxy::ratelimit::run_sweeper(
+        proxy_state.limiter.clone(),
+    ));
+
+    // Applied proxy configs, for rollback
+    let config_history = Arc::new(hr_proxy::ConfigHistory::new(
+        env.data_dir.join("proxy-config-history"),
+    ));
+    if let Err(e) = config_history.record(&proxy_config, "startup", proxy_config.history_size) {
+        warn!("Failed to record proxy config revision: {:#}", e);
+    }
+
+    // OCSP stapling for the ACME certificates
+    tls_manager.ocsp.configure(&proxy_config.ocsp);
+    tokio::spawn(hr_proxy::ocsp::run_refresh(tls_manager.ocsp.clone()));
+
     info!(
         "Loaded {} TLS certificates for {} active routes",
         tls_manager.loaded_domains().len(),
@@ -223,13 +249,30 @@ async fn main() -> anyhow::Result<()> {
         let proxy_state_c = proxy_state.clone();
         let tls_config_c = tls_config.clone();
         let acme_c = acme.clone();
+        let netcore_c = netcore_client.clone();
         let reg = service_registry.clone();
         spawn_supervised("proxy-https", ServicePriority::Critical, reg, move || {
             let proxy_state = proxy_state_c.clone();
             let tls_config = tls_config_c.clone();
             let acme = acme_c.clone();
             let port = https_port;
-            async move { run_https_server(proxy_state, tls_config, acme, port).await }
+            let netcore = netcore_c.clone();
+            async move { run_https_server(proxy_state, tls_config, acme, netcore, port).await }
+        });
+    }
+
+    // HTTP/3 over QUIC (Important — TCP keeps serving if UDP bind fails)
+    if proxy_config.http3_enabled {
+        let proxy_state_c = proxy_state.clone();
+        let resolver_c = tls_manager.resolver.clone();
+        let client_auth_c = tls_manager.client_auth.clone();
+        let reg = service_registry.clone();
+        spawn_supervised("proxy-h3", ServicePriority::Important, reg, move || {
+            let proxy_state = proxy_state_c.clone();
+            let resolver = resolver_c.clone();
+            let client_auth = client_auth_c.clone();
+            let port = https_port;
+            async move { hr_proxy::http3::run_h3_server(proxy_state, resolver, client_auth, port).await }
         });
     }
 
@@ -317,6 +360,8 @@ async fn main() -> anyhow::Result<()> {
             tls_manager: tls_manager.clone(),
             env: Arc::new(env.clone()),
             dns_route_sync: dns_route_sync.clone(),
+            config_history: config_history.clone(),
+            config_lock: Default::default(),
         });
 
         let ipc_reg = service_registry.clone();
@@ -337,7 +382,6 @@ async fn main() -> anyhow::Result<()> {
         );
     }
 
-
     // ── SIGHUP handler ───────────────────────────────────────────────
     let acme_sighup = acme.clone();
     let env_sighup = env.clone();
@@ -350,6 +394,7 @@ async fn main() -> anyhow::Result<()> {
             acme_sighup,
             env_sighup,
             dns_sync_sighup,
+            config_history,
         )
         .await
         {
@@ -374,127 +419,146 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-// ── Metrics endpoint (Prometheus text format, localhost only) ───────────
+// ── Metrics endpoint (OpenMetrics, localhost only) ─────────────────────
 
-fn build_metrics_response(
+async fn build_metrics_response(
     state: &Arc<ProxyState>,
     acme: &Arc<hr_acme::AcmeManager>,
+    netcore: &NetcoreClient,
 ) -> hyper::Response<axum::body::Body> {
-    use std::fmt::Write;
-
-    let global = state.metrics.global_snapshot();
-    let domains = state.metrics.snapshot();
-    let certs = acme.cert_expiry_info().unwrap_or_default();
-
-    let mut out = String::with_capacity(2048);
+    use hr_proxy::openmetrics::{self, MetricType, OpenMetrics};
 
-    // Global counters
-    let _ = writeln!(
-        out,
-        "# HELP hr_proxy_requests_total Total proxied requests."
-    );
-    let _ = writeln!(out, "# TYPE hr_proxy_requests_total counter");
-    let _ = writeln!(out, "hr_proxy_requests_total {}", global.total_requests);
-
-    let _ = writeln!(out, "# HELP hr_proxy_status_2xx_total Total 2xx responses.");
-    let _ = writeln!(out, "# TYPE hr_proxy_status_2xx_total counter");
-    let _ = writeln!(out, "hr_proxy_status_2xx_total {}", global.status_2xx);
-
-    let _ = writeln!(out, "# HELP hr_proxy_status_4xx_total Total 4xx responses.");
-    let _ = writeln!(out, "# TYPE hr_proxy_status_4xx_total counter");
-    let _ = writeln!(out, "hr_proxy_status_4xx_total {}", global.status_4xx);
-
-    let _ = writeln!(out, "# HELP hr_proxy_errors_5xx_total Total 5xx errors.");
-    let _ = writeln!(out, "# TYPE hr_proxy_errors_5xx_total counter");
-    let _ = writeln!(out, "hr_proxy_errors_5xx_total {}", global.status_5xx);
-
-    let _ = writeln!(
-        out,
-        "# HELP hr_proxy_uptime_seconds Proxy uptime in seconds."
-    );
-    let _ = writeln!(out, "# TYPE hr_proxy_uptime_seconds gauge");
-    let _ = writeln!(out, "hr_proxy_uptime_seconds {}", global.uptime_secs);
-
-    let _ = writeln!(
-        out,
-        "# HELP hr_proxy_requests_per_second Average requests per second since start."
-    );
-    let _ = writeln!(out, "# TYPE hr_proxy_requests_per_second gauge");
-    let _ = writeln!(
-        out,
-        "hr_proxy_requests_per_second {:.2}",
-        global.requests_per_second
-    );
-
-    // Per-domain counters
-    let _ = writeln!(
-        out,
-        "# HELP hr_proxy_domain_requests_total Requests per domain."
-    );
-    let _ = writeln!(out, "# TYPE hr_proxy_domain_requests_total counter");
-    for d in &domains {
-        let _ = writeln!(
-            out,
-            "hr_proxy_domain_requests_total{{domain=\"{}\"}} {}",
-            d.domain, d.total_requests
-        );
-    }
-
-    let _ = writeln!(
-        out,
-        "# HELP hr_proxy_domain_errors_5xx_total 5xx errors per domain."
-    );
-    let _ = writeln!(out, "# TYPE hr_proxy_domain_errors_5xx_total counter");
-    for d in &domains {
-        let _ = writeln!(
-            out,
-            "hr_proxy_domain_errors_5xx_total{{domain=\"{}\"}} {}",
-            d.domain, d.errors_5xx
-        );
-    }
+    let mut m = OpenMetrics::new();
+    openmetrics::write_proxy(&mut m, state);
 
     // Certificate expiry
-    let _ = writeln!(
-        out,
-        "# HELP hr_tls_cert_expiry_days Days until TLS certificate expires."
+    let certs = acme.cert_expiry_info().unwrap_or_default();
+    m.family(
+        "hr_tls_cert_expiry_days",
+        MetricType::Gauge,
+        "Days until TLS certificate expires.",
     );
-    let _ = writeln!(out, "# TYPE hr_tls_cert_expiry_days gauge");
     for c in &certs {
-        let _ = writeln!(
-            out,
-            "hr_tls_cert_expiry_days{{domain=\"{}\",type=\"{}\"}} {}",
-            c.domain, c.wildcard_type, c.days_remaining
+        m.sample(
+            "hr_tls_cert_expiry_days",
+            &[("domain", &c.domain), ("type", &c.wildcard_type)],
+            c.days_remaining,
         );
     }
-
-    let _ = writeln!(
-        out,
-        "# HELP hr_tls_cert_needs_renewal Whether certificate needs renewal (1=yes)."
+    m.family(
+        "hr_tls_cert_needs_renewal",
+        MetricType::Gauge,
+        "Whether certificate needs renewal (1=yes).",
     );
-    let _ = writeln!(out, "# TYPE hr_tls_cert_needs_renewal gauge");
     for c in &certs {
-        let _ = writeln!(
-            out,
-            "hr_tls_cert_needs_renewal{{domain=\"{}\",type=\"{}\"}} {}",
-            c.domain,
-            c.wildcard_type,
-            if c.needs_renewal { 1 } else { 0 }
+        m.sample(
+            "hr_tls_cert_needs_renewal",
+            &[("domain", &c.domain), ("type", &c.wildcard_type)],
+            u8::from(c.needs_renewal),
         );
     }
 
+    // DNS, DHCP and adblock from hr-netcore (skipped while it is down)
+    match netcore.metrics().await {
+        Ok(n) => {
+            m.family(
+                "hr_dns_queries",
+                MetricType::Counter,
+                "DNS queries by outcome.",
+            );
+            for (outcome, count) in &n.dns_queries {
+                m.sample("hr_dns_queries_total", &[("outcome", outcome)], count);
+            }
+            m.gauge(
+                "hr_dns_cache_entries",
+                "Entries in the DNS cache.",
+                n.dns_cache_size,
+            );
+            m.family(
+                "hr_dhcp_received",
+                MetricType::Counter,
+                "DHCP messages received by type.",
+            );
+            for (msg_type, count) in &n.dhcp_received {
+                m.sample("hr_dhcp_received_total", &[("type", msg_type)], count);
+            }
+            m.family(
+                "hr_dhcp_sent",
+                MetricType::Counter,
+                "DHCP messages sent by type.",
+            );
+            for (msg_type, count) in &n.dhcp_sent {
+                m.sample("hr_dhcp_sent_total", &[("type", msg_type)], count);
+            }
+            m.gauge(
+                "hr_dhcp_active_leases",
+                "Unexpired DHCP leases.",
+                n.dhcp_active_leases,
+            );
+            m.gauge(
+                "hr_adblock_enabled",
+                "Whether ad blocking is enabled (1=yes).",
+                u8::from(n.adblock_enabled),
+            );
+            m.gauge(
+                "hr_adblock_domains",
+                "Domains in the blocklist.",
+                n.adblock_domains,
+            );
+            let blocked = n
+                .dns_queries
+                .iter()
+                .find(|(outcome, _)| outcome == "blocked")
+                .map_or(0, |(_, count)| *count);
+            m.counter(
+                "hr_adblock_blocked_queries",
+                "DNS queries answered by the blocklist.",
+                blocked,
+            );
+        }
+        Err(e) => tracing::debug!("Netcore metrics unavailable: {}", e),
+    }
+
     hyper::Response::builder()
         .status(200)
-        .header("content-type", "text/plain; version=0.0.4; charset=utf-8")
-        .body(axum::body::Body::from(out))
+        .header("content-type", openmetrics::CONTENT_TYPE)
+        .body(axum::body::Body::from(m.finish()))
         .unwrap()
 }
 
 // ── HTTPS server ───────────────────────────────────────────────────────
 
+/// Read the client address a trusted relay puts in front of the TLS stream:
+/// either the tunnel's binary `StreamHeader`, forwarded as-is from the QUIC
+/// stream, or a PROXY protocol v1 line.
+async fn read_relay_header(stream: &mut tokio::net::TcpStream) -> anyhow::Result<Option<IpAddr>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut first = [0u8; 1];
+    stream.peek(&mut first).await?;
+    if first[0] == b'P' {
+        return hr_proxy::acl::read_proxy_header(stream).await;
+    }
+
+    // [version:u8][ip_type:u8][ip:4|16][timestamp:u64]
+    let mut header = vec![0u8; 2];
+    stream.read_exact(&mut header).await?;
+    let ip_len = match header[1] {
+        4 => 4,
+        6 => 16,
+        other => anyhow::bail!("Invalid StreamHeader IP type {}", other),
+    };
+    header.resize(2 + ip_len + 8, 0);
+    stream.read_exact(&mut header[2..]).await?;
+    let header = hr_tunnel::protocol::StreamHeader::decode(&mut header.as_slice())?;
+    Ok(Some(header.client_ip.to_canonical()))
+}
+
 async fn run_https_server(
     proxy_state: Arc<ProxyState>,
     tls_config: Arc<rustls::ServerConfig>,
     acme: Arc<hr_acme::AcmeManager>,
+    netcore: Arc<NetcoreClient>,
     port: u16,
 ) -> anyhow::Result<()> {
     use hyper::server::conn::http1;
@@ -520,9 +584,41 @@ async fn run_https_server(
         let acceptor = acceptor.clone();
         let proxy_state = proxy_state.clone();
         let acme = acme.clone();
-        let client_ip = remote_addr.ip();
+        let netcore = netcore.clone();
+        let mut tcp_stream = tcp_stream;
 
         tokio::spawn(async move {
+            // Relayed connections (QUIC tunnel) announce the real client first
+            let mut client_ip = remote_addr.ip().to_canonical();
+            if proxy_state.is_trusted_relay(client_ip) {
+                match tokio::time::timeout(
+                    hr_proxy::acl::PROXY_HEADER_TIMEOUT,
+                    read_relay_header(&mut tcp_stream),
+                )
+                .await
+                {
+                    Ok(Ok(Some(source))) => client_ip = source,
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => {
+                        tracing::debug!("Bad PROXY header from relay {}: {}", remote_addr, e);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("Relay {} sent no PROXY header", remote_addr);
+                        return;
+                    }
+                }
+            }
+
+            // Backends terminating their own TLS get the raw connection
+            if proxy_state.streams.has_passthrough()
+                && let Some(sni) = hr_proxy::stream::peek_sni(&tcp_stream).await
+                && let Some(target) = proxy_state.streams.passthrough_route(&sni)
+            {
+                target.forward(tcp_stream, client_ip).await;
+                return;
+            }
+
             let tls_stream = match acceptor.accept(tcp_stream).await {
                 Ok(s) => s,
                 Err(e) => {
@@ -531,19 +627,28 @@ async fn run_https_server(
                 }
             };
 
+            // Only certificates signed by the device CA pass the handshake
+            let client_cert =
+                hr_proxy::ClientCertIdentity::from_peer(tls_stream.get_ref().1.peer_certificates());
+
             let io = TokioIo::new(tls_stream);
             let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
                 let state = proxy_state.clone();
                 let acme = acme.clone();
+                let netcore = netcore.clone();
+                let client_cert = client_cert.clone();
                 async move {
                     // Internal /metrics endpoint — localhost only
                     if req.uri().path() == "/metrics" && client_ip.is_loopback() {
-                        return Ok::<_, std::convert::Infallible>(build_metrics_response(
-                            &state, &acme,
-                        ));
+                        return Ok::<_, std::convert::Infallible>(
+                            build_metrics_response(&state, &acme, &netcore).await,
+                        );
                     }
 
-                    let (parts, body) = req.into_parts();
+                    let (mut parts, body) = req.into_parts();
+                    if let Some(identity) = client_cert {
+                        parts.extensions.insert(identity);
+                    }
                     let req =
                         axum::extract::Request::from_parts(parts, axum::body::Body::new(body));
                     let resp = hr_proxy::proxy_handler(state, client_ip, req).await;
@@ -637,6 +742,7 @@ async fn handle_sighup(
     acme: Arc<hr_acme::AcmeManager>,
     env: hr_common::config::EnvConfig,
     dns_route_sync: Arc<DnsRouteSync>,
+    config_history: Arc<hr_proxy::ConfigHistory>,
 ) -> anyhow::Result<()> {
     let mut signals = Signals::new([SIGHUP])?;
 
@@ -646,12 +752,25 @@ async fn handle_sighup(
 
             match ProxyConfig::load_from_file(&proxy_config_path) {
                 Ok(new_config) => {
+                    let report = hr_proxy::transaction::check(&new_config);
+                    if !report.is_valid() {
+                        error!(
+                            "Proxy config reload refused, keeping the running config: {}",
+                            report.error_summary()
+                        );
+                        continue;
+                    }
                     if let Err(e) = tls_manager.reload_certificates(&new_config.routes) {
                         error!("Failed to reload TLS certificates: {}", e);
                     }
                     // Re-load ACME wildcard certs (reload_certificates does replace_all
                     // which wipes certs not referenced by routes)
                     reload_acme_certs(&tls_manager, &acme, &env.base_domain);
+                    tls_manager.ocsp.configure(&new_config.ocsp);
+                    let keep = new_config.history_size;
+                    if let Err(e) = config_history.record(&new_config, "reload", keep) {
+                        warn!("Failed to record proxy config revision: {:#}", e);
+                    }
                     proxy_state.reload_config(new_config);
                     dns_route_sync.request_sync();
                     info!("Proxy config reloaded");
@@ -0,0 +1,388 @@
+//! Queryable access log.
+//!
+//! Every logged request is also inserted into a SQLite database, batched
+//! by a background task, so the dashboard can search recent traffic by
+//! domain, status, client, user, path and time range. Rows older than the
+//! retention are purged hourly. SQLite calls run on the blocking pool.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::logging::AccessLogEntry;
+
+/// Most rows inserted per transaction.
+const BATCH_SIZE: usize = 500;
+
+/// Filters of an access log query. Every field is optional.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AccessLogQuery {
+    /// Host without port.
+    pub domain: Option<String>,
+    /// Exact status (`404`) or class (`5xx`).
+    pub status: Option<String>,
+    pub client_ip: Option<String>,
+    pub user: Option<String>,
+    /// Substring of the path (including the query string).
+    pub path: Option<String>,
+    pub method: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// Status condition for a query: `(min, max)` inclusive.
+fn status_range(status: &str) -> Option<(u16, u16)> {
+    let status = status.trim().to_ascii_lowercase();
+    if let Some(class) = status.strip_suffix("xx") {
+        let class: u16 = class.parse().ok().filter(|c| (1..=5).contains(c))?;
+        return Some((class * 100, class * 100 + 99));
+    }
+    let code: u16 = status.parse().ok()?;
+    Some((code, code))
+}
+
+/// `LIKE` pattern matching `text` anywhere, with `\` as escape character.
+fn contains_pattern(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len() + 2);
+    pattern.push('%');
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Row counts of the index.
+#[derive(Debug, Serialize)]
+pub struct AccessIndexStats {
+    pub total: u64,
+    pub oldest: Option<String>,
+    pub db_size_bytes: u64,
+}
+
+/// SQLite index of access log entries.
+pub struct AccessLogIndex {
+    db: Arc<Mutex<rusqlite::Connection>>,
+    sender: mpsc::UnboundedSender<AccessLogEntry>,
+}
+
+impl AccessLogIndex {
+    /// Open (or create) the index at `path` and start its writer and
+    /// purge tasks.
+    pub fn open(path: &Path, retention_days: u64) -> anyhow::Result<Self> {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA busy_timeout=5000;")?;
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS access_log (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                client_ip TEXT NOT NULL,
+                domain TEXT NOT NULL,
+                host TEXT NOT NULL,
+                method TEXT NOT NULL,
+                path TEXT NOT NULL,
+                protocol TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                user_agent TEXT NOT NULL,
+                referer TEXT,
+                user TEXT,
+                bytes INTEGER,
+                limited TEXT,
+                denied TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_access_ts ON access_log(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_access_domain ON access_log(domain, timestamp);
+            CREATE INDEX IF NOT EXISTS idx_access_status ON access_log(status);
+            CREATE INDEX IF NOT EXISTS idx_access_client ON access_log(client_ip);
+            CREATE INDEX IF NOT EXISTS idx_access_user ON access_log(user);
+            ",
+        )?;
+        info!("Access log index at {}", path.display());
+
+        let db = Arc::new(Mutex::new(conn));
+        let (sender, mut receiver) = mpsc::unbounded_channel::<AccessLogEntry>();
+
+        let writer_db = db.clone();
+        tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            while let Some(entry) = receiver.recv().await {
+                batch.push(entry);
+                while batch.len() < BATCH_SIZE {
+                    match receiver.try_recv() {
+                        Ok(entry) => batch.push(entry),
+                        Err(_) => break,
+                    }
+                }
+                let entries = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                let db = writer_db.clone();
+                match tokio::task::spawn_blocking(move || insert(&db.lock().unwrap(), &entries))
+                    .await
+                {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Failed to index access log entries: {}", e),
+                    Err(e) => error!("Access log index writer failed: {}", e),
+                }
+            }
+        });
+
+        let purge_db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+                let db = purge_db.clone();
+                let purged = tokio::task::spawn_blocking(move || {
+                    db.lock().unwrap().execute(
+                        "DELETE FROM access_log WHERE timestamp < ?1",
+                        params![cutoff.to_rfc3339()],
+                    )
+                })
+                .await;
+                match purged {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(n)) => info!("Purged {} indexed access log entries", n),
+                    Ok(Err(e)) => error!("Failed to purge access log index: {}", e),
+                    Err(e) => error!("Access log purge task failed: {}", e),
+                }
+            }
+        });
+
+        Ok(Self { db, sender })
+    }
+
+    /// Queue an entry for indexing (non-blocking).
+    pub fn record(&self, entry: AccessLogEntry) {
+        let _ = self.sender.send(entry);
+    }
+
+    /// Entries matching `filter`, newest first.
+    pub async fn query(&self, filter: &AccessLogQuery) -> anyhow::Result<Vec<AccessLogEntry>> {
+        let mut sql = String::from(
+            "SELECT timestamp, client_ip, host, method, path, protocol, status, duration_ms,
+                    user_agent, referer, user, bytes, limited, denied
+             FROM access_log WHERE 1=1",
+        );
+        let mut values: Vec<Value> = Vec::new();
+        let mut push = |sql: &mut String, clause: &str, value: Value| {
+            values.push(value);
+            sql.push_str(&clause.replace('?', &format!("?{}", values.len())));
+        };
+
+        if let Some(ref domain) = filter.domain {
+            push(&mut sql, " AND domain = ?", domain.to_lowercase().into());
+        }
+        if let Some(ref status) = filter.status {
+            let (min, max) = status_range(status)
+                .ok_or_else(|| anyhow::anyhow!("Invalid status filter: {}", status))?;
+            push(&mut sql, " AND status >= ?", i64::from(min).into());
+            push(&mut sql, " AND status <= ?", i64::from(max).into());
+        }
+        if let Some(ref client_ip) = filter.client_ip {
+            push(&mut sql, " AND client_ip = ?", client_ip.clone().into());
+        }
+        if let Some(ref user) = filter.user {
+            push(&mut sql, " AND user = ?", user.clone().into());
+        }
+        if let Some(ref path) = filter.path {
+            push(
+                &mut sql,
+                " AND path LIKE ? ESCAPE '\\'",
+                contains_pattern(path).into(),
+            );
+        }
+        if let Some(ref method) = filter.method {
+            push(&mut sql, " AND method = ?", method.to_uppercase().into());
+        }
+        if let Some(since) = filter.since {
+            push(&mut sql, " AND timestamp >= ?", since.to_rfc3339().into());
+        }
+        if let Some(until) = filter.until {
+            push(&mut sql, " AND timestamp <= ?", until.to_rfc3339().into());
+        }
+
+        let limit = filter.limit.unwrap_or(100).min(1000);
+        let offset = filter.offset.unwrap_or(0);
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT {limit} OFFSET {offset}"));
+
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let db = db.lock().unwrap();
+            let mut stmt = db.prepare(&sql)?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+                let bytes: Option<i64> = row.get(11)?;
+                let duration_ms: i64 = row.get(7)?;
+                Ok(AccessLogEntry {
+                    timestamp: row.get(0)?,
+                    client_ip: row.get(1)?,
+                    host: row.get(2)?,
+                    method: row.get(3)?,
+                    path: row.get(4)?,
+                    protocol: row.get(5)?,
+                    status: row.get(6)?,
+                    duration_ms: duration_ms as u64,
+                    user_agent: row.get(8)?,
+                    referer: row.get(9)?,
+                    user: row.get(10)?,
+                    bytes: bytes.map(|b| b as u64),
+                    limited: row.get(12)?,
+                    denied: row.get(13)?,
+                })
+            })?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .await?
+    }
+
+    pub async fn stats(&self) -> anyhow::Result<AccessIndexStats> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || stats(&db.lock().unwrap())).await?
+    }
+}
+
+fn stats(db: &rusqlite::Connection) -> anyhow::Result<AccessIndexStats> {
+    let (total, oldest): (i64, Option<String>) = db.query_row(
+        "SELECT COUNT(*), MIN(timestamp) FROM access_log",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let db_size_bytes: i64 = db
+        .query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap_or(0);
+    Ok(AccessIndexStats {
+        total: total as u64,
+        oldest,
+        db_size_bytes: db_size_bytes as u64,
+    })
+}
+
+fn insert(db: &rusqlite::Connection, entries: &[AccessLogEntry]) -> rusqlite::Result<()> {
+    let tx = db.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO access_log (timestamp, client_ip, domain, host, method, path, protocol,
+                status, duration_ms, user_agent, referer, user, bytes, limited, denied)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        )?;
+        for e in entries {
+            stmt.execute(params![
+                e.timestamp,
+                e.client_ip,
+                e.domain().to_lowercase(),
+                e.host,
+                e.method,
+                e.path,
+                e.protocol,
+                e.status,
+                e.duration_ms as i64,
+                e.user_agent,
+                e.referer,
+                e.user,
+                e.bytes.map(|b| b as i64),
+                e.limited,
+                e.denied,
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(host: &str, path: &str, status: u16, user: Option<&str>) -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: crate::logging::now_timestamp(),
+            client_ip: "192.168.1.10".to_string(),
+            host: host.to_string(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            status,
+            duration_ms: 12,
+            user_agent: "curl/8.0".to_string(),
+            referer: None,
+            user: user.map(str::to_string),
+            bytes: Some(42),
+            limited: None,
+            denied: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_and_query() {
+        let path = std::env::temp_dir().join(format!("hr-access-{}.db", std::process::id()));
+        let index = AccessLogIndex::open(&path, 14).unwrap();
+        index.record(entry(
+            "app.example.com:443",
+            "/api/items?page=2",
+            200,
+            Some("alice"),
+        ));
+        index.record(entry("app.example.com", "/missing", 404, None));
+        index.record(entry("other.example.com", "/", 502, None));
+        // Let the writer task flush its batch
+        for _ in 0..50 {
+            if index.stats().await.unwrap().total == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let by_domain = AccessLogQuery {
+            domain: Some("app.example.com".to_string()),
+            ..Default::default()
+        };
+        let rows = index.query(&by_domain).await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].path, "/missing");
+
+        let errors = AccessLogQuery {
+            status: Some("5xx".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(index.query(&errors).await.unwrap()[0].status, 502);
+
+        let user_path = AccessLogQuery {
+            user: Some("alice".to_string()),
+            path: Some("items".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(index.query(&user_path).await.unwrap().len(), 1);
+
+        // `_` and `%` are literal in path filters
+        let wildcard = AccessLogQuery {
+            path: Some("_".to_string()),
+            ..Default::default()
+        };
+        assert!(index.query(&wildcard).await.unwrap().is_empty());
+
+        let bad = AccessLogQuery {
+            status: Some("9xx".to_string()),
+            ..Default::default()
+        };
+        assert!(index.query(&bad).await.is_err());
+
+        drop(index);
+        let _ = std::fs::remove_file(&path);
+    }
+}
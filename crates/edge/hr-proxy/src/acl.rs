@@ -0,0 +1,278 @@
+//! Client address access control lists.
+//!
+//! Routes carry an `AccessList` of allow/deny entries: CIDRs, bare IPs or
+//! names of address lists (from `ProxyConfig::ip_lists`, or the built-in
+//! ones). Lists are checked against the real client address, which for
+//! connections from a trusted relay comes from the header it sends first
+//! (the tunnel's `StreamHeader` or a PROXY protocol v1 line).
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use ipnet::IpNet;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
+
+use crate::config::AccessList;
+
+/// Longest valid PROXY protocol v1 line, CRLF included.
+const PROXY_LINE_MAX: usize = 107;
+/// How long a relay gets to send its PROXY line.
+pub const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn builtin_list(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "lan" => Some(&[
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "169.254.0.0/16",
+            "127.0.0.0/8",
+            "fc00::/7",
+            "fe80::/10",
+            "::1/128",
+        ]),
+        "loopback" => Some(&["127.0.0.0/8", "::1/128"]),
+        _ => None,
+    }
+}
+
+/// Parse a CIDR or a bare IP (as a single-address network).
+pub fn parse_net(s: &str) -> Option<IpNet> {
+    let s = s.trim();
+    s.parse::<IpNet>()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Networks an ACL entry stands for, or `None` if it is neither an address
+/// nor a known list.
+fn expand(entry: &str, lists: &HashMap<String, Vec<String>>) -> Option<Vec<IpNet>> {
+    if let Some(net) = parse_net(entry) {
+        return Some(vec![net]);
+    }
+    match lists.get(entry) {
+        Some(list) => Some(list.iter().filter_map(|s| parse_net(s)).collect()),
+        None => builtin_list(entry).map(|l| l.iter().filter_map(|s| parse_net(s)).collect()),
+    }
+}
+
+/// Entries in `acl` that are neither addresses nor known list names.
+pub fn unknown_entries<'a>(
+    acl: &'a AccessList,
+    lists: &HashMap<String, Vec<String>>,
+) -> Vec<&'a str> {
+    acl.allow
+        .iter()
+        .chain(&acl.deny)
+        .filter(|e| expand(e, lists).is_none())
+        .map(String::as_str)
+        .collect()
+}
+
+/// Invalid addresses inside the named lists.
+pub fn invalid_list_entries(lists: &HashMap<String, Vec<String>>) -> Vec<(&str, &str)> {
+    lists
+        .iter()
+        .flat_map(|(name, list)| {
+            list.iter()
+                .filter(|s| parse_net(s).is_none())
+                .map(move |s| (name.as_str(), s.as_str()))
+        })
+        .collect()
+}
+
+/// The rule that rejected a client, as shown in logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclDenied {
+    pub rule: String,
+}
+
+struct Entry {
+    rule: String,
+    nets: Vec<IpNet>,
+}
+
+impl Entry {
+    fn matches(&self, ip: &IpAddr) -> bool {
+        self.nets.iter().any(|n| n.contains(ip))
+    }
+}
+
+/// An `AccessList` with its list names expanded.
+pub struct Acl {
+    allow: Vec<Entry>,
+    deny: Vec<Entry>,
+}
+
+impl Acl {
+    /// Unknown entries are dropped; an allow list left empty that way
+    /// still denies everyone.
+    pub fn compile(acl: &AccessList, lists: &HashMap<String, Vec<String>>) -> Self {
+        let entries = |rules: &[String]| {
+            rules
+                .iter()
+                .filter_map(|rule| {
+                    Some(Entry {
+                        rule: rule.clone(),
+                        nets: expand(rule, lists)?,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut compiled = Self {
+            allow: entries(&acl.allow),
+            deny: entries(&acl.deny),
+        };
+        if !acl.allow.is_empty() && compiled.allow.is_empty() {
+            warn!("ACL allow list has no valid entry, denying everyone");
+            compiled.allow.push(Entry {
+                rule: "(invalid allow list)".to_string(),
+                nets: Vec::new(),
+            });
+        }
+        compiled
+    }
+
+    pub fn check(&self, ip: IpAddr) -> Result<(), AclDenied> {
+        let ip = ip.to_canonical();
+        if let Some(entry) = self.deny.iter().find(|e| e.matches(&ip)) {
+            return Err(AclDenied {
+                rule: format!("deny {}", entry.rule),
+            });
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|e| e.matches(&ip)) {
+            let rules: Vec<&str> = self.allow.iter().map(|e| e.rule.as_str()).collect();
+            return Err(AclDenied {
+                rule: format!("allow {}", rules.join(",")),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether `peer` is one of the `relays` (CIDRs or IPs).
+pub fn is_trusted_relay(relays: &[String], peer: IpAddr) -> bool {
+    let peer = peer.to_canonical();
+    relays
+        .iter()
+        .filter_map(|r| parse_net(r))
+        .any(|n| n.contains(&peer))
+}
+
+/// Read a PROXY protocol v1 line (`PROXY TCP4 <src> <dst> <sport> <dport>`)
+/// from a relay connection, without consuming anything past its CRLF.
+/// Returns the source address, or `None` for `PROXY UNKNOWN`.
+pub async fn read_proxy_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> anyhow::Result<Option<IpAddr>> {
+    let mut line = Vec::with_capacity(PROXY_LINE_MAX);
+    // Byte by byte: the TLS handshake follows immediately
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= PROXY_LINE_MAX {
+            bail!("PROXY line too long");
+        }
+        line.push(stream.read_u8().await.context("Reading PROXY line")?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).context("PROXY line is not ASCII")?;
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PROXY") {
+        bail!("Missing PROXY signature");
+    }
+    match parts.next() {
+        Some("TCP4" | "TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        other => bail!("Unsupported PROXY protocol {:?}", other),
+    }
+    let source = parts
+        .next()
+        .and_then(|s| s.parse::<IpAddr>().ok())
+        .context("Invalid PROXY source address")?;
+    Ok(Some(source.to_canonical()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl(allow: &[&str], deny: &[&str]) -> AccessList {
+        AccessList {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let mut lists = HashMap::new();
+        lists.insert(
+            "family-devices".to_string(),
+            vec!["192.168.1.20".to_string(), "2001:db8::/64".to_string()],
+        );
+
+        let compiled = Acl::compile(&acl(&["lan", "family-devices"], &["192.168.1.66"]), &lists);
+        assert!(compiled.check(ip("192.168.1.10")).is_ok());
+        assert!(compiled.check(ip("2001:db8::1")).is_ok());
+        // IPv4 clients on a dual-stack listener show up as mapped addresses
+        assert!(compiled.check(ip("::ffff:10.0.0.3")).is_ok());
+        assert_eq!(
+            compiled.check(ip("192.168.1.66")).unwrap_err().rule,
+            "deny 192.168.1.66"
+        );
+        assert_eq!(
+            compiled.check(ip("8.8.8.8")).unwrap_err().rule,
+            "allow lan,family-devices"
+        );
+
+        // Deny only: everyone else is allowed
+        let compiled = Acl::compile(&acl(&[], &["203.0.113.0/24"]), &lists);
+        assert!(compiled.check(ip("8.8.8.8")).is_ok());
+        assert!(compiled.check(ip("203.0.113.9")).is_err());
+    }
+
+    #[test]
+    fn test_unknown_entries() {
+        let lists = HashMap::new();
+        let list = acl(&["lan", "tunnel", "10.0.0.0/33"], &["10.1.2.3"]);
+        assert_eq!(
+            unknown_entries(&list, &lists),
+            vec!["tunnel", "10.0.0.0/33"]
+        );
+
+        // An allow list with nothing valid fails closed
+        let compiled = Acl::compile(&acl(&["tunnel"], &[]), &lists);
+        assert!(compiled.check(ip("10.0.0.1")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_proxy_header() {
+        let mut input: &[u8] = b"PROXY TCP4 203.0.113.7 192.168.1.1 51234 443\r\n\x16\x03\x01";
+        assert_eq!(
+            read_proxy_header(&mut input).await.unwrap(),
+            Some(ip("203.0.113.7"))
+        );
+        // The TLS bytes are left in the stream
+        assert_eq!(input, b"\x16\x03\x01");
+
+        let mut input: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_proxy_header(&mut input).await.unwrap(), None);
+
+        let mut input: &[u8] = b"\x16\x03\x01\x02\x00";
+        assert!(read_proxy_header(&mut input).await.is_err());
+
+        assert!(is_trusted_relay(
+            &["127.0.0.1".to_string()],
+            ip("::ffff:127.0.0.1")
+        ));
+        assert!(!is_trusted_relay(
+            &["127.0.0.1".to_string()],
+            ip("10.0.0.1")
+        ));
+    }
+}
@@ -0,0 +1,428 @@
+//! TLS to HTTPS backends.
+//!
+//! Each `BackendTlsConfig` becomes a rustls `ClientConfig` verifying the
+//! backend against the system roots, a pinned CA or a certificate
+//! fingerprint (or nothing, when explicitly `insecure`). Configs are built
+//! once and shared by the reqwest clients and the WebSocket TLS bridge.
+//! Handshake outcomes are tracked per route for the status API.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::{BackendTlsConfig, BackendTlsVerify};
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// System trust store, loaded on first use.
+fn system_roots() -> Arc<RootCertStore> {
+    static ROOTS: OnceLock<Arc<RootCertStore>> = OnceLock::new();
+    ROOTS
+        .get_or_init(|| {
+            let loaded = rustls_native_certs::load_native_certs();
+            for e in &loaded.errors {
+                warn!("Failed to load a system root certificate: {}", e);
+            }
+            let mut roots = RootCertStore::empty();
+            let (added, ignored) = roots.add_parsable_certificates(loaded.certs);
+            if ignored > 0 {
+                warn!("Ignored {} unparsable system root certificates", ignored);
+            }
+            if added == 0 {
+                warn!("No system root certificates found: backend TLS verification will fail");
+            }
+            Arc::new(roots)
+        })
+        .clone()
+}
+
+/// Parse a SHA-256 fingerprint written as hex, with or without colons.
+fn parse_fingerprint(s: &str) -> Result<[u8; 32]> {
+    let hex: String = s.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 {
+        bail!("SHA-256 fingerprint must be 64 hex digits");
+    }
+    // Checked up front: `from_str_radix` would take a '+' sign, and slicing
+    // non-ASCII input at byte offsets panics
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("Invalid hex digit in fingerprint");
+    }
+    let mut out = [0u8; 32];
+    for (byte, pair) in out.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = (hex_value(pair[0]) << 4) | hex_value(pair[1]);
+    }
+    Ok(out)
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+fn fingerprint_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Build the rustls client config for `config`.
+pub fn client_config(config: &BackendTlsConfig) -> Result<Arc<ClientConfig>> {
+    let provider = provider();
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Unsupported TLS protocol versions")?;
+
+    let verifier: Arc<dyn ServerCertVerifier> = match config.verify {
+        BackendTlsVerify::System => {
+            WebPkiServerVerifier::builder_with_provider(system_roots(), provider.clone())
+                .build()
+                .context("Failed to build system root verifier")?
+        }
+        BackendTlsVerify::Ca => {
+            let path = config
+                .ca_path
+                .as_ref()
+                .context("verify \"ca\" requires ca_path")?;
+            let mut roots = RootCertStore::empty();
+            for cert in crate::tls::load_certs(path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+            }
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .context("Failed to build CA verifier")?
+        }
+        BackendTlsVerify::Fingerprint => {
+            let fingerprint = config
+                .fingerprint
+                .as_deref()
+                .context("verify \"fingerprint\" requires fingerprint")?;
+            Arc::new(FingerprintVerifier {
+                expected: parse_fingerprint(fingerprint)?,
+                provider: provider.clone(),
+            })
+        }
+        BackendTlsVerify::Insecure => Arc::new(NoVerifier {
+            provider: provider.clone(),
+        }),
+    };
+
+    let mut tls = builder
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(tls))
+}
+
+/// Accepts exactly one certificate, identified by its SHA-256 digest.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    expected: [u8; 32],
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = ring::digest::digest(&ring::digest::SHA256, end_entity.as_ref());
+        if actual.as_ref() == self.expected {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "certificate fingerprint mismatch: got {}",
+                fingerprint_hex(actual.as_ref())
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Accepts any certificate (`verify: "insecure"` only).
+#[derive(Debug)]
+struct NoVerifier {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// reqwest client connecting to `addr` for `server_name`, with `tls` as
+/// its TLS configuration.
+pub fn reqwest_client(
+    tls: &ClientConfig,
+    server_name: &str,
+    addr: SocketAddr,
+    connect_timeout: Option<Duration>,
+) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .tls_backend_preconfigured(tls.clone())
+        .resolve(server_name, addr)
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    builder
+        .build()
+        .context("Failed to build backend HTTPS client")
+}
+
+/// The rustls error somewhere in `err`'s source chain, if the failure was
+/// a TLS one. I/O errors are unwrapped since `io::Error::source` skips its
+/// payload.
+pub fn tls_failure(err: &(dyn std::error::Error + 'static)) -> Option<String> {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if let Some(tls) = e.downcast_ref::<rustls::Error>() {
+            return Some(tls.to_string());
+        }
+        if let Some(tls) = e
+            .downcast_ref::<std::io::Error>()
+            .and_then(|io| io.get_ref())
+            .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        {
+            return Some(tls.to_string());
+        }
+        current = e.source();
+    }
+    None
+}
+
+/// Last handshake outcome for a route's backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteTlsStatus {
+    pub domain: String,
+    pub verify: &'static str,
+    pub ok: bool,
+    pub failures: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error_at: Option<String>,
+}
+
+/// Client configs by backend settings, plus per-route handshake status.
+#[derive(Default)]
+pub struct BackendTls {
+    configs: Mutex<HashMap<BackendTlsConfig, Arc<ClientConfig>>>,
+    status: Mutex<HashMap<String, RouteTlsStatus>>,
+}
+
+impl BackendTls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Client config for `config`, built on first use.
+    pub fn client_config(&self, config: &BackendTlsConfig) -> Result<Arc<ClientConfig>> {
+        let mut configs = self.configs.lock().unwrap();
+        if let Some(c) = configs.get(config) {
+            return Ok(c.clone());
+        }
+        let built = client_config(config)?;
+        configs.insert(config.clone(), built.clone());
+        Ok(built)
+    }
+
+    /// Forget built configs so CA files are read again (config reload).
+    pub fn clear(&self) {
+        self.configs.lock().unwrap().clear();
+    }
+
+    /// Record a handshake result for `domain`; `error` is the TLS failure,
+    /// if any.
+    pub fn record(&self, domain: &str, verify: BackendTlsVerify, error: Option<String>) {
+        let mut status = self.status.lock().unwrap();
+        let entry = status
+            .entry(domain.to_string())
+            .or_insert_with(|| RouteTlsStatus {
+                domain: domain.to_string(),
+                verify: verify.as_str(),
+                ok: true,
+                failures: 0,
+                last_error: None,
+                last_error_at: None,
+            });
+        entry.verify = verify.as_str();
+        entry.ok = error.is_none();
+        if let Some(error) = error {
+            entry.failures += 1;
+            entry.last_error = Some(error);
+            entry.last_error_at = Some(crate::logging::now_timestamp());
+        }
+    }
+
+    pub fn status(&self) -> Vec<RouteTlsStatus> {
+        let mut out: Vec<RouteTlsStatus> = self.status.lock().unwrap().values().cloned().collect();
+        out.sort_by(|a, b| a.domain.cmp(&b.domain));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let hex = "AB:".repeat(31) + "cd";
+        let parsed = parse_fingerprint(&hex).unwrap();
+        assert_eq!(parsed[0], 0xab);
+        assert_eq!(parsed[31], 0xcd);
+        assert!(parse_fingerprint("abcd").is_err());
+        assert!(parse_fingerprint(&"zz".repeat(32)).is_err());
+        assert!(parse_fingerprint(&"é".repeat(32)).is_err());
+        assert!(parse_fingerprint(&"+f".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_client_config_requires_settings() {
+        let ca = BackendTlsConfig {
+            verify: BackendTlsVerify::Ca,
+            ..Default::default()
+        };
+        assert!(client_config(&ca).is_err());
+        let pinned = BackendTlsConfig {
+            verify: BackendTlsVerify::Fingerprint,
+            fingerprint: Some("00".repeat(32)),
+            ..Default::default()
+        };
+        assert!(client_config(&pinned).is_ok());
+    }
+
+    #[test]
+    fn test_fingerprint_verifier() {
+        let cert = rcgen::generate_simple_self_signed(vec!["backend.lan".to_string()]).unwrap();
+        let der = cert.cert.der().clone();
+        let digest = ring::digest::digest(&ring::digest::SHA256, der.as_ref());
+        let name = ServerName::try_from("backend.lan").unwrap();
+
+        let verifier = FingerprintVerifier {
+            expected: parse_fingerprint(&fingerprint_hex(digest.as_ref())).unwrap(),
+            provider: provider(),
+        };
+        assert!(
+            verifier
+                .verify_server_cert(&der, &[], &name, &[], UnixTime::now())
+                .is_ok()
+        );
+
+        let other = rcgen::generate_simple_self_signed(vec!["backend.lan".to_string()]).unwrap();
+        let err = verifier
+            .verify_server_cert(other.cert.der(), &[], &name, &[], UnixTime::now())
+            .unwrap_err();
+        assert!(err.to_string().contains("fingerprint mismatch"));
+
+        // TLS errors are found through I/O wrappers
+        let io = std::io::Error::new(std::io::ErrorKind::InvalidData, err);
+        assert!(tls_failure(&io).unwrap().contains("fingerprint mismatch"));
+        assert!(tls_failure(&std::io::Error::other("refused")).is_none());
+    }
+
+    #[test]
+    fn test_status_tracking() {
+        let tls = BackendTls::new();
+        tls.record(
+            "git.example.com",
+            BackendTlsVerify::Ca,
+            Some("expired".to_string()),
+        );
+        tls.record("git.example.com", BackendTlsVerify::Ca, None);
+        let status = tls.status();
+        assert_eq!(status.len(), 1);
+        assert!(status[0].ok);
+        assert_eq!(status[0].failures, 1);
+        assert_eq!(status[0].last_error.as_deref(), Some("expired"));
+    }
+}
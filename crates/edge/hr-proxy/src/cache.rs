@@ -0,0 +1,790 @@
+//! Shared HTTP response cache.
+//!
+//! Routes with a `cache` section store cacheable `GET` responses here. The
+//! freshness lifetime comes from `Cache-Control` (`s-maxage`, `max-age`) or
+//! `Expires`, falling back to the route's `default_ttl_secs`; responses that
+//! are `private`, `no-store`, set cookies or `Vary: *` are never stored.
+//! Each URL may hold several variants, one per combination of the request
+//! headers named in `Vary`. Stale entries with an `ETag` or `Last-Modified`
+//! are revalidated with a conditional request instead of being refetched.
+//!
+//! Bodies live in memory, or on disk when `disk_path` is set; the index is
+//! always in memory and is evicted least-recently-used within the budgets.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::Response;
+use bytes::Bytes;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::CacheStoreConfig;
+
+/// Headers never stored with a cached response.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "alt-svc",
+];
+
+/// Headers a `304 Not Modified` from the backend may update.
+const REVALIDATION_HEADERS: &[HeaderName] = &[
+    header::CACHE_CONTROL,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+];
+
+/// Cached body, removed from disk once the last entry sharing it is dropped.
+enum StoredBody {
+    Memory(Bytes),
+    Disk(PathBuf),
+}
+
+impl Drop for StoredBody {
+    fn drop(&mut self) {
+        if let StoredBody::Disk(path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A stored response.
+pub struct CacheEntry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Arc<StoredBody>,
+    size: u64,
+    stored_at: Instant,
+    ttl: Duration,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        self.stored_at.elapsed() < self.ttl
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Stored headers updated by a backend `304 Not Modified`.
+    pub fn revalidated_headers(&self, not_modified: &HeaderMap) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        for name in REVALIDATION_HEADERS {
+            if let Some(v) = not_modified.get(name) {
+                headers.insert(name.clone(), v.clone());
+            }
+        }
+        headers
+    }
+
+    fn has_validator(&self) -> bool {
+        self.headers.contains_key(header::ETAG) || self.headers.contains_key(header::LAST_MODIFIED)
+    }
+
+    fn on_disk(&self) -> bool {
+        matches!(*self.body, StoredBody::Disk(_))
+    }
+}
+
+struct Variant {
+    /// Request header values (by lowercase name) this variant was stored for.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    entry: Arc<CacheEntry>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Vec<Variant>>,
+    memory_used: u64,
+    disk_used: u64,
+    tick: u64,
+}
+
+impl Index {
+    fn account(&mut self, entry: &CacheEntry, add: bool) {
+        let used = if entry.on_disk() {
+            &mut self.disk_used
+        } else {
+            &mut self.memory_used
+        };
+        if add {
+            *used += entry.size;
+        } else {
+            *used = used.saturating_sub(entry.size);
+        }
+    }
+
+    /// Drop least-recently-used variants until both budgets are met.
+    fn evict(&mut self, memory_max: u64, disk_max: u64) {
+        while self.memory_used > memory_max || self.disk_used > disk_max {
+            let want_disk = self.disk_used > disk_max;
+            let oldest = self
+                .entries
+                .iter()
+                .flat_map(|(key, variants)| {
+                    variants
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, v)| v.entry.on_disk() == want_disk)
+                        .map(move |(i, v)| (v.last_used, key, i))
+                })
+                .min()
+                .map(|(_, key, i)| (key.clone(), i));
+            let Some((key, i)) = oldest else { break };
+            self.remove(&key, i);
+        }
+    }
+
+    fn remove(&mut self, key: &str, i: usize) {
+        let Some(variants) = self.entries.get_mut(key) else {
+            return;
+        };
+        let removed = variants.remove(i);
+        if variants.is_empty() {
+            self.entries.remove(key);
+        }
+        self.account(&removed.entry, false);
+    }
+}
+
+/// Cache occupancy, reported by the metrics endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub memory_bytes: u64,
+    pub disk_bytes: u64,
+}
+
+pub struct HttpCache {
+    config: RwLock<CacheStoreConfig>,
+    index: Mutex<Index>,
+    next_file: AtomicU64,
+}
+
+impl HttpCache {
+    pub fn new(config: &CacheStoreConfig) -> Self {
+        if let Some(dir) = &config.disk_path {
+            prepare_disk(dir);
+        }
+        Self {
+            config: RwLock::new(config.clone()),
+            index: Mutex::new(Index::default()),
+            next_file: AtomicU64::new(0),
+        }
+    }
+
+    /// Apply a reloaded store config. Changing the disk path drops every
+    /// entry; smaller budgets evict immediately.
+    pub fn reconfigure(&self, config: &CacheStoreConfig) {
+        let mut current = self.config.write().unwrap();
+        if current.disk_path != config.disk_path {
+            *self.index.lock().unwrap() = Index::default();
+            if let Some(dir) = &config.disk_path {
+                prepare_disk(dir);
+            }
+        }
+        *current = config.clone();
+        let (memory_max, disk_max) = budgets(config);
+        self.index.lock().unwrap().evict(memory_max, disk_max);
+    }
+
+    /// Cache key for a request on `host`.
+    pub fn key(host: &str, path_and_query: &str) -> String {
+        format!("{}{}", host.to_ascii_lowercase(), path_and_query)
+    }
+
+    /// Find the variant matching `req_headers`. Stale entries without a
+    /// validator are dropped.
+    pub fn lookup(&self, key: &str, req_headers: &HeaderMap) -> Option<Arc<CacheEntry>> {
+        let mut index = self.index.lock().unwrap();
+        index.tick += 1;
+        let tick = index.tick;
+        let variants = index.entries.get_mut(key)?;
+        let variant = variants.iter_mut().find(|v| {
+            v.vary
+                .iter()
+                .all(|(name, value)| req_headers.get(name) == value.as_ref())
+        })?;
+        variant.last_used = tick;
+        let entry = variant.entry.clone();
+        if !entry.is_fresh() && !entry.has_validator() {
+            if let Some(i) = variants.iter().position(|v| Arc::ptr_eq(&v.entry, &entry)) {
+                index.remove(key, i);
+            }
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Store a response fetched for a request with `req_headers`.
+    pub async fn store(
+        &self,
+        key: &str,
+        req_headers: &HeaderMap,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: Bytes,
+        ttl: Duration,
+    ) {
+        let Some(vary) = vary_values(headers, req_headers) else {
+            return;
+        };
+        let config = self.config.read().unwrap().clone();
+        let (memory_max, disk_max) = budgets(&config);
+        let size = body.len() as u64;
+        let stored = match &config.disk_path {
+            Some(dir) => {
+                if size > disk_max {
+                    return;
+                }
+                let id = self.next_file.fetch_add(1, Ordering::Relaxed);
+                let path = dir.join(format!("{:016x}.body", id));
+                if let Err(e) = tokio::fs::write(&path, &body).await {
+                    warn!("Cache write to {:?} failed: {}", path, e);
+                    return;
+                }
+                StoredBody::Disk(path)
+            }
+            None => {
+                if size > memory_max {
+                    return;
+                }
+                StoredBody::Memory(body)
+            }
+        };
+        let entry = CacheEntry {
+            status,
+            headers: storable_headers(headers),
+            body: Arc::new(stored),
+            size,
+            stored_at: Instant::now(),
+            ttl,
+        };
+        self.insert(key, vary, entry, memory_max, disk_max);
+    }
+
+    /// Renew `entry` with `headers` (see `revalidated_headers`) after the
+    /// backend answered `304 Not Modified`.
+    pub fn refresh(
+        &self,
+        key: &str,
+        req_headers: &HeaderMap,
+        entry: &CacheEntry,
+        headers: HeaderMap,
+        ttl: Duration,
+    ) -> Arc<CacheEntry> {
+        let renewed = CacheEntry {
+            status: entry.status,
+            headers,
+            body: entry.body.clone(),
+            size: entry.size,
+            stored_at: Instant::now(),
+            ttl,
+        };
+        let (memory_max, disk_max) = budgets(&self.config.read().unwrap());
+        match vary_values(&renewed.headers, req_headers) {
+            Some(vary) => self.insert(key, vary, renewed, memory_max, disk_max),
+            None => Arc::new(renewed),
+        }
+    }
+
+    fn insert(
+        &self,
+        key: &str,
+        vary: Vec<(HeaderName, Option<HeaderValue>)>,
+        entry: CacheEntry,
+        memory_max: u64,
+        disk_max: u64,
+    ) -> Arc<CacheEntry> {
+        let entry = Arc::new(entry);
+        let mut index = self.index.lock().unwrap();
+        index.tick += 1;
+        let tick = index.tick;
+        if let Some(i) = index
+            .entries
+            .get(key)
+            .and_then(|variants| variants.iter().position(|v| v.vary == vary))
+        {
+            index.remove(key, i);
+        }
+        index.account(&entry, true);
+        index
+            .entries
+            .entry(key.to_string())
+            .or_default()
+            .push(Variant {
+                vary,
+                entry: entry.clone(),
+                last_used: tick,
+            });
+        index.evict(memory_max, disk_max);
+        entry
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let index = self.index.lock().unwrap();
+        CacheStats {
+            entries: index.entries.values().map(Vec::len).sum(),
+            memory_bytes: index.memory_used,
+            disk_bytes: index.disk_used,
+        }
+    }
+}
+
+fn budgets(config: &CacheStoreConfig) -> (u64, u64) {
+    (
+        config.memory_max_mb * 1024 * 1024,
+        config.disk_max_mb * 1024 * 1024,
+    )
+}
+
+/// Create the cache directory and remove bodies left by a previous run.
+fn prepare_disk(dir: &Path) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        warn!("Cannot create cache directory {:?}: {}", dir, e);
+        return;
+    }
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "body") {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn storable_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut stored = headers.clone();
+    for name in HOP_BY_HOP {
+        stored.remove(*name);
+    }
+    stored
+}
+
+/// Request header values named by the response's `Vary`; `None` for `Vary: *`.
+fn vary_values(
+    resp_headers: &HeaderMap,
+    req_headers: &HeaderMap,
+) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut names: Vec<HeaderName> = Vec::new();
+    for value in resp_headers.get_all(header::VARY) {
+        for name in value.to_str().unwrap_or("").split(',') {
+            let name = name.trim();
+            if name == "*" {
+                return None;
+            }
+            if let Ok(name) = HeaderName::from_bytes(name.to_ascii_lowercase().as_bytes())
+                && !names.contains(&name)
+            {
+                names.push(name);
+            }
+        }
+    }
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    Some(
+        names
+            .into_iter()
+            .map(|name| {
+                let value = req_headers.get(&name).cloned();
+                (name, value)
+            })
+            .collect(),
+    )
+}
+
+fn cache_directives(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|d| {
+            let d = d.trim();
+            if d.is_empty() {
+                return None;
+            }
+            Some(match d.split_once('=') {
+                Some((k, v)) => (
+                    k.trim().to_ascii_lowercase(),
+                    Some(v.trim().trim_matches('"').to_string()),
+                ),
+                None => (d.to_ascii_lowercase(), None),
+            })
+        })
+        .collect()
+}
+
+/// How a client request may use the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestPolicy {
+    /// Neither answered from nor stored into the cache.
+    Bypass,
+    /// Fetched from the backend (`no-cache`), the response may be stored.
+    Refresh,
+    /// Answered from the cache when possible.
+    Lookup,
+}
+
+pub fn request_policy(method: &Method, headers: &HeaderMap) -> RequestPolicy {
+    if method != Method::GET {
+        return RequestPolicy::Bypass;
+    }
+    let directives = cache_directives(headers);
+    let has = |name: &str| directives.iter().any(|(k, _)| k == name);
+    if has("no-store") {
+        return RequestPolicy::Bypass;
+    }
+    let max_age_zero = directives
+        .iter()
+        .any(|(k, v)| k == "max-age" && v.as_deref() == Some("0"));
+    let pragma_no_cache = headers
+        .get(header::PRAGMA)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("no-cache"));
+    if has("no-cache") || max_age_zero || pragma_no_cache {
+        RequestPolicy::Refresh
+    } else {
+        RequestPolicy::Lookup
+    }
+}
+
+/// Freshness lifetime of a backend response, or `None` if it must not be
+/// stored. `authenticated` requests are only stored when marked `public`.
+pub fn freshness(
+    status: StatusCode,
+    headers: &HeaderMap,
+    authenticated: bool,
+    default_ttl: Duration,
+) -> Option<Duration> {
+    let cacheable_status = matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 410
+    );
+    if !cacheable_status || headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+    let directives = cache_directives(headers);
+    let has = |name: &str| directives.iter().any(|(k, _)| k == name);
+    if has("no-store") || has("private") || (authenticated && !has("public")) {
+        return None;
+    }
+    let has_validator =
+        headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED);
+    let seconds = |name: &str| {
+        directives
+            .iter()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| v.as_deref()?.parse::<u64>().ok())
+    };
+
+    let ttl = if has("no-cache") {
+        Duration::ZERO
+    } else if let Some(secs) = seconds("s-maxage").or_else(|| seconds("max-age")) {
+        Duration::from_secs(secs)
+    } else if let Some(expires) = headers.get(header::EXPIRES) {
+        // An unparseable Expires means "already expired"
+        let parse = |v: &HeaderValue| {
+            chrono::DateTime::parse_from_rfc2822(v.to_str().ok()?)
+                .ok()
+                .map(|d| d.timestamp())
+        };
+        let now = headers
+            .get(header::DATE)
+            .and_then(parse)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+        let secs = parse(expires).map(|e| e - now).unwrap_or(0);
+        Duration::from_secs(secs.max(0) as u64)
+    } else if !default_ttl.is_zero() {
+        default_ttl
+    } else {
+        return None;
+    };
+
+    if ttl.is_zero() && !has_validator {
+        return None;
+    }
+    Some(ttl)
+}
+
+/// Add the entry's validators to a request revalidating it. Returns false
+/// (and leaves the request alone) if the client sent its own conditionals.
+pub fn add_validators(req_headers: &mut HeaderMap, entry: &CacheEntry) -> bool {
+    if req_headers.contains_key(header::IF_NONE_MATCH)
+        || req_headers.contains_key(header::IF_MODIFIED_SINCE)
+    {
+        return false;
+    }
+    if let Some(etag) = entry.headers.get(header::ETAG) {
+        req_headers.insert(header::IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(lm) = entry.headers.get(header::LAST_MODIFIED) {
+        req_headers.insert(header::IF_MODIFIED_SINCE, lm.clone());
+    }
+    true
+}
+
+/// Weak `If-None-Match` comparison (RFC 9110 §13.1.2).
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let strip = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    let etag = strip(etag);
+    if_none_match
+        .to_str()
+        .unwrap_or("")
+        .split(',')
+        .any(|t| t.trim() == "*" || strip(t) == etag)
+}
+
+/// Build the client response for a cached entry, answering `304` when the
+/// client's `If-None-Match` matches. `None` if the body cannot be read.
+pub async fn respond(
+    entry: &CacheEntry,
+    client_headers: &HeaderMap,
+    x_cache: &'static str,
+) -> Option<Response> {
+    let not_modified = match (
+        client_headers.get(header::IF_NONE_MATCH),
+        entry.headers.get(header::ETAG),
+    ) {
+        (Some(inm), Some(etag)) => etag_matches(inm, etag),
+        _ => false,
+    };
+
+    let mut resp = if not_modified {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::NOT_MODIFIED;
+        for name in REVALIDATION_HEADERS.iter().chain([&header::VARY]) {
+            for v in entry.headers.get_all(name) {
+                resp.headers_mut().append(name.clone(), v.clone());
+            }
+        }
+        resp
+    } else {
+        let body = match &*entry.body {
+            StoredBody::Memory(bytes) => bytes.clone(),
+            StoredBody::Disk(path) => match tokio::fs::read(path).await {
+                Ok(data) => Bytes::from(data),
+                Err(e) => {
+                    warn!("Cache read from {:?} failed: {}", path, e);
+                    return None;
+                }
+            },
+        };
+        let mut resp = Response::new(Body::from(body));
+        *resp.status_mut() = entry.status;
+        *resp.headers_mut() = entry.headers.clone();
+        resp
+    };
+
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::AGE,
+        HeaderValue::from(entry.stored_at.elapsed().as_secs()),
+    );
+    headers.insert("x-cache", HeaderValue::from_static(x_cache));
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        for (k, v) in pairs {
+            h.append(
+                HeaderName::from_bytes(k.as_bytes()).unwrap(),
+                HeaderValue::from_str(v).unwrap(),
+            );
+        }
+        h
+    }
+
+    #[test]
+    fn test_freshness() {
+        let ok = StatusCode::OK;
+        let none = Duration::ZERO;
+        let f = |h: &[(&str, &str)]| freshness(ok, &headers(h), false, none);
+
+        assert_eq!(
+            f(&[("cache-control", "public, max-age=60")]),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            f(&[("cache-control", "max-age=60, s-maxage=300")]),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(f(&[("cache-control", "private, max-age=60")]), None);
+        assert_eq!(f(&[("cache-control", "no-store")]), None);
+        assert_eq!(f(&[("cache-control", "no-cache")]), None);
+        assert_eq!(
+            f(&[("cache-control", "no-cache"), ("etag", "\"a\"")]),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            f(&[
+                ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ("expires", "Wed, 21 Oct 2015 08:28:00 GMT"),
+            ]),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            f(&[("cache-control", "max-age=60"), ("set-cookie", "a=b")]),
+            None
+        );
+        // No explicit lifetime: only with a route default
+        assert_eq!(f(&[]), None);
+        assert_eq!(
+            freshness(ok, &HeaderMap::new(), false, Duration::from_secs(30)),
+            Some(Duration::from_secs(30))
+        );
+        // Authenticated responses need `public`
+        let h = headers(&[("cache-control", "max-age=60")]);
+        assert_eq!(freshness(ok, &h, true, none), None);
+        assert_eq!(
+            freshness(StatusCode::INTERNAL_SERVER_ERROR, &h, false, none),
+            None
+        );
+    }
+
+    #[test]
+    fn test_request_policy() {
+        let get = Method::GET;
+        assert_eq!(
+            request_policy(&get, &HeaderMap::new()),
+            RequestPolicy::Lookup
+        );
+        assert_eq!(
+            request_policy(&get, &headers(&[("cache-control", "no-cache")])),
+            RequestPolicy::Refresh
+        );
+        assert_eq!(
+            request_policy(&get, &headers(&[("cache-control", "no-store")])),
+            RequestPolicy::Bypass
+        );
+        assert_eq!(
+            request_policy(&Method::POST, &HeaderMap::new()),
+            RequestPolicy::Bypass
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_vary_and_conditional() {
+        let cache = HttpCache::new(&CacheStoreConfig::default());
+        let key = HttpCache::key("App.Example.com", "/app.js");
+        let resp = headers(&[
+            ("cache-control", "max-age=60"),
+            ("etag", "\"v1\""),
+            ("vary", "Accept-Language"),
+            ("connection", "keep-alive"),
+        ]);
+        let fr = headers(&[("accept-language", "fr")]);
+        let en = headers(&[("accept-language", "en")]);
+        let ttl = Duration::from_secs(60);
+
+        cache
+            .store(
+                &key,
+                &fr,
+                StatusCode::OK,
+                &resp,
+                Bytes::from("bonjour"),
+                ttl,
+            )
+            .await;
+        cache
+            .store(&key, &en, StatusCode::OK, &resp, Bytes::from("hello"), ttl)
+            .await;
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.stats().memory_bytes, 12);
+        assert!(cache.lookup(&key, &HeaderMap::new()).is_none());
+
+        let entry = cache.lookup(&key, &en).unwrap();
+        assert!(entry.is_fresh());
+        let out = respond(&entry, &en, "HIT").await.unwrap();
+        assert_eq!(out.status(), StatusCode::OK);
+        assert_eq!(out.headers()["x-cache"], "HIT");
+        assert!(!out.headers().contains_key("connection"));
+        let body = axum::body::to_bytes(out.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let conditional = headers(&[("if-none-match", "W/\"v1\"")]);
+        let out = respond(&entry, &conditional, "HIT").await.unwrap();
+        assert_eq!(out.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(out.headers()[header::ETAG], "\"v1\"");
+
+        // Vary: * is never stored
+        let star = headers(&[("cache-control", "max-age=60"), ("vary", "*")]);
+        let other = HttpCache::key("app.example.com", "/other");
+        cache
+            .store(&other, &en, StatusCode::OK, &star, Bytes::from("x"), ttl)
+            .await;
+        assert!(cache.lookup(&other, &en).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_and_evict() {
+        let cache = HttpCache::new(&CacheStoreConfig {
+            memory_max_mb: 1,
+            ..Default::default()
+        });
+        let req = HeaderMap::new();
+        let resp = headers(&[("etag", "\"v1\""), ("cache-control", "no-cache")]);
+        cache
+            .store(
+                "a",
+                &req,
+                StatusCode::OK,
+                &resp,
+                Bytes::from("body"),
+                Duration::ZERO,
+            )
+            .await;
+
+        // Stale but revalidatable
+        let entry = cache.lookup("a", &req).unwrap();
+        assert!(!entry.is_fresh());
+        let mut upstream = HeaderMap::new();
+        assert!(add_validators(&mut upstream, &entry));
+        assert_eq!(upstream[header::IF_NONE_MATCH], "\"v1\"");
+
+        let updated = entry.revalidated_headers(&headers(&[("cache-control", "max-age=30")]));
+        let renewed = cache.refresh("a", &req, &entry, updated, Duration::from_secs(30));
+        assert!(renewed.is_fresh());
+        assert_eq!(renewed.headers[header::CACHE_CONTROL], "max-age=30");
+        assert_eq!(cache.stats().entries, 1);
+
+        // A 1 MiB body evicts the older entry
+        let big = Bytes::from(vec![0u8; 1024 * 1024]);
+        let fresh = headers(&[("cache-control", "max-age=60")]);
+        cache
+            .store(
+                "b",
+                &req,
+                StatusCode::OK,
+                &fresh,
+                big,
+                Duration::from_secs(60),
+            )
+            .await;
+        assert!(cache.lookup("a", &req).is_none());
+        assert!(cache.lookup("b", &req).is_some());
+        assert_eq!(cache.stats().memory_bytes, 1024 * 1024);
+    }
+}
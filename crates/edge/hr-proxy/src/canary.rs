@@ -0,0 +1,227 @@
+//! Weighted traffic splitting for app routes.
+//!
+//! An `AppRoute` may carry a `TrafficSplit`: named targets (e.g. `stable`
+//! and `canary`) sharing its traffic by weight. A client keeps the target
+//! it was first assigned through the `hr_variant` cookie while that target
+//! still has weight, and testers can pin a target (even at weight 0) with
+//! the `X-HR-Variant` header. Promoting a target makes it the route's only
+//! backend.
+
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+
+use anyhow::bail;
+use axum::http::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+use crate::pool::cookie_value;
+
+/// Cookie remembering a client's target.
+pub const VARIANT_COOKIE: &str = "hr_variant";
+/// Request header forcing a target by name.
+pub const VARIANT_HEADER: &str = "x-hr-variant";
+/// Lifetime of the sticky cookie.
+const STICKY_MAX_AGE_SECS: u64 = 86400;
+
+/// One backend of a split app route.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppTarget {
+    /// Target name, as used by the cookie, the override header and the API.
+    pub name: String,
+    pub target_ip: Ipv4Addr,
+    pub target_port: u16,
+    /// Relative share of new clients; 0 only serves pinned testers.
+    #[serde(default)]
+    pub weight: u32,
+}
+
+fn default_sticky() -> bool {
+    true
+}
+
+/// Targets sharing the traffic of an app route.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrafficSplit {
+    pub targets: Vec<AppTarget>,
+    /// Keep clients on their first target with a cookie.
+    #[serde(default = "default_sticky")]
+    pub sticky: bool,
+}
+
+/// Target chosen for a request.
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub target: AppTarget,
+    /// `Set-Cookie` value for a new sticky assignment.
+    pub set_cookie: Option<HeaderValue>,
+}
+
+impl TrafficSplit {
+    /// Reject empty or duplicate target names and splits without weight.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for target in &self.targets {
+            let name = target.name.trim();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            {
+                bail!("Invalid target name '{}'", target.name);
+            }
+            if !names.insert(name) {
+                bail!("Duplicate target '{}'", name);
+            }
+        }
+        if self.total_weight() == 0 {
+            bail!("At least one target needs a weight");
+        }
+        Ok(())
+    }
+
+    fn total_weight(&self) -> u64 {
+        self.targets.iter().map(|t| t.weight as u64).sum()
+    }
+
+    pub fn target(&self, name: &str) -> Option<&AppTarget> {
+        self.targets.iter().find(|t| t.name == name)
+    }
+
+    /// Replace the weights of the named targets; other targets keep theirs.
+    pub fn set_weights(&mut self, weights: &HashMap<String, u32>) -> anyhow::Result<()> {
+        for name in weights.keys() {
+            if self.target(name).is_none() {
+                bail!("Unknown target '{}'", name);
+            }
+        }
+        let mut next = self.clone();
+        for target in &mut next.targets {
+            if let Some(&weight) = weights.get(&target.name) {
+                target.weight = weight;
+            }
+        }
+        next.validate()?;
+        *self = next;
+        Ok(())
+    }
+
+    /// Pick the target for a request: the override header first, then the
+    /// sticky cookie, then a weighted draw.
+    pub fn select(&self, headers: &HeaderMap) -> Option<Assignment> {
+        let pinned = headers
+            .get(VARIANT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|name| self.target(name.trim()));
+        if let Some(target) = pinned {
+            return Some(Assignment {
+                target: target.clone(),
+                set_cookie: None,
+            });
+        }
+
+        if self.sticky
+            && let Some(target) = cookie_value(headers, VARIANT_COOKIE)
+                .and_then(|name| self.target(&name))
+                .filter(|t| t.weight > 0)
+        {
+            return Some(Assignment {
+                target: target.clone(),
+                set_cookie: None,
+            });
+        }
+
+        let total = self.total_weight();
+        if total == 0 {
+            return None;
+        }
+        let draw = (uuid::Uuid::new_v4().as_u128() % total as u128) as u64;
+        let target = self.pick(draw)?;
+        let set_cookie = self
+            .sticky
+            .then(|| {
+                HeaderValue::from_str(&format!(
+                    "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                    VARIANT_COOKIE, target.name, STICKY_MAX_AGE_SECS
+                ))
+                .ok()
+            })
+            .flatten();
+        Some(Assignment {
+            target: target.clone(),
+            set_cookie,
+        })
+    }
+
+    /// Target owning position `draw` of the cumulative weights.
+    fn pick(&self, mut draw: u64) -> Option<&AppTarget> {
+        for target in &self.targets {
+            let weight = target.weight as u64;
+            if draw < weight {
+                return Some(target);
+            }
+            draw -= weight;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split() -> TrafficSplit {
+        TrafficSplit {
+            targets: vec![
+                AppTarget {
+                    name: "stable".to_string(),
+                    target_ip: Ipv4Addr::new(10, 0, 0, 10),
+                    target_port: 3000,
+                    weight: 90,
+                },
+                AppTarget {
+                    name: "canary".to_string(),
+                    target_ip: Ipv4Addr::new(10, 0, 0, 11),
+                    target_port: 3000,
+                    weight: 10,
+                },
+            ],
+            sticky: true,
+        }
+    }
+
+    #[test]
+    fn test_select_override_cookie_and_weights() {
+        let mut split = split();
+        assert_eq!(split.pick(89).unwrap().name, "stable");
+        assert_eq!(split.pick(90).unwrap().name, "canary");
+
+        let fresh = split.select(&HeaderMap::new()).unwrap();
+        assert!(fresh.set_cookie.is_some());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("a=1; hr_variant=canary"));
+        let sticky = split.select(&headers).unwrap();
+        assert_eq!(sticky.target.name, "canary");
+        assert!(sticky.set_cookie.is_none());
+
+        // A target at weight 0 no longer keeps its clients...
+        split
+            .set_weights(&HashMap::from([("canary".to_string(), 0)]))
+            .unwrap();
+        assert_eq!(split.select(&headers).unwrap().target.name, "stable");
+        // ...but testers can still pin it
+        headers.insert(VARIANT_HEADER, HeaderValue::from_static("canary"));
+        assert_eq!(split.select(&headers).unwrap().target.name, "canary");
+
+        assert!(
+            split
+                .set_weights(&HashMap::from([("stable".to_string(), 0)]))
+                .is_err()
+        );
+        assert!(
+            split
+                .set_weights(&HashMap::from([("beta".to_string(), 5)]))
+                .is_err()
+        );
+    }
+}
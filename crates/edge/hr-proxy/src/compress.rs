@@ -0,0 +1,249 @@
+//! Negotiated response compression.
+//!
+//! Responses are compressed on the fly (streamed, never buffered) when the
+//! route enables it, the client accepts one of the configured encodings and
+//! the backend did not already encode the body.
+
+use async_compression::Level;
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::Response;
+use futures_util::TryStreamExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::config::{CompressionConfig, Encoding};
+
+/// Pick the first of `preferred` the client accepts (q > 0, explicitly or
+/// through `*`).
+pub fn negotiate(accept_encoding: &str, preferred: &[Encoding]) -> Option<Encoding> {
+    let mut accepted: Vec<(&str, bool)> = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let token = parts.next().unwrap_or("").trim();
+        if token.is_empty() {
+            continue;
+        }
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        accepted.push((token, q > 0.0));
+    }
+    let lookup = |token: &str| {
+        accepted
+            .iter()
+            .find(|(t, _)| t.eq_ignore_ascii_case(token))
+            .map(|(_, ok)| *ok)
+    };
+    preferred.iter().copied().find(|enc| {
+        lookup(enc.as_str())
+            .or_else(|| lookup("*"))
+            .unwrap_or(false)
+    })
+}
+
+/// Whether `headers` describe a body worth compressing under `config`.
+fn is_compressible(config: &CompressionConfig, status: StatusCode, headers: &HeaderMap) -> bool {
+    if status.is_informational()
+        || matches!(
+            status,
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED | StatusCode::PARTIAL_CONTENT
+        )
+        || headers.contains_key(header::CONTENT_ENCODING)
+        || headers.contains_key(header::CONTENT_RANGE)
+    {
+        return false;
+    }
+    let no_transform = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.to_ascii_lowercase().contains("no-transform"));
+    if no_transform {
+        return false;
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_ascii_lowercase()
+        })
+        .unwrap_or_default();
+    config
+        .content_types
+        .iter()
+        .any(|t| content_type.starts_with(&t.to_ascii_lowercase()))
+}
+
+/// Add `Accept-Encoding` to the response's `Vary` header.
+fn add_vary(headers: &mut HeaderMap) {
+    let existing: Vec<String> = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if existing
+        .iter()
+        .any(|v| v == "*" || v.eq_ignore_ascii_case("accept-encoding"))
+    {
+        return;
+    }
+    let mut values = existing;
+    values.push("Accept-Encoding".to_string());
+    if let Ok(v) = HeaderValue::from_str(&values.join(", ")) {
+        headers.insert(header::VARY, v);
+    }
+}
+
+/// Compress `resp` for a client that sent `accept_encoding`, if the route's
+/// rules allow it. `head` requests only get the `Vary` header.
+pub fn compress_response(
+    config: &CompressionConfig,
+    accept_encoding: Option<&HeaderValue>,
+    head: bool,
+    mut resp: Response,
+) -> Response {
+    if !is_compressible(config, resp.status(), resp.headers()) {
+        return resp;
+    }
+    // Caches downstream must key on Accept-Encoding even if we send identity
+    add_vary(resp.headers_mut());
+
+    let too_small = resp
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_some_and(|len| len < config.min_size);
+    if head || too_small {
+        return resp;
+    }
+    let Some(encoding) = accept_encoding
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| negotiate(v, &config.algorithms))
+    else {
+        return resp;
+    };
+
+    let (mut parts, body) = resp.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::ACCEPT_RANGES);
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    // The encoded bytes differ from the original representation
+    if let Some(etag) = parts.headers.get(header::ETAG).cloned()
+        && !etag.as_bytes().starts_with(b"W/")
+    {
+        let mut weak = b"W/".to_vec();
+        weak.extend_from_slice(etag.as_bytes());
+        if let Ok(v) = HeaderValue::from_bytes(&weak) {
+            parts.headers.insert(header::ETAG, v);
+        }
+    }
+
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let body = match encoding {
+        Encoding::Br => {
+            // Quality 11 (the brotli default) is far too slow for on-the-fly use
+            Body::from_stream(ReaderStream::new(BrotliEncoder::with_quality(
+                reader,
+                Level::Precise(4),
+            )))
+        }
+        Encoding::Zstd => Body::from_stream(ReaderStream::new(ZstdEncoder::new(reader))),
+        Encoding::Gzip => Body::from_stream(ReaderStream::new(GzipEncoder::new(reader))),
+    };
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::GzipDecoder;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_negotiate() {
+        let prefs = [Encoding::Br, Encoding::Zstd, Encoding::Gzip];
+        assert_eq!(negotiate("gzip, deflate, br", &prefs), Some(Encoding::Br));
+        assert_eq!(negotiate("gzip, br;q=0", &prefs), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*", &prefs), Some(Encoding::Br));
+        assert_eq!(negotiate("*;q=0, zstd", &prefs), Some(Encoding::Zstd));
+        assert_eq!(negotiate("identity", &prefs), None);
+        assert_eq!(negotiate("GZIP", &[Encoding::Gzip]), Some(Encoding::Gzip));
+    }
+
+    fn response(content_type: &str, body: &str) -> Response {
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .header(header::ETAG, "\"v1\"")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compress_gzip_roundtrip() {
+        let config = CompressionConfig {
+            min_size: 16,
+            ..Default::default()
+        };
+        let text = "console.log('hello world'); ".repeat(64);
+        let accept = HeaderValue::from_static("gzip");
+
+        let resp = compress_response(
+            &config,
+            Some(&accept),
+            false,
+            response("application/javascript; charset=utf-8", &text),
+        );
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[header::VARY], "Accept-Encoding");
+        assert_eq!(resp.headers()[header::ETAG], "W/\"v1\"");
+        assert!(!resp.headers().contains_key(header::CONTENT_LENGTH));
+
+        let compressed = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(compressed.len() < text.len());
+        let mut decoded = String::new();
+        GzipDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn test_compress_skips() {
+        let config = CompressionConfig::default();
+        let accept = HeaderValue::from_static("gzip");
+
+        // Not a compressible type
+        let resp = compress_response(&config, Some(&accept), false, response("image/png", "x"));
+        assert!(!resp.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!resp.headers().contains_key(header::VARY));
+
+        // Below min_size: identity, but still Vary
+        let resp = compress_response(&config, Some(&accept), false, response("text/html", "x"));
+        assert!(!resp.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(resp.headers()[header::VARY], "Accept-Encoding");
+
+        // Already encoded by the backend
+        let mut resp = response("text/html", "x");
+        resp.headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+        let resp = compress_response(&config, Some(&accept), false, resp);
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 /// Configuration principale du reverse proxy
@@ -28,9 +28,78 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub routes: Vec<RouteConfig>,
 
+    /// Routes de niveau 4 (TCP, UDP, TLS transmis tel quel selon le SNI)
+    #[serde(default)]
+    pub streams: Vec<StreamRoute>,
+
+    /// Règles de redirection et de réécriture, évaluées avant le routage
+    #[serde(default)]
+    pub redirects: Vec<RedirectRule>,
+
+    /// Alias de domaines redirigés vers leur domaine canonique
+    #[serde(default)]
+    pub domain_aliases: Vec<DomainAlias>,
+
     /// Chemin du fichier de log d'accès JSON (optionnel)
     #[serde(default)]
     pub access_log_path: Option<String>,
+
+    /// Format, rotation et indexation du log d'accès (lus au démarrage)
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+
+    /// Pools de backends, référencés par nom depuis les routes et règles
+    #[serde(default)]
+    pub pools: Vec<BackendPool>,
+
+    /// Délais, nouvelles tentatives et coupe-circuit des routes sans
+    /// section `upstream` propre
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+
+    /// Agrafage OCSP des certificats ACME
+    #[serde(default)]
+    pub ocsp: OcspConfig,
+
+    /// Nombre de versions appliquées de la configuration conservées pour
+    /// un retour arrière
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+
+    /// Écoute HTTP/3 (QUIC) sur le port UDP `https_port`.
+    /// Penser à ouvrir ce port UDP côté pare-feu pour les clients WAN directs.
+    #[serde(default)]
+    pub http3_enabled: bool,
+
+    /// Durée (secondes) de l'annonce `Alt-Svc: h3`
+    #[serde(default = "default_alt_svc_max_age")]
+    pub alt_svc_max_age: u64,
+
+    /// Stockage du cache HTTP partagé par les routes où `cache` est défini
+    #[serde(default)]
+    pub cache: CacheStoreConfig,
+
+    /// Politiques d'en-têtes nommées, référencées par `headers.policies`.
+    /// Remplacent la politique intégrée de même nom ("strict-security").
+    #[serde(default)]
+    pub header_policies: HashMap<String, HeaderPolicy>,
+
+    /// Listes d'adresses nommées (CIDR ou IP), référencées par les ACL des
+    /// routes. Remplacent la liste intégrée de même nom ("lan", "loopback").
+    #[serde(default)]
+    pub ip_lists: HashMap<String, Vec<String>>,
+
+    /// Relais de confiance (CIDR ou IP), ex. le client du tunnel QUIC.
+    /// Leurs connexions commencent par une ligne PROXY protocol v1 qui donne
+    /// l'adresse réelle du client.
+    #[serde(default)]
+    pub trusted_relays: Vec<String>,
+
+    /// Dossier de modèles HTML pour les pages d'erreur : `401.html` …
+    /// `504.html`, `maintenance.html`, `starting.html`. Les fichiers absents
+    /// gardent la page intégrée.
+    #[serde(default)]
+    pub error_pages_dir: Option<PathBuf>,
 }
 
 fn default_http_port() -> u16 {
@@ -45,6 +114,12 @@ fn default_tls_mode() -> String {
 fn default_ca_path() -> PathBuf {
     PathBuf::from("/var/lib/server-dashboard/ca")
 }
+fn default_alt_svc_max_age() -> u64 {
+    86400
+}
+fn default_history_size() -> usize {
+    10
+}
 /// Configuration d'une route
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RouteConfig {
@@ -58,20 +133,42 @@ pub struct RouteConfig {
     #[serde(default = "default_backend")]
     pub backend: String,
 
-    /// Host cible
+    /// Host cible (ignoré si `pool` est défini)
+    #[serde(default)]
     pub target_host: String,
 
-    /// Port cible
+    /// Port cible (ignoré si `pool` est défini)
+    #[serde(default)]
     pub target_port: u16,
 
+    /// Pool de backends à utiliser à la place de `target_host:target_port`
+    #[serde(default)]
+    pub pool: Option<String>,
+
+    /// Joindre le backend en HTTPS, avec cette vérification du certificat
+    #[serde(default)]
+    pub backend_tls: Option<BackendTlsConfig>,
+
     /// Restreindre aux IPs locales uniquement
     #[serde(default)]
     pub local_only: bool,
 
+    /// Listes d'autorisation / de refus par adresse client
+    #[serde(default)]
+    pub acl: AccessList,
+
+    /// Certificat client (mTLS) émis par la CA des appareils
+    #[serde(default)]
+    pub client_cert: ClientCertMode,
+
     /// Requérir authentification
     #[serde(default)]
     pub require_auth: bool,
 
+    /// Clés d'API et identifiants basic acceptés en plus de la session
+    #[serde(default)]
+    pub machine_auth: Option<MachineAuthConfig>,
+
     /// Actif ou non
     #[serde(default = "default_enabled")]
     pub enabled: bool,
@@ -79,6 +176,814 @@ pub struct RouteConfig {
     /// ID du certificat CA (auto-généré si vide)
     #[serde(default)]
     pub cert_id: Option<String>,
+
+    /// Limites de débit et de connexions
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Compression des réponses (gzip/brotli/zstd négociée)
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+
+    /// Mise en cache des réponses (respecte `Cache-Control`, `ETag`, `Vary`)
+    #[serde(default)]
+    pub cache: Option<RouteCacheConfig>,
+
+    /// Délais, nouvelles tentatives et coupe-circuit (défaut : `upstream` global)
+    #[serde(default)]
+    pub upstream: Option<UpstreamConfig>,
+
+    /// Règles d'en-têtes de requête et de réponse
+    #[serde(default)]
+    pub headers: RouteHeaders,
+
+    /// Règles par chemin, évaluées dans l'ordre (la première qui matche gagne).
+    /// Sans correspondance, la requête part vers `target_host:target_port`.
+    #[serde(default)]
+    pub rules: Vec<PathRule>,
+}
+
+/// Règle de routage par chemin au sein d'un domaine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathRule {
+    /// Préfixe de chemin (ex: "/api"), aligné sur les segments
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+
+    /// Expression régulière sur le chemin (prioritaire sur `path_prefix`)
+    #[serde(default)]
+    pub path_regex: Option<String>,
+
+    /// Host cible de la règle (ignoré si `pool` est défini)
+    #[serde(default)]
+    pub target_host: String,
+
+    /// Port cible de la règle (ignoré si `pool` est défini)
+    #[serde(default)]
+    pub target_port: u16,
+
+    /// Pool de backends de la règle
+    #[serde(default)]
+    pub pool: Option<String>,
+
+    /// Retirer la partie matchée avant de transmettre ("/api/x" → "/x")
+    #[serde(default)]
+    pub strip_prefix: bool,
+
+    /// Remplacement de la partie matchée (accepte `$1`, `${name}` pour les regex)
+    #[serde(default)]
+    pub rewrite: Option<String>,
+
+    /// Requérir authentification (hérite de la route si absent)
+    #[serde(default)]
+    pub require_auth: Option<bool>,
+
+    /// Groupes autorisés ; non vide implique l'authentification
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+}
+
+/// Limites appliquées par client sur une route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Requêtes par seconde (0 = illimité)
+    #[serde(default)]
+    pub requests_per_second: f64,
+
+    /// Rafale tolérée au-delà du débit (défaut : débit arrondi, minimum 1)
+    #[serde(default)]
+    pub burst: Option<u32>,
+
+    /// Identification du client
+    #[serde(default)]
+    pub key: RateLimitKey,
+
+    /// Requêtes simultanées par client (0 = illimité)
+    #[serde(default)]
+    pub max_connections: u32,
+
+    /// WebSockets ouverts simultanément par client (0 = illimité)
+    #[serde(default)]
+    pub max_websockets: u32,
+}
+
+/// Identifiants machine (gérés par hr-auth) acceptés par une route
+/// authentifiée, pour les clients qui ne suivent pas le login par cookie
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MachineAuthConfig {
+    /// Accepter les clés d'API (en-tête `header` ou `Authorization: Bearer`)
+    #[serde(default)]
+    pub api_keys: bool,
+
+    /// En-tête portant la clé (défaut : `X-API-Key`)
+    #[serde(default)]
+    pub header: Option<String>,
+
+    /// Paramètre de requête portant la clé (ex: "api_key"), retiré avant le backend
+    #[serde(default)]
+    pub query_param: Option<String>,
+
+    /// Accepter `Authorization: Basic`
+    #[serde(default)]
+    pub basic: bool,
+
+    /// Portées dont l'identifiant doit couvrir au moins une (vide = toutes)
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Contrôle d'accès par adresse client, évalué avant l'authentification.
+/// Chaque entrée est un CIDR, une IP ou le nom d'une liste (`ip_lists` ou
+/// intégrée). Un refus qui matche l'emporte ; si `allow` n'est pas vide,
+/// le client doit y figurer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AccessList {
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl AccessList {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+}
+
+/// Action d'une règle de redirection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectAction {
+    /// Redirection HTTP vers `to` (URL absolue ou chemin du même hôte)
+    #[default]
+    Redirect,
+    /// Réécriture interne du chemin, le routage continue avec `to`
+    Rewrite,
+}
+
+/// Règle de redirection / réécriture. La première règle dont les regex
+/// `host` et `path` matchent s'applique. `to` accepte les captures (`$1`,
+/// `${nom}`) ainsi que `${host}`, `${path}` et `${query}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedirectRule {
+    /// Identifiant unique
+    pub id: String,
+
+    /// Regex sur l'hôte, sans port (toutes les requêtes si absent)
+    #[serde(default)]
+    pub host: Option<String>,
+
+    /// Regex sur le chemin, sans query string (tous les chemins si absent)
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default)]
+    pub action: RedirectAction,
+
+    /// Destination de la redirection ou chemin réécrit
+    pub to: String,
+
+    /// Code de redirection (301, 302, 303, 307 ou 308)
+    #[serde(default = "default_redirect_status")]
+    pub status: u16,
+
+    /// Conserver la query string d'origine
+    #[serde(default = "default_enabled")]
+    pub preserve_query: bool,
+
+    /// Active ou non
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Alias de domaine (ex: `www.example.com` → `example.com`), chemin et
+/// query string conservés
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainAlias {
+    pub from: String,
+
+    pub to: String,
+
+    /// Code de redirection (301, 302, 303, 307 ou 308)
+    #[serde(default = "default_redirect_status")]
+    pub status: u16,
+}
+
+fn default_redirect_status() -> u16 {
+    301
+}
+
+/// Protocole d'une route de niveau 4
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamProtocol {
+    #[default]
+    Tcp,
+    Udp,
+    /// Connexions TLS du port HTTPS dont le SNI est `domain`, transmises
+    /// sans déchiffrement (le backend termine TLS)
+    TlsPassthrough,
+}
+
+/// Route de niveau 4 : flux TCP/UDP relayé vers `target_host:target_port`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamRoute {
+    /// Identifiant unique
+    pub id: String,
+
+    #[serde(default)]
+    pub protocol: StreamProtocol,
+
+    /// Port d'écoute (tcp/udp ; ignoré en `tls_passthrough`)
+    #[serde(default)]
+    pub listen_port: u16,
+
+    /// Domaine SNI (`tls_passthrough` uniquement)
+    #[serde(default)]
+    pub domain: Option<String>,
+
+    pub target_host: String,
+
+    pub target_port: u16,
+
+    /// Restreindre aux IPs locales (listes `lan` et `loopback`)
+    #[serde(default)]
+    pub local_only: bool,
+
+    /// Listes d'autorisation / de refus par adresse client
+    #[serde(default)]
+    pub acl: AccessList,
+
+    /// Inactivité (secondes) après laquelle une session UDP est oubliée
+    #[serde(default = "default_stream_idle_timeout")]
+    pub idle_timeout_secs: u64,
+
+    /// Nombre maximal de sessions UDP simultanées ; au-delà, les nouveaux
+    /// clients sont ignorés jusqu'à l'expiration d'une session
+    #[serde(default = "default_stream_max_sessions")]
+    pub max_sessions: usize,
+
+    /// Actif ou non
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_stream_idle_timeout() -> u64 {
+    300
+}
+
+fn default_stream_max_sessions() -> usize {
+    1024
+}
+
+/// TLS vers un backend (routes `backend_tls`, apps en ré-chiffrement)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BackendTlsConfig {
+    #[serde(default)]
+    pub verify: BackendTlsVerify,
+
+    /// CA (PEM) signant le certificat du backend, pour `verify: "ca"`
+    #[serde(default)]
+    pub ca_path: Option<PathBuf>,
+
+    /// Empreinte SHA-256 du certificat du backend (hex, `:` toléré),
+    /// pour `verify: "fingerprint"`
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+
+    /// Nom attendu dans le certificat (et envoyé en SNI) ;
+    /// par défaut le domaine de l'app ou `target_host`
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+/// Vérification du certificat présenté par un backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendTlsVerify {
+    /// Autorités racines du système
+    #[default]
+    System,
+    /// CA interne épinglée (`ca_path`)
+    Ca,
+    /// Certificat épinglé par empreinte (`fingerprint`)
+    Fingerprint,
+    /// Aucune vérification (à éviter : n'importe qui sur le chemin peut
+    /// se faire passer pour le backend)
+    Insecure,
+}
+
+impl BackendTlsVerify {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendTlsVerify::System => "system",
+            BackendTlsVerify::Ca => "ca",
+            BackendTlsVerify::Fingerprint => "fingerprint",
+            BackendTlsVerify::Insecure => "insecure",
+        }
+    }
+}
+
+/// Vérification du certificat client d'une route
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientCertMode {
+    /// Ignoré, rien n'est transmis au backend
+    #[default]
+    Off,
+    /// Identité du certificat transmise au backend s'il est présenté
+    Optional,
+    /// Requête refusée sans certificat valide
+    Required,
+}
+
+/// Clé d'identification du client pour les limites
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    ClientIp,
+    /// Utilisateur authentifié (IP client pour les anonymes)
+    User,
+    /// Valeur d'un en-tête (IP client s'il est absent)
+    Header(String),
+}
+
+/// En-têtes d'une route : politiques nommées puis règles propres
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RouteHeaders {
+    /// Politiques nommées appliquées dans l'ordre, avant les règles propres
+    #[serde(default)]
+    pub policies: Vec<String>,
+
+    #[serde(flatten)]
+    pub rules: HeaderPolicy,
+}
+
+impl RouteHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty() && self.rules.request.is_empty() && self.rules.response.is_empty()
+    }
+}
+
+/// Règles d'en-têtes pour la requête transmise au backend et la réponse
+/// renvoyée au client
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderPolicy {
+    #[serde(default)]
+    pub request: HeaderRules,
+
+    #[serde(default)]
+    pub response: HeaderRules,
+}
+
+/// Opérations sur les en-têtes, appliquées dans l'ordre remove, set, append.
+/// Les valeurs acceptent des variables : `{user}`, `{user_id}`, `{email}`,
+/// `{groups}`, `{client_ip}`, `{host}`, `{method}`, `{path}`,
+/// `{header.<nom>}` (en-tête de la requête). Une valeur vide n'est pas posée.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderRules {
+    /// En-têtes supprimés
+    #[serde(default)]
+    pub remove: Vec<String>,
+
+    /// En-têtes remplacés (ou ajoutés)
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+
+    /// Valeurs ajoutées aux en-têtes existants
+    #[serde(default)]
+    pub append: BTreeMap<String, String>,
+}
+
+impl HeaderRules {
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.set.is_empty() && self.append.is_empty()
+    }
+}
+
+/// Compression des réponses d'une route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    /// Algorithmes par ordre de préférence
+    #[serde(default = "default_compression_algorithms")]
+    pub algorithms: Vec<Encoding>,
+
+    /// Taille minimale (octets) d'une réponse à compresser
+    #[serde(default = "default_compression_min_size")]
+    pub min_size: u64,
+
+    /// Préfixes de `Content-Type` compressibles
+    #[serde(default = "default_compressible_types")]
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Encodage de contenu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Br,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// Jeton `Content-Encoding` correspondant
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Mise en cache des réponses d'une route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteCacheConfig {
+    /// Durée de vie (secondes) des réponses sans `max-age` ni `Expires`
+    /// (0 = ne pas les mettre en cache)
+    #[serde(default)]
+    pub default_ttl_secs: u64,
+
+    /// Taille maximale (octets) d'une réponse mise en cache
+    #[serde(default = "default_cache_max_object_size")]
+    pub max_object_size: u64,
+}
+
+impl Default for RouteCacheConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Délais, nouvelles tentatives et coupe-circuit vers le backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    /// Délai d'établissement de la connexion (ms)
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+
+    /// Délai d'attente des en-têtes de réponse par tentative, puis entre deux
+    /// morceaux du corps (ms, 0 = illimité). Les flux SSE doivent envoyer un
+    /// keep-alive plus fréquent.
+    #[serde(default = "default_read_timeout_ms")]
+    pub read_timeout_ms: u64,
+
+    /// Durée maximale de l'ensemble des tentatives, jusqu'à la fin du corps
+    /// de la réponse (ms, 0 = illimitée)
+    #[serde(default)]
+    pub total_timeout_ms: u64,
+
+    /// Nouvelles tentatives des requêtes idempotentes sans corps
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+
+    /// Coupe-circuit par backend
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Nouvelles tentatives après une erreur de connexion, un délai dépassé ou
+/// un statut listé, sur un autre membre du pool s'il y en a un
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Tentatives après la première
+    #[serde(default = "default_retry_attempts")]
+    pub attempts: u32,
+
+    /// Attente avant la première nouvelle tentative, doublée ensuite (ms)
+    #[serde(default = "default_retry_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Plafond de l'attente entre deux tentatives (ms)
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+
+    /// Statuts du backend qui déclenchent une nouvelle tentative
+    #[serde(default = "default_failure_statuses")]
+    pub on_status: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Coupe-circuit : après `failure_threshold` échecs consécutifs d'un
+/// backend, ses requêtes échouent immédiatement pendant `open_secs`, puis
+/// une requête d'essai décide de sa réouverture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Échecs consécutifs (erreur, délai dépassé ou statut 502/503/504)
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Durée d'ouverture du circuit (secondes)
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Agrafage OCSP : la réponse du répondeur de l'autorité est récupérée,
+/// mise en cache et envoyée pendant la poignée de main TLS
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcspConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Répondeur utilisé à la place de celui du certificat (ex. un
+    /// répondeur local de test)
+    #[serde(default)]
+    pub responder_url: Option<String>,
+
+    /// Délai maximal d'une requête au répondeur (secondes)
+    #[serde(default = "default_ocsp_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for OcspConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Format des lignes du log d'accès
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// Une entrée JSON par ligne
+    #[default]
+    Json,
+    /// Combined Log Format (Apache/nginx)
+    Combined,
+}
+
+/// Rotation périodique du log d'accès
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    /// Rotation uniquement sur la taille
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+}
+
+/// Rotation, rétention et indexation du log d'accès
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+
+    #[serde(default)]
+    pub rotation: LogRotation,
+
+    /// Taille déclenchant une rotation (Mo, 0 = sans limite)
+    #[serde(default = "default_access_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Compression gzip des fichiers archivés
+    #[serde(default = "default_enabled")]
+    pub compress: bool,
+
+    /// Nombre d'archives conservées (0 = sans limite)
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: usize,
+
+    /// Âge maximal des archives (jours, 0 = sans limite)
+    #[serde(default = "default_access_log_max_age_days")]
+    pub max_age_days: u64,
+
+    /// Indexation des requêtes dans une base SQLite interrogeable
+    /// depuis l'API (`/api/logs/access`)
+    #[serde(default = "default_enabled")]
+    pub index: bool,
+
+    /// Durée de conservation des requêtes indexées (jours)
+    #[serde(default = "default_access_log_index_days")]
+    pub index_retention_days: u64,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn default_ocsp_timeout_secs() -> u64 {
+    10
+}
+fn default_connect_timeout_ms() -> u64 {
+    5000
+}
+fn default_read_timeout_ms() -> u64 {
+    60_000
+}
+fn default_retry_attempts() -> u32 {
+    2
+}
+fn default_retry_backoff_ms() -> u64 {
+    100
+}
+fn default_retry_max_backoff_ms() -> u64 {
+    2000
+}
+fn default_failure_statuses() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+fn default_failure_threshold() -> u32 {
+    5
+}
+fn default_open_secs() -> u64 {
+    30
+}
+fn default_access_log_max_size_mb() -> u64 {
+    100
+}
+fn default_access_log_max_files() -> usize {
+    14
+}
+fn default_access_log_max_age_days() -> u64 {
+    30
+}
+fn default_access_log_index_days() -> u64 {
+    14
+}
+
+/// Stockage du cache HTTP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheStoreConfig {
+    /// Taille maximale du cache en mémoire (Mo)
+    #[serde(default = "default_cache_memory_max_mb")]
+    pub memory_max_mb: u64,
+
+    /// Répertoire du cache sur disque ; les corps y sont stockés au lieu
+    /// de la mémoire (vidé au démarrage)
+    #[serde(default)]
+    pub disk_path: Option<PathBuf>,
+
+    /// Taille maximale du cache sur disque (Mo)
+    #[serde(default = "default_cache_disk_max_mb")]
+    pub disk_max_mb: u64,
+}
+
+impl Default for CacheStoreConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn default_compression_algorithms() -> Vec<Encoding> {
+    vec![Encoding::Br, Encoding::Zstd, Encoding::Gzip]
+}
+fn default_compression_min_size() -> u64 {
+    1024
+}
+fn default_compressible_types() -> Vec<String> {
+    [
+        "text/",
+        "application/javascript",
+        "application/json",
+        "application/xml",
+        "application/wasm",
+        "image/svg+xml",
+    ]
+    .iter()
+    .map(|t| t.to_string())
+    .collect()
+}
+fn default_cache_max_object_size() -> u64 {
+    10 * 1024 * 1024
+}
+fn default_cache_memory_max_mb() -> u64 {
+    128
+}
+fn default_cache_disk_max_mb() -> u64 {
+    1024
+}
+
+/// Pool de backends équivalents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendPool {
+    /// Nom du pool (référencé par `pool` dans les routes)
+    pub name: String,
+
+    /// Stratégie de répartition
+    #[serde(default)]
+    pub strategy: LbStrategy,
+
+    /// Cookie servant de clé pour `consistent_hash` (IP client si absent)
+    #[serde(default)]
+    pub hash_cookie: Option<String>,
+
+    /// Membres du pool
+    pub members: Vec<PoolMember>,
+
+    /// Sondes HTTP actives
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+
+    /// Erreurs de connexion consécutives avant éjection passive (0 = désactivé)
+    #[serde(default = "default_max_fails")]
+    pub max_fails: u32,
+
+    /// Durée d'éjection passive en secondes
+    #[serde(default = "default_eject_secs")]
+    pub eject_secs: u64,
+}
+
+/// Membre d'un pool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolMember {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Stratégie de répartition de charge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LbStrategy {
+    #[default]
+    RoundRobin,
+    LeastConnections,
+    ConsistentHash,
+}
+
+/// Configuration des sondes de santé actives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Chemin sondé en GET
+    #[serde(default = "default_health_path")]
+    pub path: String,
+
+    #[serde(default = "default_health_interval")]
+    pub interval_secs: u64,
+
+    #[serde(default = "default_health_timeout")]
+    pub timeout_ms: u64,
+
+    /// Succès consécutifs pour repasser un membre en bonne santé
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+
+    /// Échecs consécutifs pour marquer un membre hors service
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+
+    /// Codes HTTP attendus (vide = 2xx/3xx)
+    #[serde(default)]
+    pub expected_status: Vec<u16>,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn default_max_fails() -> u32 {
+    3
+}
+fn default_eject_secs() -> u64 {
+    30
+}
+fn default_health_path() -> String {
+    "/".to_string()
+}
+fn default_health_interval() -> u64 {
+    10
+}
+fn default_health_timeout() -> u64 {
+    2000
+}
+fn default_healthy_threshold() -> u32 {
+    2
+}
+fn default_unhealthy_threshold() -> u32 {
+    3
 }
 
 fn default_backend() -> String {
@@ -96,10 +1001,14 @@ impl ProxyConfig {
         Ok(config)
     }
 
-    /// Sauvegarde la configuration dans un fichier JSON
+    /// Sauvegarde la configuration dans un fichier JSON. Le fichier est
+    /// écrit à côté puis renommé : un lecteur ne voit jamais une version
+    /// partielle.
     pub fn save_to_file(&self, path: &PathBuf) -> anyhow::Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, content)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)?;
         Ok(())
     }
 
@@ -131,6 +1040,21 @@ mod tests {
             ca_storage_path: PathBuf::from("/var/lib/server-dashboard/ca"),
             routes: vec![],
             access_log_path: None,
+            access_log: AccessLogConfig::default(),
+            pools: vec![],
+            http3_enabled: false,
+            alt_svc_max_age: 86400,
+            cache: Default::default(),
+            header_policies: HashMap::new(),
+            ip_lists: HashMap::new(),
+            trusted_relays: vec![],
+            streams: vec![],
+            redirects: vec![],
+            domain_aliases: vec![],
+            upstream: Default::default(),
+            ocsp: Default::default(),
+            history_size: 10,
+            error_pages_dir: None,
         };
 
         assert_eq!(config.https_port, 443);
@@ -153,9 +1077,20 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8080,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: true,
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
                     cert_id: None,
+                    rules: vec![],
                 },
                 RouteConfig {
                     id: "2".to_string(),
@@ -164,9 +1099,20 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8081,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: true,
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
                     cert_id: None,
+                    rules: vec![],
                 },
                 RouteConfig {
                     id: "3".to_string(),
@@ -175,12 +1121,38 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8082,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: false,
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
                     cert_id: None,
+                    rules: vec![],
                 },
             ],
             access_log_path: None,
+            access_log: AccessLogConfig::default(),
+            pools: vec![],
+            http3_enabled: false,
+            alt_svc_max_age: 86400,
+            cache: Default::default(),
+            header_policies: HashMap::new(),
+            ip_lists: HashMap::new(),
+            trusted_relays: vec![],
+            streams: vec![],
+            redirects: vec![],
+            domain_aliases: vec![],
+            upstream: Default::default(),
+            ocsp: Default::default(),
+            history_size: 10,
+            error_pages_dir: None,
         };
 
         let active = config.active_routes();
@@ -0,0 +1,430 @@
+//! Internal CA issuing client certificates for enrolled devices.
+//!
+//! Routes with `client_cert` set accept certificates signed by this CA
+//! (see `tls::DeviceCertVerifier`). The CA key, certificate and the device
+//! registry live in one directory; revoked serials are published to the TLS
+//! verifier as a CRL regenerated on every change.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rcgen::{
+    CertificateParams, CertificateRevocationListParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    Issuer, KeyIdMethod, KeyPair, KeyUsagePurpose, PKCS_ECDSA_P256_SHA256, RevocationReason,
+    RevokedCertParams, SerialNumber,
+};
+use rustls::RootCertStore;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::server::danger::ClientCertVerifier;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+const CA_CERT_FILE: &str = "ca.crt";
+const CA_KEY_FILE: &str = "ca.key";
+const DEVICES_FILE: &str = "devices.json";
+const CA_VALIDITY_DAYS: i64 = 20 * 365;
+/// Organization set on device certificates.
+const DEVICE_ORG: &str = "HomeRoute Devices";
+
+/// A certificate issued to a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRecord {
+    /// Hex serial number, as sent to backends in `X-Client-Cert-Serial`
+    pub serial: String,
+    pub name: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl DeviceRecord {
+    fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
+
+/// Material handed to the device on enrollment (PEM).
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedDevice {
+    #[serde(flatten)]
+    pub record: DeviceRecord,
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub ca_pem: String,
+}
+
+pub struct DeviceCa {
+    dir: PathBuf,
+    ca_cert_der: CertificateDer<'static>,
+    ca_cert_pem: String,
+    ca_key_pem: String,
+    devices: Mutex<Vec<DeviceRecord>>,
+}
+
+impl DeviceCa {
+    /// Load the CA from `dir`, creating it (and the directory) on first use.
+    pub fn open_or_create(dir: &Path) -> Result<Self> {
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+        let (ca_cert_pem, ca_key_pem) = if cert_path.exists() {
+            (
+                std::fs::read_to_string(&cert_path)
+                    .with_context(|| format!("Failed to read {}", cert_path.display()))?,
+                std::fs::read_to_string(&key_path)
+                    .with_context(|| format!("Failed to read {}", key_path.display()))?,
+            )
+        } else {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            let (cert_pem, key_pem) = generate_ca()?;
+            write_private(&key_path, &key_pem)?;
+            std::fs::write(&cert_path, &cert_pem)
+                .with_context(|| format!("Failed to write {}", cert_path.display()))?;
+            info!("Created device CA in {}", dir.display());
+            (cert_pem, key_pem)
+        };
+
+        let ca_cert_der = rustls_pemfile::certs(&mut ca_cert_pem.as_bytes())
+            .next()
+            .context("No certificate in device CA file")?
+            .context("Invalid device CA certificate")?;
+
+        let devices_path = dir.join(DEVICES_FILE);
+        let devices = match std::fs::read_to_string(&devices_path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", devices_path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Failed to read device registry"),
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            ca_cert_der,
+            ca_cert_pem,
+            ca_key_pem,
+            devices: Mutex::new(devices),
+        })
+    }
+
+    pub fn ca_pem(&self) -> &str {
+        &self.ca_cert_pem
+    }
+
+    pub fn list(&self) -> Vec<DeviceRecord> {
+        self.devices.lock().unwrap().clone()
+    }
+
+    fn issuer(&self) -> Result<Issuer<'static, KeyPair>> {
+        let key = KeyPair::from_pem(&self.ca_key_pem).context("Invalid device CA key")?;
+        Issuer::from_ca_cert_der(&self.ca_cert_der, key).context("Invalid device CA certificate")
+    }
+
+    /// Issue a client certificate with `name` as its common name.
+    pub fn issue(&self, name: &str, validity_days: u32) -> Result<IssuedDevice> {
+        anyhow::ensure!(!name.trim().is_empty(), "Device name is required");
+        anyhow::ensure!(validity_days > 0, "Validity must be at least one day");
+
+        let serial = random_serial();
+        let now = time::OffsetDateTime::now_utc();
+        let not_after = now + time::Duration::days(validity_days.into());
+
+        let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
+            .context("Failed to generate device key pair")?;
+        let mut params = CertificateParams::new(Vec::<String>::new())
+            .context("Failed to create device params")?;
+        params.distinguished_name.push(DnType::CommonName, name);
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, DEVICE_ORG);
+        params.serial_number = Some(SerialNumber::from_slice(&serial));
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        params.not_before = now;
+        params.not_after = not_after;
+
+        let cert = params
+            .signed_by(&key, &self.issuer()?)
+            .context("Failed to sign device certificate")?;
+
+        let issued_at = Utc::now();
+        let record = DeviceRecord {
+            serial: hex(&serial),
+            name: name.to_string(),
+            issued_at,
+            expires_at: issued_at + chrono::Duration::days(validity_days.into()),
+            revoked_at: None,
+        };
+        {
+            let mut devices = self.devices.lock().unwrap();
+            devices.push(record.clone());
+            self.save(&devices)?;
+        }
+        info!(serial = %record.serial, "Issued device certificate for {}", name);
+
+        Ok(IssuedDevice {
+            record,
+            cert_pem: cert.pem(),
+            key_pem: key.serialize_pem(),
+            ca_pem: self.ca_cert_pem.clone(),
+        })
+    }
+
+    /// Revoke the certificate with hex `serial`. Returns false if unknown.
+    pub fn revoke(&self, serial: &str) -> Result<bool> {
+        let mut devices = self.devices.lock().unwrap();
+        let Some(record) = devices
+            .iter_mut()
+            .find(|d| d.serial.eq_ignore_ascii_case(serial))
+        else {
+            return Ok(false);
+        };
+        if record.revoked_at.is_none() {
+            record.revoked_at = Some(Utc::now());
+            info!(serial = %record.serial, "Revoked device certificate for {}", record.name);
+            self.save(&devices)?;
+        }
+        Ok(true)
+    }
+
+    /// Verifier accepting this CA's unrevoked certificates, or `None` while
+    /// no device holds a valid one (client auth is then not offered at all).
+    pub fn verifier(&self) -> Result<Option<Arc<dyn ClientCertVerifier>>> {
+        let devices = self.devices.lock().unwrap();
+        if !devices.iter().any(DeviceRecord::is_active) {
+            return Ok(None);
+        }
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(self.ca_cert_der.clone())
+            .context("Failed to add device CA to root store")?;
+
+        let now = time::OffsetDateTime::now_utc();
+        let revoked_certs = devices
+            .iter()
+            .filter_map(|d| {
+                let revoked_at = d.revoked_at?;
+                Some(RevokedCertParams {
+                    serial_number: SerialNumber::from_slice(&unhex(&d.serial)?),
+                    revocation_time: time::OffsetDateTime::from_unix_timestamp(
+                        revoked_at.timestamp(),
+                    )
+                    .ok()?,
+                    reason_code: Some(RevocationReason::CessationOfOperation),
+                    invalidity_date: None,
+                })
+            })
+            .collect();
+        let crl = CertificateRevocationListParams {
+            this_update: now,
+            next_update: now + time::Duration::days(30),
+            crl_number: SerialNumber::from(now.unix_timestamp() as u64),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&self.issuer()?)
+        .context("Failed to sign device CRL")?;
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .with_crls([crl.der().clone()])
+            .allow_unauthenticated()
+            .build()
+            .context("Failed to build client certificate verifier")?;
+        Ok(Some(verifier))
+    }
+
+    fn save(&self, devices: &[DeviceRecord]) -> Result<()> {
+        let path = self.dir.join(DEVICES_FILE);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(devices)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Verified client certificate, attached to requests as an extension by the
+/// TLS listeners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertIdentity {
+    /// Full subject DN (e.g. "CN=alice-phone, O=HomeRoute Devices")
+    pub subject: String,
+    pub common_name: String,
+    /// Hex serial number
+    pub serial: String,
+}
+
+impl ClientCertIdentity {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .unwrap_or("")
+            .to_string();
+        Some(Self {
+            subject: cert.subject().to_string(),
+            common_name,
+            serial: hex(cert.tbs_certificate.raw_serial()),
+        })
+    }
+
+    /// Identity of the first certificate a TLS peer presented.
+    pub fn from_peer(certs: Option<&[CertificateDer<'_>]>) -> Option<Self> {
+        certs?.first().and_then(|c| Self::from_der(c))
+    }
+}
+
+fn generate_ca() -> Result<(String, String)> {
+    let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
+        .context("Failed to generate device CA key pair")?;
+    let mut params =
+        CertificateParams::new(Vec::<String>::new()).context("Failed to create CA params")?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "HomeRoute Device CA");
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, DEVICE_ORG);
+    params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    params.not_before = time::OffsetDateTime::now_utc();
+    params.not_after = time::OffsetDateTime::now_utc() + time::Duration::days(CA_VALIDITY_DAYS);
+    let cert = params
+        .self_signed(&key)
+        .context("Failed to self-sign device CA")?;
+    Ok((cert.pem(), key.serialize_pem()))
+}
+
+/// Write a file readable by the owner only.
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// 16 random bytes, positive and without a leading zero byte so that the
+/// DER encoding round-trips to the same hex string.
+fn random_serial() -> [u8; 16] {
+    let mut bytes = *uuid::Uuid::new_v4().as_bytes();
+    bytes[0] = (bytes[0] & 0x7f) | 0x40;
+    bytes
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::UnixTime;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hr-device-ca-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn der(pem: &str) -> CertificateDer<'static> {
+        rustls_pemfile::certs(&mut pem.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_issue_verify_revoke() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = temp_dir("revoke");
+        let ca = DeviceCa::open_or_create(&dir).unwrap();
+        assert!(ca.verifier().unwrap().is_none());
+
+        let phone = ca.issue("alice-phone", 365).unwrap();
+        let laptop = ca.issue("alice-laptop", 365).unwrap();
+
+        let identity = ClientCertIdentity::from_der(&der(&phone.cert_pem)).unwrap();
+        assert_eq!(identity.common_name, "alice-phone");
+        assert_eq!(identity.serial, phone.record.serial);
+        assert!(identity.subject.contains("HomeRoute Devices"));
+
+        let verifier = ca.verifier().unwrap().unwrap();
+        assert!(!verifier.client_auth_mandatory());
+        let now = UnixTime::now();
+        assert!(
+            verifier
+                .verify_client_cert(&der(&phone.cert_pem), &[], now)
+                .is_ok()
+        );
+
+        assert!(ca.revoke(&phone.record.serial).unwrap());
+        assert!(!ca.revoke("00ff").unwrap());
+        let verifier = ca.verifier().unwrap().unwrap();
+        assert!(
+            verifier
+                .verify_client_cert(&der(&phone.cert_pem), &[], now)
+                .is_err()
+        );
+        assert!(
+            verifier
+                .verify_client_cert(&der(&laptop.cert_pem), &[], now)
+                .is_ok()
+        );
+
+        // The registry and CA survive a reload
+        let reopened = DeviceCa::open_or_create(&dir).unwrap();
+        assert_eq!(reopened.ca_pem(), ca.ca_pem());
+        assert_eq!(reopened.list().len(), 2);
+        assert!(reopened.list()[0].revoked_at.is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_certificate_rejected() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = temp_dir("foreign");
+        let ca = DeviceCa::open_or_create(&dir).unwrap();
+        ca.issue("tablet", 30).unwrap();
+
+        let other_dir = temp_dir("other");
+        let other = DeviceCa::open_or_create(&other_dir).unwrap();
+        let foreign = other.issue("intruder", 30).unwrap();
+
+        let verifier = ca.verifier().unwrap().unwrap();
+        assert!(
+            verifier
+                .verify_client_cert(&der(&foreign.cert_pem), &[], UnixTime::now())
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&other_dir).unwrap();
+    }
+}
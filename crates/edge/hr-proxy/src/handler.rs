@@ -1,12 +1,16 @@
 use axum::{
     body::Body,
     extract::Request,
-    http::{HeaderValue, StatusCode, Uri},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
     response::{IntoResponse, Response},
 };
 use futures_util::TryStreamExt;
 use hr_auth::AuthService;
-use hr_auth::forward_auth::{ForwardAuthResult, check_forward_auth};
+use hr_auth::forward_auth::{
+    ForwardAuthHeaders, ForwardAuthResult, MachineAuthResult, MachineCredential,
+    check_forward_auth, check_machine_auth,
+};
+use hr_auth::users::UserInfo;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 use hyper_util::rt::TokioIo;
@@ -15,9 +19,29 @@ use std::sync::{Arc, RwLock};
 use tokio::net::TcpStream;
 use tracing::{debug, error, info, warn};
 
-use crate::config::{ProxyConfig, RouteConfig};
+use crate::access_index::AccessLogIndex;
+use crate::acl::{self, Acl};
+use crate::backend_tls::{self, BackendTls};
+use crate::cache::{self, CacheEntry, HttpCache, RequestPolicy};
+use crate::canary::TrafficSplit;
+use crate::compress;
+use crate::config::{
+    AccessList, BackendTlsConfig, BackendTlsVerify, ClientCertMode, CompressionConfig, HeaderRules,
+    MachineAuthConfig, ProxyConfig, RateLimitConfig, RouteCacheConfig, RouteConfig, RouteHeaders,
+    UpstreamConfig,
+};
+use crate::device_ca::ClientCertIdentity;
+use crate::headers::{self, ResolvedHeaders, TemplateContext};
 use crate::logging::{self, AccessLogEntry, OptionalAccessLogger};
-use crate::metrics::ProxyMetrics;
+use crate::maintenance::MaintenanceStore;
+use crate::metrics::{GaugeGuard, ProxyMetrics};
+use crate::pages::{self, ErrorPages, PageVars};
+use crate::pool::{MemberGuard, Pool, PoolManager};
+use crate::ratelimit::{self, LimitExceeded, LimitGuard, RateLimiter};
+use crate::redirect::{RedirectEngine, RedirectMatch, RedirectOutcome};
+use crate::rules::{self, CompiledRule, RouteTarget};
+use crate::stream::StreamManager;
+use crate::upstream::{self, CircuitBreakers, Replay, UpstreamClients};
 
 /// Route to an agent-managed application (LXC container).
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -29,23 +53,123 @@ pub struct AppRoute {
     pub auth_required: bool,
     pub allowed_groups: Vec<String>,
     pub local_only: bool,
+    /// Client address allow/deny lists for this app.
+    #[serde(default)]
+    pub acl: AccessList,
+    /// Client certificate (device CA) requirement for this app.
+    #[serde(default)]
+    pub client_cert: ClientCertMode,
+    /// Certificate verification for re-encrypted (port 443) agent backends.
+    #[serde(default)]
+    pub backend_tls: BackendTlsConfig,
+    /// Backend pool (from the proxy config) replacing `target_ip:target_port`.
+    #[serde(default)]
+    pub pool: Option<String>,
+    /// Request rate and concurrency limits for this app.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Request and response header rules for this app.
+    #[serde(default)]
+    pub headers: RouteHeaders,
+    /// Weighted targets replacing `target_ip:target_port` (canary releases).
+    #[serde(default)]
+    pub split: Option<TrafficSplit>,
+    /// API keys and basic credentials accepted besides the session cookie.
+    #[serde(default)]
+    pub machine_auth: Option<MachineAuthConfig>,
+    /// Timeouts, retries and circuit breaking (default: the global `upstream`).
+    #[serde(default)]
+    pub upstream: Option<UpstreamConfig>,
 }
 
 /// Snapshot of parsed config for fast lookups
 struct ConfigSnapshot {
     config: ProxyConfig,
+    /// Compiled path rules keyed by route id
+    rules: std::collections::HashMap<String, Vec<CompiledRule>>,
+    /// Custom error page templates
+    pages: ErrorPages,
+    /// Domain aliases and redirect/rewrite rules
+    redirects: RedirectEngine,
+    /// Compiled access lists: static routes on load, app routes on first use
+    acls: RwLock<std::collections::HashMap<AccessList, Arc<Acl>>>,
+}
+
+impl ConfigSnapshot {
+    fn new(config: ProxyConfig) -> Self {
+        for route in &config.routes {
+            for name in headers::unknown_policies(&config.header_policies, &route.headers) {
+                warn!("Route {}: unknown header policy '{}'", route.domain, name);
+            }
+            for entry in acl::unknown_entries(&route.acl, &config.ip_lists) {
+                warn!("Route {}: unknown ACL entry '{}'", route.domain, entry);
+            }
+            if let Some(tls) = &route.backend_tls
+                && tls.verify == BackendTlsVerify::Insecure
+            {
+                warn!(
+                    "Route {}: backend certificate verification disabled",
+                    route.domain
+                );
+            }
+        }
+        for stream in &config.streams {
+            for entry in acl::unknown_entries(&stream.acl, &config.ip_lists) {
+                warn!("Stream {}: unknown ACL entry '{}'", stream.id, entry);
+            }
+        }
+        for (list, entry) in acl::invalid_list_entries(&config.ip_lists) {
+            warn!("IP list {}: invalid address '{}'", list, entry);
+        }
+        let rules = config
+            .routes
+            .iter()
+            .filter(|r| r.enabled && !r.rules.is_empty())
+            .map(|r| (r.id.clone(), rules::compile_rules(r)))
+            .collect();
+        let pages = ErrorPages::load(config.error_pages_dir.as_deref());
+        let redirects = RedirectEngine::compile(&config.domain_aliases, &config.redirects);
+        let acls = config
+            .routes
+            .iter()
+            .filter(|r| r.enabled && !r.acl.is_empty())
+            .map(|r| {
+                (
+                    r.acl.clone(),
+                    Arc::new(Acl::compile(&r.acl, &config.ip_lists)),
+                )
+            })
+            .collect();
+        Self {
+            config,
+            rules,
+            pages,
+            redirects,
+            acls: RwLock::new(acls),
+        }
+    }
+
+    /// Compiled form of `list`, compiling and caching it if needed.
+    fn acl(&self, list: &AccessList) -> Arc<Acl> {
+        if let Some(acl) = self.acls.read().unwrap().get(list) {
+            return acl.clone();
+        }
+        let acl = Arc::new(Acl::compile(list, &self.config.ip_lists));
+        self.acls.write().unwrap().insert(list.clone(), acl.clone());
+        acl
+    }
 }
 
 /// Shared proxy state with reloadable config
 pub struct ProxyState {
     /// HTTP client for backend requests
     pub client: Client<hyper_util::client::legacy::connect::HttpConnector, Body>,
-    /// HTTPS client for re-encrypt backend requests (skips cert verification for trusted LAN)
-    pub https_client: reqwest::Client,
     /// Reloadable configuration snapshot
     snapshot: RwLock<ConfigSnapshot>,
     /// Access logger
     pub access_logger: OptionalAccessLogger,
+    /// Queryable copy of the access log.
+    pub access_index: Option<AccessLogIndex>,
     /// Auth service (direct call, no HTTP round-trip)
     pub auth: Option<Arc<AuthService>>,
     /// Management API port for proxy.{base_domain} and auth.{base_domain}
@@ -58,32 +182,57 @@ pub struct ProxyState {
     app_routes_path: Option<std::path::PathBuf>,
     /// Per-domain request/error counters.
     pub metrics: ProxyMetrics,
+    /// Backend pools with their members' health state.
+    pub pools: Arc<PoolManager>,
+    /// Per-route rate limit buckets and connection counters.
+    pub limiter: Arc<RateLimiter>,
+    /// Response cache shared by routes with a `cache` section.
+    pub cache: HttpCache,
+    /// TLS configs for HTTPS backends and their handshake status.
+    pub backend_tls: BackendTls,
+    /// Domains in maintenance mode.
+    pub maintenance: MaintenanceStore,
+    /// TCP/UDP stream routes and TLS passthrough.
+    pub streams: Arc<StreamManager>,
+    /// Plain-HTTP backend clients by connect timeout.
+    upstream_clients: UpstreamClients,
+    /// Per-backend circuit breakers.
+    pub breakers: CircuitBreakers,
+    /// App domains whose process is starting (from the app supervisor).
+    starting_apps: RwLock<std::collections::HashSet<String>>,
 }
 
 impl ProxyState {
     pub fn new(config: ProxyConfig, management_port: u16, orchestrator_port: u16) -> Self {
         let client = Client::builder(TokioExecutor::new()).build_http();
 
-        // HTTPS client for re-encrypt to agent backends on port 443.
-        // Skip certificate verification: agents are on a trusted LAN.
-        let https_client = reqwest::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .expect("Failed to build HTTPS client");
+        let access_logger =
+            OptionalAccessLogger::new(config.access_log_path.clone(), &config.access_log);
 
-        let access_logger = OptionalAccessLogger::new(config.access_log_path.clone());
+        let pools = Arc::new(PoolManager::new());
+        pools.reconcile(&config.pools);
+        let cache = HttpCache::new(&config.cache);
 
         Self {
             client,
-            https_client,
-            snapshot: RwLock::new(ConfigSnapshot { config }),
+            snapshot: RwLock::new(ConfigSnapshot::new(config)),
             access_logger,
+            access_index: None,
             auth: None,
             management_port,
             orchestrator_port,
             app_routes: RwLock::new(std::collections::HashMap::new()),
             app_routes_path: None,
             metrics: ProxyMetrics::new(),
+            pools,
+            limiter: Arc::new(RateLimiter::new()),
+            cache,
+            backend_tls: BackendTls::new(),
+            maintenance: MaintenanceStore::new(),
+            streams: Arc::new(StreamManager::new()),
+            upstream_clients: UpstreamClients::default(),
+            breakers: CircuitBreakers::default(),
+            starting_apps: RwLock::new(std::collections::HashSet::new()),
         }
     }
 
@@ -125,6 +274,33 @@ impl ProxyState {
         self
     }
 
+    /// Index access log entries in the SQLite database at `path`, unless
+    /// disabled by `access_log.index`.
+    pub fn with_access_index(mut self, path: std::path::PathBuf) -> Self {
+        let config = self.config().access_log.clone();
+        if !config.index {
+            return self;
+        }
+        match AccessLogIndex::open(&path, config.index_retention_days) {
+            Ok(index) => self.access_index = Some(index),
+            Err(e) => warn!("Failed to open access log index {}: {}", path.display(), e),
+        }
+        self
+    }
+
+    /// Set the path persisting maintenance mode, loading it if present.
+    pub fn with_maintenance_path(mut self, path: std::path::PathBuf) -> Self {
+        self.maintenance = MaintenanceStore::with_path(path);
+        self
+    }
+
+    /// Start the configured stream routes (listeners need a Tokio runtime).
+    pub fn start_streams(&self) {
+        let snapshot = self.snapshot.read().unwrap();
+        self.streams
+            .reconcile(&snapshot.config.streams, &snapshot.config.ip_lists);
+    }
+
     /// Persist current app routes to disk (best-effort, non-blocking for callers).
     fn persist_app_routes(&self) {
         if let Some(ref path) = self.app_routes_path {
@@ -144,8 +320,266 @@ impl ProxyState {
 
     /// Reload the proxy config (called on SIGHUP)
     pub fn reload_config(&self, new_config: ProxyConfig) {
-        let mut snapshot = self.snapshot.write().unwrap();
-        snapshot.config = new_config;
+        self.pools.reconcile(&new_config.pools);
+        self.cache.reconfigure(&new_config.cache);
+        // CA files may have changed
+        self.backend_tls.clear();
+        self.streams
+            .reconcile(&new_config.streams, &new_config.ip_lists);
+        let members = new_config
+            .pools
+            .iter()
+            .flat_map(|p| {
+                p.members
+                    .iter()
+                    .map(|m| (p.name.clone(), format!("{}:{}", m.host, m.port)))
+            })
+            .collect();
+        self.metrics.retain_backends(&members);
+        let snapshot = ConfigSnapshot::new(new_config);
+        *self.snapshot.write().unwrap() = snapshot;
+    }
+
+    /// Timeouts, retries and breaker of a route: its own `upstream` section,
+    /// or the global one.
+    fn upstream_policy(&self, route: Option<&UpstreamConfig>) -> UpstreamConfig {
+        match route {
+            Some(policy) => policy.clone(),
+            None => self.snapshot.read().unwrap().config.upstream.clone(),
+        }
+    }
+
+    /// Pick a member of the named pool for this request, avoiding members
+    /// whose circuit is open while others are available.
+    fn select_backend(
+        &self,
+        pool_name: &str,
+        req: &Request,
+        client_ip: IpAddr,
+    ) -> Result<(Arc<Pool>, MemberGuard), ProxyError> {
+        let pool = self
+            .pools
+            .get(pool_name)
+            .ok_or_else(|| ProxyError::NoHealthyBackend(pool_name.to_string()))?;
+        let guard = pool
+            .select_filtered(req.headers(), client_ip, |m| {
+                !self.breakers.is_open(&m.address())
+            })
+            .or_else(|| pool.select(req.headers(), client_ip))
+            .ok_or_else(|| ProxyError::NoHealthyBackend(pool_name.to_string()))?;
+        Ok((pool, guard))
+    }
+
+    /// Feed the outcome of a pooled request back to passive health and metrics.
+    fn report_backend(&self, picked: &Option<(Arc<Pool>, MemberGuard)>, success: bool) {
+        if let Some((pool, guard)) = picked {
+            pool.report(&guard.member, success);
+            self.metrics
+                .record_backend(&pool.config.name, &guard.member.address(), success);
+        }
+    }
+
+    /// Apply a route's rate limit to `req`, returning the guard that holds
+    /// its concurrency slots.
+    fn check_limits(
+        &self,
+        scope: &str,
+        limit: Option<&RateLimitConfig>,
+        req: &Request,
+        user: Option<&str>,
+        client_ip: IpAddr,
+        websocket: bool,
+    ) -> Result<Option<LimitGuard>, ProxyError> {
+        let Some(limit) = limit else {
+            return Ok(None);
+        };
+        let key = ratelimit::client_key(&limit.key, req.headers(), user, client_ip);
+        match self.limiter.check(scope, limit, &key, websocket) {
+            Ok(guard) => Ok(Some(guard)),
+            Err(exceeded) => {
+                debug!(
+                    "{} limit hit on {} for {}",
+                    exceeded.kind.as_str(),
+                    scope,
+                    key
+                );
+                Err(ProxyError::RateLimited(exceeded))
+            }
+        }
+    }
+
+    /// Check `client_ip` against a route's access list.
+    fn check_acl(
+        &self,
+        domain: &str,
+        list: &AccessList,
+        client_ip: IpAddr,
+    ) -> Result<(), ProxyError> {
+        if list.is_empty() {
+            return Ok(());
+        }
+        let compiled = self.snapshot.read().unwrap().acl(list);
+        compiled.check(client_ip).map_err(|denied| {
+            warn!(
+                "ACL denied {} for {} (rule: {})",
+                client_ip, domain, denied.rule
+            );
+            ProxyError::AclDenied(denied.rule)
+        })
+    }
+
+    /// TLS client config for a route's HTTPS backend. Invalid settings
+    /// (e.g. an unreadable CA file) count as a failed handshake.
+    fn backend_tls_config(
+        &self,
+        domain: &str,
+        tls: &BackendTlsConfig,
+    ) -> Result<Arc<rustls::ClientConfig>, ProxyError> {
+        self.backend_tls.client_config(tls).map_err(|e| {
+            let reason = format!("{:#}", e);
+            warn!(
+                "Backend TLS config for {} (verify: {}) is invalid: {}",
+                domain,
+                tls.verify.as_str(),
+                reason
+            );
+            self.backend_tls
+                .record(domain, tls.verify, Some(reason.clone()));
+            ProxyError::BackendTls(reason)
+        })
+    }
+
+    /// reqwest client reaching `host:port` over TLS as `server_name`.
+    async fn backend_https_client(
+        &self,
+        domain: &str,
+        tls: &BackendTlsConfig,
+        server_name: &str,
+        (host, port): (&str, u16),
+        connect_timeout_ms: u64,
+    ) -> Result<reqwest::Client, ProxyError> {
+        let config = self.backend_tls_config(domain, tls)?;
+        let addr = tokio::net::lookup_host((host, port))
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| ProxyError::UpstreamError(format!("Cannot resolve {}", host)))?;
+        let connect_timeout =
+            (connect_timeout_ms > 0).then(|| std::time::Duration::from_millis(connect_timeout_ms));
+        backend_tls::reqwest_client(&config, server_name, addr, connect_timeout)
+            .map_err(|e| ProxyError::UpstreamError(format!("{:#}", e)))
+    }
+
+    /// Record how the TLS handshake with a route's backend went. Errors
+    /// other than a rejected handshake say nothing about TLS and are ignored.
+    fn report_backend_tls(
+        &self,
+        domain: &str,
+        tls: &BackendTlsConfig,
+        result: &Result<Response, ProxyError>,
+    ) {
+        match result {
+            Ok(_) => self.backend_tls.record(domain, tls.verify, None),
+            Err(ProxyError::BackendTls(reason)) => {
+                warn!(
+                    "Backend TLS verification failed for {} (verify: {}): {}",
+                    domain,
+                    tls.verify.as_str(),
+                    reason
+                );
+                self.backend_tls
+                    .record(domain, tls.verify, Some(reason.clone()));
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Serve the maintenance page unless `domain` is not in maintenance or
+    /// `client_ip` is on its allow list.
+    fn check_maintenance(&self, domain: &str, client_ip: IpAddr) -> Result<(), ProxyError> {
+        let Some(mode) = self.maintenance.get(domain) else {
+            return Ok(());
+        };
+        let allowed = {
+            let snapshot = self.snapshot.read().unwrap();
+            mode.allows(client_ip, &snapshot.config.ip_lists)
+        };
+        if allowed {
+            debug!("Maintenance bypass for {} from {}", domain, client_ip);
+            return Ok(());
+        }
+        Err(ProxyError::Maintenance(mode.message))
+    }
+
+    /// Record an app's process state as reported by the app supervisor.
+    /// Requests to a starting app get an auto-refreshing "starting" page.
+    pub fn set_app_state(&self, domain: &str, state: &str) {
+        let mut starting = self.starting_apps.write().unwrap();
+        if state == "starting" {
+            starting.insert(domain.to_string());
+        } else {
+            starting.remove(domain);
+        }
+    }
+
+    fn is_app_starting(&self, domain: &str) -> bool {
+        self.starting_apps.read().unwrap().contains(domain)
+    }
+
+    /// Turn a proxy error into its response, as an HTML page (custom
+    /// template or built-in) for browsers.
+    fn error_response(&self, err: ProxyError, domain: &str, html: bool) -> Response {
+        let status = err.status_code();
+        let page = match &err {
+            ProxyError::Maintenance(_) => "maintenance",
+            ProxyError::AppStarting => "starting",
+            _ => status.as_str(),
+        };
+        let title = match &err {
+            ProxyError::Maintenance(_) => "Maintenance in progress",
+            ProxyError::AppStarting => "Starting up",
+            _ => status.canonical_reason().unwrap_or("Error"),
+        };
+        let message = err.public_message();
+        let mut resp = err.into_response();
+        if !html || !pages::PAGE_NAMES.contains(&page) {
+            return resp;
+        }
+        let body = {
+            let snapshot = self.snapshot.read().unwrap();
+            snapshot.pages.render(
+                page,
+                &PageVars {
+                    status: status.as_u16(),
+                    title,
+                    message: &message,
+                    domain,
+                },
+            )
+        };
+        resp.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        resp.headers_mut().remove(header::CONTENT_LENGTH);
+        *resp.body_mut() = Body::from(body);
+        resp
+    }
+
+    /// Whether connections from `peer` start with a PROXY protocol line
+    /// carrying the real client address.
+    pub fn is_trusted_relay(&self, peer: IpAddr) -> bool {
+        let snapshot = self.snapshot.read().unwrap();
+        acl::is_trusted_relay(&snapshot.config.trusted_relays, peer)
+    }
+
+    /// Expand a route's header policies against the current config.
+    pub fn resolve_headers(&self, route_headers: &RouteHeaders) -> ResolvedHeaders {
+        if route_headers.is_empty() {
+            return ResolvedHeaders::default();
+        }
+        let snapshot = self.snapshot.read().unwrap();
+        headers::resolve(&snapshot.config.header_policies, route_headers)
     }
 
     /// Find the route matching a given Host header
@@ -160,6 +594,72 @@ impl ProxyState {
             .cloned()
     }
 
+    /// Find the route for a Host header and resolve its path rules into the
+    /// backend for `path_and_query`.
+    pub fn find_route_target(
+        &self,
+        host: &str,
+        path_and_query: &str,
+    ) -> Option<(RouteConfig, RouteTarget)> {
+        let domain = host.split(':').next().unwrap_or(host);
+        let snapshot = self.snapshot.read().unwrap();
+        let route = snapshot
+            .config
+            .routes
+            .iter()
+            .find(|r| r.enabled && r.domain == domain)?;
+        let compiled = snapshot
+            .rules
+            .get(&route.id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let target = rules::resolve(route, compiled, path_and_query);
+        Some((route.clone(), target))
+    }
+
+    /// The domain alias or redirect rule hit by a request, if any.
+    pub fn match_redirect(&self, host: &str, path_and_query: &str) -> Option<RedirectMatch> {
+        let snapshot = self.snapshot.read().unwrap();
+        if snapshot.redirects.is_empty() {
+            return None;
+        }
+        snapshot.redirects.evaluate(host, path_and_query)
+    }
+
+    /// Which alias or redirect rule a URL (`https://host/path?query`) hits.
+    pub fn test_redirect(&self, url: &str) -> anyhow::Result<Option<RedirectMatch>> {
+        let uri: Uri = url.parse()?;
+        let host = uri
+            .host()
+            .ok_or_else(|| anyhow::anyhow!("URL has no host: {}", url))?;
+        let path_and_query = uri.path_and_query().map_or("/", |pq| pq.as_str());
+        Ok(self.match_redirect(host, path_and_query))
+    }
+
+    /// `Alt-Svc` value for a response, `None` to leave it unset.
+    ///
+    /// Requests relayed by Cloudflare (`CF-Ray`) get no advertisement from
+    /// us: Cloudflare announces its own h3 endpoint, which is what the
+    /// client reaches on the WAN. Clients talking to us directly get our h3
+    /// endpoint when the QUIC listener is on, and `clear` otherwise so that
+    /// h3 entries cached from Cloudflare are not tried against the LAN
+    /// address (ERR_QUIC_PROTOCOL_ERROR when roaming from WAN to LAN).
+    pub fn alt_svc(&self, via_cloudflare: bool) -> Option<HeaderValue> {
+        if via_cloudflare {
+            return None;
+        }
+        let snapshot = self.snapshot.read().unwrap();
+        let config = &snapshot.config;
+        if !config.http3_enabled {
+            return Some(HeaderValue::from_static("clear"));
+        }
+        HeaderValue::from_str(&format!(
+            "h3=\":{}\"; ma={}",
+            config.https_port, config.alt_svc_max_age
+        ))
+        .ok()
+    }
+
     /// Get the base domain
     pub fn base_domain(&self) -> String {
         let snapshot = self.snapshot.read().unwrap();
@@ -172,15 +672,115 @@ impl ProxyState {
         snapshot.config.clone()
     }
 
-    /// Add an application route: domain → AppRoute
-    pub fn set_app_route(&self, domain: String, route: AppRoute) {
+    /// Add an application route: domain → AppRoute. The app supervisor
+    /// re-registers routes with their target only, so a setting left unset
+    /// (at its default) keeps the route's current one: access list, client
+    /// certificate mode, backend pool, rate limit, header rules, backend
+    /// certificate verification, machine credentials and upstream policy, and
+    /// the split in progress when there is no pool.
+    pub fn set_app_route(&self, domain: String, mut route: AppRoute) {
+        {
+            let snapshot = self.snapshot.read().unwrap();
+            let policies = &snapshot.config.header_policies;
+            for name in headers::unknown_policies(policies, &route.headers) {
+                warn!("App route {}: unknown header policy '{}'", domain, name);
+            }
+            for entry in acl::unknown_entries(&route.acl, &snapshot.config.ip_lists) {
+                warn!("App route {}: unknown ACL entry '{}'", domain, entry);
+            }
+        }
+        if route.backend_tls.verify == BackendTlsVerify::Insecure {
+            warn!(
+                "App route {}: backend certificate verification disabled",
+                domain
+            );
+        }
         let mut map = self.app_routes.write().unwrap();
+        if let Some(current) = map.get(&domain) {
+            keep_unset(&mut route.acl, &current.acl);
+            keep_unset(&mut route.client_cert, &current.client_cert);
+            keep_unset(&mut route.pool, &current.pool);
+            keep_unset(&mut route.rate_limit, &current.rate_limit);
+            keep_unset(&mut route.headers, &current.headers);
+            keep_unset(&mut route.backend_tls, &current.backend_tls);
+            keep_unset(&mut route.machine_auth, &current.machine_auth);
+            keep_unset(&mut route.upstream, &current.upstream);
+        }
+        if route.split.is_none() && route.pool.is_none() {
+            route.split = map.get(&domain).and_then(|r| r.split.clone());
+        }
         info!(domain = domain, target = %route.target_ip, port = route.target_port, "Added app route");
         map.insert(domain, route);
         drop(map);
         self.persist_app_routes();
     }
 
+    /// Split an app route's traffic between weighted targets, or end the
+    /// split (`None`) to send everything back to its primary target.
+    pub fn set_app_split(&self, domain: &str, split: Option<TrafficSplit>) -> anyhow::Result<()> {
+        if let Some(ref split) = split {
+            split.validate()?;
+        }
+        let mut map = self.app_routes.write().unwrap();
+        let route = map
+            .get_mut(domain)
+            .ok_or_else(|| anyhow::anyhow!("No app route for {}", domain))?;
+        if split.is_some() && route.pool.is_some() {
+            anyhow::bail!("{} uses a backend pool", domain);
+        }
+        match &split {
+            Some(split) => {
+                let weights: Vec<String> = split
+                    .targets
+                    .iter()
+                    .map(|t| format!("{}={}", t.name, t.weight))
+                    .collect();
+                info!(domain = domain, weights = %weights.join(","), "App traffic split");
+            }
+            None => info!(domain = domain, "App traffic split removed"),
+        }
+        route.split = split;
+        drop(map);
+        self.persist_app_routes();
+        Ok(())
+    }
+
+    /// Change the weights of some targets of an app route's split.
+    pub fn set_app_weights(
+        &self,
+        domain: &str,
+        weights: &std::collections::HashMap<String, u32>,
+    ) -> anyhow::Result<TrafficSplit> {
+        let mut split = self
+            .get_app_route(domain)
+            .and_then(|r| r.split)
+            .ok_or_else(|| anyhow::anyhow!("{} has no traffic split", domain))?;
+        split.set_weights(weights)?;
+        self.set_app_split(domain, Some(split.clone()))?;
+        Ok(split)
+    }
+
+    /// Make target `name` of the split the app's only backend.
+    pub fn promote_app_target(&self, domain: &str, name: &str) -> anyhow::Result<()> {
+        let mut map = self.app_routes.write().unwrap();
+        let route = map
+            .get_mut(domain)
+            .ok_or_else(|| anyhow::anyhow!("No app route for {}", domain))?;
+        let target = route
+            .split
+            .as_ref()
+            .and_then(|s| s.target(name))
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("{} has no target '{}'", domain, name))?;
+        route.target_ip = target.target_ip;
+        route.target_port = target.target_port;
+        route.split = None;
+        info!(domain = domain, target = name, "Promoted app target");
+        drop(map);
+        self.persist_app_routes();
+        Ok(())
+    }
+
     /// Return a snapshot of all current app routes.
     pub fn list_app_routes(&self) -> std::collections::HashMap<String, AppRoute> {
         self.app_routes.read().unwrap().clone()
@@ -197,6 +797,32 @@ impl ProxyState {
         }
     }
 
+    /// Metrics label for a request's host (without port): the configured
+    /// domain it matched, or `unknown`, so arbitrary Host headers cannot
+    /// create new series. Empty for requests without a Host.
+    fn metric_label(&self, domain: &str) -> String {
+        if domain.is_empty() {
+            return String::new();
+        }
+        let known = self.app_routes.read().unwrap().contains_key(domain) || {
+            let snapshot = self.snapshot.read().unwrap();
+            let config = &snapshot.config;
+            ["proxy", "auth", "dv"]
+                .iter()
+                .any(|sub| domain == format!("{}.{}", sub, config.base_domain))
+                || config
+                    .routes
+                    .iter()
+                    .any(|r| r.enabled && r.domain == domain)
+                || config.domain_aliases.iter().any(|a| a.from == domain)
+        };
+        if known {
+            domain.to_string()
+        } else {
+            "unknown".to_string()
+        }
+    }
+
     /// Look up an application route for a given domain.
     pub fn get_app_route(&self, domain: &str) -> Option<AppRoute> {
         let map = self.app_routes.read().unwrap();
@@ -208,9 +834,11 @@ impl ProxyState {
 pub async fn proxy_handler(
     state: Arc<ProxyState>,
     client_ip: IpAddr,
-    req: Request,
+    mut req: Request,
 ) -> Result<Response, ProxyError> {
     let start = std::time::Instant::now();
+    // IPv4 clients of the dual-stack listener arrive as `::ffff:a.b.c.d`
+    let client_ip = client_ip.to_canonical();
 
     // Extract info for logging before passing ownership
     let method = req.method().to_string();
@@ -219,65 +847,158 @@ pub async fn proxy_handler(
         .path_and_query()
         .map(|pq| pq.to_string())
         .unwrap_or_else(|| "/".to_string());
+    let protocol = format!("{:?}", req.version());
     let user_agent = req
         .headers()
         .get("user-agent")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
         .to_string();
+    let referer = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let logged_user = LoggedUser::default();
+    req.extensions_mut().insert(logged_user.clone());
+    let stripped_param = StrippedParam::default();
+    req.extensions_mut().insert(stripped_param.clone());
     let host_for_log = req
         .headers()
         .get("host")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
         .to_string();
+    let alt_svc = state.alt_svc(req.headers().contains_key("cf-ray"));
+    let wants_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+
+    // Per-domain traffic metrics, labelled by the domain the Host matched
+    let host_domain = host_for_log.split(':').next().unwrap_or(&host_for_log);
+    let metric_domain = state.metric_label(host_domain);
+    let in_flight = if metric_domain.is_empty() {
+        None
+    } else {
+        if is_websocket_upgrade(&req) {
+            let gauge = WebSocketGauge {
+                _guard: Arc::new(state.metrics.websocket(&metric_domain)),
+            };
+            req.extensions_mut().insert(gauge);
+        }
+        let req_body = std::mem::take(req.body_mut());
+        *req.body_mut() = state.metrics.count_received(&metric_domain, req_body);
+        Some(state.metrics.in_flight(&metric_domain))
+    };
 
     let result = proxy_handler_inner(state.clone(), client_ip, req).await;
 
     let status = match &result {
         Ok(resp) => resp.status().as_u16(),
-        Err(e) => match e {
-            ProxyError::DomainNotFound(_) => 404,
-            ProxyError::Forbidden => 403,
-            ProxyError::AuthRequired(_) => 302,
-            ProxyError::UpstreamError(_) => 502,
-            ProxyError::InvalidUri(_) => 400,
-        },
+        Err(e) => e.status_code().as_u16(),
+    };
+    let limited = match &result {
+        Err(ProxyError::RateLimited(exceeded)) => Some(exceeded.kind.as_str().to_string()),
+        _ => None,
+    };
+    let denied = match &result {
+        Err(ProxyError::AclDenied(rule)) => Some(rule.clone()),
+        _ => None,
     };
 
-    let duration_ms = start.elapsed().as_millis() as u64;
+    let elapsed = start.elapsed();
+    let duration_ms = elapsed.as_millis() as u64;
 
-    // Record metrics (domain without port)
-    let metric_domain = host_for_log.split(':').next().unwrap_or(&host_for_log);
     if !metric_domain.is_empty() {
-        state.metrics.record_request(metric_domain, status);
+        state
+            .metrics
+            .record_request(&metric_domain, status, elapsed);
+        if limited.is_some() {
+            state.metrics.record_limit_hit(&metric_domain);
+        }
     }
 
-    // Log to file
-    state.access_logger.log(AccessLogEntry {
+    let mut resp = match result {
+        Ok(mut resp) => {
+            match alt_svc {
+                Some(v) => resp.headers_mut().insert("alt-svc", v),
+                None => resp.headers_mut().remove("alt-svc"),
+            };
+            resp
+        }
+        Err(e) => state.error_response(e, host_domain, wants_html),
+    };
+
+    // Log to file and index
+    let entry = AccessLogEntry {
         timestamp: logging::now_timestamp(),
         client_ip: client_ip.to_string(),
         host: host_for_log,
         method,
-        path,
+        path: logged_path(path, &stripped_param),
+        protocol,
         status,
         duration_ms,
         user_agent,
-    });
+        referer,
+        user: logged_user.0.get().cloned(),
+        bytes: resp
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()),
+        limited,
+        denied,
+    };
+    if let Some(ref index) = state.access_index {
+        index.record(entry.clone());
+    }
+    state.access_logger.log(entry);
 
-    // Clear Alt-Svc to prevent QUIC/h3 errors in LAN — Cloudflare advertises
-    // h3 support but our proxy only speaks h1/h2, so cached Alt-Svc entries
-    // cause ERR_QUIC_PROTOCOL_ERROR when clients switch from WAN to LAN.
-    match result {
-        Ok(mut resp) => {
-            resp.headers_mut()
-                .insert("alt-svc", HeaderValue::from_static("clear"));
-            Ok(resp)
-        }
-        err => err,
+    if let Some(guard) = in_flight {
+        let body = std::mem::take(resp.body_mut());
+        *resp.body_mut() = state.metrics.count_sent(&metric_domain, body, guard);
+    }
+    Ok(resp)
+}
+
+/// Username authenticated while handling a request, for the access log.
+#[derive(Clone, Default)]
+struct LoggedUser(Arc<std::sync::OnceLock<String>>);
+
+/// Record the authenticated user of `req` for the access log.
+fn log_user(req: &Request, user: &UserInfo) {
+    if let Some(logged) = req.extensions().get::<LoggedUser>() {
+        let _ = logged.0.set(user.username.clone());
+    }
+}
+
+/// Query parameter a machine credential was taken from, kept out of the
+/// access log.
+#[derive(Clone, Default)]
+struct StrippedParam(Arc<std::sync::OnceLock<String>>);
+
+/// `path` as logged: without the credential query parameter, if any.
+fn logged_path(path: String, stripped: &StrippedParam) -> String {
+    match stripped
+        .0
+        .get()
+        .and_then(|name| take_query_param(&path, name))
+    {
+        Some((_, rest)) => rest,
+        None => path,
     }
 }
 
+/// Open-WebSocket gauge of a request's domain, carried in the request
+/// extensions to the bridging task.
+#[derive(Clone)]
+struct WebSocketGauge {
+    _guard: Arc<GaugeGuard>,
+}
+
 /// Inner proxy handler logic
 async fn proxy_handler_inner(
     state: Arc<ProxyState>,
@@ -314,6 +1035,34 @@ async fn proxy_handler_inner(
         || domain_only == format!("auth.{}", base_domain)
         || is_dv_gateway;
 
+    // Aliases and redirect rules come before routing. Management domains are
+    // exempt so a broad rule can't lock out the dashboard.
+    if !is_management {
+        let path_and_query = req.uri().path_and_query().map_or("/", |pq| pq.as_str());
+        if let Some(hit) = state.match_redirect(domain_only, path_and_query) {
+            debug!(host = domain_only, rule = hit.rule, "Redirect rule matched");
+            match hit.outcome {
+                RedirectOutcome::Redirect { status, location } => {
+                    return Response::builder()
+                        .status(status)
+                        .header(header::LOCATION, location)
+                        .body(Body::empty())
+                        .map_err(|e| ProxyError::InvalidUri(e.to_string()));
+                }
+                RedirectOutcome::Rewrite { path_and_query } => {
+                    let mut parts = req.uri().clone().into_parts();
+                    parts.path_and_query = Some(
+                        path_and_query
+                            .parse()
+                            .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?,
+                    );
+                    *req.uri_mut() = Uri::from_parts(parts)
+                        .map_err(|e| ProxyError::InvalidUri(e.to_string()))?;
+                }
+            }
+        }
+    }
+
     // Opportunistic forward-auth for the dataverse gateway: best-effort
     // session lookup, inject identity headers on success, never redirect.
     if is_dv_gateway {
@@ -330,13 +1079,8 @@ async fn proxy_handler_inner(
             if let Some(session_id) = cookie_value {
                 if let Ok(Some(session)) = auth.sessions.validate(session_id) {
                     if let Some(user) = auth.users.get(&session.user_id) {
-                        if let Ok(v) = HeaderValue::from_str(&user.username) {
-                            req.headers_mut().insert("X-Remote-User", v);
-                        }
-                        let uuid_str = user.uuid.to_string();
-                        if let Ok(v) = HeaderValue::from_str(&uuid_str) {
-                            req.headers_mut().insert("X-Remote-User-Id", v);
-                        }
+                        inject_identity_headers(req.headers_mut(), &user);
+                        log_user(&req, &user);
                     }
                 }
             }
@@ -355,60 +1099,126 @@ async fn proxy_handler_inner(
                 return Err(ProxyError::Forbidden);
             }
 
+            state.check_acl(domain_only, &app_route.acl, client_ip)?;
+            apply_client_cert(app_route.client_cert, &mut req, domain_only)?;
+            state.check_maintenance(domain_only, client_ip)?;
+
+            // Canary splits pick the target before anything depends on it
+            let assignment = match (&app_route.pool, &app_route.split) {
+                (None, Some(split)) => split.select(req.headers()),
+                _ => None,
+            };
+            let (app_ip, app_port) = match &assignment {
+                Some(a) => (a.target.target_ip, a.target.target_port),
+                None => (app_route.target_ip, app_route.target_port),
+            };
             // Agent routes (target_port == 443) handle their own auth — skip forward-auth.
-            // Non-agent routes still need central forward-auth.
-            let is_agent_route = app_route.target_port == 443;
-
-            if !is_agent_route && app_route.auth_required {
-                if let Some(ref auth) = state.auth {
-                    let req_uri = req
-                        .uri()
-                        .path_and_query()
-                        .map(|pq| pq.to_string())
-                        .unwrap_or_else(|| "/".to_string());
-
-                    let cookie_value = req
-                        .headers()
-                        .get("cookie")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(|cookies| {
-                            cookies
-                                .split(';')
-                                .find_map(|c| c.trim().strip_prefix("auth_session="))
-                        });
-
-                    match check_forward_auth(auth, cookie_value, domain_only, &req_uri, "https") {
-                        ForwardAuthResult::Success { user } => {
-                            if let Ok(v) = HeaderValue::from_str(&user.username) {
-                                req.headers_mut().insert("X-Forwarded-User", v.clone());
-                                req.headers_mut().insert("X-Remote-User", v);
-                            }
-                            // Identity uuid for the dataverse gateway —
-                            // populates `created_by` / `updated_by`.
-                            let uuid_str = user.uuid.to_string();
-                            if let Ok(v) = HeaderValue::from_str(&uuid_str) {
-                                req.headers_mut().insert("X-Remote-User-Id", v);
-                            }
-                        }
-                        ForwardAuthResult::Unauthorized { login_url } => {
-                            return Err(ProxyError::AuthRequired(Some(login_url)));
+            // Non-agent routes still need central forward-auth. Pooled routes
+            // always target plain-HTTP members.
+            let is_agent_route = app_route.pool.is_none() && app_port == 443;
+
+            // A group restriction implies authentication and is enforced
+            // here for every route, agent routes included.
+            let needs_auth = (app_route.auth_required && !is_agent_route)
+                || !app_route.allowed_groups.is_empty();
+            let mut auth_user = None;
+
+            if needs_auth && let Some(ref auth) = state.auth {
+                let machine_user = check_machine_credential(
+                    auth,
+                    &mut req,
+                    app_route.machine_auth.as_ref(),
+                    client_ip,
+                    domain_only,
+                    &app_route.allowed_groups,
+                )
+                .await?;
+
+                let req_uri = req
+                    .uri()
+                    .path_and_query()
+                    .map(|pq| pq.to_string())
+                    .unwrap_or_else(|| "/".to_string());
+
+                let cookie_value = req
+                    .headers()
+                    .get("cookie")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|cookies| {
+                        cookies
+                            .split(';')
+                            .find_map(|c| c.trim().strip_prefix("auth_session="))
+                    });
+
+                let result = match machine_user {
+                    Some(user) => ForwardAuthResult::Success { user },
+                    None => check_forward_auth(
+                        auth,
+                        cookie_value,
+                        domain_only,
+                        &req_uri,
+                        "https",
+                        &app_route.allowed_groups,
+                    ),
+                };
+                match result {
+                    ForwardAuthResult::Success { user } => {
+                        if let Ok(v) = HeaderValue::from_str(&user.username) {
+                            req.headers_mut().insert("X-Forwarded-User", v);
                         }
+                        inject_identity_headers(req.headers_mut(), &user);
+                        log_user(&req, &user);
+                        auth_user = Some(user);
+                    }
+                    ForwardAuthResult::Unauthorized { login_url } => {
+                        return Err(ProxyError::AuthRequired(Some(login_url)));
+                    }
+                    ForwardAuthResult::Forbidden { reason, .. } => {
+                        return Err(ProxyError::AccessDenied(reason));
                     }
                 }
             }
 
-            // Determine scheme based on target port (re-encrypt for agent port 443)
-            let scheme = if app_route.target_port == 443 {
-                "https"
-            } else {
-                "http"
-            };
-
-            let target_host_for_url = app_route.target_ip.to_string();
+            // The backend isn't listening yet
+            if state.is_app_starting(domain_only) {
+                return Err(ProxyError::AppStarting);
+            }
 
             // Check for WebSocket upgrade
             let is_websocket = is_websocket_upgrade(&req);
 
+            let limit_guard = state.check_limits(
+                &format!("app:{}", domain_only),
+                app_route.rate_limit.as_ref(),
+                &req,
+                auth_user.as_ref().map(|u| u.username.as_str()),
+                client_ip,
+                is_websocket,
+            )?;
+
+            let ResolvedHeaders {
+                request: request_rules,
+                response: response_rules,
+            } = state.resolve_headers(&app_route.headers);
+            let template = (!request_rules.is_empty() || !response_rules.is_empty())
+                .then(|| template_context(&req, auth_user.as_ref(), client_ip));
+            let finish = ResponseFinish {
+                compression: None,
+                accept_encoding: None,
+                head: false,
+                response_rules,
+                template: template.clone(),
+            };
+
+            let picked = match app_route.pool.as_deref() {
+                Some(name) => Some(state.select_backend(name, &req, client_ip)?),
+                None => None,
+            };
+            let (target_host_for_url, target_port) = match &picked {
+                Some((_, guard)) => (guard.member.host.clone(), guard.member.port),
+                None => (app_ip.to_string(), app_port),
+            };
+
             if is_websocket {
                 debug!("WebSocket upgrade detected for app route {}", host);
                 let target_route = RouteConfig {
@@ -416,11 +1226,22 @@ async fn proxy_handler_inner(
                     domain: domain_only.to_string(),
                     backend: "app".to_string(),
                     target_host: target_host_for_url.clone(),
-                    target_port: app_route.target_port,
+                    target_port,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: true,
                     cert_id: None,
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
+                    rules: vec![],
                 };
                 let path_and_query = req
                     .uri()
@@ -430,15 +1251,29 @@ async fn proxy_handler_inner(
                 let path_uri: Uri = path_and_query
                     .parse()
                     .unwrap_or_else(|_| "/".parse().unwrap());
+                if let Some(ref t) = template {
+                    headers::apply(&request_rules, req.headers_mut(), t);
+                }
                 let ws_result = if is_agent_route {
-                    handle_websocket_upgrade_tls(req, &target_route, path_uri, &host).await
+                    let tls = &app_route.backend_tls;
+                    let config = state.backend_tls_config(domain_only, tls)?;
+                    let server_name = tls.server_name.as_deref().unwrap_or(domain_only);
+                    let result = handle_websocket_upgrade_tls(
+                        req,
+                        &target_route,
+                        path_uri,
+                        config,
+                        server_name,
+                        limit_guard,
+                    )
+                    .await;
+                    state.report_backend_tls(domain_only, tls, &result);
+                    result
                 } else {
-                    handle_websocket_upgrade(req, &target_route, path_uri).await
+                    handle_websocket_upgrade(req, &target_route, path_uri, limit_guard).await
                 };
-                match ws_result {
-                    Ok(resp) => return Ok(resp),
-                    Err(e) => return Err(e),
-                }
+                state.report_backend(&picked, !is_backend_failure(&ws_result));
+                return ws_result;
             }
 
             // Regular HTTP proxy to container
@@ -447,10 +1282,6 @@ async fn proxy_handler_inner(
                 .path_and_query()
                 .map(|pq| pq.to_string())
                 .unwrap_or_else(|| "/".to_string());
-            let target_uri_str = format!(
-                "{}://{}:{}{}",
-                scheme, target_host_for_url, app_route.target_port, path
-            );
 
             // Forward headers
             let headers = req.headers_mut();
@@ -466,47 +1297,80 @@ async fn proxy_handler_inner(
             headers.remove("connection");
             headers.remove("upgrade");
 
+            if let Some(ref t) = template {
+                headers::apply(&request_rules, headers, t);
+            }
+
             // For HTTPS backends (re-encrypt), use reqwest with domain URL + IP resolve.
             // We use the original domain in the URL (for correct SNI) but resolve it
             // to the agent's IP address to avoid DNS lookups that may return Cloudflare.
+            let policy = state.upstream_policy(app_route.upstream.as_ref());
+            let backend = (target_host_for_url, target_port);
             let proxy_result = if is_agent_route {
-                let domain_uri = format!(
-                    "https://{}:443{}",
-                    domain_only,
-                    req.uri()
-                        .path_and_query()
-                        .map(|pq| pq.to_string())
-                        .unwrap_or_else(|| "/".to_string())
-                );
-                let agent_addr =
-                    std::net::SocketAddr::new(std::net::IpAddr::V4(app_route.target_ip), 443);
-                let agent_client = reqwest::Client::builder()
-                    .danger_accept_invalid_certs(true)
-                    .resolve(domain_only, agent_addr)
-                    .redirect(reqwest::redirect::Policy::none())
-                    .build()
-                    .unwrap_or_else(|_| state.https_client.clone());
-                proxy_via_reqwest(&agent_client, req, &domain_uri, &host).await
+                let tls = &app_route.backend_tls;
+                let server_name = tls.server_name.as_deref().unwrap_or(domain_only);
+                let domain_uri = format!("https://{}:443{}", server_name, path);
+                let connect_timeout_ms = policy.connect_timeout_ms;
+                let (proxy, domain_uri, original_host) = (&state, &domain_uri, &host);
+                let result = send_upstream(
+                    &state,
+                    &policy,
+                    req,
+                    client_ip,
+                    picked,
+                    backend,
+                    move |req, backend_host, backend_port| async move {
+                        let agent_client = proxy
+                            .backend_https_client(
+                                domain_only,
+                                tls,
+                                server_name,
+                                (&backend_host, backend_port),
+                                connect_timeout_ms,
+                            )
+                            .await?;
+                        proxy_via_reqwest(&agent_client, req, domain_uri, original_host).await
+                    },
+                )
+                .await;
+                state.report_backend_tls(domain_only, tls, &result);
+                result
             } else {
-                let uri: Uri = target_uri_str
-                    .parse()
-                    .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
-                *req.uri_mut() = uri;
-                state
-                    .client
-                    .request(req)
-                    .await
-                    .map(|r| r.into_response())
-                    .map_err(|e| e.to_string())
+                let client = state.upstream_clients.get(policy.connect_timeout_ms);
+                let (client, path) = (&client, &path);
+                send_upstream(
+                    &state,
+                    &policy,
+                    req,
+                    client_ip,
+                    picked,
+                    backend,
+                    move |mut req, backend_host, backend_port| async move {
+                        *req.uri_mut() =
+                            format!("http://{}:{}{}", backend_host, backend_port, path)
+                                .parse()
+                                .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
+                        client
+                            .request(req)
+                            .await
+                            .map(|r| r.into_response())
+                            .map_err(|e| ProxyError::UpstreamError(e.to_string()))
+                    },
+                )
+                .await
             };
 
             match proxy_result {
                 Ok(resp) => {
+                    let mut resp = finish.apply(resp, limit_guard);
+                    if let Some(cookie) = assignment.and_then(|a| a.set_cookie) {
+                        resp.headers_mut().append(header::SET_COOKIE, cookie);
+                    }
                     return Ok(resp);
                 }
-                Err(err_str) => {
-                    warn!("App route proxy error for {}: {}", host, err_str);
-                    return Err(ProxyError::UpstreamError(err_str));
+                Err(err) => {
+                    warn!("App route proxy error for {}: {}", host, err);
+                    return Err(err);
                 }
             }
         }
@@ -516,7 +1380,8 @@ async fn proxy_handler_inner(
     if is_management && req.uri().path() == "/api/edge/stats" {
         let global = state.metrics.global_snapshot();
         let domains = state.metrics.snapshot();
-        let body = serde_json::json!({ "global": global, "domains": domains });
+        let cache = state.cache.stats();
+        let body = serde_json::json!({ "global": global, "domains": domains, "cache": cache });
         return Ok(Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "application/json")
@@ -547,9 +1412,20 @@ async fn proxy_handler_inner(
                 target_host: "localhost".to_string(),
                 target_port: state.orchestrator_port,
                 local_only: false,
+                acl: Default::default(),
+                client_cert: Default::default(),
                 require_auth: false,
+                machine_auth: None,
                 enabled: true,
                 cert_id: None,
+                pool: None,
+                backend_tls: None,
+                rate_limit: None,
+                compression: None,
+                cache: None,
+                upstream: None,
+                headers: Default::default(),
+                rules: vec![],
             };
 
             let is_websocket = is_websocket_upgrade(&req);
@@ -562,7 +1438,8 @@ async fn proxy_handler_inner(
                 let rewritten_uri: Uri = rewritten_path
                     .parse()
                     .unwrap_or_else(|_| "/".parse().unwrap());
-                return handle_websocket_upgrade(req, &orchestrator_route, rewritten_uri).await;
+                return handle_websocket_upgrade(req, &orchestrator_route, rewritten_uri, None)
+                    .await;
             }
 
             // Non-WebSocket request to orchestrator path: proxy as regular HTTP
@@ -598,22 +1475,41 @@ async fn proxy_handler_inner(
         }
     }
 
-    let route = if is_management {
-        RouteConfig {
+    let mut raw_path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| "/".to_string());
+
+    let (route, mut target) = if is_management {
+        let route = RouteConfig {
             id: "__management__".to_string(),
             domain: domain_only.to_string(),
             backend: "rust".to_string(),
             target_host: "localhost".to_string(),
             target_port: state.management_port,
             local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
             require_auth: false,
+            machine_auth: None,
             enabled: true,
             cert_id: None,
-        }
+            pool: None,
+            backend_tls: None,
+            rate_limit: None,
+            compression: None,
+            cache: None,
+            upstream: None,
+            headers: Default::default(),
+            rules: vec![],
+        };
+        let target = rules::resolve(&route, &[], &raw_path_and_query);
+        (route, target)
     } else {
         // Find matching static route first
-        match state.find_route(&host) {
-            Some(route) => route,
+        match state.find_route_target(&host, &raw_path_and_query) {
+            Some(found) => found,
             None => {
                 debug!(domain = domain_only, "No route found");
                 return Err(ProxyError::DomainNotFound(host.clone()));
@@ -630,9 +1526,38 @@ async fn proxy_handler_inner(
         return Err(ProxyError::Forbidden);
     }
 
+    state.check_acl(&route.domain, &route.acl, client_ip)?;
+    apply_client_cert(route.client_cert, &mut req, &route.domain)?;
+    state.check_maintenance(&route.domain, client_ip)?;
+
     // Forward-auth for routes requiring authentication (direct call, no HTTP)
-    if route.require_auth {
+    let mut auth_user = None;
+    if target.require_auth {
         if let Some(ref auth) = state.auth {
+            let machine_user = check_machine_credential(
+                auth,
+                &mut req,
+                route.machine_auth.as_ref(),
+                client_ip,
+                &route.domain,
+                &target.allowed_groups,
+            )
+            .await?;
+            // The backend path and cache key were resolved before the key
+            // was taken off
+            if let Some(param) = route
+                .machine_auth
+                .as_ref()
+                .and_then(|m| m.query_param.as_deref())
+            {
+                if let Some((_, rest)) = take_query_param(&target.path_and_query, param) {
+                    target.path_and_query = rest;
+                }
+                if let Some((_, rest)) = take_query_param(&raw_path_and_query, param) {
+                    raw_path_and_query = rest;
+                }
+            }
+
             let req_uri = req
                 .uri()
                 .path_and_query()
@@ -651,20 +1576,30 @@ async fn proxy_handler_inner(
                     })
                 });
 
-            match check_forward_auth(auth, cookie_value, &host, &req_uri, "https") {
+            let result = match machine_user {
+                Some(user) => ForwardAuthResult::Success { user },
+                None => check_forward_auth(
+                    auth,
+                    cookie_value,
+                    &host,
+                    &req_uri,
+                    "https",
+                    &target.allowed_groups,
+                ),
+            };
+            match result {
                 ForwardAuthResult::Success { user } => {
                     debug!("Auth OK for user: {}", user.username);
-                    if let Ok(v) = HeaderValue::from_str(&user.username) {
-                        req.headers_mut().insert("X-Remote-User", v);
-                    }
-                    let uuid_str = user.uuid.to_string();
-                    if let Ok(v) = HeaderValue::from_str(&uuid_str) {
-                        req.headers_mut().insert("X-Remote-User-Id", v);
-                    }
+                    inject_identity_headers(req.headers_mut(), &user);
+                    log_user(&req, &user);
+                    auth_user = Some(user);
                 }
                 ForwardAuthResult::Unauthorized { login_url } => {
                     return Err(ProxyError::AuthRequired(Some(login_url)));
                 }
+                ForwardAuthResult::Forbidden { reason, .. } => {
+                    return Err(ProxyError::AccessDenied(reason));
+                }
             }
         } else {
             // No auth service configured but route requires auth
@@ -679,32 +1614,92 @@ async fn proxy_handler_inner(
     // Check if this is a WebSocket upgrade request
     let is_websocket = is_websocket_upgrade(&req);
 
-    // Build target URL
-    let raw_path_and_query = req
-        .uri()
-        .path_and_query()
-        .map(|x| x.to_string())
-        .unwrap_or_else(|| "/".to_string());
+    let limit_guard = state.check_limits(
+        &format!("route:{}", route.id),
+        route.rate_limit.as_ref(),
+        &req,
+        auth_user.as_ref().map(|u| u.username.as_str()),
+        client_ip,
+        is_websocket,
+    )?;
+
+    let ResolvedHeaders {
+        request: request_rules,
+        response: response_rules,
+    } = state.resolve_headers(&route.headers);
+    let template = (!request_rules.is_empty() || !response_rules.is_empty())
+        .then(|| template_context(&req, auth_user.as_ref(), client_ip));
+    let finish = ResponseFinish {
+        compression: route.compression.clone(),
+        accept_encoding: req.headers().get(header::ACCEPT_ENCODING).cloned(),
+        head: req.method() == Method::HEAD,
+        response_rules,
+        template: template.clone(),
+    };
 
-    // Path rewrite for the dataverse gateway: external callers hit
+    // Response cache: fresh hits never reach the backend
+    let cache_ctx = match &route.cache {
+        Some(config) if !is_websocket => match cache::request_policy(req.method(), req.headers()) {
+            RequestPolicy::Bypass => None,
+            policy => Some(CacheContext {
+                config: config.clone(),
+                key: HttpCache::key(domain_only, &raw_path_and_query),
+                client_headers: req.headers().clone(),
+                lookup: policy == RequestPolicy::Lookup,
+                authenticated: auth_user.is_some()
+                    || req.headers().contains_key(header::AUTHORIZATION),
+            }),
+        },
+        _ => None,
+    };
+    let mut revalidating = None;
+    if let Some(ctx) = cache_ctx.as_ref().filter(|c| c.lookup)
+        && let Some(entry) = state.cache.lookup(&ctx.key, &ctx.client_headers)
+    {
+        if entry.is_fresh() {
+            if let Some(resp) = cache::respond(&entry, &ctx.client_headers, "HIT").await {
+                state.metrics.record_cache(true);
+                return Ok(finish.apply(resp, limit_guard));
+            }
+        } else if cache::add_validators(req.headers_mut(), &entry) {
+            revalidating = Some(entry);
+        }
+    }
+
+    // From here on, `route` points at the backend picked by the path rules
+    // (or the pool member chosen for this request)
+    let picked = match target.pool.as_deref() {
+        Some(name) => Some(state.select_backend(name, &req, client_ip)?),
+        None => None,
+    };
+    let (target_host, target_port) = match &picked {
+        Some((_, guard)) => (guard.member.host.clone(), guard.member.port),
+        None => (target.target_host, target.target_port),
+    };
+    let route = RouteConfig {
+        target_host,
+        target_port,
+        ..route
+    };
+
+    // Build target URL
+    // Path rewrite for the dataverse gateway: external callers hit
     // `dv.{base_domain}/{slug}/$schema`, the upstream API expects
     // `/api/dv/{slug}/$schema`. We mount the gateway under that prefix
     // here so the public surface stays clean.
     let path_and_query = if is_dv_gateway {
-        format!("/api/dv{}", raw_path_and_query)
+        format!("/api/dv{}", target.path_and_query)
     } else {
-        raw_path_and_query
+        target.path_and_query
     };
 
-    let target_url = format!(
-        "http://{}:{}{}",
-        route.target_host, route.target_port, &path_and_query
-    );
-
-    let target_uri: Uri = target_url
-        .parse()
-        .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
-
+    // HTTPS backends are addressed by their certificate name (for SNI and
+    // verification) and resolved to the target host
+    let server_name = route.backend_tls.as_ref().map(|tls| {
+        tls.server_name
+            .clone()
+            .unwrap_or_else(|| route.target_host.clone())
+    });
     // Set forwarding headers
     let headers = req.headers_mut();
 
@@ -716,42 +1711,249 @@ async fn proxy_handler_inner(
         headers.insert("X-Forwarded-For", val.clone());
         headers.insert("X-Real-IP", val);
     }
+    if let Some(ref t) = template {
+        headers::apply(&request_rules, headers, t);
+    }
 
     if is_websocket {
         debug!("WebSocket upgrade detected for {}", host);
         let path_only: Uri = path_and_query
             .parse()
             .unwrap_or_else(|_| "/".parse().unwrap());
-        return handle_websocket_upgrade(req, &route, path_only).await;
+        let result = match (&route.backend_tls, &server_name) {
+            (Some(tls), Some(name)) => {
+                let config = state.backend_tls_config(&route.domain, tls)?;
+                let result =
+                    handle_websocket_upgrade_tls(req, &route, path_only, config, name, limit_guard)
+                        .await;
+                state.report_backend_tls(&route.domain, tls, &result);
+                result
+            }
+            _ => handle_websocket_upgrade(req, &route, path_only, limit_guard).await,
+        };
+        state.report_backend(&picked, !is_backend_failure(&result));
+        return result;
     }
 
     // For normal HTTP: remove hop-by-hop headers
     headers.remove("connection");
     headers.remove("upgrade");
 
-    // Update the URI
-    *req.uri_mut() = target_uri;
+    // The management API streams and long-polls, and the dashboard must not
+    // fail fast: no header timeout, retries or breaker there
+    let policy = if is_management {
+        UpstreamConfig {
+            read_timeout_ms: 0,
+            total_timeout_ms: 0,
+            retry: None,
+            circuit_breaker: None,
+            ..state.upstream_policy(None)
+        }
+    } else {
+        state.upstream_policy(route.upstream.as_ref())
+    };
+    let backend = (route.target_host.clone(), route.target_port);
+    let result = match &route.backend_tls {
+        Some(tls) => {
+            let connect_timeout_ms = policy.connect_timeout_ms;
+            let (proxy, domain, path_and_query, original_host) =
+                (&state, route.domain.as_str(), &path_and_query, &host);
+            let result = send_upstream(
+                &state,
+                &policy,
+                req,
+                client_ip,
+                picked,
+                backend,
+                move |req, backend_host, backend_port| async move {
+                    let name = tls.server_name.as_deref().unwrap_or(&backend_host);
+                    let url = format!("https://{}:{}{}", name, backend_port, path_and_query);
+                    let client = proxy
+                        .backend_https_client(
+                            domain,
+                            tls,
+                            name,
+                            (&backend_host, backend_port),
+                            connect_timeout_ms,
+                        )
+                        .await?;
+                    proxy_via_reqwest(&client, req, &url, original_host).await
+                },
+            )
+            .await;
+            state.report_backend_tls(&route.domain, tls, &result);
+            result
+        }
+        None => {
+            // Forward the request via pooled client
+            let client = state.upstream_clients.get(policy.connect_timeout_ms);
+            let (client, path_and_query) = (&client, &path_and_query);
+            send_upstream(
+                &state,
+                &policy,
+                req,
+                client_ip,
+                picked,
+                backend,
+                move |mut req, backend_host, backend_port| async move {
+                    *req.uri_mut() =
+                        format!("http://{}:{}{}", backend_host, backend_port, path_and_query)
+                            .parse()
+                            .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
+                    client
+                        .request(req)
+                        .await
+                        .map(|r| r.into_response())
+                        .map_err(|e| ProxyError::UpstreamError(e.to_string()))
+                },
+            )
+            .await
+        }
+    };
+    let response = result?;
+    let response = match cache_ctx {
+        Some(ctx) => cache_response(&state, ctx, revalidating, response).await?,
+        None => response,
+    };
+
+    Ok(finish.apply(response, limit_guard))
+}
+
+/// Cache bookkeeping for a static-route request.
+struct CacheContext {
+    config: RouteCacheConfig,
+    key: String,
+    /// Request headers as sent by the client (before revalidation).
+    client_headers: HeaderMap,
+    /// False for `no-cache` requests, which only refresh the cache.
+    lookup: bool,
+    authenticated: bool,
+}
+
+/// Serve a revalidated entry, or store the backend response if cacheable.
+async fn cache_response(
+    state: &ProxyState,
+    ctx: CacheContext,
+    revalidating: Option<Arc<CacheEntry>>,
+    response: Response,
+) -> Result<Response, ProxyError> {
+    let default_ttl = std::time::Duration::from_secs(ctx.config.default_ttl_secs);
+
+    if let Some(entry) = revalidating
+        && response.status() == StatusCode::NOT_MODIFIED
+    {
+        let headers = entry.revalidated_headers(response.headers());
+        let ttl = cache::freshness(entry.status(), &headers, ctx.authenticated, default_ttl)
+            .unwrap_or_default();
+        let entry = state
+            .cache
+            .refresh(&ctx.key, &ctx.client_headers, &entry, headers, ttl);
+        state.metrics.record_cache(true);
+        // The client sent no conditionals: it needs the full cached body
+        return cache::respond(&entry, &ctx.client_headers, "REVALIDATED")
+            .await
+            .ok_or_else(|| ProxyError::UpstreamError("Cached body unavailable".to_string()));
+    }
 
-    // Forward the request via pooled client
-    let response = state
-        .client
-        .request(req)
+    state.metrics.record_cache(false);
+    let ttl = cache::freshness(
+        response.status(),
+        response.headers(),
+        ctx.authenticated,
+        default_ttl,
+    );
+    // Only bodies of known, bounded size are buffered for the cache
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert("x-cache", HeaderValue::from_static("MISS"));
+    let (Some(ttl), Some(length)) = (ttl, length) else {
+        return Ok(Response::from_parts(parts, body));
+    };
+    if length > ctx.config.max_object_size {
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    let bytes = axum::body::to_bytes(body, length as usize)
         .await
-        .map_err(|e| ProxyError::UpstreamError(e.to_string()))?;
+        .map_err(|e| ProxyError::UpstreamError(format!("Backend body error: {}", e)))?;
+    state
+        .cache
+        .store(
+            &ctx.key,
+            &ctx.client_headers,
+            parts.status,
+            &parts.headers,
+            bytes.clone(),
+            ttl,
+        )
+        .await;
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+/// Template data for header rules, taken from the request as received
+/// (plus the forward-auth identity).
+fn template_context(req: &Request, user: Option<&UserInfo>, client_ip: IpAddr) -> TemplateContext {
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    TemplateContext::new(user, client_ip, req.method().as_str(), path, req.headers())
+}
+
+/// Per-route response post-processing, captured before the request is
+/// consumed.
+struct ResponseFinish {
+    compression: Option<CompressionConfig>,
+    accept_encoding: Option<HeaderValue>,
+    head: bool,
+    response_rules: Vec<HeaderRules>,
+    /// Set when the route has header rules.
+    template: Option<TemplateContext>,
+}
 
-    Ok(response.into_response())
+impl ResponseFinish {
+    /// Compress, apply the response header rules, then hold the rate-limit
+    /// slots until the body is sent.
+    fn apply(self, response: Response, limit_guard: Option<LimitGuard>) -> Response {
+        let mut response = match &self.compression {
+            Some(config) => compress::compress_response(
+                config,
+                self.accept_encoding.as_ref(),
+                self.head,
+                response,
+            ),
+            None => response,
+        };
+        if let Some(ref t) = self.template {
+            headers::apply(&self.response_rules, response.headers_mut(), t);
+        }
+        match limit_guard {
+            Some(guard) => guard.attach(response),
+            None => response,
+        }
+    }
 }
 
-/// Handle WebSocket upgrade by establishing a direct connection to the backend
+/// Handle WebSocket upgrade by establishing a direct connection to the backend.
+/// `limit_guard` is held until the bridged connection closes.
 async fn handle_websocket_upgrade(
     mut req: Request,
     route: &RouteConfig,
     target_uri: Uri,
+    limit_guard: Option<LimitGuard>,
 ) -> Result<Response, ProxyError> {
     use hyper::client::conn::http1::Builder;
     use tokio::io::AsyncWriteExt;
 
     let client_upgrade = hyper::upgrade::on(&mut req);
+    let ws_gauge = req.extensions_mut().remove::<WebSocketGauge>();
 
     let backend_addr = format!("{}:{}", route.target_host, route.target_port);
     let tcp_stream = TcpStream::connect(&backend_addr).await.map_err(|e| {
@@ -807,6 +2009,8 @@ async fn handle_websocket_upgrade(
     let client_response = response_builder.body(Body::empty()).unwrap();
 
     tokio::spawn(async move {
+        let _limit_guard = limit_guard;
+        let _ws_gauge = ws_gauge;
         match tokio::try_join!(client_upgrade, backend_upgrade) {
             Ok((client_io, backend_io)) => {
                 let mut client_io = TokioIo::new(client_io);
@@ -845,7 +2049,7 @@ async fn proxy_via_reqwest(
     req: Request,
     target_url: &str,
     original_host: &str,
-) -> Result<Response, String> {
+) -> Result<Response, ProxyError> {
     let method = req.method().clone();
     let headers = req.headers().clone();
 
@@ -871,15 +2075,21 @@ async fn proxy_via_reqwest(
     let body_stream = req.into_body();
     let body_bytes = axum::body::to_bytes(body_stream, 512 * 1024 * 1024)
         .await
-        .map_err(|e| format!("Failed to read request body: {}", e))?;
+        .map_err(|e| ProxyError::UpstreamError(format!("Failed to read request body: {}", e)))?;
     if !body_bytes.is_empty() {
         builder = builder.body(body_bytes);
     }
 
+    let request = builder
+        .build()
+        .map_err(|e| ProxyError::UpstreamError(e.to_string()))?;
     let resp = client
-        .execute(builder.build().map_err(|e| e.to_string())?)
+        .execute(request)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| match backend_tls::tls_failure(&e) {
+            Some(reason) => ProxyError::BackendTls(reason),
+            None => ProxyError::UpstreamError(e.to_string()),
+        })?;
 
     // Convert reqwest Response → axum Response
     let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
@@ -898,7 +2108,146 @@ async fn proxy_via_reqwest(
         .map_err(|e| std::io::Error::other(e.to_string()));
     response_builder
         .body(Body::from_stream(body_stream))
-        .map_err(|e| e.to_string())
+        .map_err(|e| ProxyError::UpstreamError(e.to_string()))
+}
+
+/// Send a request to `backend` under `policy`: circuit breaker check,
+/// response header and body timeouts, then retries with backoff (on another pool
+/// member when pooled) while the outcome and the total budget allow it.
+/// `send` makes one attempt against a backend host and port.
+async fn send_upstream<F, Fut>(
+    state: &ProxyState,
+    policy: &UpstreamConfig,
+    req: Request,
+    client_ip: IpAddr,
+    mut picked: Option<(Arc<Pool>, MemberGuard)>,
+    (mut backend_host, mut backend_port): (String, u16),
+    mut send: F,
+) -> Result<Response, ProxyError>
+where
+    F: FnMut(Request, String, u16) -> Fut,
+    Fut: Future<Output = Result<Response, ProxyError>>,
+{
+    use std::time::{Duration, Instant};
+
+    let started = Instant::now();
+    let budget =
+        (policy.total_timeout_ms > 0).then(|| Duration::from_millis(policy.total_timeout_ms));
+    let read_timeout =
+        (policy.read_timeout_ms > 0).then(|| Duration::from_millis(policy.read_timeout_ms));
+    let replay = policy.retry.as_ref().and_then(|_| Replay::capture(&req));
+    let mut next = req;
+    let mut tried = Vec::new();
+    let mut retries = 0;
+
+    loop {
+        let addr = format!("{}:{}", backend_host, backend_port);
+        let breaker = policy.circuit_breaker.as_ref();
+        let result = match breaker.map(|config| state.breakers.allow(&addr, config)) {
+            Some(Err(retry_after_secs)) => Err(ProxyError::CircuitOpen {
+                backend: addr.clone(),
+                retry_after_secs,
+            }),
+            _ => {
+                let remaining = budget.map(|b| b.saturating_sub(started.elapsed()));
+                let limit = match (read_timeout, remaining) {
+                    (Some(read), Some(remaining)) => Some(read.min(remaining)),
+                    (read, remaining) => read.or(remaining),
+                };
+                let attempt = send(next, backend_host.clone(), backend_port);
+                let result = match limit {
+                    Some(limit) => {
+                        tokio::time::timeout(limit, attempt)
+                            .await
+                            .unwrap_or_else(|_| {
+                                Err(ProxyError::UpstreamTimeout(format!(
+                                    "{} did not answer within {} ms",
+                                    addr,
+                                    limit.as_millis()
+                                )))
+                            })
+                    }
+                    None => attempt.await,
+                };
+                let result = result.map(|resp| {
+                    upstream::timed_response(resp, read_timeout, budget.map(|b| started + b))
+                });
+                if let Some(config) = breaker {
+                    state
+                        .breakers
+                        .record(&addr, config, !upstream::is_failure(&result));
+                }
+                state.report_backend(&picked, result.is_ok());
+                result
+            }
+        };
+
+        let (Some(retry), Some(replay)) = (&policy.retry, &replay) else {
+            return result;
+        };
+        let pool = picked.as_ref().map(|(pool, _)| pool.clone());
+        if retries >= retry.attempts
+            || !upstream::should_retry(&result, retry)
+            || (pool.is_none() && matches!(result, Err(ProxyError::CircuitOpen { .. })))
+        {
+            return result;
+        }
+        retries += 1;
+        let delay = upstream::backoff(retry, retries);
+        if budget.is_some_and(|b| started.elapsed() + delay >= b) {
+            return result;
+        }
+
+        // Pooled routes move to a member not tried yet, or at least not
+        // failing fast
+        if let Some(pool) = pool {
+            tried.push(addr.clone());
+            let guard = pool
+                .select_filtered(&replay.headers, client_ip, |m| {
+                    let member = m.address();
+                    !tried.contains(&member) && !state.breakers.is_open(&member)
+                })
+                .or_else(|| {
+                    pool.select_filtered(&replay.headers, client_ip, |m| {
+                        !state.breakers.is_open(&m.address())
+                    })
+                });
+            let Some(guard) = guard else {
+                return result;
+            };
+            backend_host = guard.member.host.clone();
+            backend_port = guard.member.port;
+            picked = Some((pool, guard));
+        }
+        let reason = match &result {
+            Ok(resp) => resp.status().to_string(),
+            Err(e) => e.to_string(),
+        };
+        debug!(
+            "Retrying {} ({}/{}) on {}:{} after {:?}: {}",
+            addr, retries, retry.attempts, backend_host, backend_port, delay, reason
+        );
+        tokio::time::sleep(delay).await;
+        next = replay.request();
+    }
+}
+
+/// Keep `current` when a re-registration leaves `value` at its default.
+fn keep_unset<T: Default + PartialEq + Clone>(value: &mut T, current: &T) {
+    if *value == T::default() {
+        *value = current.clone();
+    }
+}
+
+/// Whether a proxy error means the backend itself failed (for passive
+/// health checks).
+fn is_backend_failure(result: &Result<Response, ProxyError>) -> bool {
+    matches!(
+        result,
+        Err(ProxyError::UpstreamError(_)
+            | ProxyError::UpstreamTimeout(_)
+            | ProxyError::BackendTls(_))
+    )
 }
 
 /// Handle WebSocket upgrade to a TLS backend (re-encrypt).
@@ -908,13 +2257,16 @@ async fn handle_websocket_upgrade_tls(
     mut req: Request,
     route: &RouteConfig,
     target_uri: Uri,
-    original_host: &str,
+    tls_config: Arc<rustls::ClientConfig>,
+    server_name: &str,
+    limit_guard: Option<LimitGuard>,
 ) -> Result<Response, ProxyError> {
     use hyper::client::conn::http1::Builder;
     use tokio::io::AsyncWriteExt;
     use tokio_rustls::TlsConnector;
 
     let client_upgrade = hyper::upgrade::on(&mut req);
+    let ws_gauge = req.extensions_mut().remove::<WebSocketGauge>();
 
     let backend_addr = format!("{}:{}", route.target_host, route.target_port);
     let tcp_stream = TcpStream::connect(&backend_addr).await.map_err(|e| {
@@ -924,26 +2276,20 @@ async fn handle_websocket_upgrade_tls(
         ))
     })?;
 
-    // Build a TLS config that skips certificate verification (trusted LAN)
-    let tls_config = rustls::ClientConfig::builder()
-        .dangerous()
-        .with_custom_certificate_verifier(Arc::new(NoVerifier))
-        .with_no_client_auth();
-    let connector = TlsConnector::from(Arc::new(tls_config));
+    let connector = TlsConnector::from(tls_config);
 
-    let server_name = rustls::pki_types::ServerName::try_from(original_host.to_string())
-        .unwrap_or_else(|_| {
-            rustls::pki_types::ServerName::try_from("localhost".to_string()).unwrap()
-        });
+    let server_name = rustls::pki_types::ServerName::try_from(server_name.to_string())
+        .map_err(|e| ProxyError::BackendTls(format!("Invalid server name: {}", e)))?;
 
     let tls_stream = connector
         .connect(server_name, tcp_stream)
         .await
-        .map_err(|e| {
-            ProxyError::UpstreamError(format!(
+        .map_err(|e| match backend_tls::tls_failure(&e) {
+            Some(reason) => ProxyError::BackendTls(reason),
+            None => ProxyError::UpstreamError(format!(
                 "TLS handshake to backend {} failed: {}",
                 backend_addr, e
-            ))
+            )),
         })?;
 
     let io = TokioIo::new(tls_stream);
@@ -992,6 +2338,8 @@ async fn handle_websocket_upgrade_tls(
     let client_response = response_builder.body(Body::empty()).unwrap();
 
     tokio::spawn(async move {
+        let _limit_guard = limit_guard;
+        let _ws_gauge = ws_gauge;
         match tokio::try_join!(client_upgrade, backend_upgrade) {
             Ok((client_io, backend_io)) => {
                 let mut client_io = TokioIo::new(client_io);
@@ -1022,45 +2370,183 @@ async fn handle_websocket_upgrade_tls(
     Ok(client_response)
 }
 
-/// Certificate verifier that accepts any certificate (for trusted LAN backends).
-#[derive(Debug)]
-struct NoVerifier;
-
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
+/// Header carrying an API key when the route doesn't name another one.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Take the machine credentials a route accepts off the request so they never
+/// reach the backend. The key header wins over `Authorization`, which wins
+/// over the query parameter.
+fn take_machine_credential(
+    req: &mut Request,
+    config: &MachineAuthConfig,
+) -> Option<MachineCredential> {
+    let mut found = None;
+    if config.api_keys {
+        let name = config.header.as_deref().unwrap_or(API_KEY_HEADER);
+        found = req
+            .headers_mut()
+            .remove(name)
+            .and_then(|v| v.to_str().ok().map(|key| key.trim().to_string()))
+            .map(MachineCredential::ApiKey);
+    }
+    let from_authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(MachineCredential::from_authorization)
+        .filter(|credential| match credential {
+            MachineCredential::ApiKey(_) => config.api_keys,
+            MachineCredential::Basic { .. } => config.basic,
+        });
+    if let Some(credential) = from_authorization {
+        req.headers_mut().remove(header::AUTHORIZATION);
+        found = found.or(Some(credential));
     }
+    if config.api_keys
+        && let Some(param) = config.query_param.as_deref()
+    {
+        let path_and_query = req.uri().path_and_query().map_or("/", |pq| pq.as_str());
+        if let Some((key, rest)) = take_query_param(path_and_query, param) {
+            if let Some(stripped) = req.extensions().get::<StrippedParam>() {
+                let _ = stripped.0.set(param.to_string());
+            }
+            let mut parts = req.uri().clone().into_parts();
+            parts.path_and_query = rest.parse().ok();
+            if let Ok(uri) = Uri::from_parts(parts) {
+                *req.uri_mut() = uri;
+            }
+            found = found.or(Some(MachineCredential::ApiKey(key)));
+        }
+    }
+    found
+}
 
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+/// Remove the first `name` parameter from `path_and_query`, returning its
+/// value and what is left.
+fn take_query_param(path_and_query: &str, name: &str) -> Option<(String, String)> {
+    let (path, query) = path_and_query.split_once('?')?;
+    let mut value = None;
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split_once('=').map_or(*pair, |(k, _)| k);
+            if value.is_none() && key == name {
+                value = Some(pair.split_once('=').map_or("", |(_, v)| v).to_string());
+                return false;
+            }
+            true
+        })
+        .collect();
+    let value = value?;
+    let rest = if kept.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, kept.join("&"))
+    };
+    Some((value, rest))
+}
+
+/// Authenticate by API key or basic credentials on a route that accepts
+/// them. `Ok(None)` means the request carries none and goes through the
+/// session check instead.
+async fn check_machine_credential(
+    auth: &Arc<AuthService>,
+    req: &mut Request,
+    config: Option<&MachineAuthConfig>,
+    client_ip: IpAddr,
+    domain: &str,
+    allowed_groups: &[String],
+) -> Result<Option<UserInfo>, ProxyError> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+    let Some(credential) = take_machine_credential(req, config) else {
+        return Ok(None);
+    };
+    let client = client_ip.to_string();
+    match check_machine_auth(
+        auth,
+        credential,
+        &client,
+        domain,
+        &config.scopes,
+        allowed_groups,
+    )
+    .await
+    {
+        MachineAuthResult::Success { user } => Ok(Some(user)),
+        MachineAuthResult::Invalid => Err(ProxyError::InvalidCredentials {
+            basic: config.basic,
+        }),
+        MachineAuthResult::Forbidden { reason, .. } => Err(ProxyError::AccessDenied(reason)),
+        MachineAuthResult::Throttled { retry_after_secs } => {
+            warn!(
+                "Too many credential failures from {} for {}",
+                client, domain
+            );
+            Err(ProxyError::RateLimited(LimitExceeded {
+                kind: ratelimit::LimitKind::Rate,
+                retry_after_secs,
+            }))
+        }
     }
+}
 
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+/// Inject the authenticated identity for the backend. Client-supplied
+/// identity headers are always dropped first so they cannot be spoofed.
+fn inject_identity_headers(headers: &mut HeaderMap, user: &UserInfo) {
+    let identity = ForwardAuthHeaders::from(user);
+    for (name, value) in [
+        ("X-Remote-User", identity.remote_user),
+        ("X-Remote-User-Id", identity.remote_user_id),
+        ("X-Remote-Groups", identity.remote_groups),
+    ] {
+        headers.remove(name);
+        if let Ok(v) = HeaderValue::from_str(&value)
+            && !value.is_empty()
+        {
+            headers.insert(name, v);
+        }
     }
+}
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        rustls::crypto::ring::default_provider()
-            .signature_verification_algorithms
-            .supported_schemes()
+/// Headers carrying the verified client certificate to backends.
+const CLIENT_CERT_HEADERS: [&str; 3] = [
+    "X-Client-Cert-Subject",
+    "X-Client-Cert-CN",
+    "X-Client-Cert-Serial",
+];
+
+/// Enforce a route's client certificate mode and pass the verified identity
+/// to the backend. Client-supplied copies of the headers are always dropped.
+fn apply_client_cert(
+    mode: ClientCertMode,
+    req: &mut Request,
+    domain: &str,
+) -> Result<(), ProxyError> {
+    for name in CLIENT_CERT_HEADERS {
+        req.headers_mut().remove(name);
+    }
+    if mode == ClientCertMode::Off {
+        return Ok(());
+    }
+    let Some(identity) = req.extensions().get::<ClientCertIdentity>().cloned() else {
+        if mode == ClientCertMode::Required {
+            warn!("Blocked request without client certificate for {}", domain);
+            return Err(ProxyError::ClientCertRequired);
+        }
+        return Ok(());
+    };
+    for (name, value) in CLIENT_CERT_HEADERS.into_iter().zip([
+        &identity.subject,
+        &identity.common_name,
+        &identity.serial,
+    ]) {
+        if let Ok(v) = HeaderValue::from_str(value) {
+            req.headers_mut().insert(name, v);
+        }
     }
+    Ok(())
 }
 
 /// Check if the request is a WebSocket upgrade
@@ -1094,14 +2580,101 @@ pub enum ProxyError {
     #[error("Upstream error: {0}")]
     UpstreamError(String),
 
+    #[error("Upstream timeout: {0}")]
+    UpstreamTimeout(String),
+
+    /// The backend's circuit breaker is open.
+    #[error("Circuit open for {backend}")]
+    CircuitOpen {
+        backend: String,
+        retry_after_secs: u64,
+    },
+
+    #[error("Backend TLS verification failed: {0}")]
+    BackendTls(String),
+
     #[error("Authentication required")]
     AuthRequired(Option<String>),
 
+    /// Rejected API key or basic credentials; `basic` adds a Basic challenge.
+    #[error("Invalid credentials")]
+    InvalidCredentials { basic: bool },
+
     #[error("Forbidden")]
     Forbidden,
 
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Denied by ACL rule {0}")]
+    AclDenied(String),
+
+    #[error("Client certificate required")]
+    ClientCertRequired,
+
     #[error("Domain not found: {0}")]
     DomainNotFound(String),
+
+    #[error("No healthy backend in pool {0}")]
+    NoHealthyBackend(String),
+
+    #[error("Too many requests ({} limit)", .0.kind.as_str())]
+    RateLimited(LimitExceeded),
+
+    #[error("Maintenance in progress")]
+    Maintenance(Option<String>),
+
+    #[error("Application is starting")]
+    AppStarting,
+}
+
+impl ProxyError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ProxyError::InvalidUri(_) => StatusCode::BAD_REQUEST,
+            ProxyError::UpstreamError(_) | ProxyError::BackendTls(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::AuthRequired(Some(_)) => StatusCode::FOUND,
+            ProxyError::AuthRequired(None) | ProxyError::InvalidCredentials { .. } => {
+                StatusCode::UNAUTHORIZED
+            }
+            ProxyError::Forbidden
+            | ProxyError::AccessDenied(_)
+            | ProxyError::AclDenied(_)
+            | ProxyError::ClientCertRequired => StatusCode::FORBIDDEN,
+            ProxyError::DomainNotFound(_) => StatusCode::NOT_FOUND,
+            ProxyError::NoHealthyBackend(_)
+            | ProxyError::CircuitOpen { .. }
+            | ProxyError::Maintenance(_)
+            | ProxyError::AppStarting => StatusCode::SERVICE_UNAVAILABLE,
+            ProxyError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Text shown to the client. ACL rules and backend details stay in
+    /// the logs.
+    pub fn public_message(&self) -> String {
+        match self {
+            ProxyError::InvalidUri(msg) | ProxyError::UpstreamError(msg) => msg.clone(),
+            ProxyError::BackendTls(_) => "Backend certificate rejected".to_string(),
+            ProxyError::UpstreamTimeout(_) => "The backend did not respond in time".to_string(),
+            ProxyError::CircuitOpen { .. } => "Backend temporarily unavailable".to_string(),
+            ProxyError::AuthRequired(_) => "Authentication required".to_string(),
+            ProxyError::InvalidCredentials { .. } => "Invalid credentials".to_string(),
+            ProxyError::Forbidden | ProxyError::AclDenied(_) => "Forbidden".to_string(),
+            ProxyError::AccessDenied(reason) => reason.clone(),
+            ProxyError::ClientCertRequired => "Client certificate required".to_string(),
+            ProxyError::DomainNotFound(domain) => format!("Domain not configured: {}", domain),
+            ProxyError::NoHealthyBackend(pool) => format!("No healthy backend in pool {}", pool),
+            ProxyError::RateLimited(_) => "Too Many Requests".to_string(),
+            ProxyError::Maintenance(message) => message.clone().unwrap_or_else(|| {
+                "This site is under maintenance. Please come back later.".to_string()
+            }),
+            ProxyError::AppStarting => {
+                "The application is starting. This page will reload automatically.".to_string()
+            }
+        }
+    }
 }
 
 impl IntoResponse for ProxyError {
@@ -1112,26 +2685,69 @@ impl IntoResponse for ProxyError {
                 .header("Location", &redirect_url)
                 .body(Body::empty())
                 .unwrap(),
-            other => {
-                let (status, message) = match other {
-                    ProxyError::InvalidUri(msg) => (StatusCode::BAD_REQUEST, msg),
-                    ProxyError::UpstreamError(msg) => (StatusCode::BAD_GATEWAY, msg),
-                    ProxyError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden".to_string()),
-                    ProxyError::DomainNotFound(domain) => (
-                        StatusCode::NOT_FOUND,
-                        format!("Domain not configured: {}", domain),
-                    ),
-                    _ => (
-                        StatusCode::UNAUTHORIZED,
-                        "Authentication required".to_string(),
-                    ),
-                };
-                (status, message).into_response()
-            }
+            ProxyError::InvalidCredentials { basic: true } => Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(
+                    header::WWW_AUTHENTICATE,
+                    "Basic realm=\"homeroute\", charset=\"UTF-8\"",
+                )
+                .header("content-type", "text/plain")
+                .body(Body::from("Invalid credentials"))
+                .unwrap(),
+            ProxyError::RateLimited(exceeded) => Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("Retry-After", exceeded.retry_after_secs.to_string())
+                .header("content-type", "text/plain")
+                .body(Body::from("Too Many Requests"))
+                .unwrap(),
+            ProxyError::CircuitOpen {
+                retry_after_secs, ..
+            } => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header("Retry-After", retry_after_secs.to_string())
+                .header("content-type", "text/plain")
+                .body(Body::from("Backend temporarily unavailable"))
+                .unwrap(),
+            ProxyError::AccessDenied(reason) => Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header("content-type", "text/html; charset=utf-8")
+                .body(Body::from(access_denied_page(&reason)))
+                .unwrap(),
+            ProxyError::AppStarting => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header("Retry-After", pages::STARTING_REFRESH_SECS.to_string())
+                .header("Refresh", pages::STARTING_REFRESH_SECS.to_string())
+                .header("content-type", "text/plain")
+                .body(Body::from(ProxyError::AppStarting.public_message()))
+                .unwrap(),
+            ProxyError::Maintenance(message) => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header("Retry-After", "300")
+                .header("content-type", "text/plain")
+                .body(Body::from(
+                    ProxyError::Maintenance(message).public_message(),
+                ))
+                .unwrap(),
+            other => (other.status_code(), other.public_message()).into_response(),
         }
     }
 }
 
+/// Minimal 403 page shown when forward-auth succeeds but group checks fail.
+fn access_denied_page(reason: &str) -> String {
+    let reason = reason
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>403 Forbidden</title></head>\
+         <body style=\"font-family:sans-serif;text-align:center;margin-top:15vh\">\
+         <h1>403 Forbidden</h1><p>{}</p></body></html>",
+        reason
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1153,9 +2769,20 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3000,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: true,
                     cert_id: Some("cert-1".to_string()),
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
+                    rules: vec![],
                 },
                 RouteConfig {
                     id: "route-2".to_string(),
@@ -1164,9 +2791,20 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3001,
                     local_only: true,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: true,
                     cert_id: Some("cert-2".to_string()),
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
+                    rules: vec![],
                 },
                 RouteConfig {
                     id: "route-3".to_string(),
@@ -1175,9 +2813,20 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3002,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: true,
+                    machine_auth: None,
                     enabled: true,
                     cert_id: Some("cert-3".to_string()),
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
+                    rules: vec![],
                 },
                 RouteConfig {
                     id: "route-4".to_string(),
@@ -1186,12 +2835,38 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3003,
                     local_only: false,
+                    acl: Default::default(),
+                    client_cert: Default::default(),
                     require_auth: false,
+                    machine_auth: None,
                     enabled: false,
                     cert_id: None,
+                    pool: None,
+                    backend_tls: None,
+                    rate_limit: None,
+                    compression: None,
+                    cache: None,
+                    upstream: None,
+                    headers: Default::default(),
+                    rules: vec![],
                 },
             ],
             access_log_path: None,
+            access_log: Default::default(),
+            pools: vec![],
+            http3_enabled: false,
+            alt_svc_max_age: 86400,
+            cache: Default::default(),
+            header_policies: Default::default(),
+            ip_lists: Default::default(),
+            trusted_relays: vec![],
+            streams: vec![],
+            redirects: vec![],
+            domain_aliases: vec![],
+            upstream: Default::default(),
+            ocsp: Default::default(),
+            history_size: 10,
+            error_pages_dir: None,
         }
     }
 
@@ -1203,6 +2878,233 @@ mod tests {
         assert_eq!(route.unwrap().target_port, 3000);
     }
 
+    #[test]
+    fn test_app_split_and_promote() {
+        use crate::canary::AppTarget;
+        let state = ProxyState::new(test_config(), 4000, 4001);
+        let target = |name: &str, last: u8, weight| AppTarget {
+            name: name.to_string(),
+            target_ip: std::net::Ipv4Addr::new(10, 0, 0, last),
+            target_port: 3000,
+            weight,
+        };
+        let route = AppRoute {
+            app_id: "shop".to_string(),
+            host_id: "local".to_string(),
+            target_ip: std::net::Ipv4Addr::new(10, 0, 0, 10),
+            target_port: 3000,
+            auth_required: false,
+            allowed_groups: vec![],
+            local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
+            backend_tls: Default::default(),
+            pool: None,
+            rate_limit: None,
+            headers: Default::default(),
+            split: None,
+            machine_auth: None,
+            upstream: None,
+        };
+        state.set_app_route("shop.example.com".to_string(), route.clone());
+        let split = TrafficSplit {
+            targets: vec![target("stable", 10, 100), target("canary", 11, 0)],
+            sticky: true,
+        };
+        state
+            .set_app_split("shop.example.com", Some(split))
+            .unwrap();
+
+        // Re-registration by the app supervisor keeps the split
+        state.set_app_route("shop.example.com".to_string(), route);
+        let weights = std::collections::HashMap::from([("canary".to_string(), 50)]);
+        let split = state.set_app_weights("shop.example.com", &weights).unwrap();
+        assert_eq!(split.target("canary").unwrap().weight, 50);
+
+        assert!(
+            state
+                .promote_app_target("shop.example.com", "beta")
+                .is_err()
+        );
+        state
+            .promote_app_target("shop.example.com", "canary")
+            .unwrap();
+        let promoted = state.get_app_route("shop.example.com").unwrap();
+        assert_eq!(promoted.target_ip, std::net::Ipv4Addr::new(10, 0, 0, 11));
+        assert!(promoted.split.is_none());
+        assert!(state.set_app_weights("shop.example.com", &weights).is_err());
+    }
+
+    #[test]
+    fn test_app_route_reregistration_keeps_settings() {
+        let state = ProxyState::new(test_config(), 4000, 4001);
+        let bare = AppRoute {
+            app_id: "wiki".to_string(),
+            host_id: "local".to_string(),
+            target_ip: std::net::Ipv4Addr::new(10, 0, 0, 20),
+            target_port: 3000,
+            auth_required: false,
+            allowed_groups: vec![],
+            local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
+            backend_tls: Default::default(),
+            pool: None,
+            rate_limit: None,
+            headers: Default::default(),
+            split: None,
+            machine_auth: None,
+            upstream: None,
+        };
+        let configured = AppRoute {
+            acl: AccessList {
+                allow: vec!["192.168.0.0/16".to_string()],
+                deny: vec![],
+            },
+            client_cert: ClientCertMode::Required,
+            pool: Some("wiki".to_string()),
+            rate_limit: Some(RateLimitConfig {
+                requests_per_second: 5.0,
+                burst: None,
+                key: Default::default(),
+                max_connections: 0,
+                max_websockets: 0,
+            }),
+            headers: RouteHeaders {
+                policies: vec!["strict".to_string()],
+                ..Default::default()
+            },
+            backend_tls: BackendTlsConfig {
+                verify: BackendTlsVerify::Fingerprint,
+                fingerprint: Some("ab".repeat(32)),
+                ..Default::default()
+            },
+            machine_auth: Some(MachineAuthConfig {
+                api_keys: true,
+                ..Default::default()
+            }),
+            upstream: Some(UpstreamConfig {
+                total_timeout_ms: 5000,
+                ..Default::default()
+            }),
+            ..bare.clone()
+        };
+        state.set_app_route("wiki.example.com".to_string(), configured.clone());
+
+        // The app supervisor re-registers the route after an update
+        let moved = AppRoute {
+            target_port: 3001,
+            ..bare
+        };
+        state.set_app_route("wiki.example.com".to_string(), moved);
+        let route = state.get_app_route("wiki.example.com").unwrap();
+        assert_eq!(route.target_port, 3001);
+        let mut req = Request::new(Body::empty());
+        assert!(matches!(
+            apply_client_cert(route.client_cert, &mut req, "wiki.example.com"),
+            Err(ProxyError::ClientCertRequired)
+        ));
+        assert_eq!(route.pool, configured.pool);
+        assert_eq!(route.rate_limit, configured.rate_limit);
+        assert_eq!(route.headers, configured.headers);
+        assert_eq!(route.backend_tls, configured.backend_tls);
+        assert_eq!(route.machine_auth, configured.machine_auth);
+        assert_eq!(route.upstream, configured.upstream);
+        let lan: IpAddr = "192.168.1.20".parse().unwrap();
+        let wan: IpAddr = "203.0.113.4".parse().unwrap();
+        assert!(state.check_acl("wiki.example.com", &route.acl, lan).is_ok());
+        assert!(matches!(
+            state.check_acl("wiki.example.com", &route.acl, wan),
+            Err(ProxyError::AclDenied(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_app_route_header_rules_apply_once() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let backend = axum::Router::new().fallback(|req: Request| async move {
+            let tags: Vec<String> = req
+                .headers()
+                .get_all("x-tag")
+                .iter()
+                .filter_map(|v| v.to_str().ok().map(str::to_string))
+                .collect();
+            tags.join("|")
+        });
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let state = Arc::new(ProxyState::new(test_config(), 4000, 4001));
+        let mut headers = RouteHeaders::default();
+        headers
+            .rules
+            .request
+            .append
+            .insert("X-Tag".to_string(), "edge".to_string());
+        let route = AppRoute {
+            app_id: "tags".to_string(),
+            host_id: "local".to_string(),
+            target_ip: std::net::Ipv4Addr::LOCALHOST,
+            target_port: port,
+            auth_required: false,
+            allowed_groups: vec![],
+            local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
+            backend_tls: Default::default(),
+            pool: None,
+            rate_limit: None,
+            headers,
+            split: None,
+            machine_auth: None,
+            upstream: None,
+        };
+        state.set_app_route("tags.example.com".to_string(), route);
+
+        let req = Request::builder()
+            .uri("/")
+            .header("host", "tags.example.com")
+            .header("x-tag", "client")
+            .body(Body::empty())
+            .unwrap();
+        let client_ip: IpAddr = "192.168.1.20".parse().unwrap();
+        let resp = proxy_handler(state, client_ip, req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "client|edge");
+    }
+
+    #[test]
+    fn test_redirect_lookup() {
+        let mut config = test_config();
+        config.domain_aliases = vec![crate::config::DomainAlias {
+            from: "www.example.com".to_string(),
+            to: "example.com".to_string(),
+            status: 308,
+        }];
+        let state = ProxyState::new(config, 4000, 4001);
+        let hit = state
+            .test_redirect("https://www.example.com/docs?v=2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            hit.outcome,
+            RedirectOutcome::Redirect {
+                status: 308,
+                location: "https://example.com/docs?v=2".to_string()
+            }
+        );
+        assert!(
+            state
+                .test_redirect("https://app.example.com/")
+                .unwrap()
+                .is_none()
+        );
+        assert!(state.test_redirect("/no-host").is_err());
+    }
+
     #[test]
     fn test_find_route_strips_port() {
         let state = ProxyState::new(test_config(), 4000, 4001);
@@ -1223,6 +3125,57 @@ mod tests {
         assert!(state.find_route("disabled.example.com").is_none());
     }
 
+    #[test]
+    fn test_metric_label() {
+        let state = ProxyState::new(test_config(), 4000, 4001);
+        assert_eq!(state.metric_label("app.example.com"), "app.example.com");
+        assert_eq!(state.metric_label("random-1234.example.com"), "unknown");
+        assert_eq!(state.metric_label("disabled.example.com"), "unknown");
+        assert_eq!(state.metric_label(""), "");
+    }
+
+    #[test]
+    fn test_find_route_target_path_rules() {
+        let mut config = test_config();
+        config.routes[0].rules = vec![crate::config::PathRule {
+            path_prefix: Some("/grafana".to_string()),
+            target_host: "10.0.0.5".to_string(),
+            target_port: 3100,
+            strip_prefix: true,
+            allowed_groups: vec!["admins".to_string()],
+            ..Default::default()
+        }];
+        let state = ProxyState::new(config, 4000, 4001);
+
+        let (route, target) = state
+            .find_route_target("app.example.com", "/grafana/login?next=/")
+            .unwrap();
+        assert_eq!(route.id, "route-1");
+        assert_eq!(target.target_host, "10.0.0.5");
+        assert_eq!(target.path_and_query, "/login?next=/");
+        assert!(target.require_auth);
+
+        let (_, target) = state
+            .find_route_target("app.example.com", "/index.html")
+            .unwrap();
+        assert_eq!(target.target_port, 3000);
+        assert_eq!(target.path_and_query, "/index.html");
+        assert!(!target.require_auth);
+    }
+
+    #[test]
+    fn test_alt_svc() {
+        let mut config = test_config();
+        let state = ProxyState::new(config.clone(), 4000, 4001);
+        assert_eq!(state.alt_svc(false).unwrap(), "clear");
+        assert!(state.alt_svc(true).is_none());
+
+        config.http3_enabled = true;
+        state.reload_config(config);
+        assert_eq!(state.alt_svc(false).unwrap(), "h3=\":443\"; ma=86400");
+        assert!(state.alt_svc(true).is_none());
+    }
+
     #[test]
     fn test_websocket_upgrade_detection() {
         let req = Request::builder()
@@ -1276,6 +3229,192 @@ mod tests {
         let err = ProxyError::UpstreamError("timeout".to_string());
         let resp = err.into_response();
         assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+        let err = ProxyError::NoHealthyBackend("web".to_string());
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let err = ProxyError::RateLimited(LimitExceeded {
+            kind: ratelimit::LimitKind::Rate,
+            retry_after_secs: 3,
+        });
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "3");
+
+        let err = ProxyError::AccessDenied("not in <adults>".to_string());
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(access_denied_page("not in <adults>").contains("not in &lt;adults&gt;"));
+
+        let resp = ProxyError::InvalidCredentials { basic: true }.into_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key("www-authenticate"));
+    }
+
+    #[test]
+    fn test_identity_headers_replace_client_values() {
+        let user: UserInfo = serde_json::from_value(serde_json::json!({
+            "username": "alice",
+            "uuid": "00000000-0000-0000-0000-000000000000",
+            "displayname": "Alice",
+            "email": "",
+            "created": null,
+            "last_login": null,
+        }))
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Remote-Groups", HeaderValue::from_static("admins"));
+        inject_identity_headers(&mut headers, &user);
+        assert_eq!(headers.get("X-Remote-User").unwrap(), "alice");
+        assert!(headers.get("X-Remote-Groups").is_none());
+
+        let user = UserInfo {
+            groups: vec!["adults".to_string(), "family".to_string()],
+            ..user
+        };
+        inject_identity_headers(&mut headers, &user);
+        assert_eq!(headers.get("X-Remote-Groups").unwrap(), "adults,family");
+    }
+
+    #[test]
+    fn test_take_machine_credential() {
+        let config = MachineAuthConfig {
+            api_keys: true,
+            query_param: Some("api_key".to_string()),
+            ..Default::default()
+        };
+        let mut req = Request::builder()
+            .uri("/hook?a=1&api_key=hrk_q_1&b=2")
+            .header("authorization", "Basic dTpw")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            take_machine_credential(&mut req, &config),
+            Some(MachineCredential::ApiKey("hrk_q_1".to_string()))
+        );
+        assert_eq!(req.uri().to_string(), "/hook?a=1&b=2");
+        // Basic isn't accepted by this route, so it reaches the backend
+        assert!(req.headers().contains_key("authorization"));
+
+        let mut req = Request::builder()
+            .uri("/hook?api_key=hrk_q_1")
+            .header("x-api-key", "hrk_h_1")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            take_machine_credential(&mut req, &config),
+            Some(MachineCredential::ApiKey("hrk_h_1".to_string()))
+        );
+        assert_eq!(req.uri().to_string(), "/hook");
+        assert!(!req.headers().contains_key("x-api-key"));
+
+        let basic = MachineAuthConfig {
+            basic: true,
+            ..Default::default()
+        };
+        let mut req = Request::builder()
+            .uri("/?api_key=hrk_q_1")
+            .header("authorization", "Basic dTpw")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            take_machine_credential(&mut req, &basic),
+            Some(MachineCredential::Basic {
+                login: "u".to_string(),
+                password: "p".to_string(),
+            })
+        );
+        assert_eq!(req.uri().to_string(), "/?api_key=hrk_q_1");
+
+        // The key taken from the query string stays out of the access log
+        let stripped = StrippedParam::default();
+        let logged = "/hook?a=1&api_key=hrk_q_1&b=2".to_string();
+        let mut req = Request::builder()
+            .uri(logged.as_str())
+            .extension(stripped.clone())
+            .body(Body::empty())
+            .unwrap();
+        assert!(take_machine_credential(&mut req, &config).is_some());
+        let logged = logged_path(logged, &stripped);
+        assert_eq!(logged, "/hook?a=1&b=2");
+        assert!(!logged.contains("hrk_q_1"));
+        assert_eq!(
+            logged_path("/hook?api_key=x".to_string(), &StrippedParam::default()),
+            "/hook?api_key=x"
+        );
+    }
+
+    #[test]
+    fn test_client_cert_modes() {
+        let request = |cert: bool| {
+            let mut req = Request::builder()
+                .header("X-Client-Cert-CN", "spoofed")
+                .body(Body::empty())
+                .unwrap();
+            if cert {
+                req.extensions_mut().insert(ClientCertIdentity {
+                    subject: "CN=alice-phone, O=HomeRoute Devices".to_string(),
+                    common_name: "alice-phone".to_string(),
+                    serial: "4a01".to_string(),
+                });
+            }
+            req
+        };
+
+        let mut req = request(true);
+        apply_client_cert(ClientCertMode::Off, &mut req, "git.example.com").unwrap();
+        assert!(!req.headers().contains_key("x-client-cert-cn"));
+
+        let mut req = request(false);
+        apply_client_cert(ClientCertMode::Optional, &mut req, "git.example.com").unwrap();
+        assert!(!req.headers().contains_key("x-client-cert-cn"));
+        assert!(matches!(
+            apply_client_cert(ClientCertMode::Required, &mut req, "git.example.com"),
+            Err(ProxyError::ClientCertRequired)
+        ));
+
+        let mut req = request(true);
+        apply_client_cert(ClientCertMode::Required, &mut req, "git.example.com").unwrap();
+        assert_eq!(req.headers()["x-client-cert-cn"], "alice-phone");
+        assert_eq!(req.headers()["x-client-cert-serial"], "4a01");
+    }
+
+    #[test]
+    fn test_maintenance_and_starting_pages() {
+        let state = ProxyState::new(test_config(), 4000, 4001);
+        let lan: IpAddr = "192.168.1.20".parse().unwrap();
+        let wan: IpAddr = "203.0.113.4".parse().unwrap();
+        state.maintenance.set(
+            "app.example.com".to_string(),
+            Some("Back at <noon>".to_string()),
+            vec!["lan".to_string()],
+        );
+        assert!(state.check_maintenance("app.example.com", lan).is_ok());
+        let err = state.check_maintenance("app.example.com", wan).unwrap_err();
+
+        let resp = state.error_response(err, "app.example.com", true);
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers().contains_key("retry-after"));
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+
+        state.set_app_state("app.example.com", "starting");
+        assert!(state.is_app_starting("app.example.com"));
+        let resp = state.error_response(ProxyError::AppStarting, "app.example.com", false);
+        assert_eq!(resp.headers()["refresh"], "5");
+        state.set_app_state("app.example.com", "running");
+        assert!(!state.is_app_starting("app.example.com"));
+
+        // Non-page errors keep their plain response
+        let resp = state.error_response(
+            ProxyError::AuthRequired(Some("https://auth.example.com/login".to_string())),
+            "app.example.com",
+            true,
+        );
+        assert_eq!(resp.status(), StatusCode::FOUND);
     }
 
     #[test]
@@ -1292,9 +3431,20 @@ mod tests {
             target_host: "localhost".to_string(),
             target_port: 5000,
             local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
             require_auth: false,
+            machine_auth: None,
             enabled: true,
             cert_id: None,
+            pool: None,
+            backend_tls: None,
+            rate_limit: None,
+            compression: None,
+            cache: None,
+            upstream: None,
+            headers: Default::default(),
+            rules: vec![],
         });
         state.reload_config(config);
 
@@ -0,0 +1,331 @@
+//! Declarative header rules for routes.
+//!
+//! A route's `headers` section lists named policies (from
+//! `ProxyConfig::header_policies`, or the built-in ones) followed by its own
+//! request and response rules. Values are templates rendered per request
+//! from the forward-auth identity and the client request.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use hr_auth::forward_auth::ForwardAuthHeaders;
+use hr_auth::users::UserInfo;
+use tracing::warn;
+
+use crate::config::{HeaderPolicy, HeaderRules, RouteHeaders};
+
+/// Policy applied by `"policies": ["strict-security"]` unless the config
+/// defines its own under that name.
+pub const STRICT_SECURITY: &str = "strict-security";
+
+fn builtin_policy(name: &str) -> Option<HeaderPolicy> {
+    if name != STRICT_SECURITY {
+        return None;
+    }
+    let set = [
+        (
+            "Strict-Transport-Security",
+            "max-age=63072000; includeSubDomains",
+        ),
+        ("X-Content-Type-Options", "nosniff"),
+        ("X-Frame-Options", "SAMEORIGIN"),
+        ("Referrer-Policy", "strict-origin-when-cross-origin"),
+        (
+            "Permissions-Policy",
+            "camera=(), microphone=(), geolocation=()",
+        ),
+    ];
+    Some(HeaderPolicy {
+        request: HeaderRules::default(),
+        response: HeaderRules {
+            remove: vec!["Server".to_string(), "X-Powered-By".to_string()],
+            set: set
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            append: Default::default(),
+        },
+    })
+}
+
+/// A route's policies and own rules, flattened in application order.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedHeaders {
+    pub request: Vec<HeaderRules>,
+    pub response: Vec<HeaderRules>,
+}
+
+impl ResolvedHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.request.is_empty() && self.response.is_empty()
+    }
+}
+
+/// Expand `route`'s policy names against `policies` and the built-ins.
+/// Unknown names are skipped (see `unknown_policies`).
+pub fn resolve(policies: &HashMap<String, HeaderPolicy>, route: &RouteHeaders) -> ResolvedHeaders {
+    let mut resolved = ResolvedHeaders::default();
+    let named = route
+        .policies
+        .iter()
+        .filter_map(|name| policies.get(name).cloned().or_else(|| builtin_policy(name)));
+    for policy in named.chain(std::iter::once(route.rules.clone())) {
+        if !policy.request.is_empty() {
+            resolved.request.push(policy.request);
+        }
+        if !policy.response.is_empty() {
+            resolved.response.push(policy.response);
+        }
+    }
+    resolved
+}
+
+/// Policy names in `route` that match neither the config nor a built-in.
+pub fn unknown_policies<'a>(
+    policies: &HashMap<String, HeaderPolicy>,
+    route: &'a RouteHeaders,
+) -> Vec<&'a str> {
+    route
+        .policies
+        .iter()
+        .filter(|name| !policies.contains_key(*name) && builtin_policy(name).is_none())
+        .map(String::as_str)
+        .collect()
+}
+
+/// Request data available to header templates.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    identity: Option<ForwardAuthHeaders>,
+    client_ip: String,
+    host: String,
+    method: String,
+    path: String,
+    request_headers: HeaderMap,
+}
+
+impl TemplateContext {
+    pub fn new(
+        user: Option<&UserInfo>,
+        client_ip: IpAddr,
+        method: &str,
+        path: &str,
+        request_headers: &HeaderMap,
+    ) -> Self {
+        let host = request_headers
+            .get("host")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        Self {
+            identity: user.map(ForwardAuthHeaders::from),
+            client_ip: client_ip.to_string(),
+            host,
+            method: method.to_string(),
+            path: path.to_string(),
+            request_headers: request_headers.clone(),
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<String> {
+        let id = self.identity.as_ref();
+        Some(match name {
+            "user" => id.map(|i| i.remote_user.clone()).unwrap_or_default(),
+            "user_id" => id.map(|i| i.remote_user_id.clone()).unwrap_or_default(),
+            "email" => id.map(|i| i.remote_email.clone()).unwrap_or_default(),
+            "groups" => id.map(|i| i.remote_groups.clone()).unwrap_or_default(),
+            "client_ip" => self.client_ip.clone(),
+            "host" => self.host.clone(),
+            "method" => self.method.clone(),
+            "path" => self.path.clone(),
+            _ => {
+                let header = name.strip_prefix("header.")?;
+                self.request_headers
+                    .get(header)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("")
+                    .to_string()
+            }
+        })
+    }
+
+    /// Replace `{variable}` placeholders; unknown ones are kept verbatim.
+    pub fn render(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after
+                .find('}')
+                .and_then(|end| Some((end, self.variable(&after[..end])?)))
+            {
+                Some((end, value)) => {
+                    out.push_str(&value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Apply `rules` in order to `headers`.
+pub fn apply(rules: &[HeaderRules], headers: &mut HeaderMap, ctx: &TemplateContext) {
+    for rule in rules {
+        for name in &rule.remove {
+            headers.remove(name.as_str());
+        }
+        for (name, template, append) in rule
+            .set
+            .iter()
+            .map(|(n, t)| (n, t, false))
+            .chain(rule.append.iter().map(|(n, t)| (n, t, true)))
+        {
+            let value = ctx.render(template);
+            if value.is_empty() {
+                continue;
+            }
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) if append => {
+                    headers.append(name, value);
+                }
+                (Ok(name), Ok(value)) => {
+                    headers.insert(name, value);
+                }
+                _ => warn!("Invalid header rule {}: {:?}", name, value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(set: &[(&str, &str)], append: &[(&str, &str)], remove: &[&str]) -> HeaderRules {
+        HeaderRules {
+            remove: remove.iter().map(|s| s.to_string()).collect(),
+            set: set
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            append: append
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_templates() {
+        let user: UserInfo = serde_json::from_value(serde_json::json!({
+            "username": "alice",
+            "uuid": "8f14e45f-ceea-467f-a0e6-3f1d1a8e7c9b",
+            "displayname": "Alice",
+            "email": "alice@example.com",
+            "groups": ["admins", "family"],
+            "created": null,
+            "lastLogin": null,
+        }))
+        .unwrap();
+        let mut req = HeaderMap::new();
+        req.insert("host", "app.example.com".parse().unwrap());
+        req.insert("origin", "https://www.example.com".parse().unwrap());
+        let ctx = TemplateContext::new(Some(&user), "10.0.0.5".parse().unwrap(), "GET", "/x", &req);
+
+        assert_eq!(
+            ctx.render("{user} ({groups}) from {client_ip}"),
+            "alice (admins,family) from 10.0.0.5"
+        );
+        assert_eq!(ctx.render("{header.origin}"), "https://www.example.com");
+        assert_eq!(ctx.render("{header.missing}"), "");
+        assert_eq!(
+            ctx.render("{host}{path} {nope} {"),
+            "app.example.com/x {nope} {"
+        );
+
+        let anonymous = TemplateContext::new(None, "10.0.0.5".parse().unwrap(), "GET", "/", &req);
+        assert_eq!(anonymous.render("{user}"), "");
+    }
+
+    #[test]
+    fn test_apply_order() {
+        let ctx = TemplateContext::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "nginx".parse().unwrap());
+        headers.insert("vary", "Origin".parse().unwrap());
+        headers.insert("x-keep", "1".parse().unwrap());
+
+        apply(
+            &[rules(
+                &[("X-Frame-Options", "DENY"), ("X-Empty", "{header.none}")],
+                &[("Vary", "Accept")],
+                &["Server"],
+            )],
+            &mut headers,
+            &ctx,
+        );
+        assert!(!headers.contains_key("server"));
+        assert!(!headers.contains_key("x-empty"));
+        assert_eq!(headers["x-frame-options"], "DENY");
+        assert_eq!(headers.get_all("vary").iter().count(), 2);
+        assert_eq!(headers["x-keep"], "1");
+    }
+
+    #[test]
+    fn test_resolve_policies() {
+        let route = RouteHeaders {
+            policies: vec![
+                STRICT_SECURITY.to_string(),
+                "cors".to_string(),
+                "nope".to_string(),
+            ],
+            rules: HeaderPolicy {
+                response: rules(&[("X-Frame-Options", "DENY")], &[], &[]),
+                ..Default::default()
+            },
+        };
+        let mut policies = HashMap::new();
+        policies.insert(
+            "cors".to_string(),
+            HeaderPolicy {
+                response: rules(
+                    &[("Access-Control-Allow-Origin", "{header.origin}")],
+                    &[],
+                    &[],
+                ),
+                ..Default::default()
+            },
+        );
+        assert_eq!(unknown_policies(&policies, &route), vec!["nope"]);
+
+        let resolved = resolve(&policies, &route);
+        assert!(resolved.request.is_empty());
+        assert_eq!(resolved.response.len(), 3);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-powered-by", "PHP".parse().unwrap());
+        apply(
+            &resolved.response,
+            &mut headers,
+            &TemplateContext::default(),
+        );
+        assert!(!headers.contains_key("x-powered-by"));
+        assert_eq!(
+            headers["strict-transport-security"],
+            "max-age=63072000; includeSubDomains"
+        );
+        // The route's own rules come last and win
+        assert_eq!(headers["x-frame-options"], "DENY");
+    }
+}
@@ -0,0 +1,235 @@
+//! HTTP/3 (QUIC) listener.
+//!
+//! Shares the TCP listener's `SniResolver` and feeds every request through
+//! the same `proxy_handler`, so routing, auth, metrics and access logs behave
+//! identically over QUIC. WebSocket upgrades are HTTP/1.1-only and never
+//! arrive here: browsers open those over TCP.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderValue, Version};
+use axum::response::IntoResponse;
+use bytes::{Buf, Bytes};
+use futures_util::StreamExt;
+use h3::server::RequestStream;
+use tracing::{debug, info};
+
+use crate::device_ca::ClientCertIdentity;
+use crate::handler::{ProxyState, proxy_handler};
+use crate::tls::{DeviceCertVerifier, SniResolver};
+
+/// Connection-specific headers, forbidden in HTTP/3 (RFC 9114 §4.2).
+const HOP_BY_HOP: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// QUIC server config presenting the same certificates, and accepting the
+/// same client certificates, as the TCP listener.
+pub fn server_config(
+    resolver: Arc<SniResolver>,
+    client_auth: Arc<DeviceCertVerifier>,
+) -> Result<quinn::ServerConfig> {
+    let mut tls = rustls::ServerConfig::builder_with_protocol_versions(&[&rustls::version::TLS13])
+        .with_client_cert_verifier(client_auth)
+        .with_cert_resolver(resolver);
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .context("TLS config unsuitable for QUIC")?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Accept QUIC connections on UDP `port` until the endpoint closes.
+pub async fn run_h3_server(
+    state: Arc<ProxyState>,
+    resolver: Arc<SniResolver>,
+    client_auth: Arc<DeviceCertVerifier>,
+    port: u16,
+) -> Result<()> {
+    let addr: SocketAddr = format!("[::]:{}", port).parse()?;
+    let endpoint = quinn::Endpoint::server(server_config(resolver, client_auth)?, addr)
+        .with_context(|| format!("Failed to bind QUIC endpoint on {}", addr))?;
+
+    info!("HTTP/3 proxy listening on {} (udp)", addr);
+
+    while let Some(incoming) = endpoint.accept().await {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let remote = incoming.remote_address();
+            match incoming.await {
+                Ok(conn) => {
+                    if let Err(e) = serve_connection(state, conn).await {
+                        debug!("HTTP/3 connection error from {}: {}", remote, e);
+                    }
+                }
+                Err(e) => debug!("QUIC handshake failed from {}: {}", remote, e),
+            }
+        });
+    }
+
+    anyhow::bail!("QUIC endpoint closed")
+}
+
+async fn serve_connection(state: Arc<ProxyState>, conn: quinn::Connection) -> Result<()> {
+    let client_ip = conn.remote_address().ip();
+    let client_cert = conn
+        .peer_identity()
+        .and_then(|id| {
+            id.downcast::<Vec<rustls::pki_types::CertificateDer<'static>>>()
+                .ok()
+        })
+        .and_then(|certs| ClientCertIdentity::from_peer(Some(&certs)));
+    let mut h3_conn: h3::server::Connection<h3_quinn::Connection, Bytes> =
+        h3::server::Connection::new(h3_quinn::Connection::new(conn)).await?;
+
+    loop {
+        match h3_conn.accept().await {
+            Ok(Some(resolver)) => {
+                let state = state.clone();
+                let client_cert = client_cert.clone();
+                tokio::spawn(async move {
+                    match resolver.resolve_request().await {
+                        Ok((mut req, stream)) => {
+                            if let Some(identity) = client_cert {
+                                req.extensions_mut().insert(identity);
+                            }
+                            if let Err(e) = serve_request(state, client_ip, req, stream).await {
+                                debug!("HTTP/3 request error from {}: {}", client_ip, e);
+                            }
+                        }
+                        Err(e) => debug!("HTTP/3 bad request from {}: {}", client_ip, e),
+                    }
+                });
+            }
+            Ok(None) => return Ok(()),
+            Err(e) if e.is_h3_no_error() => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+async fn serve_request<S>(
+    state: Arc<ProxyState>,
+    client_ip: IpAddr,
+    req: axum::http::Request<()>,
+    stream: RequestStream<S, Bytes>,
+) -> Result<()>
+where
+    S: h3::quic::BidiStream<Bytes> + Send + 'static,
+    S::RecvStream: Send + 'static,
+{
+    let (mut send, mut recv) = stream.split();
+
+    // Stream the request body to the backend as it arrives
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(8);
+    tokio::spawn(async move {
+        loop {
+            match recv.recv_data().await {
+                Ok(Some(mut chunk)) => {
+                    let bytes = chunk.copy_to_bytes(chunk.remaining());
+                    if tx.send(Ok(bytes)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                    break;
+                }
+            }
+        }
+    });
+    let body = Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    }));
+
+    let (mut parts, ()) = req.into_parts();
+    normalize_request_head(&mut parts);
+    let req = Request::from_parts(parts, body);
+
+    let resp = match proxy_handler(state, client_ip, req).await {
+        Ok(resp) => resp,
+        Err(e) => e.into_response(),
+    };
+
+    let (mut parts, body) = resp.into_parts();
+    for name in HOP_BY_HOP {
+        parts.headers.remove(name);
+    }
+    send.send_response(axum::http::Response::from_parts(parts, ()))
+        .await?;
+
+    let mut data = body.into_data_stream();
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        if !chunk.is_empty() {
+            send.send_data(chunk).await?;
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+/// Make an HTTP/3 request head look like what the HTTP/1.1 path sees: the
+/// host comes from `:authority`, split cookie fields are re-joined
+/// (RFC 9114 §4.2.1) and the version is what the backend client speaks.
+fn normalize_request_head(parts: &mut axum::http::request::Parts) {
+    if !parts.headers.contains_key("host")
+        && let Some(authority) = parts.uri.authority()
+        && let Ok(v) = HeaderValue::from_str(authority.as_str())
+    {
+        parts.headers.insert("host", v);
+    }
+
+    let cookies: Vec<&str> = parts
+        .headers
+        .get_all("cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    if cookies.len() > 1
+        && let Ok(v) = HeaderValue::from_str(&cookies.join("; "))
+    {
+        parts.headers.insert("cookie", v);
+    }
+
+    parts.version = Version::HTTP_11;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_request_head() {
+        let req = axum::http::Request::builder()
+            .uri("https://app.example.com/path?q=1")
+            .version(Version::HTTP_3)
+            .header("cookie", "auth_session=abc")
+            .header("cookie", "theme=dark")
+            .body(())
+            .unwrap();
+        let (mut parts, ()) = req.into_parts();
+        normalize_request_head(&mut parts);
+
+        assert_eq!(parts.headers.get("host").unwrap(), "app.example.com");
+        assert_eq!(
+            parts.headers.get_all("cookie").iter().count(),
+            1,
+            "cookie fields must be merged"
+        );
+        assert_eq!(
+            parts.headers.get("cookie").unwrap(),
+            "auth_session=abc; theme=dark"
+        );
+        assert_eq!(parts.version, Version::HTTP_11);
+        assert_eq!(parts.uri.path(), "/path");
+    }
+}
@@ -1,11 +1,54 @@
+pub mod access_index;
+pub mod acl;
+pub mod backend_tls;
+pub mod cache;
+pub mod canary;
+pub mod compress;
 pub mod config;
+pub mod device_ca;
 pub mod handler;
+pub mod headers;
+pub mod http3;
 pub mod logging;
+pub mod maintenance;
 pub mod metrics;
+pub mod ocsp;
+pub mod openmetrics;
+pub mod pages;
+pub mod pool;
+pub mod ratelimit;
+pub mod redirect;
+pub mod rules;
+pub mod stream;
 pub mod tls;
+pub mod transaction;
+pub mod upstream;
 
-pub use config::{ProxyConfig, RouteConfig};
+pub use access_index::{AccessLogIndex, AccessLogQuery};
+pub use backend_tls::{BackendTls, RouteTlsStatus};
+pub use cache::{CacheStats, HttpCache};
+pub use canary::{AppTarget, TrafficSplit};
+pub use config::{
+    AccessList, AccessLogConfig, AccessLogFormat, BackendPool, BackendTlsConfig, BackendTlsVerify,
+    CacheStoreConfig, CircuitBreakerConfig, ClientCertMode, CompressionConfig, DomainAlias,
+    HeaderPolicy, HeaderRules, LogRotation, MachineAuthConfig, OcspConfig, PathRule, ProxyConfig,
+    RateLimitConfig, RateLimitKey, RedirectAction, RedirectRule, RetryConfig, RouteCacheConfig,
+    RouteConfig, RouteHeaders, StreamProtocol, StreamRoute, UpstreamConfig,
+};
+pub use device_ca::{ClientCertIdentity, DeviceRecord, IssuedDevice};
 pub use handler::{AppRoute, ProxyError, ProxyState, proxy_handler};
 pub use logging::{AccessLogEntry, AccessLogger, OptionalAccessLogger};
-pub use metrics::{DomainStats, GlobalStats, ProxyMetrics};
-pub use tls::{SniResolver, TlsManager};
+pub use maintenance::{MaintenanceMode, MaintenanceStore};
+pub use metrics::{BackendStats, DomainStats, GlobalStats, ProxyMetrics, RouteMetrics};
+pub use ocsp::{OcspStapler, StapleState, StapleStatus};
+pub use openmetrics::OpenMetrics;
+pub use pool::{PoolManager, PoolStatus};
+pub use ratelimit::RateLimiter;
+pub use redirect::{RedirectEngine, RedirectMatch, RedirectOutcome};
+pub use stream::{StreamManager, StreamStats};
+pub use tls::{DeviceCertVerifier, SniResolver, TlsManager};
+pub use transaction::{
+    ConfigChange, ConfigDiff, ConfigHistory, ConfigIssue, Revision, RevisionInfo, Severity,
+    ValidationReport,
+};
+pub use upstream::{BreakerState, BreakerStatus, CircuitBreakers};
@@ -1,24 +1,225 @@
-use chrono::Utc;
-use serde::Serialize;
-use std::path::PathBuf;
-use tokio::fs::OpenOptions;
+//! Access log file: one line per request, JSON or Combined Log Format,
+//! rotated on size and/or period. Rotated files are renamed with a
+//! timestamp suffix, optionally gzipped, and pruned by count and age.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use crate::config::{AccessLogConfig, AccessLogFormat, LogRotation};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessLogEntry {
     pub timestamp: String,
     pub client_ip: String,
     pub host: String,
     pub method: String,
     pub path: String,
+    /// Request HTTP version ("HTTP/1.1", "HTTP/2.0", ...).
+    #[serde(default)]
+    pub protocol: String,
     pub status: u16,
     pub duration_ms: u64,
     pub user_agent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+    /// Authenticated username (forward-auth).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Response Content-Length, when known up front.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Limit that rejected the request ("rate", "connections", "websockets").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limited: Option<String>,
+    /// ACL rule that rejected the client (e.g. "deny 203.0.113.0/24").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denied: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Host without port.
+    pub fn domain(&self) -> &str {
+        self.host.split(':').next().unwrap_or(&self.host)
+    }
+
+    /// The entry in Combined Log Format.
+    pub fn combined(&self) -> String {
+        fn quoted(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        let time = DateTime::parse_from_rfc3339(&self.timestamp)
+            .map(|t| t.format("%d/%b/%Y:%H:%M:%S %z").to_string())
+            .unwrap_or_else(|_| self.timestamp.clone());
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\"",
+            self.client_ip,
+            self.user.as_deref().unwrap_or("-"),
+            time,
+            self.method,
+            quoted(&self.path),
+            self.protocol,
+            self.status,
+            self.bytes.map_or("-".to_string(), |b| b.to_string()),
+            quoted(self.referer.as_deref().unwrap_or("-")),
+            quoted(&self.user_agent),
+        )
+    }
+}
+
+/// Rotation period an instant falls in (`None` when rotating on size only).
+fn period(rotation: LogRotation, at: DateTime<Utc>) -> Option<String> {
+    match rotation {
+        LogRotation::Never => None,
+        LogRotation::Hourly => Some(at.format("%Y%m%d%H").to_string()),
+        LogRotation::Daily => Some(at.format("%Y%m%d").to_string()),
+    }
 }
 
-/// Async access logger that writes JSON lines via a channel
+/// The open log file and what decides its next rotation.
+struct LogFile {
+    path: PathBuf,
+    config: AccessLogConfig,
+    file: File,
+    size: u64,
+    period: Option<String>,
+}
+
+impl LogFile {
+    async fn open(path: PathBuf, config: AccessLogConfig) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let meta = file.metadata().await?;
+        // An existing file belongs to the period it was last written in
+        let modified = meta
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        Ok(Self {
+            period: period(config.rotation, modified),
+            size: meta.len(),
+            path,
+            config,
+            file,
+        })
+    }
+
+    async fn write(&mut self, line: &str) -> std::io::Result<()> {
+        let max_size = self.config.max_size_mb * 1024 * 1024;
+        let now_period = period(self.config.rotation, Utc::now());
+        let oversized = max_size > 0 && self.size > 0 && self.size + line.len() as u64 > max_size;
+        if oversized || now_period != self.period {
+            if self.size > 0 {
+                self.rotate().await?;
+            }
+            self.period = now_period;
+        }
+        self.file.write_all(line.as_bytes()).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Move the current file aside and start a new one. Compression and
+    /// pruning run in the background.
+    async fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush().await?;
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+        let mut archived = PathBuf::from(format!("{}.{}", self.path.display(), stamp));
+        let mut n = 1;
+        while tokio::fs::try_exists(&archived).await.unwrap_or(false)
+            || tokio::fs::try_exists(gz_path(&archived))
+                .await
+                .unwrap_or(false)
+        {
+            archived = PathBuf::from(format!("{}.{}-{}", self.path.display(), stamp, n));
+            n += 1;
+        }
+        tokio::fs::rename(&self.path, &archived).await?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        self.size = 0;
+        info!("Rotated access log to {}", archived.display());
+
+        let path = self.path.clone();
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            if config.compress
+                && let Err(e) = compress(&archived).await
+            {
+                warn!("Failed to compress {}: {}", archived.display(), e);
+            }
+            prune(&path, config.max_files, config.max_age_days).await;
+        });
+        Ok(())
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.gz", path.display()))
+}
+
+/// Gzip `path` to `path.gz` and remove it.
+async fn compress(path: &Path) -> std::io::Result<()> {
+    use async_compression::tokio::write::GzipEncoder;
+
+    let mut source = File::open(path).await?;
+    let mut encoder = GzipEncoder::new(File::create(gz_path(path)).await?);
+    tokio::io::copy(&mut source, &mut encoder).await?;
+    encoder.shutdown().await?;
+    tokio::fs::remove_file(path).await
+}
+
+/// Delete the archives of `path` beyond `max_files` (newest kept) or
+/// older than `max_age_days`. Zero disables a limit.
+async fn prune(path: &Path, max_files: usize, max_age_days: u64) {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut archives = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.starts_with(&prefix) {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        archives.push((file_name, modified, entry.path()));
+    }
+    // Suffixes are timestamps: newest first
+    archives.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let max_age = Duration::from_secs(max_age_days * 86_400);
+    for (i, (_, modified, archive)) in archives.iter().enumerate() {
+        let too_many = max_files > 0 && i >= max_files;
+        let too_old = max_age_days > 0 && modified.elapsed().unwrap_or_default() > max_age;
+        if too_many || too_old {
+            match tokio::fs::remove_file(archive).await {
+                Ok(()) => info!("Removed old access log {}", archive.display()),
+                Err(e) => warn!("Failed to remove {}: {}", archive.display(), e),
+            }
+        }
+    }
+}
+
+/// Async access logger that writes lines via a channel
 #[derive(Clone)]
 pub struct AccessLogger {
     sender: mpsc::UnboundedSender<AccessLogEntry>,
@@ -26,17 +227,12 @@ pub struct AccessLogger {
 
 impl AccessLogger {
     /// Start the access logger. Spawns a background task that writes to the log file.
-    pub fn start(log_path: PathBuf) -> Self {
+    pub fn start(log_path: PathBuf, config: AccessLogConfig) -> Self {
         let (sender, mut receiver) = mpsc::unbounded_channel::<AccessLogEntry>();
 
         tokio::spawn(async move {
-            let file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_path)
-                .await;
-
-            let mut file = match file {
+            let format = config.format;
+            let mut file = match LogFile::open(log_path.clone(), config).await {
                 Ok(f) => f,
                 Err(e) => {
                     error!("Failed to open access log file {:?}: {}", log_path, e);
@@ -47,16 +243,18 @@ impl AccessLogger {
             info!("Access logging to {:?}", log_path);
 
             while let Some(entry) = receiver.recv().await {
-                match serde_json::to_string(&entry) {
-                    Ok(json) => {
-                        let line = format!("{}\n", json);
-                        if let Err(e) = file.write_all(line.as_bytes()).await {
-                            error!("Failed to write access log: {}", e);
+                let line = match format {
+                    AccessLogFormat::Json => match serde_json::to_string(&entry) {
+                        Ok(json) => json,
+                        Err(e) => {
+                            error!("Failed to serialize access log entry: {}", e);
+                            continue;
                         }
-                    }
-                    Err(e) => {
-                        error!("Failed to serialize access log entry: {}", e);
-                    }
+                    },
+                    AccessLogFormat::Combined => entry.combined(),
+                };
+                if let Err(e) = file.write(&format!("{}\n", line)).await {
+                    error!("Failed to write access log: {}", e);
                 }
             }
         });
@@ -77,8 +275,8 @@ pub struct OptionalAccessLogger {
 }
 
 impl OptionalAccessLogger {
-    pub fn new(log_path: Option<String>) -> Self {
-        let inner = log_path.map(|p| AccessLogger::start(PathBuf::from(p)));
+    pub fn new(log_path: Option<String>, config: &AccessLogConfig) -> Self {
+        let inner = log_path.map(|p| AccessLogger::start(PathBuf::from(p), config.clone()));
         Self { inner }
     }
 
@@ -97,3 +295,32 @@ impl OptionalAccessLogger {
 pub fn now_timestamp() -> String {
     Utc::now().to_rfc3339()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combined_format() {
+        let entry = AccessLogEntry {
+            timestamp: "2026-03-04T10:20:30+00:00".to_string(),
+            client_ip: "203.0.113.7".to_string(),
+            host: "app.example.com".to_string(),
+            method: "GET".to_string(),
+            path: "/search?q=\"x\"".to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            status: 200,
+            duration_ms: 3,
+            user_agent: "curl/8.0".to_string(),
+            referer: None,
+            user: Some("alice".to_string()),
+            bytes: Some(512),
+            limited: None,
+            denied: None,
+        };
+        assert_eq!(
+            entry.combined(),
+            "203.0.113.7 - alice [04/Mar/2026:10:20:30 +0000] \"GET /search?q=\\\"x\\\" HTTP/1.1\" 200 512 \"-\" \"curl/8.0\""
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Per-domain maintenance mode.
+//!
+//! A domain in maintenance gets the maintenance page (503) instead of its
+//! backend, except for clients matching its allow list (CIDRs, IPs or
+//! address list names, as in route ACLs). The state is toggled at runtime
+//! and persisted so it survives edge restarts.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::acl::Acl;
+use crate::config::AccessList;
+
+/// Maintenance settings of one domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    /// Shown on the maintenance page.
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Clients still reaching the backend.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// When maintenance was turned on.
+    pub since: String,
+}
+
+impl MaintenanceMode {
+    /// Whether `ip` bypasses maintenance.
+    pub fn allows(&self, ip: IpAddr, lists: &HashMap<String, Vec<String>>) -> bool {
+        if self.allow.is_empty() {
+            return false;
+        }
+        let list = AccessList {
+            allow: self.allow.clone(),
+            deny: Vec::new(),
+        };
+        Acl::compile(&list, lists).check(ip).is_ok()
+    }
+}
+
+/// Domains in maintenance, optionally persisted to a JSON file.
+#[derive(Default)]
+pub struct MaintenanceStore {
+    domains: RwLock<HashMap<String, MaintenanceMode>>,
+    path: Option<PathBuf>,
+}
+
+impl MaintenanceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store backed by `path`, loading it if it exists.
+    pub fn with_path(path: PathBuf) -> Self {
+        let domains = match std::fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str::<HashMap<String, MaintenanceMode>>(&json) {
+                Ok(domains) => {
+                    if !domains.is_empty() {
+                        info!("{} domains in maintenance mode", domains.len());
+                    }
+                    domains
+                }
+                Err(e) => {
+                    warn!("Failed to parse {}: {}", path.display(), e);
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        Self {
+            domains: RwLock::new(domains),
+            path: Some(path),
+        }
+    }
+
+    pub fn get(&self, domain: &str) -> Option<MaintenanceMode> {
+        self.domains.read().unwrap().get(domain).cloned()
+    }
+
+    pub fn list(&self) -> HashMap<String, MaintenanceMode> {
+        self.domains.read().unwrap().clone()
+    }
+
+    /// Put `domain` in maintenance (or update its settings).
+    pub fn set(&self, domain: String, message: Option<String>, allow: Vec<String>) {
+        info!(domain = domain, "Maintenance mode on");
+        let mode = MaintenanceMode {
+            message,
+            allow,
+            since: crate::logging::now_timestamp(),
+        };
+        self.domains.write().unwrap().insert(domain, mode);
+        self.persist();
+    }
+
+    /// Take `domain` out of maintenance. Returns false if it wasn't in it.
+    pub fn clear(&self, domain: &str) -> bool {
+        let removed = self.domains.write().unwrap().remove(domain).is_some();
+        if removed {
+            info!(domain = domain, "Maintenance mode off");
+            self.persist();
+        }
+        removed
+    }
+
+    fn persist(&self) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+        let json = match serde_json::to_string_pretty(&*self.domains.read().unwrap()) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize maintenance state: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::write(path, json) {
+            warn!("Failed to persist maintenance state: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_allow_list() {
+        let store = MaintenanceStore::new();
+        store.set(
+            "app.example.com".to_string(),
+            Some("Upgrading".to_string()),
+            vec!["lan".to_string()],
+        );
+        let mode = store.get("app.example.com").unwrap();
+        let lists = HashMap::new();
+        assert!(mode.allows("192.168.1.5".parse().unwrap(), &lists));
+        assert!(!mode.allows("8.8.8.8".parse().unwrap(), &lists));
+
+        store.set("other.example.com".to_string(), None, vec![]);
+        let mode = store.get("other.example.com").unwrap();
+        assert!(!mode.allows("192.168.1.5".parse().unwrap(), &lists));
+
+        assert!(store.clear("app.example.com"));
+        assert!(!store.clear("app.example.com"));
+        assert_eq!(store.list().len(), 1);
+    }
+}
@@ -1,14 +1,146 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use axum::body::Body;
+use bytes::Bytes;
+use hyper::body::{Body as HttpBody, Frame, SizeHint};
 use serde::Serialize;
 
+/// Upper bounds (seconds) of the request latency histogram buckets.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Latency histogram with fixed `LATENCY_BUCKETS`.
+struct Histogram {
+    /// Observations per bucket (not cumulative); slower ones only count
+    /// in `count`.
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: LATENCY_BUCKETS.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            bounds: LATENCY_BUCKETS.to_vec(),
+            counts: self
+                .buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        }
+    }
+}
+
+/// Point-in-time copy of a histogram.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramSnapshot {
+    /// Bucket upper bounds, ascending.
+    pub bounds: Vec<f64>,
+    /// Observations per bucket, not cumulative.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
 /// Per-domain counters.
 struct DomainCounters {
     total_requests: AtomicU64,
     errors_5xx: AtomicU64,
+    rate_limited: AtomicU64,
+    /// Responses by status class (1xx to 5xx).
+    status_classes: [AtomicU64; 5],
+    latency: Histogram,
+    bytes_received: Arc<AtomicU64>,
+    bytes_sent: Arc<AtomicU64>,
+    in_flight: Arc<AtomicU64>,
+    websockets: Arc<AtomicU64>,
+}
+
+/// Holds a gauge up while alive.
+pub struct GaugeGuard(Arc<AtomicU64>);
+
+impl GaugeGuard {
+    pub(crate) fn new(gauge: &Arc<AtomicU64>) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge.clone())
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Body wrapper adding the size of each data frame to a byte counter.
+/// An optional guard is released once the body is done or dropped.
+struct CountingBody {
+    inner: Body,
+    bytes: Arc<AtomicU64>,
+    guard: Option<GaugeGuard>,
+}
+
+impl HttpBody for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.get_mut();
+        let polled = Pin::new(&mut this.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    this.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+                }
+            }
+            Poll::Ready(_) => this.guard = None,
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Per pool-member counters.
+struct BackendCounters {
+    total_requests: AtomicU64,
+    failures: AtomicU64,
 }
 
 /// Simple atomic counter system for proxy metrics.
@@ -17,10 +149,15 @@ struct DomainCounters {
 /// while the actual increments are lock-free `AtomicU64` operations.
 pub struct ProxyMetrics {
     domains: Mutex<HashMap<String, Arc<DomainCounters>>>,
+    /// Keyed by (pool, member address).
+    backends: Mutex<HashMap<(String, String), Arc<BackendCounters>>>,
     global_total: AtomicU64,
     global_2xx: AtomicU64,
     global_4xx: AtomicU64,
     global_5xx: AtomicU64,
+    global_rate_limited: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
     started_at: Instant,
 }
 
@@ -28,10 +165,14 @@ impl ProxyMetrics {
     pub fn new() -> Self {
         Self {
             domains: Mutex::new(HashMap::new()),
+            backends: Mutex::new(HashMap::new()),
             global_total: AtomicU64::new(0),
             global_2xx: AtomicU64::new(0),
             global_4xx: AtomicU64::new(0),
             global_5xx: AtomicU64::new(0),
+            global_rate_limited: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
             started_at: Instant::now(),
         }
     }
@@ -44,14 +185,22 @@ impl ProxyMetrics {
                 Arc::new(DomainCounters {
                     total_requests: AtomicU64::new(0),
                     errors_5xx: AtomicU64::new(0),
+                    rate_limited: AtomicU64::new(0),
+                    status_classes: Default::default(),
+                    latency: Histogram::new(),
+                    bytes_received: Default::default(),
+                    bytes_sent: Default::default(),
+                    in_flight: Default::default(),
+                    websockets: Default::default(),
                 })
             })
             .clone()
     }
 
     /// Record a completed request.  Call once per request after the
-    /// response status code is known.
-    pub fn record_request(&self, domain: &str, status: u16) {
+    /// response status code is known; `elapsed` is the time to the
+    /// response headers.
+    pub fn record_request(&self, domain: &str, status: u16, elapsed: Duration) {
         self.global_total.fetch_add(1, Ordering::Relaxed);
         match status {
             200..=299 => {
@@ -71,6 +220,125 @@ impl ProxyMetrics {
         if (500..600).contains(&status) {
             c.errors_5xx.fetch_add(1, Ordering::Relaxed);
         }
+        if (100..600).contains(&status) {
+            c.status_classes[(status / 100 - 1) as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        c.latency.observe(elapsed);
+    }
+
+    /// Count a request of `domain` as in flight until the guard drops.
+    pub fn in_flight(&self, domain: &str) -> GaugeGuard {
+        GaugeGuard::new(&self.counters(domain).in_flight)
+    }
+
+    /// Count an open WebSocket of `domain` until the guard drops.
+    pub fn websocket(&self, domain: &str) -> GaugeGuard {
+        GaugeGuard::new(&self.counters(domain).websockets)
+    }
+
+    /// Wrap a request body so its bytes count as received by `domain`.
+    pub fn count_received(&self, domain: &str, body: Body) -> Body {
+        Body::new(CountingBody {
+            inner: body,
+            bytes: self.counters(domain).bytes_received.clone(),
+            guard: None,
+        })
+    }
+
+    /// Wrap a response body so its bytes count as sent by `domain`.
+    /// `in_flight` is held until the body is fully sent.
+    pub fn count_sent(&self, domain: &str, body: Body, in_flight: GaugeGuard) -> Body {
+        Body::new(CountingBody {
+            inner: body,
+            bytes: self.counters(domain).bytes_sent.clone(),
+            guard: Some(in_flight),
+        })
+    }
+
+    /// Record a request rejected by a rate or connection limit (in addition
+    /// to `record_request`).
+    pub fn record_limit_hit(&self, domain: &str) {
+        self.global_rate_limited.fetch_add(1, Ordering::Relaxed);
+        self.counters(domain)
+            .rate_limited
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the outcome of a cacheable request (revalidated entries
+    /// count as hits).
+    pub fn record_cache(&self, hit: bool) {
+        if hit {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a request forwarded to a pool member.  `success` is false
+    /// when the member could not be reached.
+    pub fn record_backend(&self, pool: &str, member: &str, success: bool) {
+        let c = {
+            let mut map = self.backends.lock().unwrap();
+            map.entry((pool.to_string(), member.to_string()))
+                .or_insert_with(|| {
+                    Arc::new(BackendCounters {
+                        total_requests: AtomicU64::new(0),
+                        failures: AtomicU64::new(0),
+                    })
+                })
+                .clone()
+        };
+        c.total_requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            c.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Forget the counters of pool members no longer configured.
+    /// `live` holds (pool, member address) pairs.
+    pub fn retain_backends(&self, live: &HashSet<(String, String)>) {
+        self.backends
+            .lock()
+            .unwrap()
+            .retain(|key, _| live.contains(key));
+    }
+
+    /// Snapshot of per pool-member counters, sorted by pool then member.
+    pub fn backend_snapshot(&self) -> Vec<BackendStats> {
+        let map = self.backends.lock().unwrap();
+        let mut out: Vec<BackendStats> = map
+            .iter()
+            .map(|((pool, member), c)| BackendStats {
+                pool: pool.clone(),
+                member: member.clone(),
+                total_requests: c.total_requests.load(Ordering::Relaxed),
+                failures: c.failures.load(Ordering::Relaxed),
+            })
+            .collect();
+        out.sort_by(|a, b| (&a.pool, &a.member).cmp(&(&b.pool, &b.member)));
+        out
+    }
+
+    /// Detailed per-domain metrics, sorted by domain.
+    pub fn route_snapshot(&self) -> Vec<RouteMetrics> {
+        let map = self.domains.lock().unwrap();
+        let mut out: Vec<RouteMetrics> = map
+            .iter()
+            .map(|(domain, c)| RouteMetrics {
+                domain: domain.clone(),
+                status_classes: std::array::from_fn(|i| {
+                    c.status_classes[i].load(Ordering::Relaxed)
+                }),
+                rate_limited: c.rate_limited.load(Ordering::Relaxed),
+                latency: c.latency.snapshot(),
+                bytes_received: c.bytes_received.load(Ordering::Relaxed),
+                bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+                in_flight: c.in_flight.load(Ordering::Relaxed),
+                websockets: c.websockets.load(Ordering::Relaxed),
+            })
+            .collect();
+        out.sort_by(|a, b| a.domain.cmp(&b.domain));
+        out
     }
 
     /// Snapshot of all per-domain metrics for serialization.
@@ -82,6 +350,7 @@ impl ProxyMetrics {
                 domain: domain.clone(),
                 total_requests: c.total_requests.load(Ordering::Relaxed),
                 errors_5xx: c.errors_5xx.load(Ordering::Relaxed),
+                rate_limited: c.rate_limited.load(Ordering::Relaxed),
             })
             .collect();
         out.sort_by(|a, b| b.total_requests.cmp(&a.total_requests));
@@ -97,11 +366,22 @@ impl ProxyMetrics {
         } else {
             0.0
         };
+        let cache_hits = self.cache_hits.load(Ordering::Relaxed);
+        let cache_misses = self.cache_misses.load(Ordering::Relaxed);
+        let cache_lookups = cache_hits + cache_misses;
         GlobalStats {
             total_requests: total,
             status_2xx: self.global_2xx.load(Ordering::Relaxed),
             status_4xx: self.global_4xx.load(Ordering::Relaxed),
             status_5xx: self.global_5xx.load(Ordering::Relaxed),
+            rate_limited: self.global_rate_limited.load(Ordering::Relaxed),
+            cache_hits,
+            cache_misses,
+            cache_hit_ratio: if cache_lookups > 0 {
+                cache_hits as f64 / cache_lookups as f64
+            } else {
+                0.0
+            },
             uptime_secs,
             requests_per_second: rps,
         }
@@ -115,6 +395,11 @@ pub struct GlobalStats {
     pub status_2xx: u64,
     pub status_4xx: u64,
     pub status_5xx: u64,
+    pub rate_limited: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Hits over cacheable requests (0 before the first one).
+    pub cache_hit_ratio: f64,
     pub uptime_secs: u64,
     pub requests_per_second: f64,
 }
@@ -125,4 +410,73 @@ pub struct DomainStats {
     pub domain: String,
     pub total_requests: u64,
     pub errors_5xx: u64,
+    pub rate_limited: u64,
+}
+
+/// Per-domain metrics exported in OpenMetrics format.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteMetrics {
+    pub domain: String,
+    /// Responses by status class, 1xx first.
+    pub status_classes: [u64; 5],
+    pub rate_limited: u64,
+    pub latency: HistogramSnapshot,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub in_flight: u64,
+    pub websockets: u64,
+}
+
+/// Serializable per pool-member stats entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendStats {
+    pub pool: String,
+    pub member: String,
+    pub total_requests: u64,
+    pub failures: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_route_metrics() {
+        let metrics = ProxyMetrics::new();
+        metrics.record_request("a.example.com", 200, Duration::from_millis(30));
+        metrics.record_request("a.example.com", 502, Duration::from_secs(20));
+
+        let guard = metrics.in_flight("a.example.com");
+        let ws = metrics.websocket("a.example.com");
+        let body = metrics.count_sent("a.example.com", Body::from("hello"), guard);
+        assert_eq!(metrics.route_snapshot()[0].in_flight, 1);
+        axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let received = metrics.count_received("a.example.com", Body::from("hi"));
+        axum::body::to_bytes(received, usize::MAX).await.unwrap();
+
+        let route = &metrics.route_snapshot()[0];
+        assert_eq!(route.status_classes, [0, 1, 0, 0, 1]);
+        assert_eq!(route.latency.count, 2);
+        // 30ms lands in the 0.05 bucket, 20s only in the count
+        assert_eq!(route.latency.counts.iter().sum::<u64>(), 1);
+        assert_eq!(route.latency.counts[3], 1);
+        assert_eq!((route.bytes_sent, route.bytes_received), (5, 2));
+        assert_eq!((route.in_flight, route.websockets), (0, 1));
+        drop(ws);
+        assert_eq!(metrics.route_snapshot()[0].websockets, 0);
+    }
+
+    #[test]
+    fn test_retain_backends() {
+        let metrics = ProxyMetrics::new();
+        metrics.record_backend("web", "10.0.0.1:80", true);
+        metrics.record_backend("web", "10.0.0.2:80", false);
+
+        let live = HashSet::from([("web".to_string(), "10.0.0.2:80".to_string())]);
+        metrics.retain_backends(&live);
+        let backends = metrics.backend_snapshot();
+        assert_eq!(backends.len(), 1);
+        assert_eq!(backends[0].member, "10.0.0.2:80");
+        assert_eq!(backends[0].failures, 1);
+    }
 }
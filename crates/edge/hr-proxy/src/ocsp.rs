@@ -0,0 +1,1037 @@
+//! OCSP stapling for ACME certificates.
+//!
+//! Certificates handed to `TlsManager::add_cert` are tracked here: a
+//! background task (`run_refresh`) asks the issuer's OCSP responder (or
+//! `OcspConfig::responder_url`) for the leaf's status and, when the answer
+//! is `good`, attaches the DER response to every `CertifiedKey` of the
+//! `SniResolver` serving that leaf so rustls staples it. Responses are
+//! refreshed halfway through their validity and dropped once expired.
+//!
+//! A response is only stapled when it is signed by the leaf's issuer, or
+//! by a responder certificate the issuer delegated OCSP signing to, and
+//! its CertID matches the leaf's serial and issuer name and key hashes.
+//! The embedded certificate transparency SCTs are reported alongside the
+//! status.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use rustls::pki_types::CertificateDer;
+use rustls::sign::CertifiedKey;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::oid_registry::OID_PKIX_ACCESS_DESCRIPTOR_OCSP;
+use x509_parser::prelude::{SubjectPublicKeyInfo, X509Certificate};
+
+use crate::config::OcspConfig;
+use crate::device_ca::hex;
+use crate::tls::SniResolver;
+
+/// DER of the SHA-1 OID (1.3.14.3.2.26), the hash CertIDs use.
+const SHA1_OID: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+/// DER of id-pkix-ocsp-basic (1.3.6.1.5.5.7.48.1.1).
+const OCSP_BASIC_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// DER of the response signature algorithms we verify.
+const ECDSA_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const ECDSA_SHA384_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const RSA_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const RSA_SHA384_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const RSA_SHA512_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+/// Retry delay after a failed fetch.
+const RETRY_SECS: i64 = 600;
+/// Earliest refresh after a successful fetch.
+const MIN_REFRESH_SECS: i64 = 300;
+/// Refresh interval for responses without `nextUpdate`, and recheck
+/// interval for certificates without a responder.
+const DEFAULT_REFRESH_SECS: i64 = 12 * 3600;
+
+const SEQUENCE: u8 = 0x30;
+const OCTET_STRING: u8 = 0x04;
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+const GENERALIZED_TIME: u8 = 0x18;
+const BIT_STRING: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StapleState {
+    /// Not fetched yet.
+    Pending,
+    /// The responder vouches for the certificate; the response is stapled.
+    Good,
+    Revoked,
+    /// The responder doesn't know the certificate.
+    Unknown,
+    /// The certificate names no OCSP responder and none is configured.
+    NoResponder,
+    /// The last fetch failed (see `error`).
+    Error,
+    Disabled,
+}
+
+/// Certificate status from an OCSP response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// The part of an OCSP response covering our certificate.
+#[derive(Debug, Clone, PartialEq)]
+struct SingleResponse {
+    status: CertStatus,
+    this_update: DateTime<Utc>,
+    next_update: Option<DateTime<Utc>>,
+}
+
+/// A signed certificate timestamp embedded in a certificate.
+#[derive(Debug, Clone, Serialize)]
+pub struct SctInfo {
+    /// Hex SHA-256 of the CT log's public key.
+    pub log_id: String,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// How requests and responses name a certificate (RFC 6960 CertID, with
+/// SHA-1 hashes).
+#[derive(Debug, Clone, PartialEq)]
+struct CertId {
+    issuer_name_hash: Vec<u8>,
+    issuer_key_hash: Vec<u8>,
+    /// DER INTEGER content.
+    serial: Vec<u8>,
+}
+
+impl CertId {
+    fn new(leaf: &X509Certificate, issuer: &X509Certificate) -> Self {
+        let sha1 = |data: &[u8]| {
+            ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data)
+                .as_ref()
+                .to_vec()
+        };
+        Self {
+            issuer_name_hash: sha1(leaf.issuer().as_raw()),
+            issuer_key_hash: sha1(&issuer.public_key().subject_public_key.data),
+            serial: leaf.raw_serial().to_vec(),
+        }
+    }
+
+    fn to_der(&self) -> Vec<u8> {
+        let algorithm = der(SEQUENCE, &[der(OID, SHA1_OID), vec![0x05, 0x00]].concat());
+        der(
+            SEQUENCE,
+            &[
+                algorithm,
+                der(OCTET_STRING, &self.issuer_name_hash),
+                der(OCTET_STRING, &self.issuer_key_hash),
+                der(INTEGER, &self.serial),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Whether the CertID of a SingleResponse names this certificate.
+    fn matches(&self, cert_id: &[u8]) -> Result<bool> {
+        let mut cert_id = DerReader(cert_id);
+        let algorithm = DerReader(cert_id.expect(SEQUENCE)?).expect(OID)?;
+        let name_hash = cert_id.expect(OCTET_STRING)?;
+        let key_hash = cert_id.expect(OCTET_STRING)?;
+        let serial = cert_id.expect(INTEGER)?;
+        Ok(algorithm == SHA1_OID
+            && name_hash == self.issuer_name_hash
+            && key_hash == self.issuer_key_hash
+            && serial == self.serial)
+    }
+}
+
+/// What we need to know about a tracked leaf.
+#[derive(Debug, Clone)]
+struct LeafInfo {
+    /// Serial as encoded in the certificate (DER INTEGER content).
+    serial: Vec<u8>,
+    not_after: Option<DateTime<Utc>>,
+    responder: Option<String>,
+    scts: Vec<SctInfo>,
+}
+
+impl LeafInfo {
+    fn parse(leaf: &[u8]) -> Result<Self> {
+        let (_, cert) =
+            x509_parser::parse_x509_certificate(leaf).context("Invalid leaf certificate")?;
+        let mut responder = None;
+        let mut scts = Vec::new();
+        for ext in cert.iter_extensions() {
+            match ext.parsed_extension() {
+                ParsedExtension::AuthorityInfoAccess(aia) => {
+                    responder = aia.accessdescs.iter().find_map(|desc| {
+                        match (&desc.access_method, &desc.access_location) {
+                            (method, GeneralName::URI(uri))
+                                if *method == OID_PKIX_ACCESS_DESCRIPTOR_OCSP =>
+                            {
+                                Some(uri.to_string())
+                            }
+                            _ => None,
+                        }
+                    });
+                }
+                ParsedExtension::SCT(list) => {
+                    scts = list
+                        .iter()
+                        .map(|sct| SctInfo {
+                            log_id: hex(sct.id.key_id),
+                            timestamp: Utc.timestamp_millis_opt(sct.timestamp as i64).single(),
+                        })
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            serial: cert.raw_serial().to_vec(),
+            not_after: Utc
+                .timestamp_opt(cert.validity().not_after.timestamp(), 0)
+                .single(),
+            responder,
+            scts,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Staple {
+    der: Vec<u8>,
+    this_update: DateTime<Utc>,
+    next_update: Option<DateTime<Utc>>,
+}
+
+impl Staple {
+    fn is_valid(&self, now: DateTime<Utc>) -> bool {
+        self.next_update.is_none_or(|next| next > now)
+    }
+}
+
+struct Entry {
+    chain: Vec<CertificateDer<'static>>,
+    info: LeafInfo,
+    state: StapleState,
+    staple: Option<Staple>,
+    error: Option<String>,
+    checked_at: Option<DateTime<Utc>>,
+    next_refresh: DateTime<Utc>,
+}
+
+/// Stapling status of one certificate, for `/api/acme/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StapleStatus {
+    pub domain: String,
+    /// Hex serial of the leaf certificate.
+    pub serial: String,
+    pub not_after: Option<DateTime<Utc>>,
+    /// Responder queried (the configured override or the certificate's).
+    pub responder: Option<String>,
+    pub state: StapleState,
+    /// A response is currently sent in TLS handshakes.
+    pub stapled: bool,
+    pub this_update: Option<DateTime<Utc>>,
+    pub next_update: Option<DateTime<Utc>>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub next_refresh: DateTime<Utc>,
+    pub error: Option<String>,
+    /// Certificate transparency timestamps embedded in the certificate.
+    pub scts: Vec<SctInfo>,
+}
+
+/// Fetches, caches and staples OCSP responses, keyed by the domain
+/// pattern each certificate is served for.
+pub struct OcspStapler {
+    resolver: Arc<SniResolver>,
+    config: RwLock<OcspConfig>,
+    entries: Mutex<HashMap<String, Entry>>,
+    wake: Notify,
+}
+
+impl OcspStapler {
+    pub fn new(resolver: Arc<SniResolver>) -> Self {
+        Self {
+            resolver,
+            config: RwLock::new(OcspConfig::default()),
+            entries: Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Apply a (re)loaded config: every certificate is checked again.
+    pub fn configure(&self, config: &OcspConfig) {
+        {
+            let mut current = self.config.write().unwrap();
+            if *current == *config {
+                return;
+            }
+            *current = config.clone();
+        }
+        let now = Utc::now();
+        let mut entries = self.entries.lock().unwrap();
+        for entry in entries.values_mut() {
+            entry.next_refresh = now;
+            if !config.enabled && entry.staple.take().is_some() {
+                self.resolver.staple(&entry.chain[0], None);
+            }
+        }
+        drop(entries);
+        self.wake.notify_one();
+    }
+
+    /// Start stapling the certificate served for `domain`. A response
+    /// already fetched for the same leaf is attached right away (e.g. when
+    /// certificates are reloaded).
+    pub fn track(&self, domain: &str, key: &CertifiedKey) {
+        let Some(leaf) = key.cert.first() else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get(domain)
+            && entry.chain[0] == *leaf
+        {
+            if let Some(staple) = entry.staple.as_ref().filter(|s| s.is_valid(Utc::now())) {
+                self.resolver.staple(leaf, Some(staple.der.clone()));
+            }
+            return;
+        }
+        let info = match LeafInfo::parse(leaf) {
+            Ok(info) => info,
+            Err(e) => {
+                warn!(domain, "OCSP stapling unavailable: {:#}", e);
+                return;
+            }
+        };
+        entries.insert(
+            domain.to_string(),
+            Entry {
+                chain: key.cert.clone(),
+                info,
+                state: StapleState::Pending,
+                staple: None,
+                error: None,
+                checked_at: None,
+                next_refresh: Utc::now(),
+            },
+        );
+        drop(entries);
+        self.wake.notify_one();
+    }
+
+    /// Re-attach valid responses, after the resolver's keys were replaced.
+    pub fn reapply(&self) {
+        let now = Utc::now();
+        let entries = self.entries.lock().unwrap();
+        for entry in entries.values() {
+            if let Some(staple) = entry.staple.as_ref().filter(|s| s.is_valid(now)) {
+                self.resolver
+                    .staple(&entry.chain[0], Some(staple.der.clone()));
+            }
+        }
+    }
+
+    pub fn status(&self) -> Vec<StapleStatus> {
+        let override_url = self.config.read().unwrap().responder_url.clone();
+        let entries = self.entries.lock().unwrap();
+        let mut status: Vec<StapleStatus> = entries
+            .iter()
+            .map(|(domain, entry)| StapleStatus {
+                domain: domain.clone(),
+                serial: hex(&entry.info.serial),
+                not_after: entry.info.not_after,
+                responder: override_url
+                    .clone()
+                    .or_else(|| entry.info.responder.clone()),
+                state: entry.state,
+                stapled: entry.staple.is_some(),
+                this_update: entry.staple.as_ref().map(|s| s.this_update),
+                next_update: entry.staple.as_ref().and_then(|s| s.next_update),
+                checked_at: entry.checked_at,
+                next_refresh: entry.next_refresh,
+                error: entry.error.clone(),
+                scts: entry.info.scts.clone(),
+            })
+            .collect();
+        status.sort_by(|a, b| a.domain.cmp(&b.domain));
+        status
+    }
+
+    /// Drop expired responses and fetch the ones due for refresh.
+    pub async fn refresh_due(&self) {
+        let config = self.config.read().unwrap().clone();
+        let now = Utc::now();
+        let due: Vec<(String, Vec<CertificateDer<'static>>, LeafInfo)> = {
+            let mut entries = self.entries.lock().unwrap();
+            for (domain, entry) in entries.iter_mut() {
+                if entry.staple.as_ref().is_some_and(|s| !s.is_valid(now)) {
+                    warn!(domain, "OCSP response expired, no longer stapled");
+                    entry.staple = None;
+                    self.resolver.staple(&entry.chain[0], None);
+                }
+            }
+            entries
+                .iter()
+                .filter(|(_, e)| e.next_refresh <= now)
+                .map(|(d, e)| (d.clone(), e.chain.clone(), e.info.clone()))
+                .collect()
+        };
+
+        for (domain, chain, info) in due {
+            let responder = config.responder_url.clone().or(info.responder.clone());
+            let outcome = match (&responder, config.enabled) {
+                (_, false) => Ok(None),
+                (None, true) => Ok(None),
+                (Some(url), true) => fetch(url, &chain, config.timeout_secs).await.map(Some),
+            };
+            let now = Utc::now();
+            let mut entries = self.entries.lock().unwrap();
+            // The certificate may have been replaced meanwhile
+            let Some(entry) = entries.get_mut(&domain).filter(|e| e.chain[0] == chain[0]) else {
+                continue;
+            };
+            entry.checked_at = Some(now);
+            match outcome {
+                Ok(None) => {
+                    entry.state = if config.enabled {
+                        StapleState::NoResponder
+                    } else {
+                        StapleState::Disabled
+                    };
+                    entry.error = None;
+                    entry.next_refresh = now + chrono::Duration::seconds(DEFAULT_REFRESH_SECS);
+                }
+                Ok(Some((response, der))) => {
+                    entry.error = None;
+                    entry.next_refresh = refresh_time(&response, now);
+                    entry.state = match response.status {
+                        CertStatus::Good => StapleState::Good,
+                        CertStatus::Revoked => StapleState::Revoked,
+                        CertStatus::Unknown => StapleState::Unknown,
+                    };
+                    if response.status == CertStatus::Good {
+                        debug!(domain, "OCSP response stapled");
+                        self.resolver.staple(&chain[0], Some(der.clone()));
+                        entry.staple = Some(Staple {
+                            der,
+                            this_update: response.this_update,
+                            next_update: response.next_update,
+                        });
+                    } else {
+                        warn!(domain, state = ?entry.state, "OCSP responder does not vouch for certificate");
+                        if entry.staple.take().is_some() {
+                            self.resolver.staple(&chain[0], None);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(domain, "OCSP fetch failed: {:#}", e);
+                    // A still-valid response stays stapled
+                    if entry.staple.is_none() {
+                        entry.state = StapleState::Error;
+                    }
+                    entry.error = Some(format!("{:#}", e));
+                    entry.next_refresh = now + chrono::Duration::seconds(RETRY_SECS);
+                }
+            }
+        }
+    }
+
+    /// Time until the next refresh is due, capped at an hour.
+    fn next_wakeup(&self) -> Duration {
+        let now = Utc::now();
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|e| (e.next_refresh - now).to_std().unwrap_or_default())
+            .min()
+            .unwrap_or(Duration::from_secs(3600))
+            .clamp(Duration::from_secs(1), Duration::from_secs(3600))
+    }
+}
+
+/// Keep stapled responses fresh (runs forever).
+pub async fn run_refresh(stapler: Arc<OcspStapler>) {
+    info!("OCSP stapling refresh task started");
+    loop {
+        stapler.refresh_due().await;
+        let wait = stapler.next_wakeup();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = stapler.wake.notified() => {}
+        }
+    }
+}
+
+/// Halfway through the response's validity, not before `MIN_REFRESH_SECS`.
+fn refresh_time(response: &SingleResponse, now: DateTime<Utc>) -> DateTime<Utc> {
+    let target = match response.next_update {
+        Some(next) => response.this_update + (next - response.this_update) / 2,
+        None => now + chrono::Duration::seconds(DEFAULT_REFRESH_SECS),
+    };
+    target.max(now + chrono::Duration::seconds(MIN_REFRESH_SECS))
+}
+
+/// Ask `responder` about the chain's leaf; returns the parsed status and
+/// the raw response to staple.
+async fn fetch(
+    responder: &str,
+    chain: &[CertificateDer<'static>],
+    timeout_secs: u64,
+) -> Result<(SingleResponse, Vec<u8>)> {
+    let issuer = chain
+        .get(1)
+        .context("Issuer certificate missing from chain")?;
+    let (_, leaf) = x509_parser::parse_x509_certificate(&chain[0])?;
+    let (_, issuer) = x509_parser::parse_x509_certificate(issuer)?;
+    let cert_id = CertId::new(&leaf, &issuer);
+    let request = build_request(&cert_id);
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()?;
+    let resp = client
+        .post(responder)
+        .header("content-type", "application/ocsp-request")
+        .body(request)
+        .send()
+        .await
+        .with_context(|| format!("Cannot reach {}", responder))?;
+    if !resp.status().is_success() {
+        bail!("{} answered {}", responder, resp.status());
+    }
+    let der = resp.bytes().await?.to_vec();
+    let response = parse_response(&der, &cert_id, &issuer)?;
+    if response.next_update.is_some_and(|next| next <= Utc::now()) {
+        bail!("Response from {} is already expired", responder);
+    }
+    Ok((response, der))
+}
+
+/// DER OCSPRequest (RFC 6960) for the certificate `cert_id` names.
+fn build_request(cert_id: &CertId) -> Vec<u8> {
+    // OCSPRequest { TBSRequest { requestList { Request { CertID } } } }
+    der(
+        SEQUENCE,
+        &der(SEQUENCE, &der(SEQUENCE, &der(SEQUENCE, &cert_id.to_der()))),
+    )
+}
+
+/// Status of the certificate `cert_id` names in a DER OCSPResponse, once
+/// the response signature is checked against `issuer`.
+fn parse_response(
+    data: &[u8],
+    cert_id: &CertId,
+    issuer: &X509Certificate,
+) -> Result<SingleResponse> {
+    let mut response = DerReader(DerReader(data).expect(SEQUENCE)?);
+    let status = response.expect(ENUMERATED)?;
+    if status != [0] {
+        let name = match status.first() {
+            Some(1) => "malformedRequest",
+            Some(2) => "internalError",
+            Some(3) => "tryLater",
+            Some(5) => "sigRequired",
+            Some(6) => "unauthorized",
+            _ => "unknown",
+        };
+        bail!("Responder refused the request ({})", name);
+    }
+    let mut bytes = DerReader(DerReader(response.expect(0xa0)?).expect(SEQUENCE)?);
+    if bytes.expect(OID)? != OCSP_BASIC_OID {
+        bail!("Unsupported OCSP response type");
+    }
+    let mut basic = DerReader(DerReader(bytes.expect(OCTET_STRING)?).expect(SEQUENCE)?);
+    let tbs = basic.next_raw()?;
+    let algorithm = DerReader(basic.expect(SEQUENCE)?).expect(OID)?;
+    let signature = match basic.expect(BIT_STRING)? {
+        [0, signature @ ..] => signature,
+        _ => bail!("Invalid response signature"),
+    };
+    let mut certs = Vec::new();
+    if basic.peek() == Some(0xa0) {
+        let mut list = DerReader(DerReader(basic.expect(0xa0)?).expect(SEQUENCE)?);
+        while !list.0.is_empty() {
+            certs.push(list.next_raw()?);
+        }
+    }
+    verify_signer(issuer, algorithm, tbs, signature, &certs)?;
+
+    let mut data = DerReader(DerReader(tbs).expect(SEQUENCE)?);
+    if data.peek() == Some(0xa0) {
+        data.next()?; // version
+    }
+    data.next()?; // responderID
+    data.expect(GENERALIZED_TIME)?; // producedAt
+
+    let mut responses = DerReader(data.expect(SEQUENCE)?);
+    while !responses.0.is_empty() {
+        let mut single = DerReader(responses.expect(SEQUENCE)?);
+        if !cert_id.matches(single.expect(SEQUENCE)?)? {
+            continue;
+        }
+        let status = match single.next()?.0 {
+            0x80 => CertStatus::Good,
+            0xa1 => CertStatus::Revoked,
+            0x82 => CertStatus::Unknown,
+            tag => bail!("Invalid certificate status tag {:#x}", tag),
+        };
+        let this_update = generalized_time(single.expect(GENERALIZED_TIME)?)?;
+        let next_update = match single.peek() {
+            Some(0xa0) => Some(generalized_time(
+                DerReader(single.next()?.1).expect(GENERALIZED_TIME)?,
+            )?),
+            _ => None,
+        };
+        return Ok(SingleResponse {
+            status,
+            this_update,
+            next_update,
+        });
+    }
+    bail!("Response does not cover the certificate")
+}
+
+/// Check that the response data was signed by the issuer itself, or by a
+/// responder certificate among `certs` that the issuer signed for OCSP
+/// signing (RFC 6960 section 4.2.2.2).
+fn verify_signer(
+    issuer: &X509Certificate,
+    algorithm: &[u8],
+    tbs: &[u8],
+    signature: &[u8],
+    certs: &[&[u8]],
+) -> Result<()> {
+    if verify_signature(issuer.public_key(), algorithm, tbs, signature).is_ok() {
+        return Ok(());
+    }
+    for cert in certs {
+        let Ok((_, responder)) = x509_parser::parse_x509_certificate(cert) else {
+            continue;
+        };
+        let delegated = responder.issuer().as_raw() == issuer.subject().as_raw()
+            && responder.validity().is_valid()
+            && responder
+                .extended_key_usage()
+                .ok()
+                .flatten()
+                .is_some_and(|eku| eku.value.ocsp_signing)
+            && verify_signature(
+                issuer.public_key(),
+                responder.signature_algorithm.algorithm.as_bytes(),
+                responder.tbs_certificate.as_ref(),
+                &responder.signature_value.data,
+            )
+            .is_ok();
+        if delegated && verify_signature(responder.public_key(), algorithm, tbs, signature).is_ok()
+        {
+            return Ok(());
+        }
+    }
+    bail!("Response is not signed by the issuer or a responder it delegated to")
+}
+
+/// Check `signature` over `message` by `key`, for the signature algorithm
+/// with DER OID `algorithm`.
+fn verify_signature(
+    key: &SubjectPublicKeyInfo,
+    algorithm: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    use ring::signature;
+
+    let point = &key.subject_public_key.data;
+    let scheme: &dyn signature::VerificationAlgorithm = match (algorithm, point.len()) {
+        (ECDSA_SHA256_OID, 65) => &signature::ECDSA_P256_SHA256_ASN1,
+        (ECDSA_SHA256_OID, 97) => &signature::ECDSA_P384_SHA256_ASN1,
+        (ECDSA_SHA384_OID, 65) => &signature::ECDSA_P256_SHA384_ASN1,
+        (ECDSA_SHA384_OID, 97) => &signature::ECDSA_P384_SHA384_ASN1,
+        (RSA_SHA256_OID, _) => &signature::RSA_PKCS1_2048_8192_SHA256,
+        (RSA_SHA384_OID, _) => &signature::RSA_PKCS1_2048_8192_SHA384,
+        (RSA_SHA512_OID, _) => &signature::RSA_PKCS1_2048_8192_SHA512,
+        (ED25519_OID, _) => &signature::ED25519,
+        _ => bail!("Unsupported signature algorithm"),
+    };
+    signature::UnparsedPublicKey::new(scheme, point)
+        .verify(message, signature)
+        .map_err(|_| anyhow::anyhow!("Invalid signature"))
+}
+
+/// `YYYYMMDDHHMMSS[.fff]Z`, fractions ignored.
+fn generalized_time(value: &[u8]) -> Result<DateTime<Utc>> {
+    let text = std::str::from_utf8(value)?;
+    let (Some(digits), true) = (text.get(..14), text.ends_with('Z')) else {
+        bail!("Invalid time '{}'", text);
+    };
+    let time = NaiveDateTime::parse_from_str(digits, "%Y%m%d%H%M%S")
+        .with_context(|| format!("Invalid time '{}'", text))?;
+    Ok(time.and_utc())
+}
+
+/// DER TLV with a definite length.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Reads consecutive DER TLVs (single-byte tags, definite lengths).
+struct DerReader<'a>(&'a [u8]);
+
+impl<'a> DerReader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    fn next(&mut self) -> Result<(u8, &'a [u8])> {
+        let [tag, first, rest @ ..] = self.0 else {
+            bail!("Truncated DER");
+        };
+        let (len, rest) = if first & 0x80 == 0 {
+            (*first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                bail!("Unsupported DER length");
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, &rest[count..])
+        };
+        if rest.len() < len {
+            bail!("Truncated DER");
+        }
+        self.0 = &rest[len..];
+        Ok((*tag, &rest[..len]))
+    }
+
+    /// The next TLV as a whole, tag and length included.
+    fn next_raw(&mut self) -> Result<&'a [u8]> {
+        let start = self.0;
+        self.next()?;
+        Ok(&start[..start.len() - self.0.len()])
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8]> {
+        let (found, content) = self.next()?;
+        if found != tag {
+            bail!("Expected DER tag {:#x}, found {:#x}", tag, found);
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, ExtendedKeyUsagePurpose, IsCa,
+        Issuer, KeyPair, PKCS_ECDSA_P256_SHA256,
+    };
+
+    /// A throwaway CA and a leaf it signed.
+    struct Pki {
+        chain: Vec<CertificateDer<'static>>,
+        leaf_key: KeyPair,
+        ca_params: CertificateParams,
+        ca_key: KeyPair,
+    }
+
+    impl Pki {
+        /// The leaf names `responder` in its AIA.
+        fn new(responder: &str) -> Self {
+            let ca_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+
+            let aia = der(
+                SEQUENCE,
+                &der(
+                    SEQUENCE,
+                    &[
+                        der(OID, &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01]),
+                        der(0x86, responder.as_bytes()),
+                    ]
+                    .concat(),
+                ),
+            );
+            let leaf_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut params = CertificateParams::new(vec!["*.example.com".to_string()]).unwrap();
+            params.custom_extensions = vec![CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+                aia,
+            )];
+            let leaf = params
+                .signed_by(&leaf_key, &Issuer::from_params(&ca_params, &ca_key))
+                .unwrap();
+            Self {
+                chain: vec![leaf.der().clone(), ca.der().clone()],
+                leaf_key,
+                ca_params,
+                ca_key,
+            }
+        }
+
+        fn cert_id(&self) -> CertId {
+            let (_, leaf) = x509_parser::parse_x509_certificate(&self.chain[0]).unwrap();
+            let (_, issuer) = x509_parser::parse_x509_certificate(&self.chain[1]).unwrap();
+            CertId::new(&leaf, &issuer)
+        }
+
+        /// A responder certificate signed by the CA, with or without the
+        /// OCSP signing EKU.
+        fn responder(&self, ocsp_signing: bool) -> (Vec<u8>, KeyPair) {
+            let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            if ocsp_signing {
+                params.extended_key_usages = vec![ExtendedKeyUsagePurpose::OcspSigning];
+            }
+            let cert = params
+                .signed_by(&key, &Issuer::from_params(&self.ca_params, &self.ca_key))
+                .unwrap();
+            (cert.der().to_vec(), key)
+        }
+
+        fn parse(&self, response: &[u8], cert_id: &CertId) -> Result<SingleResponse> {
+            let (_, issuer) = x509_parser::parse_x509_certificate(&self.chain[1]).unwrap();
+            parse_response(response, cert_id, &issuer)
+        }
+    }
+
+    /// BasicOCSPResponse for `cert_id`, signed by `signer` and carrying
+    /// `certs`.
+    fn response(
+        cert_id: &CertId,
+        status: &[u8],
+        next_update: &str,
+        signer: &KeyPair,
+        certs: &[&[u8]],
+    ) -> Vec<u8> {
+        let single = der(
+            SEQUENCE,
+            &[
+                cert_id.to_der(),
+                status.to_vec(),
+                der(GENERALIZED_TIME, b"20260101000000Z"),
+                der(0xa0, &der(GENERALIZED_TIME, next_update.as_bytes())),
+            ]
+            .concat(),
+        );
+        let data = der(
+            SEQUENCE,
+            &[
+                der(0xa2, &der(OCTET_STRING, &[1; 20])),
+                der(GENERALIZED_TIME, b"20260101000000Z"),
+                der(SEQUENCE, &single),
+            ]
+            .concat(),
+        );
+        let rng = ring::rand::SystemRandom::new();
+        let key = ring::signature::EcdsaKeyPair::from_pkcs8(
+            &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+            &signer.serialize_der(),
+            &rng,
+        )
+        .unwrap();
+        let signature = key.sign(&rng, &data).unwrap();
+        let mut basic = vec![
+            data,
+            der(SEQUENCE, &der(OID, ECDSA_SHA256_OID)),
+            der(BIT_STRING, &[&[0], signature.as_ref()].concat()),
+        ];
+        if !certs.is_empty() {
+            basic.push(der(0xa0, &der(SEQUENCE, &certs.concat())));
+        }
+        let basic = der(SEQUENCE, &basic.concat());
+        let bytes = der(
+            SEQUENCE,
+            &[der(OID, OCSP_BASIC_OID), der(OCTET_STRING, &basic)].concat(),
+        );
+        der(
+            SEQUENCE,
+            &[der(ENUMERATED, &[0]), der(0xa0, &bytes)].concat(),
+        )
+    }
+
+    #[test]
+    fn test_request_and_response_parsing() {
+        let pki = Pki::new("http://ocsp.example.test");
+        let info = LeafInfo::parse(&pki.chain[0]).unwrap();
+        assert_eq!(info.responder.as_deref(), Some("http://ocsp.example.test"));
+
+        let cert_id = pki.cert_id();
+        assert_eq!(cert_id.serial, info.serial);
+        let request = build_request(&cert_id);
+        let mut reader = DerReader(&request);
+        assert!(reader.expect(SEQUENCE).is_ok());
+        assert!(reader.0.is_empty());
+
+        let good = response(&cert_id, &[0x80, 0x00], "20260108000000Z", &pki.ca_key, &[]);
+        let parsed = pki.parse(&good, &cert_id).unwrap();
+        assert_eq!(parsed.status, CertStatus::Good);
+        assert_eq!(
+            parsed.next_update.unwrap() - parsed.this_update,
+            chrono::Duration::days(7)
+        );
+        assert_eq!(
+            refresh_time(&parsed, parsed.this_update),
+            parsed.this_update + chrono::Duration::days(7) / 2
+        );
+
+        let revoked = response(
+            &cert_id,
+            &der(0xa1, b""),
+            "20260108000000Z",
+            &pki.ca_key,
+            &[],
+        );
+        assert_eq!(
+            pki.parse(&revoked, &cert_id).unwrap().status,
+            CertStatus::Revoked
+        );
+        let other_serial = CertId {
+            serial: vec![0x42],
+            ..cert_id.clone()
+        };
+        assert!(pki.parse(&revoked, &other_serial).is_err());
+        assert!(
+            pki.parse(&der(SEQUENCE, &der(ENUMERATED, &[3])), &cert_id)
+                .is_err()
+        );
+
+        // Same serial under another issuer key
+        let other_issuer = CertId {
+            issuer_key_hash: vec![0; 20],
+            ..cert_id.clone()
+        };
+        let misissued = response(
+            &other_issuer,
+            &[0x80, 0x00],
+            "20260108000000Z",
+            &pki.ca_key,
+            &[],
+        );
+        assert!(pki.parse(&misissued, &cert_id).is_err());
+
+        // Signed by a key the issuer never vouched for, or altered
+        let forged = response(
+            &cert_id,
+            &[0x80, 0x00],
+            "20260108000000Z",
+            &pki.leaf_key,
+            &[],
+        );
+        assert!(pki.parse(&forged, &cert_id).is_err());
+        let mut tampered = good.clone();
+        let at = tampered
+            .windows(15)
+            .position(|w| w == b"20260108000000Z")
+            .unwrap();
+        tampered[at + 3] = b'9';
+        assert!(pki.parse(&tampered, &cert_id).is_err());
+    }
+
+    #[test]
+    fn test_delegated_responder() {
+        let pki = Pki::new("http://ocsp.example.test");
+        let cert_id = pki.cert_id();
+
+        let (cert, key) = pki.responder(true);
+        let delegated = response(&cert_id, &[0x80, 0x00], "20260108000000Z", &key, &[&cert]);
+        assert_eq!(
+            pki.parse(&delegated, &cert_id).unwrap().status,
+            CertStatus::Good
+        );
+        // Responder certificate not included
+        let bare = response(&cert_id, &[0x80, 0x00], "20260108000000Z", &key, &[]);
+        assert!(pki.parse(&bare, &cert_id).is_err());
+
+        // Issued by the CA but not for OCSP signing
+        let (cert, key) = pki.responder(false);
+        let plain = response(&cert_id, &[0x80, 0x00], "20260108000000Z", &key, &[&cert]);
+        assert!(pki.parse(&plain, &cert_id).is_err());
+
+        // For OCSP signing, but self-issued under the CA's name
+        let other = Pki::new("http://ocsp.example.test");
+        let (cert, key) = Pki {
+            ca_params: pki.ca_params.clone(),
+            ..other
+        }
+        .responder(true);
+        let rogue = response(&cert_id, &[0x80, 0x00], "20260108000000Z", &key, &[&cert]);
+        assert!(pki.parse(&rogue, &cert_id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_staples_from_local_responder() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let pki = Pki::new("http://unreachable.invalid");
+        let canned = response(
+            &pki.cert_id(),
+            &[0x80, 0x00],
+            "29990101000000Z",
+            &pki.ca_key,
+            &[],
+        );
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::post(move || {
+                let canned = canned.clone();
+                async move { canned }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let resolver = Arc::new(SniResolver::new());
+        let key = rustls::crypto::ring::sign::any_supported_type(
+            &rustls::pki_types::PrivateKeyDer::try_from(pki.leaf_key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+        let certified = Arc::new(CertifiedKey::new(pki.chain.clone(), key));
+        resolver.insert("*.example.com".to_string(), certified.clone());
+
+        let stapler = OcspStapler::new(resolver.clone());
+        stapler.configure(&OcspConfig {
+            responder_url: Some(url),
+            ..Default::default()
+        });
+        stapler.track("*.example.com", &certified);
+        stapler.refresh_due().await;
+
+        let status = stapler.status();
+        assert_eq!(status[0].state, StapleState::Good, "{:?}", status[0].error);
+        assert!(status[0].stapled);
+        assert!(resolver.get("*.example.com").unwrap().ocsp.is_some());
+
+        // Reloaded keys get the cached response back
+        resolver.insert("*.example.com".to_string(), certified.clone());
+        stapler.track("*.example.com", &certified);
+        assert!(resolver.get("*.example.com").unwrap().ocsp.is_some());
+    }
+}
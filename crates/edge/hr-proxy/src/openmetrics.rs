@@ -0,0 +1,395 @@
+//! OpenMetrics text exposition.
+//!
+//! Minimal writer for the text format scraped by Prometheus
+//! (<https://github.com/OpenObservability/OpenMetrics>): each family is
+//! declared once with `# TYPE`/`# HELP`, followed by its samples, and the
+//! document ends with `# EOF`. Counter families are declared without the
+//! `_total` suffix their samples carry.
+
+use std::fmt::{Display, Write};
+
+use crate::config::StreamProtocol;
+use crate::handler::ProxyState;
+use crate::metrics::HistogramSnapshot;
+use crate::stream::StreamStats;
+
+/// Content type of an OpenMetrics document.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, Copy)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+/// Accumulates metric families into an OpenMetrics document.
+#[derive(Default)]
+pub struct OpenMetrics {
+    out: String,
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl OpenMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare family `name`; samples written next belong to it.
+    pub fn family(&mut self, name: &str, kind: MetricType, help: &str) {
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind.as_str());
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+    }
+
+    /// Write one sample. `name` carries the sample suffix (`_total`,
+    /// `_bucket`, ...), if any.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (key, val)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                let _ = write!(self.out, "{}=\"{}\"", key, escape_label(val));
+            }
+            self.out.push('}');
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    /// Declare a counter family holding a single unlabelled sample.
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.family(name, MetricType::Counter, help);
+        self.sample(&format!("{}_total", name), &[], value);
+    }
+
+    /// Declare a gauge family holding a single unlabelled sample.
+    pub fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, MetricType::Gauge, help);
+        self.sample(name, &[], value);
+    }
+
+    /// Write the bucket, count and sum samples of histogram `name`.
+    pub fn histogram(&mut self, name: &str, labels: &[(&str, &str)], h: &HistogramSnapshot) {
+        let bucket = format!("{}_bucket", name);
+        let mut cumulative = 0;
+        for (bound, count) in h.bounds.iter().zip(&h.counts) {
+            cumulative += count;
+            // Debug keeps the canonical `1.0` form for whole bounds
+            let le = format!("{:?}", bound);
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &le));
+            self.sample(&bucket, &bucket_labels, cumulative);
+        }
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", "+Inf"));
+        self.sample(&bucket, &bucket_labels, h.count);
+        self.sample(&format!("{}_count", name), labels, h.count);
+        self.sample(&format!("{}_sum", name), labels, h.sum);
+    }
+
+    /// Terminate the document.
+    pub fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Reads one counter of a stream route.
+type StreamValue = fn(&StreamStats) -> u64;
+
+/// Write the reverse proxy families: global and per-domain traffic,
+/// response cache, backend pools and stream routes.
+pub fn write_proxy(m: &mut OpenMetrics, state: &ProxyState) {
+    let global = state.metrics.global_snapshot();
+    m.counter(
+        "hr_proxy_requests",
+        "Total proxied requests.",
+        global.total_requests,
+    );
+    m.counter(
+        "hr_proxy_rate_limited",
+        "Requests rejected by rate or connection limits.",
+        global.rate_limited,
+    );
+    m.gauge(
+        "hr_proxy_uptime_seconds",
+        "Proxy uptime in seconds.",
+        global.uptime_secs,
+    );
+
+    // Response cache
+    let cache = state.cache.stats();
+    m.counter(
+        "hr_proxy_cache_hits",
+        "Cacheable requests served from the cache.",
+        global.cache_hits,
+    );
+    m.counter(
+        "hr_proxy_cache_misses",
+        "Cacheable requests fetched from the backend.",
+        global.cache_misses,
+    );
+    m.gauge("hr_proxy_cache_entries", "Cached responses.", cache.entries);
+    m.family(
+        "hr_proxy_cache_bytes",
+        MetricType::Gauge,
+        "Size of cached bodies by store.",
+    );
+    m.sample(
+        "hr_proxy_cache_bytes",
+        &[("store", "memory")],
+        cache.memory_bytes,
+    );
+    m.sample(
+        "hr_proxy_cache_bytes",
+        &[("store", "disk")],
+        cache.disk_bytes,
+    );
+
+    // Per-domain traffic
+    let routes = state.metrics.route_snapshot();
+    m.family(
+        "hr_proxy_domain_responses",
+        MetricType::Counter,
+        "Responses per domain and status class.",
+    );
+    for r in &routes {
+        for (class, count) in STATUS_CLASSES.iter().zip(r.status_classes) {
+            m.sample(
+                "hr_proxy_domain_responses_total",
+                &[("domain", &r.domain), ("class", class)],
+                count,
+            );
+        }
+    }
+    m.family(
+        "hr_proxy_domain_rate_limited",
+        MetricType::Counter,
+        "Rate-limited requests per domain.",
+    );
+    for r in &routes {
+        m.sample(
+            "hr_proxy_domain_rate_limited_total",
+            &[("domain", &r.domain)],
+            r.rate_limited,
+        );
+    }
+    m.family(
+        "hr_proxy_request_duration_seconds",
+        MetricType::Histogram,
+        "Time to the response headers per domain.",
+    );
+    for r in &routes {
+        m.histogram(
+            "hr_proxy_request_duration_seconds",
+            &[("domain", &r.domain)],
+            &r.latency,
+        );
+    }
+    m.family(
+        "hr_proxy_domain_received_bytes",
+        MetricType::Counter,
+        "Request body bytes received per domain.",
+    );
+    for r in &routes {
+        m.sample(
+            "hr_proxy_domain_received_bytes_total",
+            &[("domain", &r.domain)],
+            r.bytes_received,
+        );
+    }
+    m.family(
+        "hr_proxy_domain_sent_bytes",
+        MetricType::Counter,
+        "Response body bytes sent per domain.",
+    );
+    for r in &routes {
+        m.sample(
+            "hr_proxy_domain_sent_bytes_total",
+            &[("domain", &r.domain)],
+            r.bytes_sent,
+        );
+    }
+    m.family(
+        "hr_proxy_domain_active_requests",
+        MetricType::Gauge,
+        "Requests in flight per domain, until their body is sent.",
+    );
+    for r in &routes {
+        m.sample(
+            "hr_proxy_domain_active_requests",
+            &[("domain", &r.domain)],
+            r.in_flight,
+        );
+    }
+    m.family(
+        "hr_proxy_domain_websockets",
+        MetricType::Gauge,
+        "Open WebSocket connections per domain.",
+    );
+    for r in &routes {
+        m.sample(
+            "hr_proxy_domain_websockets",
+            &[("domain", &r.domain)],
+            r.websockets,
+        );
+    }
+
+    // Backend pools
+    let pools = state.pools.status();
+    m.family(
+        "hr_proxy_backend_up",
+        MetricType::Gauge,
+        "Whether a pool member receives traffic (1=yes).",
+    );
+    for p in &pools {
+        for member in &p.members {
+            m.sample(
+                "hr_proxy_backend_up",
+                &[("pool", &p.name), ("member", &member.address)],
+                u8::from(member.healthy && !member.ejected),
+            );
+        }
+    }
+    let backends = state.metrics.backend_snapshot();
+    m.family(
+        "hr_proxy_backend_requests",
+        MetricType::Counter,
+        "Requests forwarded per pool member.",
+    );
+    for b in &backends {
+        m.sample(
+            "hr_proxy_backend_requests_total",
+            &[("pool", &b.pool), ("member", &b.member)],
+            b.total_requests,
+        );
+    }
+    m.family(
+        "hr_proxy_backend_failures",
+        MetricType::Counter,
+        "Failed connections per pool member.",
+    );
+    for b in &backends {
+        m.sample(
+            "hr_proxy_backend_failures_total",
+            &[("pool", &b.pool), ("member", &b.member)],
+            b.failures,
+        );
+    }
+
+    // Stream routes
+    let streams = state.streams.stats();
+    let families: [(&str, MetricType, &str, StreamValue); 6] = [
+        (
+            "hr_proxy_stream_connections",
+            MetricType::Counter,
+            "TCP connections or UDP sessions per stream route.",
+            |s| s.connections,
+        ),
+        (
+            "hr_proxy_stream_active",
+            MetricType::Gauge,
+            "Open connections or sessions per stream route.",
+            |s| s.active,
+        ),
+        (
+            "hr_proxy_stream_denied",
+            MetricType::Counter,
+            "Clients rejected by a stream route's ACL.",
+            |s| s.denied,
+        ),
+        (
+            "hr_proxy_stream_failures",
+            MetricType::Counter,
+            "Stream connections that could not reach the backend.",
+            |s| s.failures,
+        ),
+        (
+            "hr_proxy_stream_received_bytes",
+            MetricType::Counter,
+            "Bytes received from clients per stream route.",
+            |s| s.bytes_received,
+        ),
+        (
+            "hr_proxy_stream_sent_bytes",
+            MetricType::Counter,
+            "Bytes sent to clients per stream route.",
+            |s| s.bytes_sent,
+        ),
+    ];
+    for (name, kind, help, value) in families {
+        m.family(name, kind, help);
+        let sample = match kind {
+            MetricType::Counter => format!("{}_total", name),
+            _ => name.to_string(),
+        };
+        for s in &streams {
+            let protocol = match s.protocol {
+                StreamProtocol::Tcp => "tcp",
+                StreamProtocol::Udp => "udp",
+                StreamProtocol::TlsPassthrough => "tls_passthrough",
+            };
+            m.sample(
+                &sample,
+                &[("stream", &s.id), ("protocol", protocol)],
+                value(s),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_families_and_histogram() {
+        let mut m = OpenMetrics::new();
+        m.counter("hr_requests", "Requests.", 3);
+        m.family("hr_latency_seconds", MetricType::Histogram, "Latency.");
+        m.histogram(
+            "hr_latency_seconds",
+            &[("domain", "a\"b")],
+            &HistogramSnapshot {
+                bounds: vec![0.1, 1.0],
+                counts: vec![1, 2],
+                count: 4,
+                sum: 7.5,
+            },
+        );
+        assert_eq!(
+            m.finish(),
+            "# TYPE hr_requests counter\n\
+             # HELP hr_requests Requests.\n\
+             hr_requests_total 3\n\
+             # TYPE hr_latency_seconds histogram\n\
+             # HELP hr_latency_seconds Latency.\n\
+             hr_latency_seconds_bucket{domain=\"a\\\"b\",le=\"0.1\"} 1\n\
+             hr_latency_seconds_bucket{domain=\"a\\\"b\",le=\"1.0\"} 3\n\
+             hr_latency_seconds_bucket{domain=\"a\\\"b\",le=\"+Inf\"} 4\n\
+             hr_latency_seconds_count{domain=\"a\\\"b\"} 4\n\
+             hr_latency_seconds_sum{domain=\"a\\\"b\"} 7.5\n\
+             # EOF\n"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! HTML pages served in place of proxy errors.
+//!
+//! Each page is a template looked up by name (`"404"`, `"502"`,
+//! `"maintenance"`, `"starting"`, ...) in `ProxyConfig::error_pages_dir`,
+//! falling back to a built-in page. Templates may use `{{status}}`,
+//! `{{title}}`, `{{message}}` and `{{domain}}`; values are HTML-escaped.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use tracing::{info, warn};
+
+/// Page names a template directory may provide.
+pub const PAGE_NAMES: &[&str] = &[
+    "401",
+    "403",
+    "404",
+    "429",
+    "502",
+    "503",
+    "504",
+    "maintenance",
+    "starting",
+];
+
+/// Seconds between reloads of the "app is starting" page.
+pub const STARTING_REFRESH_SECS: u64 = 5;
+
+const BUILTIN: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+{{head}}<title>{{title}}</title></head>\
+<body style=\"font-family:sans-serif;text-align:center;margin-top:15vh;color:#222\">\
+<h1>{{title}}</h1><p>{{message}}</p>\
+<p style=\"color:#888;font-size:.85em\">{{domain}} &middot; HomeRoute</p></body></html>";
+
+/// Values substituted into a page template.
+pub struct PageVars<'a> {
+    pub status: u16,
+    pub title: &'a str,
+    pub message: &'a str,
+    pub domain: &'a str,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Templates loaded from the configured directory.
+#[derive(Default)]
+pub struct ErrorPages {
+    templates: HashMap<&'static str, String>,
+}
+
+impl ErrorPages {
+    /// Read the known page names from `dir`; missing files are skipped.
+    pub fn load(dir: Option<&Path>) -> Self {
+        let mut templates = HashMap::new();
+        let Some(dir) = dir else {
+            return Self { templates };
+        };
+        for name in PAGE_NAMES {
+            let path = dir.join(format!("{}.html", name));
+            match std::fs::read_to_string(&path) {
+                Ok(html) => {
+                    templates.insert(*name, html);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Failed to read error page {}: {}", path.display(), e),
+            }
+        }
+        if !templates.is_empty() {
+            info!(
+                "Loaded {} custom error pages from {}",
+                templates.len(),
+                dir.display()
+            );
+        }
+        Self { templates }
+    }
+
+    /// Render page `name`.
+    pub fn render(&self, name: &str, vars: &PageVars) -> String {
+        let builtin;
+        let template = match self.templates.get(name) {
+            Some(t) => t.as_str(),
+            None => {
+                // The built-in starting page reloads itself
+                let head = if name == "starting" {
+                    format!(
+                        "<meta http-equiv=\"refresh\" content=\"{}\">",
+                        STARTING_REFRESH_SECS
+                    )
+                } else {
+                    String::new()
+                };
+                builtin = BUILTIN.replace("{{head}}", &head);
+                builtin.as_str()
+            }
+        };
+        template
+            .replace("{{status}}", &vars.status.to_string())
+            .replace("{{title}}", &escape(vars.title))
+            .replace("{{message}}", &escape(vars.message))
+            .replace("{{domain}}", &escape(vars.domain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_custom_and_builtin() {
+        let dir = std::env::temp_dir().join(format!("hr-pages-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("502.html"),
+            "<h1>{{status}} on {{domain}}</h1><p>{{message}}</p>",
+        )
+        .unwrap();
+        let pages = ErrorPages::load(Some(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let vars = PageVars {
+            status: 502,
+            title: "Bad Gateway",
+            message: "backend <down>",
+            domain: "app.example.com",
+        };
+        assert_eq!(
+            pages.render("502", &vars),
+            "<h1>502 on app.example.com</h1><p>backend &lt;down&gt;</p>"
+        );
+
+        let page = pages.render("starting", &vars);
+        assert!(page.contains("http-equiv=\"refresh\""));
+        assert!(page.contains("<title>Bad Gateway</title>"));
+        assert!(!pages.render("404", &vars).contains("refresh"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Backend pools: member selection, passive ejection and active health checks.
+//!
+//! Pools are declared in `ProxyConfig::pools` and referenced by name from
+//! routes, path rules and app routes. A member is eligible for traffic when
+//! it is marked healthy by the active probes (members start healthy) and is
+//! not currently ejected after repeated connection errors.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::HeaderMap;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{BackendPool, LbStrategy, PoolMember};
+
+/// Runtime state of one pool member.
+#[derive(Debug)]
+pub struct MemberState {
+    pub host: String,
+    pub port: u16,
+    healthy: AtomicBool,
+    active: AtomicUsize,
+    probe_successes: AtomicU32,
+    probe_failures: AtomicU32,
+    passive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl MemberState {
+    fn new(member: &PoolMember) -> Self {
+        Self {
+            host: member.host.clone(),
+            port: member.port,
+            healthy: AtomicBool::new(true),
+            active: AtomicUsize::new(0),
+            probe_successes: AtomicU32::new(0),
+            probe_failures: AtomicU32::new(0),
+            passive_failures: AtomicU32::new(0),
+            ejected_until: Mutex::new(None),
+        }
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn is_ejected(&self) -> bool {
+        self.ejected_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn is_available(&self) -> bool {
+        self.is_healthy() && !self.is_ejected()
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps a member's in-flight counter raised while a request is outstanding.
+pub struct MemberGuard {
+    pub member: Arc<MemberState>,
+}
+
+impl MemberGuard {
+    fn new(member: Arc<MemberState>) -> Self {
+        member.active.fetch_add(1, Ordering::Relaxed);
+        Self { member }
+    }
+}
+
+impl Drop for MemberGuard {
+    fn drop(&mut self) {
+        self.member.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A configured pool with its members' runtime state.
+#[derive(Debug)]
+pub struct Pool {
+    pub config: BackendPool,
+    pub members: Vec<Arc<MemberState>>,
+    next: AtomicUsize,
+    last_probe: Mutex<Option<Instant>>,
+}
+
+impl Pool {
+    fn new(config: BackendPool, members: Vec<Arc<MemberState>>) -> Self {
+        Self {
+            config,
+            members,
+            next: AtomicUsize::new(0),
+            last_probe: Mutex::new(None),
+        }
+    }
+
+    /// Pick a member for a request, or `None` if every member is down.
+    pub fn select(&self, headers: &HeaderMap, client_ip: IpAddr) -> Option<MemberGuard> {
+        self.select_filtered(headers, client_ip, |_| true)
+    }
+
+    /// Like `select`, among the available members `accept` keeps.
+    pub fn select_filtered(
+        &self,
+        headers: &HeaderMap,
+        client_ip: IpAddr,
+        accept: impl Fn(&MemberState) -> bool,
+    ) -> Option<MemberGuard> {
+        let available: Vec<&Arc<MemberState>> = self
+            .members
+            .iter()
+            .filter(|m| m.is_available() && accept(m))
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+
+        let chosen = match self.config.strategy {
+            LbStrategy::RoundRobin => {
+                let i = self.next.fetch_add(1, Ordering::Relaxed);
+                available[i % available.len()]
+            }
+            LbStrategy::LeastConnections => {
+                // Rotate the starting point so ties are spread evenly
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % available.len();
+                available
+                    .iter()
+                    .cycle()
+                    .skip(start)
+                    .take(available.len())
+                    .min_by_key(|m| m.active_connections())
+                    .copied()?
+            }
+            LbStrategy::ConsistentHash => {
+                let key = self
+                    .config
+                    .hash_cookie
+                    .as_deref()
+                    .and_then(|name| cookie_value(headers, name))
+                    .unwrap_or_else(|| client_ip.to_string());
+                // Rendezvous hashing: only keys owned by a removed member move
+                available
+                    .iter()
+                    .max_by_key(|m| {
+                        let mut h = DefaultHasher::new();
+                        key.hash(&mut h);
+                        m.host.hash(&mut h);
+                        m.port.hash(&mut h);
+                        h.finish()
+                    })
+                    .copied()?
+            }
+        };
+        Some(MemberGuard::new(chosen.clone()))
+    }
+
+    /// Record the outcome of a proxied request (passive health).
+    pub fn report(&self, member: &MemberState, success: bool) {
+        if success {
+            member.passive_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        if self.config.max_fails == 0 {
+            return;
+        }
+        let fails = member.passive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if fails >= self.config.max_fails {
+            member.passive_failures.store(0, Ordering::Relaxed);
+            *member.ejected_until.lock().unwrap() =
+                Some(Instant::now() + Duration::from_secs(self.config.eject_secs));
+            warn!(
+                pool = %self.config.name,
+                member = %member.address(),
+                "Ejected backend after {} consecutive errors ({}s)",
+                fails,
+                self.config.eject_secs
+            );
+        }
+    }
+
+    /// Apply an active probe result against the configured thresholds.
+    fn record_probe(&self, member: &MemberState, success: bool) {
+        let hc = &self.config.health_check;
+        if success {
+            member.probe_failures.store(0, Ordering::Relaxed);
+            let ok = member.probe_successes.fetch_add(1, Ordering::Relaxed) + 1;
+            if !member.is_healthy() && ok >= hc.healthy_threshold {
+                member.healthy.store(true, Ordering::Relaxed);
+                info!(pool = %self.config.name, member = %member.address(), "Backend healthy");
+            }
+        } else {
+            member.probe_successes.store(0, Ordering::Relaxed);
+            let fails = member.probe_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if member.is_healthy() && fails >= hc.unhealthy_threshold {
+                member.healthy.store(false, Ordering::Relaxed);
+                warn!(pool = %self.config.name, member = %member.address(), "Backend unhealthy");
+            }
+        }
+    }
+
+    fn status(&self) -> PoolStatus {
+        PoolStatus {
+            name: self.config.name.clone(),
+            strategy: self.config.strategy,
+            health_check: self.config.health_check.enabled,
+            members: self
+                .members
+                .iter()
+                .map(|m| MemberStatus {
+                    address: m.address(),
+                    healthy: m.is_healthy(),
+                    ejected: m.is_ejected(),
+                    active_connections: m.active_connections(),
+                })
+                .collect(),
+        }
+    }
+}
+
+pub(crate) fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all("cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| {
+            let (k, v) = c.trim().split_once('=')?;
+            (k == name).then(|| v.to_string())
+        })
+}
+
+/// Serializable pool state for the status API.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    pub name: String,
+    pub strategy: LbStrategy,
+    pub health_check: bool,
+    pub members: Vec<MemberStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberStatus {
+    pub address: String,
+    pub healthy: bool,
+    pub ejected: bool,
+    pub active_connections: usize,
+}
+
+/// All pools, rebuilt on config reload.
+#[derive(Default)]
+pub struct PoolManager {
+    pools: RwLock<HashMap<String, Arc<Pool>>>,
+}
+
+impl PoolManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the pool set, keeping the runtime state of members whose
+    /// address is unchanged so a reload does not reset health.
+    pub fn reconcile(&self, configs: &[BackendPool]) {
+        let mut pools = self.pools.write().unwrap();
+        let mut next = HashMap::new();
+        for config in configs {
+            let previous = pools.get(&config.name);
+            let members = config
+                .members
+                .iter()
+                .map(|m| {
+                    previous
+                        .and_then(|p| {
+                            p.members
+                                .iter()
+                                .find(|s| s.host == m.host && s.port == m.port)
+                        })
+                        .cloned()
+                        .unwrap_or_else(|| Arc::new(MemberState::new(m)))
+                })
+                .collect();
+            next.insert(
+                config.name.clone(),
+                Arc::new(Pool::new(config.clone(), members)),
+            );
+        }
+        *pools = next;
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Pool>> {
+        self.pools.read().unwrap().get(name).cloned()
+    }
+
+    /// Pool state sorted by name.
+    pub fn status(&self) -> Vec<PoolStatus> {
+        let pools = self.pools.read().unwrap();
+        let mut out: Vec<PoolStatus> = pools.values().map(|p| p.status()).collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
+    fn all(&self) -> Vec<Arc<Pool>> {
+        self.pools.read().unwrap().values().cloned().collect()
+    }
+}
+
+/// Probe every pool with active health checks enabled, each at its own
+/// interval. Runs until the process exits.
+pub async fn run_health_checks(manager: Arc<PoolManager>) {
+    let client: Client<_, Body> = Client::builder(TokioExecutor::new()).build_http();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tick.tick().await;
+        for pool in manager.all() {
+            let hc = &pool.config.health_check;
+            if !hc.enabled {
+                continue;
+            }
+            {
+                let mut last = pool.last_probe.lock().unwrap();
+                if last.is_some_and(|t| t.elapsed() < Duration::from_secs(hc.interval_secs)) {
+                    continue;
+                }
+                *last = Some(Instant::now());
+            }
+            for member in pool.members.clone() {
+                let pool = pool.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let ok = probe(&client, &pool, &member).await;
+                    pool.record_probe(&member, ok);
+                });
+            }
+        }
+    }
+}
+
+async fn probe(
+    client: &Client<hyper_util::client::legacy::connect::HttpConnector, Body>,
+    pool: &Pool,
+    member: &MemberState,
+) -> bool {
+    let hc = &pool.config.health_check;
+    let uri = format!("http://{}{}", member.address(), hc.path);
+    let Ok(req) = axum::http::Request::get(uri).body(Body::empty()) else {
+        return false;
+    };
+    match tokio::time::timeout(Duration::from_millis(hc.timeout_ms), client.request(req)).await {
+        Ok(Ok(resp)) => {
+            let status = resp.status().as_u16();
+            if hc.expected_status.is_empty() {
+                (200..400).contains(&status)
+            } else {
+                hc.expected_status.contains(&status)
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HealthCheckConfig;
+
+    fn pool_config(strategy: LbStrategy) -> BackendPool {
+        BackendPool {
+            name: "web".to_string(),
+            strategy,
+            hash_cookie: Some("sid".to_string()),
+            members: (1..=3)
+                .map(|i| PoolMember {
+                    host: format!("10.0.0.{}", i),
+                    port: 8080,
+                })
+                .collect(),
+            health_check: HealthCheckConfig::default(),
+            max_fails: 2,
+            eject_secs: 30,
+        }
+    }
+
+    fn manager(strategy: LbStrategy) -> (PoolManager, Arc<Pool>) {
+        let m = PoolManager::new();
+        m.reconcile(&[pool_config(strategy)]);
+        let pool = m.get("web").unwrap();
+        (m, pool)
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[test]
+    fn test_round_robin_skips_unavailable() {
+        let (_m, pool) = manager(LbStrategy::RoundRobin);
+        let headers = HeaderMap::new();
+        let picks: Vec<String> = (0..3)
+            .map(|_| pool.select(&headers, ip(1)).unwrap().member.host.clone())
+            .collect();
+        assert_eq!(picks, vec!["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+
+        // Active probes take member 2 down after 3 failures
+        for _ in 0..3 {
+            pool.record_probe(&pool.members[1], false);
+        }
+        // Passive ejection takes member 3 out after max_fails errors
+        pool.report(&pool.members[2], false);
+        assert!(!pool.members[2].is_ejected());
+        pool.report(&pool.members[2], false);
+        assert!(pool.members[2].is_ejected());
+
+        for _ in 0..4 {
+            let g = pool.select(&headers, ip(1)).unwrap();
+            assert_eq!(g.member.host, "10.0.0.1");
+        }
+
+        pool.record_probe(&pool.members[0], false);
+        pool.record_probe(&pool.members[0], false);
+        pool.record_probe(&pool.members[0], false);
+        assert!(pool.select(&headers, ip(1)).is_none());
+
+        // Recovery needs healthy_threshold successes
+        pool.record_probe(&pool.members[1], true);
+        assert!(pool.select(&headers, ip(1)).is_none());
+        pool.record_probe(&pool.members[1], true);
+        assert_eq!(
+            pool.select(&headers, ip(1)).unwrap().member.host,
+            "10.0.0.2"
+        );
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let (_m, pool) = manager(LbStrategy::LeastConnections);
+        let headers = HeaderMap::new();
+        let a = pool.select(&headers, ip(1)).unwrap();
+        let b = pool.select(&headers, ip(1)).unwrap();
+        assert_ne!(a.member.host, b.member.host);
+        let c = pool.select(&headers, ip(1)).unwrap();
+        let mut hosts = vec![
+            a.member.host.clone(),
+            b.member.host.clone(),
+            c.member.host.clone(),
+        ];
+        hosts.sort();
+        assert_eq!(hosts, vec!["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+
+        // Releasing b makes it the only idle-most member
+        let freed = b.member.host.clone();
+        drop(b);
+        assert_eq!(pool.select(&headers, ip(1)).unwrap().member.host, freed);
+        assert_eq!(a.member.active_connections(), 1);
+    }
+
+    #[test]
+    fn test_consistent_hash_by_cookie_and_ip() {
+        let (_m, pool) = manager(LbStrategy::ConsistentHash);
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", "theme=dark; sid=abc123".parse().unwrap());
+
+        let first = pool.select(&headers, ip(1)).unwrap().member.host.clone();
+        for last in 2..10 {
+            // Same cookie wins over a changing client IP
+            assert_eq!(pool.select(&headers, ip(last)).unwrap().member.host, first);
+        }
+
+        // Without the cookie, the client IP is the key
+        let empty = HeaderMap::new();
+        let by_ip = pool.select(&empty, ip(7)).unwrap().member.host.clone();
+        assert_eq!(pool.select(&empty, ip(7)).unwrap().member.host, by_ip);
+
+        // Ejecting another member does not move the key
+        let other = pool.members.iter().find(|m| m.host != first).unwrap();
+        pool.report(other, false);
+        pool.report(other, false);
+        assert_eq!(pool.select(&headers, ip(1)).unwrap().member.host, first);
+    }
+
+    #[test]
+    fn test_reconcile_keeps_member_state() {
+        let (m, pool) = manager(LbStrategy::RoundRobin);
+        for _ in 0..3 {
+            pool.record_probe(&pool.members[0], false);
+        }
+
+        let mut config = pool_config(LbStrategy::RoundRobin);
+        config.members.push(PoolMember {
+            host: "10.0.0.4".to_string(),
+            port: 8080,
+        });
+        m.reconcile(&[config]);
+
+        let status = m.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].members.len(), 4);
+        assert!(!status[0].members[0].healthy);
+        assert!(status[0].members[3].healthy);
+
+        m.reconcile(&[]);
+        assert!(m.get("web").is_none());
+    }
+}
@@ -0,0 +1,354 @@
+//! Per-route request rate limits and concurrency caps.
+//!
+//! Every route (static or app) with a `RateLimitConfig` gets its own scope;
+//! within a scope, clients are told apart by IP (IPv6 clients by their /64),
+//! authenticated user or a header. Rates use a token bucket refilled
+//! continuously; concurrency caps use counters released by `LimitGuard` when
+//! the response body (or the WebSocket bridge) finishes. Idle entries are
+//! dropped by [`run_sweeper`], off the request path.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use futures_util::StreamExt;
+
+use crate::config::{RateLimitConfig, RateLimitKey};
+
+/// Buckets idle for this long are dropped by the sweeper.
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+/// How often the sweeper runs.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Which limit rejected a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Rate,
+    Connections,
+    WebSockets,
+}
+
+impl LimitKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LimitKind::Rate => "rate",
+            LimitKind::Connections => "connections",
+            LimitKind::WebSockets => "websockets",
+        }
+    }
+}
+
+/// A rejected request: the limit hit and when to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub kind: LimitKind,
+    pub retry_after_secs: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+type ScopedKey = (String, String);
+
+/// Holds concurrency slots until dropped.
+pub struct LimitGuard {
+    counters: Vec<Arc<AtomicUsize>>,
+}
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        for c in &self.counters {
+            c.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl LimitGuard {
+    /// Keep the slots until the response body has been fully sent.
+    pub fn attach(self, resp: Response) -> Response {
+        let (parts, body) = resp.into_parts();
+        let stream = body.into_data_stream().map(move |chunk| {
+            let _held = &self;
+            chunk
+        });
+        Response::from_parts(parts, Body::from_stream(stream))
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<ScopedKey, Bucket>>,
+    connections: Mutex<HashMap<ScopedKey, Arc<AtomicUsize>>>,
+    websockets: Mutex<HashMap<ScopedKey, Arc<AtomicUsize>>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `config` to a request in `scope` (a route id or app domain).
+    /// On success the returned guard holds the concurrency slots.
+    pub fn check(
+        &self,
+        scope: &str,
+        config: &RateLimitConfig,
+        key: &str,
+        websocket: bool,
+    ) -> Result<LimitGuard, LimitExceeded> {
+        let scoped = (scope.to_string(), key.to_string());
+        self.take_token(&scoped, config, Instant::now())?;
+
+        let mut counters = Vec::new();
+        if config.max_connections > 0 {
+            counters.push(acquire(
+                &self.connections,
+                &scoped,
+                config.max_connections,
+                LimitKind::Connections,
+            )?);
+        }
+        if websocket && config.max_websockets > 0 {
+            match acquire(
+                &self.websockets,
+                &scoped,
+                config.max_websockets,
+                LimitKind::WebSockets,
+            ) {
+                Ok(c) => counters.push(c),
+                Err(e) => {
+                    // Release the connection slot taken above
+                    drop(LimitGuard { counters });
+                    return Err(e);
+                }
+            }
+        }
+        Ok(LimitGuard { counters })
+    }
+
+    fn take_token(
+        &self,
+        scoped: &ScopedKey,
+        config: &RateLimitConfig,
+        now: Instant,
+    ) -> Result<(), LimitExceeded> {
+        let rate = config.requests_per_second;
+        if rate <= 0.0 {
+            return Ok(());
+        }
+        let burst = config
+            .burst
+            .map(f64::from)
+            .unwrap_or_else(|| rate.round())
+            .max(1.0);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(scoped.clone()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / rate;
+            Err(LimitExceeded {
+                kind: LimitKind::Rate,
+                retry_after_secs: wait.ceil().max(1.0) as u64,
+            })
+        }
+    }
+
+    /// Drop idle buckets and concurrency counters nobody holds.
+    fn sweep(&self, now: Instant) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, b| now.duration_since(b.updated) < BUCKET_IDLE);
+        for map in [&self.connections, &self.websockets] {
+            map.lock()
+                .unwrap()
+                .retain(|_, c| c.load(Ordering::Relaxed) > 0);
+        }
+    }
+}
+
+/// Periodically drop idle limiter state. Runs until the process exits.
+pub async fn run_sweeper(limiter: Arc<RateLimiter>) {
+    let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        tick.tick().await;
+        limiter.sweep(Instant::now());
+    }
+}
+
+fn acquire(
+    map: &Mutex<HashMap<ScopedKey, Arc<AtomicUsize>>>,
+    scoped: &ScopedKey,
+    max: u32,
+    kind: LimitKind,
+) -> Result<Arc<AtomicUsize>, LimitExceeded> {
+    let mut map = map.lock().unwrap();
+    let counter = map.entry(scoped.clone()).or_default().clone();
+    if counter.load(Ordering::Relaxed) >= max as usize {
+        return Err(LimitExceeded {
+            kind,
+            retry_after_secs: 1,
+        });
+    }
+    counter.fetch_add(1, Ordering::Relaxed);
+    Ok(counter)
+}
+
+/// Client identity used as the limit key. `user` is the username set by
+/// forward-auth, never a client-supplied header. IPv6 clients share the
+/// limit of their /64, since a host can pick any address inside it.
+pub fn client_key(
+    key: &RateLimitKey,
+    headers: &HeaderMap,
+    user: Option<&str>,
+    client_ip: IpAddr,
+) -> String {
+    let value = match key {
+        RateLimitKey::ClientIp => None,
+        RateLimitKey::User => user.map(|u| format!("user:{}", u)),
+        RateLimitKey::Header(name) => headers
+            .get(name.as_str())
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("header:{}", v)),
+    };
+    value.unwrap_or_else(|| match client_ip.to_canonical() {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let network = u128::from(v6) & !(u64::MAX as u128);
+            format!("{}/64", Ipv6Addr::from(network))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rps: f64, burst: Option<u32>) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second: rps,
+            burst,
+            key: RateLimitKey::ClientIp,
+            max_connections: 0,
+            max_websockets: 0,
+        }
+    }
+
+    #[test]
+    fn test_token_bucket_burst_and_refill() {
+        let limiter = RateLimiter::new();
+        let cfg = config(2.0, Some(3));
+        let key = ("route".to_string(), "1.2.3.4".to_string());
+        let t0 = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.take_token(&key, &cfg, t0).is_ok());
+        }
+        let err = limiter.take_token(&key, &cfg, t0).unwrap_err();
+        assert_eq!(err.kind, LimitKind::Rate);
+        assert_eq!(err.retry_after_secs, 1);
+
+        // Half a second at 2 rps refills one token
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(limiter.take_token(&key, &cfg, t1).is_ok());
+        assert!(limiter.take_token(&key, &cfg, t1).is_err());
+
+        // Other clients have their own bucket
+        let other = ("route".to_string(), "5.6.7.8".to_string());
+        assert!(limiter.take_token(&other, &cfg, t1).is_ok());
+    }
+
+    #[test]
+    fn test_connection_and_websocket_caps() {
+        let limiter = RateLimiter::new();
+        let cfg = RateLimitConfig {
+            max_connections: 2,
+            max_websockets: 1,
+            ..config(0.0, None)
+        };
+
+        let ws = limiter.check("app", &cfg, "ip", true).unwrap();
+        let err = limiter.check("app", &cfg, "ip", true).err().unwrap();
+        assert_eq!(err.kind, LimitKind::WebSockets);
+
+        // The rejected WebSocket released its connection slot
+        let plain = limiter.check("app", &cfg, "ip", false).unwrap();
+        let err = limiter.check("app", &cfg, "ip", false).err().unwrap();
+        assert_eq!(err.kind, LimitKind::Connections);
+
+        drop(ws);
+        drop(plain);
+        assert!(limiter.check("app", &cfg, "ip", true).is_ok());
+    }
+
+    #[test]
+    fn test_client_key() {
+        let mut headers = HeaderMap::new();
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        // A spoofed identity header is not a user
+        headers.insert("X-Remote-User", "alice".parse().unwrap());
+        assert_eq!(
+            client_key(&RateLimitKey::User, &headers, None, ip),
+            "192.168.1.10"
+        );
+        assert_eq!(
+            client_key(&RateLimitKey::User, &headers, Some("bob"), ip),
+            "user:bob"
+        );
+        let by_header = RateLimitKey::Header("x-api-key".into());
+        assert_eq!(client_key(&by_header, &headers, None, ip), "192.168.1.10");
+        headers.insert("X-Api-Key", "k1".parse().unwrap());
+        assert_eq!(client_key(&by_header, &headers, None, ip), "header:k1");
+        assert_eq!(
+            client_key(&RateLimitKey::ClientIp, &headers, Some("bob"), ip),
+            "192.168.1.10"
+        );
+
+        // IPv6 clients are limited per /64
+        let a: IpAddr = "2001:db8:1:2::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:1:2:dead:beef::7".parse().unwrap();
+        let key = |ip| client_key(&RateLimitKey::ClientIp, &headers, None, ip);
+        assert_eq!(key(a), "2001:db8:1:2::/64");
+        assert_eq!(key(a), key(b));
+        assert_eq!(key("::ffff:192.168.1.10".parse().unwrap()), "192.168.1.10");
+    }
+
+    #[test]
+    fn test_sweep_drops_idle_state() {
+        let limiter = RateLimiter::new();
+        let cfg = RateLimitConfig {
+            max_connections: 1,
+            ..config(1.0, None)
+        };
+        let held = limiter.check("app", &cfg, "a", false).unwrap();
+        drop(limiter.check("app", &cfg, "b", false).unwrap());
+
+        let later = Instant::now() + BUCKET_IDLE + Duration::from_secs(1);
+        limiter.sweep(later);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+        // Only the slot still held survives
+        let connections = limiter.connections.lock().unwrap();
+        assert_eq!(connections.len(), 1);
+        assert!(connections.contains_key(&("app".to_string(), "a".to_string())));
+        drop(connections);
+        drop(held);
+    }
+}
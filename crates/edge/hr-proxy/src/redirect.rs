@@ -0,0 +1,359 @@
+//! Redirect and rewrite rules evaluated before routing.
+//!
+//! `ProxyConfig::domain_aliases` send every request for an alias host to
+//! its canonical domain, keeping the path. `ProxyConfig::redirects` are then
+//! tried in order: the first rule whose `host` and `path` regexes match
+//! either answers with a redirect or rewrites the path before the request
+//! is routed. Templates in `to` expand `$1`/`${1}` and `${name}` from the
+//! path captures, named host captures, and `${host}`, `${path}` and
+//! `${query}`; a generic `www.` to apex rule is therefore
+//! `host: "^www\.(?<apex>.+)$"`, `to: "https://${apex}${path}"`.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use regex::{Captures, Regex};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::{DomainAlias, RedirectAction, RedirectRule};
+
+const REDIRECT_STATUSES: [u16; 5] = [301, 302, 303, 307, 308];
+
+/// What a matching rule does with the request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RedirectOutcome {
+    Redirect { status: u16, location: String },
+    Rewrite { path_and_query: String },
+}
+
+/// The rule hit by a request and its effect.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RedirectMatch {
+    /// Rule id, or `alias:<host>` for a domain alias.
+    pub rule: String,
+    #[serde(flatten)]
+    pub outcome: RedirectOutcome,
+}
+
+/// A `RedirectRule` with its regexes compiled once per config load.
+struct CompiledRedirect {
+    rule: RedirectRule,
+    host: Option<Regex>,
+    path: Option<Regex>,
+}
+
+impl CompiledRedirect {
+    fn compile(rule: &RedirectRule) -> Result<Self> {
+        let regex = |re: &Option<String>| {
+            re.as_deref()
+                .map(|re| Regex::new(re).with_context(|| format!("Invalid regex '{}'", re)))
+                .transpose()
+        };
+        if rule.action == RedirectAction::Redirect && !REDIRECT_STATUSES.contains(&rule.status) {
+            bail!("Invalid redirect status {}", rule.status);
+        }
+        if rule.to.is_empty() {
+            bail!("Empty destination");
+        }
+        Ok(Self {
+            host: regex(&rule.host)?,
+            path: regex(&rule.path)?,
+            rule: rule.clone(),
+        })
+    }
+}
+
+/// Aliases and enabled rules that `RedirectEngine::compile` would skip,
+/// with the reason (`alias:<host>` or the rule id first).
+pub fn invalid_entries(aliases: &[DomainAlias], rules: &[RedirectRule]) -> Vec<(String, String)> {
+    let aliases = aliases
+        .iter()
+        .filter(|alias| !REDIRECT_STATUSES.contains(&alias.status))
+        .map(|alias| {
+            (
+                format!("alias:{}", alias.from),
+                format!("Invalid status {}", alias.status),
+            )
+        });
+    let rules =
+        rules.iter().filter(|r| r.enabled).filter_map(|rule| {
+            match CompiledRedirect::compile(rule) {
+                Ok(_) => None,
+                Err(e) => Some((rule.id.clone(), format!("{:#}", e))),
+            }
+        });
+    aliases.chain(rules).collect()
+}
+
+/// Compiled aliases and rules of a config.
+#[derive(Default)]
+pub struct RedirectEngine {
+    /// Canonical domain and status by lowercase alias host.
+    aliases: HashMap<String, (String, u16)>,
+    rules: Vec<CompiledRedirect>,
+}
+
+impl RedirectEngine {
+    /// Compile aliases and enabled rules, skipping (and logging) invalid ones.
+    pub fn compile(aliases: &[DomainAlias], rules: &[RedirectRule]) -> Self {
+        let aliases = aliases
+            .iter()
+            .filter_map(|alias| {
+                if !REDIRECT_STATUSES.contains(&alias.status) {
+                    warn!(
+                        "Domain alias {} ignored: invalid status {}",
+                        alias.from, alias.status
+                    );
+                    return None;
+                }
+                Some((
+                    alias.from.to_ascii_lowercase(),
+                    (alias.to.clone(), alias.status),
+                ))
+            })
+            .collect();
+        let rules = rules
+            .iter()
+            .filter(|r| r.enabled)
+            .filter_map(|rule| match CompiledRedirect::compile(rule) {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    warn!("Redirect rule {} ignored: {:#}", rule.id, e);
+                    None
+                }
+            })
+            .collect();
+        Self { aliases, rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty() && self.rules.is_empty()
+    }
+
+    /// The alias or rule hit by a request for `host` (port ignored).
+    pub fn evaluate(&self, host: &str, path_and_query: &str) -> Option<RedirectMatch> {
+        let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+        let (path, query) = match path_and_query.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (path_and_query, ""),
+        };
+
+        if let Some((to, status)) = self.aliases.get(&host) {
+            return Some(RedirectMatch {
+                rule: format!("alias:{}", host),
+                outcome: RedirectOutcome::Redirect {
+                    status: *status,
+                    location: format!("https://{}{}", to, path_and_query),
+                },
+            });
+        }
+
+        for compiled in &self.rules {
+            let host_caps = match &compiled.host {
+                Some(re) => match re.captures(&host) {
+                    Some(caps) => Some(caps),
+                    None => continue,
+                },
+                None => None,
+            };
+            let path_caps = match &compiled.path {
+                Some(re) => match re.captures(path) {
+                    Some(caps) => Some(caps),
+                    None => continue,
+                },
+                None => None,
+            };
+            let rule = &compiled.rule;
+            let vars = Vars {
+                host: &host,
+                path,
+                query,
+                host_caps: host_caps.as_ref(),
+                path_caps: path_caps.as_ref(),
+            };
+            let mut target = expand(&rule.to, &vars);
+            if rule.preserve_query && !query.is_empty() {
+                target.push(if target.contains('?') { '&' } else { '?' });
+                target.push_str(query);
+            }
+            let outcome = match rule.action {
+                RedirectAction::Redirect => RedirectOutcome::Redirect {
+                    status: rule.status,
+                    location: if target.starts_with('/') {
+                        format!("https://{}{}", host, target)
+                    } else {
+                        target
+                    },
+                },
+                RedirectAction::Rewrite => RedirectOutcome::Rewrite {
+                    path_and_query: if target.starts_with('/') {
+                        target
+                    } else {
+                        format!("/{}", target)
+                    },
+                },
+            };
+            return Some(RedirectMatch {
+                rule: rule.id.clone(),
+                outcome,
+            });
+        }
+        None
+    }
+}
+
+/// Values a `to` template can reference.
+struct Vars<'a> {
+    host: &'a str,
+    path: &'a str,
+    query: &'a str,
+    host_caps: Option<&'a Captures<'a>>,
+    path_caps: Option<&'a Captures<'a>>,
+}
+
+impl<'a> Vars<'a> {
+    fn get(&self, name: &str) -> Option<&'a str> {
+        if let Some(value) = capture(self.path_caps, name) {
+            return Some(value);
+        }
+        if name.parse::<usize>().is_err()
+            && let Some(value) = capture(self.host_caps, name)
+        {
+            return Some(value);
+        }
+        match name {
+            "host" => Some(self.host),
+            "path" => Some(self.path),
+            "query" => Some(self.query),
+            _ => None,
+        }
+    }
+}
+
+/// Group `name` (a number or a group name) of `caps`.
+fn capture<'h>(caps: Option<&Captures<'h>>, name: &str) -> Option<&'h str> {
+    let caps = caps?;
+    let m = match name.parse::<usize>() {
+        Ok(i) => caps.get(i),
+        Err(_) => caps.name(name),
+    }?;
+    Some(m.as_str())
+}
+
+/// Expand `$N`, `${name}` and `$$` in `template`; unknown names expand to
+/// nothing.
+fn expand(template: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            out.push_str(vars.get(&braced[..end]).unwrap_or_default());
+            rest = &braced[end + 1..];
+        } else {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            if digits == 0 {
+                out.push('$');
+            } else {
+                out.push_str(vars.get(&rest[..digits]).unwrap_or_default());
+                rest = &rest[digits..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, host: Option<&str>, path: Option<&str>, to: &str) -> RedirectRule {
+        RedirectRule {
+            id: id.to_string(),
+            host: host.map(str::to_string),
+            path: path.map(str::to_string),
+            action: RedirectAction::Redirect,
+            to: to.to_string(),
+            status: 301,
+            preserve_query: true,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_aliases_redirects_and_rewrites() {
+        let mut rewrite = rule("api-v1", None, Some(r"^/api/v1/(.*)$"), "/api/v2/$1");
+        rewrite.action = RedirectAction::Rewrite;
+        let mut moved = rule(
+            "blog",
+            Some(r"^(?<site>\w+)\.example\.com$"),
+            Some(r"^/blog/(?<slug>[^/]+)$"),
+            "https://blog.example.com/${site}/${slug}",
+        );
+        moved.status = 308;
+        let mut bad = rule("bad", None, Some("("), "/x");
+        bad.status = 200;
+        let engine = RedirectEngine::compile(
+            &[DomainAlias {
+                from: "www.example.com".to_string(),
+                to: "example.com".to_string(),
+                status: 301,
+            }],
+            &[
+                bad,
+                rewrite,
+                moved,
+                rule(
+                    "apex",
+                    Some(r"^www\.(?<apex>.+)$"),
+                    None,
+                    "https://${apex}${path}",
+                ),
+            ],
+        );
+        assert_eq!(engine.rules.len(), 3);
+
+        let alias = engine.evaluate("WWW.example.com:443", "/a?b=1").unwrap();
+        assert_eq!(alias.rule, "alias:www.example.com");
+        assert_eq!(
+            alias.outcome,
+            RedirectOutcome::Redirect {
+                status: 301,
+                location: "https://example.com/a?b=1".to_string()
+            }
+        );
+
+        let hit = engine
+            .evaluate("app.example.com", "/api/v1/items?page=2")
+            .unwrap();
+        assert_eq!(
+            hit.outcome,
+            RedirectOutcome::Rewrite {
+                path_and_query: "/api/v2/items?page=2".to_string()
+            }
+        );
+
+        let hit = engine.evaluate("shop.example.com", "/blog/launch").unwrap();
+        assert_eq!(hit.rule, "blog");
+        assert_eq!(
+            hit.outcome,
+            RedirectOutcome::Redirect {
+                status: 308,
+                location: "https://blog.example.com/shop/launch".to_string()
+            }
+        );
+
+        let hit = engine.evaluate("www.other.org", "/x").unwrap();
+        assert_eq!(hit.rule, "apex");
+        assert!(engine.evaluate("other.org", "/blog/launch/more").is_none());
+    }
+}
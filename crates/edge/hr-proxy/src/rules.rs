@@ -0,0 +1,341 @@
+//! Path-based routing within a domain.
+//!
+//! A `RouteConfig` may carry an ordered list of `PathRule`s; the first rule
+//! whose prefix or regex matches the request path picks the backend, the
+//! rewritten path and the auth requirements. Requests that match no rule go
+//! to the route's own `target_host:target_port` unchanged.
+//!
+//! Rules are evaluated against a normalized path (unreserved
+//! percent-escapes decoded, dot-segments and duplicate slashes removed) so
+//! `/%61dmin`, `//admin` or `/./admin` cannot slip past a rule on `/admin`.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use tracing::warn;
+
+use crate::config::{PathRule, RouteConfig};
+
+/// A `PathRule` with its regex compiled once per config load.
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub rule: PathRule,
+    regex: Option<Regex>,
+}
+
+/// Backend selected for a request after rule evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteTarget {
+    pub target_host: String,
+    pub target_port: u16,
+    /// Backend pool replacing `target_host:target_port` when set.
+    pub pool: Option<String>,
+    /// Path and query to send upstream.
+    pub path_and_query: String,
+    pub require_auth: bool,
+    pub allowed_groups: Vec<String>,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: &PathRule) -> Result<Self> {
+        let regex = match &rule.path_regex {
+            Some(re) => {
+                Some(Regex::new(re).with_context(|| format!("Invalid path_regex '{}'", re))?)
+            }
+            None => None,
+        };
+        if regex.is_none() && rule.path_prefix.is_none() {
+            anyhow::bail!("Rule has neither path_prefix nor path_regex");
+        }
+        Ok(Self {
+            rule: rule.clone(),
+            regex,
+        })
+    }
+
+    /// Upstream path if this rule matches `path` (without query string).
+    pub fn apply(&self, path: &str) -> Option<String> {
+        let rewritten = match (&self.regex, &self.rule.path_prefix) {
+            (Some(re), _) => {
+                let caps = re.captures(path)?;
+                let m = caps.get(0)?;
+                if let Some(ref replacement) = self.rule.rewrite {
+                    let mut expanded = String::new();
+                    caps.expand(replacement, &mut expanded);
+                    format!("{}{}{}", &path[..m.start()], expanded, &path[m.end()..])
+                } else if self.rule.strip_prefix && m.start() == 0 {
+                    path[m.end()..].to_string()
+                } else {
+                    path.to_string()
+                }
+            }
+            (None, Some(prefix)) => {
+                // "/api" matches "/api" and "/api/x" but not "/apix"
+                let prefix = prefix.trim_end_matches('/');
+                let rest = path.strip_prefix(prefix)?;
+                if !prefix.is_empty() && !rest.is_empty() && !rest.starts_with('/') {
+                    return None;
+                }
+                if let Some(ref replacement) = self.rule.rewrite {
+                    format!("{}{}", replacement.trim_end_matches('/'), rest)
+                } else if self.rule.strip_prefix {
+                    rest.to_string()
+                } else {
+                    path.to_string()
+                }
+            }
+            (None, None) => return None,
+        };
+        Some(if rewritten.starts_with('/') {
+            rewritten
+        } else {
+            format!("/{}", rewritten)
+        })
+    }
+}
+
+/// Compile a route's rules, skipping (and logging) invalid ones.
+pub fn compile_rules(route: &RouteConfig) -> Vec<CompiledRule> {
+    route
+        .rules
+        .iter()
+        .enumerate()
+        .filter_map(|(i, rule)| match CompiledRule::compile(rule) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                warn!("Route {} rule #{} ignored: {:#}", route.domain, i, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Pick the backend for `path_and_query` on `route`.
+pub fn resolve(route: &RouteConfig, rules: &[CompiledRule], path_and_query: &str) -> RouteTarget {
+    let (path, query) = match path_and_query.find('?') {
+        Some(i) => path_and_query.split_at(i),
+        None => (path_and_query, ""),
+    };
+
+    let path = normalize_path(path);
+    for compiled in rules {
+        if let Some(upstream_path) = compiled.apply(&path) {
+            let rule = &compiled.rule;
+            return RouteTarget {
+                target_host: rule.target_host.clone(),
+                target_port: rule.target_port,
+                pool: rule.pool.clone(),
+                path_and_query: format!("{}{}", upstream_path, query),
+                require_auth: rule.require_auth.unwrap_or(route.require_auth)
+                    || !rule.allowed_groups.is_empty(),
+                allowed_groups: rule.allowed_groups.clone(),
+            };
+        }
+    }
+
+    RouteTarget {
+        target_host: route.target_host.clone(),
+        target_port: route.target_port,
+        pool: route.pool.clone(),
+        path_and_query: path_and_query.to_string(),
+        require_auth: route.require_auth,
+        allowed_groups: vec![],
+    }
+}
+
+/// Canonical form of a request path for rule matching: percent-encoded
+/// unreserved characters (RFC 3986 §2.3) are decoded, then empty and
+/// dot-segments are resolved (§5.2.4). A trailing slash is kept.
+fn normalize_path(path: &str) -> String {
+    if !path.starts_with('/') {
+        return path.to_string();
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = path.get(i + 1..i + 3)
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+            && (b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'))
+        {
+            decoded.push(b);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    let decoded = String::from_utf8_lossy(&decoded);
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    let dir = decoded.ends_with('/') || decoded.ends_with("/.") || decoded.ends_with("/..");
+    if dir && !segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(rules: Vec<PathRule>) -> RouteConfig {
+        RouteConfig {
+            id: "home".to_string(),
+            domain: "home.example.com".to_string(),
+            backend: "rust".to_string(),
+            target_host: "localhost".to_string(),
+            target_port: 8080,
+            local_only: false,
+            acl: Default::default(),
+            client_cert: Default::default(),
+            require_auth: false,
+            machine_auth: None,
+            enabled: true,
+            pool: None,
+            backend_tls: None,
+            rate_limit: None,
+            compression: None,
+            cache: None,
+            upstream: None,
+            headers: Default::default(),
+            cert_id: None,
+            rules,
+        }
+    }
+
+    fn prefix_rule(prefix: &str, port: u16) -> PathRule {
+        PathRule {
+            path_prefix: Some(prefix.to_string()),
+            target_host: "10.0.0.2".to_string(),
+            target_port: port,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prefix_rules_in_order() {
+        let r = route(vec![
+            PathRule {
+                strip_prefix: true,
+                ..prefix_rule("/api/", 3000)
+            },
+            PathRule {
+                allowed_groups: vec!["admins".to_string()],
+                ..prefix_rule("/grafana", 3001)
+            },
+        ]);
+        let rules = compile_rules(&r);
+
+        let t = resolve(&r, &rules, "/api/users?page=2");
+        assert_eq!(t.target_port, 3000);
+        assert_eq!(t.path_and_query, "/users?page=2");
+        assert!(!t.require_auth);
+
+        let t = resolve(&r, &rules, "/api");
+        assert_eq!((t.target_port, t.path_and_query.as_str()), (3000, "/"));
+
+        let t = resolve(&r, &rules, "/grafana/d/abc");
+        assert_eq!(t.target_port, 3001);
+        assert_eq!(t.path_and_query, "/grafana/d/abc");
+        assert!(t.require_auth);
+        assert_eq!(t.allowed_groups, vec!["admins".to_string()]);
+
+        // Segment boundary: "/apix" is not under "/api"
+        let t = resolve(&r, &rules, "/apix");
+        assert_eq!(t.target_port, 8080);
+        assert_eq!(t.path_and_query, "/apix");
+    }
+
+    #[test]
+    fn test_rewrite_and_regex() {
+        let r = route(vec![
+            PathRule {
+                rewrite: Some("/v2".to_string()),
+                require_auth: Some(true),
+                ..prefix_rule("/api/v1", 3000)
+            },
+            PathRule {
+                path_regex: Some(r"^/u/(?P<name>[a-z]+)".to_string()),
+                rewrite: Some("/users/$name".to_string()),
+                ..prefix_rule("", 3002)
+            },
+            PathRule {
+                path_regex: Some(r"^/static".to_string()),
+                strip_prefix: true,
+                ..prefix_rule("", 3003)
+            },
+        ]);
+        let rules = compile_rules(&r);
+        assert_eq!(rules.len(), 3);
+
+        let t = resolve(&r, &rules, "/api/v1/items");
+        assert_eq!(t.path_and_query, "/v2/items");
+        assert!(t.require_auth);
+
+        let t = resolve(&r, &rules, "/u/alice/profile?tab=1");
+        assert_eq!(t.target_port, 3002);
+        assert_eq!(t.path_and_query, "/users/alice/profile?tab=1");
+
+        let t = resolve(&r, &rules, "/static/app.js");
+        assert_eq!(t.target_port, 3003);
+        assert_eq!(t.path_and_query, "/app.js");
+    }
+
+    #[test]
+    fn test_encoded_and_dotted_paths_match_rules() {
+        let r = route(vec![PathRule {
+            require_auth: Some(true),
+            ..prefix_rule("/admin", 3000)
+        }]);
+        let rules = compile_rules(&r);
+
+        for path in [
+            "/%61dmin",
+            "/%61%64%6D%69%6E/users",
+            "//admin",
+            "/./admin",
+            "/x/../admin",
+            "/%2e/admin",
+            "/../admin",
+        ] {
+            let t = resolve(&r, &rules, path);
+            assert_eq!(t.target_port, 3000, "{} bypassed the rule", path);
+            assert!(t.require_auth, "{} bypassed auth", path);
+            assert!(t.path_and_query.starts_with("/admin"), "{}", path);
+        }
+
+        // Reserved escapes stay encoded
+        let t = resolve(&r, &rules, "/x%2Fadmin");
+        assert_eq!(t.target_port, 8080);
+        assert_eq!(normalize_path("/a//b/./c/"), "/a/b/c/");
+        assert_eq!(normalize_path("/a/b/.."), "/a/");
+    }
+
+    #[test]
+    fn test_invalid_rules_skipped() {
+        let r = route(vec![
+            PathRule {
+                path_regex: Some("(".to_string()),
+                ..prefix_rule("/x", 3000)
+            },
+            PathRule {
+                target_host: "10.0.0.2".to_string(),
+                target_port: 3001,
+                ..Default::default()
+            },
+        ]);
+        assert!(compile_rules(&r).is_empty());
+    }
+}
@@ -0,0 +1,768 @@
+//! Layer 4 stream routes.
+//!
+//! `ProxyConfig::streams` forwards raw TCP or UDP from a dedicated port to
+//! a backend (SSH into a container, MQTT, game servers, databases), or
+//! hands TLS connections of the HTTPS port whose SNI names a
+//! `tls_passthrough` route to a backend that terminates TLS itself.
+//! Clients are filtered with the route's ACL; `local_only` restricts a
+//! stream to the built-in `lan` list. UDP is tracked as sessions, one
+//! upstream socket per client address, forgotten after `idle_timeout_secs`
+//! and capped at `max_sessions` per route.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+use crate::acl::Acl;
+use crate::config::{AccessList, StreamProtocol, StreamRoute};
+use crate::metrics::GaugeGuard;
+
+/// How long a backend gets to accept a TCP connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a client gets to send its TLS ClientHello.
+pub const SNI_PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest TLS record, header included.
+const TLS_RECORD_MAX: usize = 5 + 16384;
+/// Bind attempts, 100 ms apart, while a port is still in use.
+const BIND_RETRIES: u32 = 20;
+
+/// Connection counters of one stream route, kept across reloads.
+#[derive(Default)]
+struct StreamCounters {
+    connections: AtomicU64,
+    active: Arc<AtomicU64>,
+    denied: AtomicU64,
+    failures: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+/// Point-in-time counters of a stream route.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStats {
+    pub id: String,
+    pub protocol: StreamProtocol,
+    pub listen: String,
+    pub target: String,
+    /// Whether the route's listener is running (always true for passthrough).
+    pub listening: bool,
+    /// TCP connections or UDP sessions accepted.
+    pub connections: u64,
+    pub active: u64,
+    pub denied: u64,
+    /// Connections that could not reach the backend.
+    pub failures: u64,
+    /// Bytes from clients, counted when a TCP connection closes.
+    pub bytes_received: u64,
+    /// Bytes to clients, counted when a TCP connection closes.
+    pub bytes_sent: u64,
+}
+
+/// A configured stream route with its compiled access rules.
+pub struct StreamTarget {
+    route: StreamRoute,
+    acl: Acl,
+    local: Option<Acl>,
+    counters: Arc<StreamCounters>,
+}
+
+impl StreamTarget {
+    fn new(
+        route: StreamRoute,
+        lists: &HashMap<String, Vec<String>>,
+        counters: Arc<StreamCounters>,
+    ) -> Self {
+        let local = route.local_only.then(|| {
+            let lan = AccessList {
+                allow: vec!["lan".to_string()],
+                deny: Vec::new(),
+            };
+            Acl::compile(&lan, lists)
+        });
+        Self {
+            acl: Acl::compile(&route.acl, lists),
+            local,
+            route,
+            counters,
+        }
+    }
+
+    pub fn route(&self) -> &StreamRoute {
+        &self.route
+    }
+
+    /// Check `client_ip` against `local_only` and the route's ACL.
+    fn admit(&self, client_ip: IpAddr) -> bool {
+        let checked = match &self.local {
+            Some(lan) => lan.check(client_ip).and_then(|_| self.acl.check(client_ip)),
+            None => self.acl.check(client_ip),
+        };
+        match checked {
+            Ok(()) => true,
+            Err(denied) => {
+                self.counters.denied.fetch_add(1, Ordering::Relaxed);
+                // Every datagram of a denied UDP client ends up here
+                if self.route.protocol == StreamProtocol::Udp {
+                    debug!(
+                        "Stream {} denied {} (rule: {})",
+                        self.route.id, client_ip, denied.rule
+                    );
+                } else {
+                    warn!(
+                        "Stream {} denied {} (rule: {})",
+                        self.route.id, client_ip, denied.rule
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    fn target(&self) -> (&str, u16) {
+        (&self.route.target_host, self.route.target_port)
+    }
+
+    /// Relay `inbound` to the route's backend until either side closes.
+    pub async fn forward(&self, mut inbound: TcpStream, client_ip: IpAddr) {
+        let counters = &self.counters;
+        counters.connections.fetch_add(1, Ordering::Relaxed);
+        if !self.admit(client_ip) {
+            return;
+        }
+        let mut upstream =
+            match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(self.target())).await {
+                Ok(Ok(s)) => s,
+                Ok(Err(e)) => {
+                    counters.failures.fetch_add(1, Ordering::Relaxed);
+                    warn!("Stream {}: backend connect failed: {}", self.route.id, e);
+                    return;
+                }
+                Err(_) => {
+                    counters.failures.fetch_add(1, Ordering::Relaxed);
+                    warn!("Stream {}: backend connect timed out", self.route.id);
+                    return;
+                }
+            };
+        let _active = GaugeGuard::new(&counters.active);
+        let _ = inbound.set_nodelay(true);
+        let _ = upstream.set_nodelay(true);
+        match tokio::io::copy_bidirectional(&mut inbound, &mut upstream).await {
+            Ok((received, sent)) => {
+                counters
+                    .bytes_received
+                    .fetch_add(received, Ordering::Relaxed);
+                counters.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+            }
+            Err(e) => debug!("Stream {} from {} closed: {}", self.route.id, client_ip, e),
+        }
+    }
+}
+
+/// A stream route and its listener task, aborted when replaced.
+struct ActiveStream {
+    target: Arc<StreamTarget>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Stream routes with their listeners and counters.
+#[derive(Default)]
+pub struct StreamManager {
+    streams: RwLock<HashMap<String, ActiveStream>>,
+    /// Passthrough routes by lowercase SNI.
+    passthrough: RwLock<HashMap<String, Arc<StreamTarget>>>,
+    /// Counters by route id, kept when a route is edited.
+    counters: Mutex<HashMap<String, Arc<StreamCounters>>>,
+    ip_lists: RwLock<HashMap<String, Vec<String>>>,
+}
+
+impl StreamManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the configured streams: unchanged routes keep their listener,
+    /// changed or removed ones are stopped (open connections are left to
+    /// finish) and new ones start listening. Must run inside a Tokio runtime
+    /// when a TCP or UDP route is enabled.
+    pub fn reconcile(&self, routes: &[StreamRoute], ip_lists: &HashMap<String, Vec<String>>) {
+        let lists_changed = *self.ip_lists.read().unwrap() != *ip_lists;
+        *self.ip_lists.write().unwrap() = ip_lists.clone();
+
+        let mut streams = self.streams.write().unwrap();
+        // Stop changed and removed routes first so their ports can be rebound
+        streams.retain(|id, active| {
+            !lists_changed
+                && routes
+                    .iter()
+                    .any(|r| r.enabled && &r.id == id && *r == active.target.route)
+        });
+        let mut counters = self.counters.lock().unwrap();
+        counters.retain(|id, _| routes.iter().any(|r| &r.id == id));
+
+        for route in routes.iter().filter(|r| r.enabled) {
+            if streams.contains_key(&route.id) {
+                continue;
+            }
+            let route_counters = counters.entry(route.id.clone()).or_default().clone();
+            let target = Arc::new(StreamTarget::new(route.clone(), ip_lists, route_counters));
+            let task = match route.protocol {
+                StreamProtocol::TlsPassthrough => None,
+                StreamProtocol::Tcp => Some(tokio::spawn(serve_tcp(target.clone()))),
+                StreamProtocol::Udp => Some(tokio::spawn(serve_udp(target.clone()))),
+            };
+            streams.insert(route.id.clone(), ActiveStream { target, task });
+        }
+
+        let mut passthrough = HashMap::new();
+        for active in streams.values() {
+            if active.target.route.protocol == StreamProtocol::TlsPassthrough {
+                register_passthrough(&mut passthrough, &active.target);
+            }
+        }
+        *self.passthrough.write().unwrap() = passthrough;
+    }
+
+    /// Whether any route takes TLS connections by SNI.
+    pub fn has_passthrough(&self) -> bool {
+        !self.passthrough.read().unwrap().is_empty()
+    }
+
+    /// The passthrough route for `sni`, if any.
+    pub fn passthrough_route(&self, sni: &str) -> Option<Arc<StreamTarget>> {
+        self.passthrough
+            .read()
+            .unwrap()
+            .get(&sni.to_ascii_lowercase())
+            .cloned()
+    }
+
+    /// Counters of the enabled stream routes, sorted by id.
+    pub fn stats(&self) -> Vec<StreamStats> {
+        let streams = self.streams.read().unwrap();
+        let mut out: Vec<StreamStats> = streams
+            .values()
+            .map(|s| {
+                let route = &s.target.route;
+                let c = &s.target.counters;
+                StreamStats {
+                    id: route.id.clone(),
+                    protocol: route.protocol,
+                    listen: match route.protocol {
+                        StreamProtocol::TlsPassthrough => {
+                            format!("sni:{}", route.domain.as_deref().unwrap_or_default())
+                        }
+                        _ => route.listen_port.to_string(),
+                    },
+                    target: format!("{}:{}", route.target_host, route.target_port),
+                    listening: route.protocol == StreamProtocol::TlsPassthrough
+                        || s.task.as_ref().is_some_and(|t| !t.is_finished()),
+                    connections: c.connections.load(Ordering::Relaxed),
+                    active: c.active.load(Ordering::Relaxed),
+                    denied: c.denied.load(Ordering::Relaxed),
+                    failures: c.failures.load(Ordering::Relaxed),
+                    bytes_received: c.bytes_received.load(Ordering::Relaxed),
+                    bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.id.cmp(&b.id));
+        out
+    }
+}
+
+fn register_passthrough(map: &mut HashMap<String, Arc<StreamTarget>>, target: &Arc<StreamTarget>) {
+    match target.route.domain.as_deref().map(str::trim) {
+        Some(domain) if !domain.is_empty() => {
+            map.insert(domain.to_ascii_lowercase(), target.clone());
+        }
+        _ => warn!("Stream {}: tls_passthrough without domain", target.route.id),
+    }
+}
+
+/// Bind the route's port, retrying briefly while a replaced listener
+/// (aborted, but not yet dropped) still holds it.
+async fn bind<T, F>(target: &StreamTarget, bind: impl Fn(SocketAddr) -> F) -> Option<T>
+where
+    F: Future<Output = std::io::Result<T>>,
+{
+    let route = &target.route;
+    let addr = SocketAddr::from(([0u16; 8], route.listen_port));
+    let mut attempt = 0;
+    loop {
+        match bind(addr).await {
+            Ok(socket) => {
+                info!(
+                    "Stream {} ({:?}) on port {} -> {}:{}",
+                    route.id,
+                    route.protocol,
+                    route.listen_port,
+                    route.target_host,
+                    route.target_port
+                );
+                return Some(socket);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempt < BIND_RETRIES => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => {
+                error!(
+                    "Stream {}: failed to bind port {}: {}",
+                    route.id, route.listen_port, e
+                );
+                return None;
+            }
+        }
+    }
+}
+
+async fn serve_tcp(target: Arc<StreamTarget>) {
+    let Some(listener) = bind(&target, TcpListener::bind).await else {
+        return;
+    };
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("Stream {}: accept error: {}", target.route.id, e);
+                continue;
+            }
+        };
+        let target = target.clone();
+        tokio::spawn(async move {
+            target.forward(stream, remote.ip().to_canonical()).await;
+        });
+    }
+}
+
+/// Upstream side of one UDP client.
+struct UdpSession {
+    socket: UdpSocket,
+    last_seen: Mutex<Instant>,
+}
+
+/// UDP sessions of a route by client address, `None` while the backend
+/// socket is being opened.
+type UdpSessions = Arc<Mutex<HashMap<SocketAddr, Option<Arc<UdpSession>>>>>;
+
+async fn serve_udp(target: Arc<StreamTarget>) {
+    if let Some(socket) = bind(&target, UdpSocket::bind).await {
+        relay_udp(target, Arc::new(socket)).await;
+    }
+}
+
+/// Receive loop of a UDP route: datagrams go to their client's session,
+/// opened in its own task on first contact.
+async fn relay_udp(target: Arc<StreamTarget>, socket: Arc<UdpSocket>) {
+    let sessions = UdpSessions::default();
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let (n, peer) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("Stream {}: UDP receive error: {}", target.route.id, e);
+                continue;
+            }
+        };
+        let session = {
+            let mut sessions_map = sessions.lock().unwrap();
+            match sessions_map.get(&peer) {
+                Some(Some(session)) => session.clone(),
+                // Still opening: dropped, as UDP allows
+                Some(None) => continue,
+                None => {
+                    if sessions_map.len() >= target.route.max_sessions {
+                        debug!(
+                            "Stream {}: {} UDP sessions open, ignoring {}",
+                            target.route.id,
+                            sessions_map.len(),
+                            peer
+                        );
+                        continue;
+                    }
+                    target.counters.connections.fetch_add(1, Ordering::Relaxed);
+                    if !target.admit(peer.ip().to_canonical()) {
+                        continue;
+                    }
+                    // Resolving and connecting can take a while: not on
+                    // the receive loop
+                    sessions_map.insert(peer, None);
+                    tokio::spawn(run_udp_session(
+                        target.clone(),
+                        socket.clone(),
+                        sessions.clone(),
+                        peer,
+                        buf[..n].to_vec(),
+                    ));
+                    continue;
+                }
+            }
+        };
+        forward_datagram(&target, &session, &buf[..n]).await;
+    }
+}
+
+/// Send a client datagram to the backend.
+async fn forward_datagram(target: &StreamTarget, session: &UdpSession, datagram: &[u8]) {
+    *session.last_seen.lock().unwrap() = Instant::now();
+    match session.socket.send(datagram).await {
+        Ok(sent) => {
+            target
+                .counters
+                .bytes_received
+                .fetch_add(sent as u64, Ordering::Relaxed);
+        }
+        Err(e) => debug!("Stream {}: UDP send to backend: {}", target.route.id, e),
+    }
+}
+
+/// Connect a socket to the backend for `peer`, send it the client's first
+/// datagram and relay replies until the session has been idle for the
+/// route's timeout.
+async fn run_udp_session(
+    target: Arc<StreamTarget>,
+    listener: Arc<UdpSocket>,
+    sessions: UdpSessions,
+    peer: SocketAddr,
+    first: Vec<u8>,
+) {
+    let upstream = match tokio::time::timeout(CONNECT_TIMEOUT, connect_udp(target.target())).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            target.counters.failures.fetch_add(1, Ordering::Relaxed);
+            warn!("Stream {}: backend unreachable: {}", target.route.id, e);
+            sessions.lock().unwrap().remove(&peer);
+            return;
+        }
+        Err(_) => {
+            target.counters.failures.fetch_add(1, Ordering::Relaxed);
+            warn!("Stream {}: backend lookup timed out", target.route.id);
+            sessions.lock().unwrap().remove(&peer);
+            return;
+        }
+    };
+    let relay = Arc::new(UdpSession {
+        socket: upstream,
+        last_seen: Mutex::new(Instant::now()),
+    });
+    sessions.lock().unwrap().insert(peer, Some(relay.clone()));
+    forward_datagram(&target, &relay, &first).await;
+
+    let idle = Duration::from_secs(target.route.idle_timeout_secs.max(1));
+    let _active = GaugeGuard::new(&target.counters.active);
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let deadline = *relay.last_seen.lock().unwrap() + idle;
+        match tokio::time::timeout_at(deadline, relay.socket.recv(&mut buf)).await {
+            Ok(Ok(n)) => {
+                *relay.last_seen.lock().unwrap() = Instant::now();
+                if listener.send_to(&buf[..n], peer).await.is_ok() {
+                    target
+                        .counters
+                        .bytes_sent
+                        .fetch_add(n as u64, Ordering::Relaxed);
+                }
+            }
+            // ICMP errors surface here; the backend may come back
+            Ok(Err(e)) => debug!("Stream {}: UDP backend error: {}", target.route.id, e),
+            Err(_) => {
+                // The client may have kept sending in the meantime
+                if relay.last_seen.lock().unwrap().elapsed() >= idle {
+                    break;
+                }
+            }
+        }
+    }
+    sessions.lock().unwrap().remove(&peer);
+    debug!("Stream {}: UDP session {} expired", target.route.id, peer);
+}
+
+async fn connect_udp(target: (&str, u16)) -> anyhow::Result<UdpSocket> {
+    let addr = tokio::net::lookup_host(target)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} did not resolve", target.0))?;
+    let local = if addr.is_ipv4() {
+        SocketAddr::from(([0u8; 4], 0))
+    } else {
+        SocketAddr::from(([0u16; 8], 0))
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(addr).await?;
+    Ok(socket)
+}
+
+/// Server name of the TLS ClientHello at the start of `stream`, read
+/// without consuming it. `None` if the client does not speak TLS, sends no
+/// SNI or takes longer than `SNI_PEEK_TIMEOUT`.
+pub async fn peek_sni(stream: &TcpStream) -> Option<String> {
+    let mut buf = vec![0u8; TLS_RECORD_MAX];
+    let peek = async {
+        loop {
+            let n = stream.peek(&mut buf).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            match parse_sni(&buf[..n]) {
+                Some(sni) => return sni,
+                // Wait for the rest of the record
+                None if n < buf.len() => tokio::time::sleep(Duration::from_millis(5)).await,
+                None => return None,
+            }
+        }
+    };
+    tokio::time::timeout(SNI_PEEK_TIMEOUT, peek)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Parse the SNI of a ClientHello record. `None` if `buf` holds only part
+/// of the record, `Some(None)` if it is not a ClientHello or has no SNI.
+fn parse_sni(buf: &[u8]) -> Option<Option<String>> {
+    if buf.is_empty() {
+        return None;
+    }
+    // Handshake record
+    if buf[0] != 0x16 {
+        return Some(None);
+    }
+    if buf.len() < 5 {
+        return None;
+    }
+    let record_len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    if buf.len() < 5 + record_len {
+        return None;
+    }
+    Some(client_hello_sni(&buf[5..5 + record_len]))
+}
+
+fn client_hello_sni(hello: &[u8]) -> Option<String> {
+    let mut r = Reader(hello);
+    // ClientHello header, then legacy version and random
+    if r.u8()? != 0x01 {
+        return None;
+    }
+    r.skip(3 + 2 + 32)?;
+    let session_id = r.u8()? as usize;
+    r.skip(session_id)?;
+    let suites = r.u16()? as usize;
+    r.skip(suites)?;
+    let compression = r.u8()? as usize;
+    r.skip(compression)?;
+    let mut extensions = Reader(r.vec16()?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let mut data = Reader(extensions.vec16()?);
+        if kind != 0x0000 {
+            continue;
+        }
+        let mut names = Reader(data.vec16()?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name = names.vec16()?;
+            if name_type == 0 {
+                return std::str::from_utf8(name)
+                    .ok()
+                    .map(|s| s.to_ascii_lowercase());
+            }
+        }
+    }
+    None
+}
+
+/// Big-endian cursor over a handshake message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    /// Bytes prefixed by a 16-bit length.
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ClientHello record carrying `sni` (when given) and one other extension.
+    fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        let mut ext = vec![0x00, 0x17, 0x00, 0x00]; // extended_master_secret
+        if let Some(name) = sni {
+            let name = name.as_bytes();
+            let list_len = (name.len() + 3) as u16;
+            ext.extend_from_slice(&[0x00, 0x00]);
+            ext.extend_from_slice(&(list_len + 2).to_be_bytes());
+            ext.extend_from_slice(&list_len.to_be_bytes());
+            ext.push(0);
+            ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            ext.extend_from_slice(name);
+        }
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0u8; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+        body.extend_from_slice(&ext);
+
+        let mut hs = vec![0x01, 0x00];
+        hs.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        hs.extend_from_slice(&body);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(hs.len() as u16).to_be_bytes());
+        record.extend_from_slice(&hs);
+        record
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello(Some("DB.Example.com"));
+        assert_eq!(parse_sni(&hello), Some(Some("db.example.com".to_string())));
+        assert_eq!(parse_sni(&hello[..hello.len() - 4]), None);
+        assert_eq!(parse_sni(&client_hello(None)), Some(None));
+        assert_eq!(parse_sni(b"GET / HTTP/1.1\r\n"), Some(None));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_stream_acl() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut s, _) = backend.accept().await.unwrap();
+            let (mut r, mut w) = s.split();
+            tokio::io::copy(&mut r, &mut w).await.unwrap();
+        });
+
+        let route = |id: &str, deny: Vec<String>| StreamRoute {
+            id: id.to_string(),
+            protocol: StreamProtocol::Tcp,
+            listen_port: 0,
+            domain: None,
+            target_host: "127.0.0.1".to_string(),
+            target_port: backend_port,
+            local_only: true,
+            acl: AccessList {
+                allow: vec![],
+                deny,
+            },
+            idle_timeout_secs: 300,
+            max_sessions: 1024,
+            enabled: true,
+        };
+        let counters = Arc::new(StreamCounters::default());
+        let open = StreamTarget::new(route("echo", vec![]), &HashMap::new(), counters.clone());
+        let closed = StreamTarget::new(
+            route("echo", vec!["loopback".to_string()]),
+            &HashMap::new(),
+            counters.clone(),
+        );
+
+        let front = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let front_addr = front.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let mut c = TcpStream::connect(front_addr).await.unwrap();
+            c.write_all(b"ping").await.unwrap();
+            let mut reply = [0u8; 4];
+            c.read_exact(&mut reply).await.unwrap();
+            assert_eq!(&reply, b"ping");
+        });
+        let (inbound, remote) = front.accept().await.unwrap();
+        let forward = tokio::spawn(async move { open.forward(inbound, remote.ip()).await });
+        client.await.unwrap();
+        forward.await.unwrap();
+
+        assert!(!closed.admit("127.0.0.1".parse().unwrap()));
+        assert!(!closed.admit("8.8.8.8".parse().unwrap()));
+        assert_eq!(counters.connections.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.denied.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_udp_max_sessions() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            loop {
+                let (n, from) = backend.recv_from(&mut buf).await.unwrap();
+                backend.send_to(&buf[..n], from).await.unwrap();
+            }
+        });
+
+        let route = StreamRoute {
+            id: "echo".to_string(),
+            protocol: StreamProtocol::Udp,
+            listen_port: 0,
+            domain: None,
+            target_host: "127.0.0.1".to_string(),
+            target_port: backend_port,
+            local_only: false,
+            acl: AccessList::default(),
+            idle_timeout_secs: 300,
+            max_sessions: 1,
+            enabled: true,
+        };
+        let counters = Arc::new(StreamCounters::default());
+        let target = Arc::new(StreamTarget::new(route, &HashMap::new(), counters.clone()));
+        let front = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let front_addr = front.local_addr().unwrap();
+        tokio::spawn(relay_udp(target, Arc::new(front)));
+
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        first.send_to(b"ping", front_addr).await.unwrap();
+        let mut reply = [0u8; 4];
+        tokio::time::timeout(Duration::from_secs(5), first.recv(&mut reply))
+            .await
+            .expect("no reply")
+            .unwrap();
+        assert_eq!(&reply, b"ping");
+
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        second.send_to(b"pong", front_addr).await.unwrap();
+        let ignored =
+            tokio::time::timeout(Duration::from_millis(300), second.recv(&mut reply)).await;
+        assert!(ignored.is_err());
+        assert_eq!(counters.connections.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.active.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,15 +1,19 @@
 use anyhow::{Context, Result};
-use rustls::ServerConfig;
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::CertifiedKey;
+use rustls::{DigitallySignedStruct, DistinguishedName, ServerConfig, SignatureScheme};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use tracing::{error, info, warn};
 
+use crate::device_ca::{DeviceCa, DeviceRecord, IssuedDevice};
+use crate::ocsp::OcspStapler;
+
 /// SNI-based certificate resolver for rustls
 #[derive(Debug)]
 pub struct SniResolver {
@@ -67,6 +71,11 @@ impl SniResolver {
             .unwrap_or_default()
     }
 
+    /// Key loaded for exactly `domain` (no wildcard or fallback lookup)
+    pub fn get(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.certs.read().ok()?.get(domain).cloned()
+    }
+
     /// Clear all certificates
     pub fn clear(&self) {
         if let Ok(mut certs) = self.certs.write() {
@@ -86,6 +95,49 @@ impl SniResolver {
         Ok(())
     }
 
+    /// Attach an OCSP response (or none) to every key whose leaf is `leaf`,
+    /// including the fallback certificate
+    pub fn staple(&self, leaf: &CertificateDer<'_>, ocsp: Option<Vec<u8>>) {
+        let restaple = |key: &mut Arc<CertifiedKey>| {
+            if key.cert.first() == Some(leaf) && key.ocsp != ocsp {
+                let mut stapled = (**key).clone();
+                stapled.ocsp = ocsp.clone();
+                *key = Arc::new(stapled);
+            }
+        };
+        if let Ok(mut certs) = self.certs.write() {
+            certs.values_mut().for_each(restaple);
+        }
+        if let Ok(mut default) = self.default_cert.write()
+            && let Some(key) = default.as_mut()
+        {
+            restaple(key);
+        }
+    }
+
+    /// Whether the fallback certificate may be served for `server_name`
+    fn is_local(&self, server_name: &str) -> bool {
+        self.local_domain
+            .read()
+            .ok()
+            .and_then(|ld| {
+                ld.as_ref()
+                    .map(|d| server_name == d.as_str() || server_name.ends_with(&format!(".{}", d)))
+            })
+            .unwrap_or(true) // if no local_domain set, allow fallback (backwards compat)
+    }
+
+    /// Whether a handshake for `domain` would get a certificate (exact,
+    /// wildcard or local fallback)
+    pub fn covers(&self, domain: &str) -> bool {
+        if let Ok(certs) = self.certs.read()
+            && matching_key(&certs, domain).is_some()
+        {
+            return true;
+        }
+        self.is_local(domain) && self.default_cert.read().is_ok_and(|d| d.is_some())
+    }
+
     /// Set the default/fallback certificate
     pub fn set_default_cert(&self, key: Arc<CertifiedKey>) {
         if let Ok(mut default) = self.default_cert.write() {
@@ -97,46 +149,43 @@ impl SniResolver {
     }
 }
 
+/// Exact match first, then wildcards walking up domain levels
+/// (most-specific first). For "code.www.mynetwk.biz":
+///   1. Try *.www.mynetwk.biz  → matches per-app cert
+///   2. Try *.mynetwk.biz      → matches global cert
+fn matching_key(
+    certs: &HashMap<String, Arc<CertifiedKey>>,
+    server_name: &str,
+) -> Option<Arc<CertifiedKey>> {
+    if let Some(key) = certs.get(server_name) {
+        return Some(key.clone());
+    }
+    let mut remaining = server_name;
+    while let Some(dot_pos) = remaining.find('.') {
+        let parent = &remaining[dot_pos + 1..];
+        if let Some(key) = certs.get(&format!("*.{}", parent)) {
+            return Some(key.clone());
+        }
+        remaining = parent;
+    }
+    None
+}
+
 impl ResolvesServerCert for SniResolver {
     fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
         let server_name = client_hello.server_name()?;
         let certs = self.certs.read().ok()?;
 
-        // Try exact match first
-        if let Some(key) = certs.get(server_name).cloned() {
+        if let Some(key) = matching_key(&certs, server_name) {
             return Some(key);
         }
 
-        // Walk up domain levels trying wildcard matches (most-specific first).
-        // For "code.www.mynetwk.biz":
-        //   1. Try *.www.mynetwk.biz  → matches per-app cert
-        //   2. Try *.mynetwk.biz      → matches global cert
-        let mut remaining = server_name;
-        while let Some(dot_pos) = remaining.find('.') {
-            let parent = &remaining[dot_pos + 1..];
-            let wildcard = format!("*.{}", parent);
-            if let Some(key) = certs.get(&wildcard).cloned() {
-                return Some(key);
-            }
-            remaining = parent;
-        }
-
         // Use fallback certificate ONLY for local domain subdomains.
         // External domains (e.g. api.stripe.com) are rejected so the client
         // falls back to a direct connection instead of getting a cert mismatch.
         drop(certs); // Release read lock before acquiring another
 
-        let is_local = self
-            .local_domain
-            .read()
-            .ok()
-            .and_then(|ld| {
-                ld.as_ref()
-                    .map(|d| server_name == d.as_str() || server_name.ends_with(&format!(".{}", d)))
-            })
-            .unwrap_or(true); // if no local_domain set, allow fallback (backwards compat)
-
-        if is_local {
+        if self.is_local(server_name) {
             if let Ok(default) = self.default_cert.read() {
                 if let Some(key) = default.clone() {
                     warn!(
@@ -159,6 +208,88 @@ impl ResolvesServerCert for SniResolver {
     }
 }
 
+/// Client certificate verifier backed by the device CA.
+///
+/// Client auth is offered (never required at the TLS level) once the device
+/// CA holds a valid certificate; routes decide whether a certificate is
+/// optional or required. The inner verifier is swapped whenever devices are
+/// issued or revoked, so revocations apply to new handshakes immediately.
+#[derive(Debug, Default)]
+pub struct DeviceCertVerifier {
+    inner: RwLock<Option<Arc<dyn ClientCertVerifier>>>,
+    /// The CA never changes once created, so its hint is kept for good
+    root_hints: OnceLock<Vec<DistinguishedName>>,
+}
+
+impl DeviceCertVerifier {
+    pub fn set(&self, verifier: Option<Arc<dyn ClientCertVerifier>>) {
+        if let Some(v) = &verifier {
+            self.root_hints
+                .get_or_init(|| v.root_hint_subjects().to_vec());
+        }
+        *self.inner.write().unwrap() = verifier;
+    }
+
+    fn current(&self) -> Result<Arc<dyn ClientCertVerifier>, rustls::Error> {
+        self.inner
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| rustls::Error::General("client authentication disabled".into()))
+    }
+}
+
+impl ClientCertVerifier for DeviceCertVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.read().unwrap().is_some()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.root_hints.get().map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.current()?
+            .verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.current()?.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.current()?.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        match self.current() {
+            Ok(v) => v.supported_verify_schemes(),
+            Err(_) => rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes(),
+        }
+    }
+}
+
 /// TLS Manager - loads certificates from CA storage and builds the SNI resolver
 pub struct TlsManager {
     /// CA storage path
@@ -166,16 +297,78 @@ pub struct TlsManager {
 
     /// SNI resolver (shared with the TLS acceptor)
     pub resolver: Arc<SniResolver>,
+
+    /// Client certificate verifier (shared with the TLS and QUIC acceptors)
+    pub client_auth: Arc<DeviceCertVerifier>,
+
+    /// Device CA, opened on startup if present or on first enrollment
+    device_ca: Mutex<Option<Arc<DeviceCa>>>,
+
+    /// OCSP responses stapled to ACME certificates
+    pub ocsp: Arc<OcspStapler>,
 }
 
 impl TlsManager {
     pub fn new(ca_storage_path: PathBuf) -> Self {
+        let resolver = Arc::new(SniResolver::new());
         Self {
             ca_storage_path,
-            resolver: Arc::new(SniResolver::new()),
+            ocsp: Arc::new(OcspStapler::new(resolver.clone())),
+            resolver,
+            client_auth: Arc::new(DeviceCertVerifier::default()),
+            device_ca: Mutex::new(None),
         }
     }
 
+    fn device_ca_dir(&self) -> PathBuf {
+        self.ca_storage_path.join("device-ca")
+    }
+
+    /// Load an existing device CA and start offering client auth.
+    /// Does nothing until a first device has been enrolled.
+    pub fn load_device_ca(&self) -> Result<()> {
+        if !self.device_ca_dir().exists() {
+            return Ok(());
+        }
+        self.device_ca()?;
+        Ok(())
+    }
+
+    fn device_ca(&self) -> Result<Arc<DeviceCa>> {
+        let mut slot = self.device_ca.lock().unwrap();
+        if let Some(ca) = slot.as_ref() {
+            return Ok(ca.clone());
+        }
+        let ca = Arc::new(DeviceCa::open_or_create(&self.device_ca_dir())?);
+        self.client_auth.set(ca.verifier()?);
+        *slot = Some(ca.clone());
+        Ok(ca)
+    }
+
+    /// Issue a client certificate for a device.
+    pub fn issue_device_cert(&self, name: &str, validity_days: u32) -> Result<IssuedDevice> {
+        let ca = self.device_ca()?;
+        let issued = ca.issue(name, validity_days)?;
+        self.client_auth.set(ca.verifier()?);
+        Ok(issued)
+    }
+
+    /// Revoke a device certificate by hex serial. Returns false if unknown.
+    pub fn revoke_device_cert(&self, serial: &str) -> Result<bool> {
+        let ca = self.device_ca()?;
+        let found = ca.revoke(serial)?;
+        if found {
+            self.client_auth.set(ca.verifier()?);
+        }
+        Ok(found)
+    }
+
+    /// Issued device certificates, with the CA certificate to trust.
+    pub fn list_device_certs(&self) -> Result<(Vec<DeviceRecord>, String)> {
+        let ca = self.device_ca()?;
+        Ok((ca.list(), ca.ca_pem().to_string()))
+    }
+
     /// Load a certificate for a specific domain
     pub fn load_certificate(&self, domain: &str, cert_id: &str) -> Result<()> {
         let certified_key = self.load_certified_key(cert_id)?;
@@ -185,6 +378,11 @@ impl TlsManager {
         Ok(())
     }
 
+    /// Whether the certificate `cert_id` can be loaded (for config validation)
+    pub fn check_certificate(&self, cert_id: &str) -> Result<()> {
+        self.load_certified_key(cert_id).map(|_| ())
+    }
+
     /// Load a CertifiedKey from cert_id without inserting it
     fn load_certified_key(&self, cert_id: &str) -> Result<CertifiedKey> {
         let cert_path = self
@@ -247,25 +445,30 @@ impl TlsManager {
     }
 
     /// Insert a certified key for a domain pattern (e.g. "*.mynetwk.biz")
+    /// and staple OCSP responses to it
     pub fn add_cert(&self, domain: &str, key: Arc<CertifiedKey>) {
-        self.resolver.insert(domain.to_string(), key);
+        self.resolver.insert(domain.to_string(), key.clone());
+        self.ocsp.track(domain, &key);
     }
 
     /// Set the fallback certificate from PEM file paths (for ACME/Let's Encrypt certs)
     pub fn set_fallback_certificate_from_pem(&self, cert_path: &str, key_path: &str) -> Result<()> {
         let certified_key = load_certified_key_from_paths(cert_path, key_path)?;
         self.resolver.set_default_cert(Arc::new(certified_key));
+        self.ocsp.reapply();
         info!("Fallback TLS certificate configured from: {}", cert_path);
         Ok(())
     }
 
-    /// Build the rustls ServerConfig with our SNI resolver
+    /// Build the rustls ServerConfig with our SNI resolver and the device
+    /// client certificate verifier
     pub fn build_server_config(&self) -> Result<Arc<ServerConfig>> {
         let mut config = ServerConfig::builder()
-            .with_no_client_auth()
+            .with_client_cert_verifier(self.client_auth.clone())
             .with_cert_resolver(self.resolver.clone());
 
-        // Advertise only HTTP/1.1 via ALPN — we don't support h2 or h3.
+        // Advertise only HTTP/1.1 via ALPN — we don't support h2, and h3 is
+        // served by the separate QUIC listener (see `http3`).
         // This helps browsers negotiate the correct protocol on LAN.
         config.alpn_protocols = vec![b"http/1.1".to_vec()];
 
@@ -308,6 +511,7 @@ impl TlsManager {
 
         // Phase 2: Atomic swap - replace all certs at once
         self.resolver.replace_all(new_certs)?;
+        self.ocsp.reapply();
         info!("Certificate reload completed atomically");
 
         if !load_errors.is_empty() {
@@ -333,7 +537,7 @@ fn load_certified_key_from_paths(cert_path: &str, key_path: &str) -> Result<Cert
 }
 
 /// Load certificates from a PEM file
-fn load_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>> {
+pub(crate) fn load_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>> {
     let file =
         File::open(path).with_context(|| format!("Failed to open certificate file: {:?}", path))?;
     let mut reader = BufReader::new(file);
@@ -450,9 +654,20 @@ mod tests {
                 target_host: "localhost".to_string(),
                 target_port: 8080,
                 local_only: false,
+                acl: Default::default(),
+                client_cert: Default::default(),
                 require_auth: false,
+                machine_auth: None,
                 enabled: true,
                 cert_id: None, // No cert_id, so loading is skipped
+                pool: None,
+                backend_tls: None,
+                rate_limit: None,
+                compression: None,
+                cache: None,
+                upstream: None,
+                headers: Default::default(),
+                rules: vec![],
             },
             crate::config::RouteConfig {
                 id: "2".to_string(),
@@ -461,9 +676,20 @@ mod tests {
                 target_host: "localhost".to_string(),
                 target_port: 8081,
                 local_only: false,
+                acl: Default::default(),
+                client_cert: Default::default(),
                 require_auth: false,
+                machine_auth: None,
                 enabled: false,
                 cert_id: Some("cert-2".to_string()),
+                pool: None,
+                backend_tls: None,
+                rate_limit: None,
+                compression: None,
+                cache: None,
+                upstream: None,
+                headers: Default::default(),
+                rules: vec![],
             },
         ];
         // Should succeed - disabled route is skipped, enabled route has no cert_id so skipped too
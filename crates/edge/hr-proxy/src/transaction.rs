@@ -0,0 +1,637 @@
+//! Transactional proxy configuration changes.
+//!
+//! A candidate config is checked before it replaces the running one:
+//! structural problems (duplicate domains, unknown pools, invalid rules,
+//! unloadable certificates) reject it, while unreachable targets and
+//! domains no certificate covers are reported as warnings. `diff` shows
+//! what applying it would change, and `ConfigHistory` keeps the last
+//! applied versions on disk so any of them can be restored in one call.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::{BackendTlsVerify, ProxyConfig, StreamProtocol};
+use crate::rules::CompiledRule;
+use crate::tls::TlsManager;
+use crate::{acl, headers, redirect};
+
+/// Time allowed to open a TCP connection to a target during validation.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Fields only read when hr-edge starts.
+const STARTUP_FIELDS: [&str; 5] = [
+    "http_port",
+    "https_port",
+    "http3_enabled",
+    "access_log_path",
+    "access_log",
+];
+
+/// List fields diffed item by item, with the field identifying an item.
+const KEYED_LISTS: [(&str, &str); 5] = [
+    ("routes", "id"),
+    ("streams", "id"),
+    ("redirects", "id"),
+    ("pools", "name"),
+    ("domain_aliases", "from"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The config is rejected.
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    /// What the issue is about, e.g. `route app.example.com`.
+    pub subject: String,
+    pub message: String,
+}
+
+/// Outcome of checking a candidate config.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ValidationReport {
+    fn push(&mut self, severity: Severity, subject: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ConfigIssue {
+            severity,
+            subject: subject.into(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, subject: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, subject, message);
+    }
+
+    fn warning(&mut self, subject: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, subject, message);
+    }
+
+    /// Whether the config may be applied (warnings only).
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    /// The errors on one line, for error messages.
+    pub fn error_summary(&self) -> String {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| format!("{}: {}", i.subject, i.message))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Checks needing neither the network nor the certificate store.
+pub fn check(config: &ProxyConfig) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if config.http_port == config.https_port {
+        report.error(
+            "ports",
+            format!("http_port and https_port are both {}", config.http_port),
+        );
+    }
+
+    let mut pools = HashSet::new();
+    for pool in &config.pools {
+        let subject = format!("pool {}", pool.name);
+        if !pools.insert(pool.name.as_str()) {
+            report.error(&subject, "Duplicate pool name");
+        }
+        if pool.members.is_empty() {
+            report.warning(&subject, "Pool has no members");
+        }
+    }
+
+    let mut ids = HashSet::new();
+    let mut domains: HashMap<String, &str> = HashMap::new();
+    for route in &config.routes {
+        let subject = format!("route {}", route.domain);
+        if route.id.is_empty() {
+            report.error(&subject, "Empty route id");
+        } else if !ids.insert(route.id.as_str()) {
+            report.error(&subject, format!("Duplicate route id '{}'", route.id));
+        }
+        if route.domain.trim().is_empty() {
+            report.error(&subject, "Empty domain");
+        }
+        if !route.enabled {
+            continue;
+        }
+        if let Some(other) = domains.insert(route.domain.to_ascii_lowercase(), &route.id) {
+            report.error(
+                &subject,
+                format!("Domain already served by route '{}'", other),
+            );
+        }
+        match &route.pool {
+            Some(pool) if !pools.contains(pool.as_str()) => {
+                report.error(&subject, format!("Unknown pool '{}'", pool));
+            }
+            Some(_) => {}
+            None if route.target_host.is_empty() || route.target_port == 0 => {
+                report.error(&subject, "No target_host:target_port nor pool");
+            }
+            None => {}
+        }
+        for (i, rule) in route.rules.iter().enumerate() {
+            if let Err(e) = CompiledRule::compile(rule) {
+                report.error(&subject, format!("Rule #{}: {:#}", i, e));
+            }
+            match &rule.pool {
+                Some(pool) if !pools.contains(pool.as_str()) => {
+                    report.error(&subject, format!("Rule #{}: unknown pool '{}'", i, pool));
+                }
+                Some(_) => {}
+                None if rule.target_host.is_empty() || rule.target_port == 0 => {
+                    report.error(
+                        &subject,
+                        format!("Rule #{}: no target_host:target_port nor pool", i),
+                    );
+                }
+                None => {}
+            }
+        }
+        for name in headers::unknown_policies(&config.header_policies, &route.headers) {
+            report.warning(&subject, format!("Unknown header policy '{}'", name));
+        }
+        for entry in acl::unknown_entries(&route.acl, &config.ip_lists) {
+            report.warning(&subject, format!("Unknown ACL entry '{}'", entry));
+        }
+        if let Some(tls) = &route.backend_tls
+            && tls.verify == BackendTlsVerify::Insecure
+        {
+            report.warning(&subject, "Backend certificate verification disabled");
+        }
+    }
+
+    let mut aliases = HashSet::new();
+    for alias in &config.domain_aliases {
+        let subject = format!("alias {}", alias.from);
+        let from = alias.from.to_ascii_lowercase();
+        if !aliases.insert(from.clone()) {
+            report.error(&subject, "Duplicate alias");
+        }
+        if let Some(route) = domains.get(&from) {
+            report.error(
+                &subject,
+                format!("Domain is also served by route '{}'", route),
+            );
+        }
+    }
+    for (entry, reason) in redirect::invalid_entries(&config.domain_aliases, &config.redirects) {
+        report.error(format!("redirect {}", entry), reason);
+    }
+
+    let mut stream_ids = HashSet::new();
+    let mut listeners = HashSet::new();
+    for stream in &config.streams {
+        let subject = format!("stream {}", stream.id);
+        if !stream_ids.insert(stream.id.as_str()) {
+            report.error(&subject, "Duplicate stream id");
+        }
+        if !stream.enabled {
+            continue;
+        }
+        match stream.protocol {
+            StreamProtocol::TlsPassthrough if stream.domain.is_none() => {
+                report.error(&subject, "tls_passthrough needs a domain");
+            }
+            StreamProtocol::TlsPassthrough => {}
+            _ if stream.listen_port == 0 => report.error(&subject, "No listen_port"),
+            protocol => {
+                if !listeners.insert((protocol, stream.listen_port)) {
+                    report.error(
+                        &subject,
+                        format!("Port {} already used by another stream", stream.listen_port),
+                    );
+                }
+            }
+        }
+        for entry in acl::unknown_entries(&stream.acl, &config.ip_lists) {
+            report.warning(&subject, format!("Unknown ACL entry '{}'", entry));
+        }
+    }
+    for (list, entry) in acl::invalid_list_entries(&config.ip_lists) {
+        report.warning(
+            format!("ip list {}", list),
+            format!("Invalid address '{}'", entry),
+        );
+    }
+
+    report
+}
+
+/// `check`, plus the certificates of enabled routes and a TCP connection to
+/// every direct target (pool members are left to health checks).
+pub async fn validate(config: &ProxyConfig, tls: &TlsManager) -> ValidationReport {
+    let mut report = check(config);
+
+    let mut targets: HashMap<(String, u16), String> = HashMap::new();
+    for route in config.routes.iter().filter(|r| r.enabled) {
+        let subject = format!("route {}", route.domain);
+        match &route.cert_id {
+            Some(cert_id) => {
+                if let Err(e) = tls.check_certificate(cert_id) {
+                    report.error(&subject, format!("Certificate {}: {:#}", cert_id, e));
+                }
+            }
+            None if !tls.resolver.covers(&route.domain) => {
+                report.warning(&subject, "No certificate covers this domain");
+            }
+            None => {}
+        }
+        let rule_targets = route.rules.iter().filter(|r| r.pool.is_none());
+        let direct = route
+            .pool
+            .is_none()
+            .then_some((&route.target_host, route.target_port));
+        for (host, port) in direct
+            .into_iter()
+            .chain(rule_targets.map(|r| (&r.target_host, r.target_port)))
+        {
+            if !host.is_empty() && port != 0 {
+                targets
+                    .entry((host.clone(), port))
+                    .or_insert_with(|| subject.clone());
+            }
+        }
+    }
+    for stream in config.streams.iter().filter(|s| s.enabled) {
+        targets
+            .entry((stream.target_host.clone(), stream.target_port))
+            .or_insert_with(|| format!("stream {}", stream.id));
+    }
+
+    let mut probes = JoinSet::new();
+    for ((host, port), subject) in targets {
+        probes.spawn(async move {
+            let result =
+                tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host.as_str(), port)))
+                    .await;
+            let error = match result {
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(_) => Some("connection timed out".to_string()),
+            };
+            (subject, host, port, error)
+        });
+    }
+    let mut unreachable = Vec::new();
+    while let Some(probe) = probes.join_next().await {
+        if let Ok((subject, host, port, Some(error))) = probe {
+            unreachable.push((
+                subject,
+                format!("Target {}:{} unreachable: {}", host, port, error),
+            ));
+        }
+    }
+    unreachable.sort();
+    for (subject, message) in unreachable {
+        report.warning(subject, message);
+    }
+
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    /// Top-level field, or `field[key]` for an item of a keyed list
+    /// (routes and streams by id, pools by name, aliases by host).
+    pub path: String,
+    pub kind: ChangeKind,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// What applying a candidate config would change.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigDiff {
+    pub changes: Vec<ConfigChange>,
+    /// Changed fields that only take effect when hr-edge restarts.
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+fn change(path: String, before: Option<&Value>, after: Option<&Value>) -> ConfigChange {
+    let kind = match (before, after) {
+        (None, _) => ChangeKind::Added,
+        (_, None) => ChangeKind::Removed,
+        _ => ChangeKind::Modified,
+    };
+    ConfigChange {
+        path,
+        kind,
+        before: before.cloned(),
+        after: after.cloned(),
+    }
+}
+
+/// Items of a keyed list by key, in list order.
+fn keyed<'a>(list: Option<&'a Value>, key: &str) -> Vec<(String, &'a Value)> {
+    list.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, item)| {
+            let id = match item.get(key) {
+                Some(Value::String(s)) => s.clone(),
+                _ => format!("#{}", i),
+            };
+            (id, item)
+        })
+        .collect()
+}
+
+/// Changes from `running` to `candidate`.
+pub fn diff(running: &ProxyConfig, candidate: &ProxyConfig) -> ConfigDiff {
+    let before = serde_json::to_value(running).unwrap_or_default();
+    let after = serde_json::to_value(candidate).unwrap_or_default();
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut diff = ConfigDiff::default();
+    let fields: std::collections::BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for field in fields {
+        let (old, new) = (before.get(field), after.get(field));
+        if old == new {
+            continue;
+        }
+        if STARTUP_FIELDS.contains(&field.as_str()) {
+            diff.restart_required.push(field.clone());
+        }
+        let Some((_, key)) = KEYED_LISTS.iter().find(|(name, _)| name == field) else {
+            diff.changes.push(change(field.clone(), old, new));
+            continue;
+        };
+        let old_items = keyed(old, key);
+        let new_items = keyed(new, key);
+        for (id, item) in &old_items {
+            match new_items.iter().find(|(other, _)| other == id) {
+                None => diff
+                    .changes
+                    .push(change(format!("{}[{}]", field, id), Some(item), None)),
+                Some((_, new_item)) if new_item != item => diff.changes.push(change(
+                    format!("{}[{}]", field, id),
+                    Some(item),
+                    Some(new_item),
+                )),
+                Some(_) => {}
+            }
+        }
+        for (id, item) in &new_items {
+            if !old_items.iter().any(|(other, _)| other == id) {
+                diff.changes
+                    .push(change(format!("{}[{}]", field, id), None, Some(item)));
+            }
+        }
+    }
+    diff
+}
+
+/// An applied config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub id: u64,
+    pub created_at: String,
+    /// What applied it: `startup`, `save`, `reload`, `rollback:<id>`…
+    pub source: String,
+    pub config: ProxyConfig,
+}
+
+/// A revision without its config, for listings.
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionInfo {
+    pub id: u64,
+    pub created_at: String,
+    pub source: String,
+    pub routes: usize,
+}
+
+/// The last applied configs, one JSON file per revision in `dir`.
+pub struct ConfigHistory {
+    dir: PathBuf,
+    /// Serializes `record` so two applies don't pick the same id.
+    lock: Mutex<()>,
+}
+
+impl ConfigHistory {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:08}.json", id))
+    }
+
+    /// Revision ids on disk, oldest first.
+    fn ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                name.to_str()?.strip_suffix(".json")?.parse().ok()
+            })
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn get(&self, id: u64) -> anyhow::Result<Revision> {
+        let path = self.path(id);
+        let json =
+            std::fs::read_to_string(&path).with_context(|| format!("Revision {} not found", id))?;
+        serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Revisions, newest first.
+    pub fn list(&self) -> Vec<RevisionInfo> {
+        self.ids()
+            .into_iter()
+            .rev()
+            .filter_map(|id| match self.get(id) {
+                Ok(rev) => Some(RevisionInfo {
+                    id: rev.id,
+                    created_at: rev.created_at,
+                    source: rev.source,
+                    routes: rev.config.routes.len(),
+                }),
+                Err(e) => {
+                    warn!("Skipping config revision {}: {:#}", id, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Id of the revision before the latest one, the default rollback
+    /// target. Rolling back records the restored config as a new revision,
+    /// so a second default rollback undoes the first.
+    pub fn previous(&self) -> Option<u64> {
+        self.ids().into_iter().rev().nth(1)
+    }
+
+    /// Record `config` as applied, unless it is the latest revision already,
+    /// and keep the `keep` newest revisions. Returns the revision id.
+    pub fn record(&self, config: &ProxyConfig, source: &str, keep: usize) -> anyhow::Result<u64> {
+        let _guard = self.lock.lock().unwrap();
+        let ids = self.ids();
+        if let Some(&latest) = ids.last()
+            && let Ok(rev) = self.get(latest)
+            && serde_json::to_value(&rev.config)? == serde_json::to_value(config)?
+        {
+            return Ok(latest);
+        }
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let id = ids.last().map_or(1, |id| id + 1);
+        let revision = Revision {
+            id,
+            created_at: crate::logging::now_timestamp(),
+            source: source.to_string(),
+            config: config.clone(),
+        };
+        let tmp = self.dir.join(format!("{:08}.json.tmp", id));
+        std::fs::write(&tmp, serde_json::to_string_pretty(&revision)?)?;
+        std::fs::rename(&tmp, self.path(id))?;
+        info!(revision = id, source, "Recorded proxy config revision");
+
+        let stale = (ids.len() + 1).saturating_sub(keep.max(1));
+        for old in ids.into_iter().take(stale) {
+            if let Err(e) = std::fs::remove_file(self.path(old)) {
+                warn!("Failed to remove config revision {}: {}", old, e);
+            }
+        }
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: Value) -> ProxyConfig {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_check_and_diff() {
+        let running = config(serde_json::json!({
+            "base_domain": "example.com",
+            "routes": [
+                {"id": "a", "domain": "a.example.com", "target_host": "10.0.0.1", "target_port": 80},
+                {"id": "b", "domain": "b.example.com", "target_host": "10.0.0.2", "target_port": 80}
+            ]
+        }));
+        assert!(check(&running).is_valid());
+
+        let candidate = config(serde_json::json!({
+            "base_domain": "example.com",
+            "https_port": 8443,
+            "routes": [
+                {"id": "a", "domain": "a.example.com", "target_host": "10.0.0.1", "target_port": 81},
+                {"id": "c", "domain": "A.example.com", "pool": "web"},
+                {"id": "d", "domain": "d.example.com", "target_host": "10.0.0.4", "target_port": 80,
+                 "rules": [{"path_regex": "(", "target_host": "10.0.0.5", "target_port": 80}]}
+            ],
+            "domain_aliases": [{"from": "d.example.com", "to": "a.example.com"}]
+        }));
+        let report = check(&candidate);
+        assert!(!report.is_valid());
+        let errors: Vec<&str> = report
+            .issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| i.message.as_str())
+            .collect();
+        assert!(errors.contains(&"Domain already served by route 'a'"));
+        assert!(errors.contains(&"Unknown pool 'web'"));
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.starts_with("Rule #0: Invalid path_regex"))
+        );
+        assert!(errors.contains(&"Domain is also served by route 'd'"));
+
+        let diff = diff(&running, &candidate);
+        let changes: Vec<(&str, ChangeKind)> = diff
+            .changes
+            .iter()
+            .map(|c| (c.path.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("domain_aliases[d.example.com]", ChangeKind::Added),
+                ("https_port", ChangeKind::Modified),
+                ("routes[a]", ChangeKind::Modified),
+                ("routes[b]", ChangeKind::Removed),
+                ("routes[c]", ChangeKind::Added),
+                ("routes[d]", ChangeKind::Added),
+            ]
+        );
+        assert_eq!(diff.restart_required, vec!["https_port"]);
+    }
+
+    #[test]
+    fn test_history_records_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("hr-config-history-{}", uuid::Uuid::new_v4()));
+        let history = ConfigHistory::new(dir.clone());
+        assert!(history.list().is_empty());
+        assert_eq!(history.previous(), None);
+
+        let mut cfg = config(serde_json::json!({"base_domain": "example.com"}));
+        assert_eq!(history.record(&cfg, "startup", 3).unwrap(), 1);
+        // Unchanged config: no new revision
+        assert_eq!(history.record(&cfg, "reload", 3).unwrap(), 1);
+
+        for port in [8080, 8081, 8082] {
+            cfg.http_port = port;
+            history.record(&cfg, "save", 3).unwrap();
+        }
+        let ids: Vec<u64> = history.list().iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(history.previous(), Some(3));
+        assert_eq!(history.get(3).unwrap().config.http_port, 8081);
+        assert!(history.get(1).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,464 @@
+//! Backend timeouts, retries and circuit breaking.
+//!
+//! Every proxied request runs under its route's `UpstreamConfig` (or the
+//! global one): a connect timeout on the connector, a read timeout on the
+//! response headers of each attempt and between body frames, and an
+//! optional budget for all attempts up to the end of the response body.
+//! Idempotent requests without a body may be retried with
+//! exponential backoff, on another member when the route is pooled. A
+//! circuit breaker per backend address fast-fails requests after repeated
+//! failures, then lets one trial request through after `open_secs`.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderMap, Method, Uri, Version};
+use axum::response::Response;
+use hyper::body::Frame;
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
+use tokio::time::Sleep;
+use tracing::{info, warn};
+
+use crate::config::{CircuitBreakerConfig, RetryConfig};
+use crate::handler::ProxyError;
+
+/// Backend statuses counted as failures by the circuit breaker.
+const FAILURE_STATUSES: [u16; 3] = [502, 503, 504];
+
+pub type HttpClient = Client<HttpConnector, Body>;
+
+/// Plain-HTTP backend clients, one per connect timeout in use so each keeps
+/// its own connection pool.
+#[derive(Default)]
+pub struct UpstreamClients {
+    clients: RwLock<HashMap<u64, HttpClient>>,
+}
+
+impl UpstreamClients {
+    pub fn get(&self, connect_timeout_ms: u64) -> HttpClient {
+        if let Some(client) = self.clients.read().unwrap().get(&connect_timeout_ms) {
+            return client.clone();
+        }
+        self.clients
+            .write()
+            .unwrap()
+            .entry(connect_timeout_ms)
+            .or_insert_with(|| {
+                let mut connector = HttpConnector::new();
+                connector.set_connect_timeout(
+                    (connect_timeout_ms > 0).then(|| Duration::from_millis(connect_timeout_ms)),
+                );
+                Client::builder(TokioExecutor::new()).build(connector)
+            })
+            .clone()
+    }
+}
+
+/// Method, target and headers of a bodiless idempotent request, kept to
+/// send it again.
+pub struct Replay {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    pub headers: HeaderMap,
+}
+
+impl Replay {
+    /// `None` when repeating the request could have side effects or would
+    /// need its body.
+    pub fn capture(req: &Request) -> Option<Self> {
+        let idempotent = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+        );
+        if !idempotent || !req.body().is_end_stream() {
+            return None;
+        }
+        Some(Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        })
+    }
+
+    pub fn request(&self) -> Request {
+        let mut req = Request::new(Body::empty());
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+        req
+    }
+}
+
+/// Bound a backend response body: it fails when no frame arrives within
+/// `idle` or when `deadline` passes before the end of the body.
+pub fn timed_response(
+    resp: Response,
+    idle: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Response {
+    if idle.is_none() && deadline.is_none() {
+        return resp;
+    }
+    let (parts, body) = resp.into_parts();
+    let timer = Box::pin(tokio::time::sleep_until(
+        TimedBody::next_deadline(idle, deadline).into(),
+    ));
+    Response::from_parts(
+        parts,
+        Body::new(TimedBody {
+            inner: body,
+            idle,
+            deadline,
+            timer,
+        }),
+    )
+}
+
+struct TimedBody {
+    inner: Body,
+    idle: Option<Duration>,
+    deadline: Option<Instant>,
+    timer: Pin<Box<Sleep>>,
+}
+
+impl TimedBody {
+    /// When the body times out if no frame arrives before.
+    fn next_deadline(idle: Option<Duration>, deadline: Option<Instant>) -> Instant {
+        let idle = idle.map(|idle| Instant::now() + idle);
+        match (idle, deadline) {
+            (Some(idle), Some(deadline)) => idle.min(deadline),
+            (idle, deadline) => idle
+                .or(deadline)
+                .unwrap_or_else(|| Instant::now() + Duration::from_secs(86_400 * 365)),
+        }
+    }
+}
+
+impl HttpBody for TimedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            if frame.is_some() {
+                let next = Self::next_deadline(self.idle, self.deadline);
+                self.timer.as_mut().reset(next.into());
+            }
+            return Poll::Ready(frame);
+        }
+        match self.timer.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                let what = match self.deadline {
+                    Some(deadline) if Instant::now() >= deadline => "total timeout",
+                    _ => "read timeout",
+                };
+                Poll::Ready(Some(Err(axum::Error::new(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("backend response body exceeded the {}", what),
+                )))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Whether an attempt's outcome is worth another try.
+pub fn should_retry(result: &Result<Response, ProxyError>, retry: &RetryConfig) -> bool {
+    match result {
+        Ok(resp) => retry.on_status.contains(&resp.status().as_u16()),
+        Err(err) => matches!(
+            err,
+            ProxyError::UpstreamError(_)
+                | ProxyError::UpstreamTimeout(_)
+                | ProxyError::CircuitOpen { .. }
+        ),
+    }
+}
+
+/// Whether an attempt's outcome counts against the backend's breaker.
+pub fn is_failure(result: &Result<Response, ProxyError>) -> bool {
+    match result {
+        Ok(resp) => FAILURE_STATUSES.contains(&resp.status().as_u16()),
+        Err(err) => matches!(
+            err,
+            ProxyError::UpstreamError(_)
+                | ProxyError::UpstreamTimeout(_)
+                | ProxyError::BackendTls(_)
+        ),
+    }
+}
+
+/// Delay before retry number `retry` (1-based): `backoff_ms` doubled per
+/// retry, capped at `max_backoff_ms`.
+pub fn backoff(config: &RetryConfig, retry: u32) -> Duration {
+    let factor = 1u64
+        .checked_shl(retry.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+    Duration::from_millis(
+        config
+            .backoff_ms
+            .saturating_mul(factor)
+            .min(config.max_backoff_ms),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    /// Requests fail fast until `until`.
+    Open,
+    /// A trial request is in flight; it closes or reopens the circuit.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    failures: u32,
+    /// End of the open period, or of the trial's allotted time.
+    until: Instant,
+    trips: u64,
+}
+
+/// Breaker of one backend, for the status endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub backend: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Seconds until the next trial request (open circuits).
+    pub retry_in_secs: Option<u64>,
+    /// Times the circuit opened.
+    pub trips: u64,
+}
+
+/// Circuit breakers keyed by backend address (`host:port`). Backends
+/// without recent failures have no entry.
+#[derive(Default)]
+pub struct CircuitBreakers {
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl CircuitBreakers {
+    /// Admit a request to `backend`, or return the seconds until its
+    /// circuit lets a trial through.
+    pub fn allow(&self, backend: &str, config: &CircuitBreakerConfig) -> Result<(), u64> {
+        let mut breakers = self.breakers.lock().unwrap();
+        let Some(breaker) = breakers.get_mut(backend) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        match breaker.state {
+            BreakerState::Closed => Ok(()),
+            // A trial that never reported back (client gone) doesn't keep
+            // the circuit stuck: another one goes after the same delay.
+            BreakerState::Open | BreakerState::HalfOpen if now >= breaker.until => {
+                breaker.state = BreakerState::HalfOpen;
+                breaker.until = now + Duration::from_secs(config.open_secs);
+                Ok(())
+            }
+            BreakerState::Open | BreakerState::HalfOpen => {
+                Err(breaker.until.saturating_duration_since(now).as_secs() + 1)
+            }
+        }
+    }
+
+    /// Whether `backend` currently fails fast (pool members in that state
+    /// are skipped).
+    pub fn is_open(&self, backend: &str) -> bool {
+        self.breakers
+            .lock()
+            .unwrap()
+            .get(backend)
+            .is_some_and(|b| b.state != BreakerState::Closed && Instant::now() < b.until)
+    }
+
+    /// Feed the outcome of a request admitted by `allow`.
+    pub fn record(&self, backend: &str, config: &CircuitBreakerConfig, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if success {
+            if let Some(breaker) = breakers.remove(backend)
+                && breaker.state != BreakerState::Closed
+            {
+                info!(backend, "Circuit closed");
+            }
+            return;
+        }
+        let now = Instant::now();
+        let breaker = breakers.entry(backend.to_string()).or_insert(Breaker {
+            state: BreakerState::Closed,
+            failures: 0,
+            until: now,
+            trips: 0,
+        });
+        breaker.failures += 1;
+        let trip = match breaker.state {
+            BreakerState::Closed => breaker.failures >= config.failure_threshold.max(1),
+            BreakerState::HalfOpen => true,
+            BreakerState::Open => false,
+        };
+        if trip {
+            breaker.state = BreakerState::Open;
+            breaker.until = now + Duration::from_secs(config.open_secs);
+            breaker.trips += 1;
+            warn!(
+                backend,
+                "Circuit opened after {} consecutive failures ({}s)",
+                breaker.failures,
+                config.open_secs
+            );
+        }
+    }
+
+    pub fn status(&self) -> Vec<BreakerStatus> {
+        let now = Instant::now();
+        let mut status: Vec<BreakerStatus> = self
+            .breakers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(backend, b)| BreakerStatus {
+                backend: backend.clone(),
+                state: b.state,
+                consecutive_failures: b.failures,
+                retry_in_secs: (b.state == BreakerState::Open)
+                    .then(|| b.until.saturating_duration_since(now).as_secs()),
+                trips: b.trips,
+            })
+            .collect();
+        status.sort_by(|a, b| a.backend.cmp(&b.backend));
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_and_recovers() {
+        let breakers = CircuitBreakers::default();
+        let config = CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_secs: 0,
+        };
+        let backend = "10.0.0.5:8080";
+
+        breakers.record(backend, &config, false);
+        assert!(breakers.allow(backend, &config).is_ok());
+        breakers.record(backend, &config, false);
+        assert_eq!(breakers.status()[0].state, BreakerState::Open);
+        assert_eq!(breakers.status()[0].trips, 1);
+
+        // open_secs elapsed: one trial goes through, the next waits for it
+        assert!(breakers.allow(backend, &config).is_ok());
+        assert_eq!(breakers.status()[0].state, BreakerState::HalfOpen);
+        breakers.record(backend, &config, false);
+        assert_eq!(breakers.status()[0].trips, 2);
+
+        assert!(breakers.allow(backend, &config).is_ok());
+        breakers.record(backend, &config, true);
+        assert!(breakers.status().is_empty());
+
+        let long = CircuitBreakerConfig {
+            failure_threshold: 1,
+            open_secs: 30,
+        };
+        breakers.record(backend, &long, false);
+        assert!(breakers.is_open(backend));
+        assert!(breakers.allow(backend, &long).unwrap_err() >= 29);
+    }
+
+    #[test]
+    fn test_backoff_and_replay() {
+        let retry = RetryConfig {
+            attempts: 5,
+            backoff_ms: 100,
+            max_backoff_ms: 350,
+            on_status: vec![503],
+        };
+        let delays: Vec<u64> = (1..=4)
+            .map(|n| backoff(&retry, n).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 350, 350]);
+
+        let get = Request::builder()
+            .uri("/items")
+            .header("accept", "application/json")
+            .body(Body::empty())
+            .unwrap();
+        let replay = Replay::capture(&get).unwrap();
+        let again = replay.request();
+        assert_eq!(again.uri(), "/items");
+        assert_eq!(again.headers()["accept"], "application/json");
+
+        let post = Request::builder()
+            .method(Method::POST)
+            .body(Body::empty())
+            .unwrap();
+        assert!(Replay::capture(&post).is_none());
+        let put = Request::builder()
+            .method(Method::PUT)
+            .body(Body::from("payload"))
+            .unwrap();
+        assert!(Replay::capture(&put).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timed_response_bounds_the_body() {
+        use futures_util::StreamExt;
+
+        fn trickle(gap: Duration) -> Response {
+            let chunks = futures_util::stream::iter(0..3).then(move |_| async move {
+                tokio::time::sleep(gap).await;
+                Ok::<_, std::io::Error>(Bytes::from_static(b"x"))
+            });
+            Response::new(Body::from_stream(chunks))
+        }
+        async fn collect(resp: Response) -> Result<Bytes, axum::Error> {
+            axum::body::to_bytes(resp.into_body(), usize::MAX).await
+        }
+
+        let idle = Some(Duration::from_millis(200));
+        let ok = timed_response(trickle(Duration::from_millis(20)), idle, None);
+        assert_eq!(collect(ok).await.unwrap(), "xxx");
+
+        // A stalled body fails on the read timeout
+        let stalled = Response::new(Body::from_stream(futures_util::stream::pending::<
+            Result<Bytes, std::io::Error>,
+        >()));
+        let err = collect(timed_response(stalled, idle, None))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("read timeout"));
+
+        // Each frame arrives in time but the whole body overruns the budget
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let slow = timed_response(trickle(Duration::from_millis(60)), idle, Some(deadline));
+        let err = collect(slow).await.unwrap_err();
+        assert!(err.to_string().contains("total timeout"));
+    }
+}
@@ -26,6 +26,8 @@ pub struct DhcpConfig {
     pub lease_file: String,
     #[serde(default)]
     pub static_leases: Vec<StaticLease>,
+    #[serde(default)]
+    pub failover: FailoverConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +38,91 @@ pub struct StaticLease {
     pub hostname: String,
 }
 
+/// Active/standby pairing with a second homeroute instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub role: FailoverRole,
+    #[serde(default)]
+    pub mode: FailoverMode,
+    /// Peer endpoint (`host:port`). The secondary connects to the primary;
+    /// the primary only accepts connections from this IP (port optional).
+    #[serde(default)]
+    pub peer_address: String,
+    /// Address the primary listens on for the secondary's connection.
+    #[serde(default = "default_failover_listen")]
+    pub listen_address: String,
+    /// Secret used to authenticate every message on the peer channel (HMAC-SHA256).
+    #[serde(default)]
+    pub shared_secret: String,
+    /// Load-balance mode: number of hash buckets (out of 256, RFC 3074) served
+    /// by the primary. Also used to split the free address pool.
+    #[serde(default = "default_split")]
+    pub split: u8,
+    /// Hot-standby mode: percentage of the range reserved for the secondary.
+    #[serde(default = "default_standby_reserve")]
+    pub standby_reserve_percent: u8,
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval_secs: u64,
+    /// Silence after which the peer is considered unreachable.
+    #[serde(default = "default_peer_timeout")]
+    pub peer_timeout_secs: u64,
+    /// Time spent in communications-interrupted before taking over the whole
+    /// range (partner-down). 0 disables the automatic transition.
+    #[serde(default = "default_auto_partner_down")]
+    pub auto_partner_down_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverRole {
+    #[default]
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverMode {
+    /// Both servers answer, clients are split by MAC hash.
+    #[default]
+    LoadBalance,
+    /// The primary answers everything, the secondary only takes over when it is down.
+    HotStandby,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn default_failover_listen() -> String {
+    "0.0.0.0:647".to_string()
+}
+
+fn default_split() -> u8 {
+    128
+}
+
+fn default_standby_reserve() -> u8 {
+    10
+}
+
+fn default_heartbeat_interval() -> u64 {
+    5
+}
+
+fn default_peer_timeout() -> u64 {
+    15
+}
+
+fn default_auto_partner_down() -> u64 {
+    600
+}
+
 fn default_true() -> bool {
     true
 }
@@ -69,6 +156,8 @@ mod tests {
         assert_eq!(config.netmask, "255.255.255.0");
         assert_eq!(config.default_lease_time_secs, 86400);
         assert_eq!(config.lease_file, "/var/lib/server-dashboard/dhcp-leases");
+        assert!(!config.failover.enabled);
+        assert_eq!(config.failover.split, 128);
     }
 
     #[test]
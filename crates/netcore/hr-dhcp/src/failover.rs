@@ -0,0 +1,876 @@
+//! DHCP failover between two homeroute instances.
+//!
+//! Loosely follows the ISC dhcpd failover model: a primary and a secondary
+//! share lease state over an authenticated TCP channel (JSON lines signed with
+//! HMAC-SHA256), split clients by MAC hash (RFC 3074) or run active/standby,
+//! and each server only allocates fresh addresses from its own half of the
+//! free pool until the partner is declared down.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+use crate::SharedDhcpState;
+use crate::config::{FailoverConfig, FailoverMode, FailoverRole};
+use crate::lease_store::Lease;
+use crate::options::{DHCPDISCOVER, DHCPREQUEST};
+use crate::packet::DhcpPacket;
+
+/// Maximum accepted clock skew between peers for a signed message.
+const MAX_CLOCK_SKEW_SECS: u64 = 60;
+
+/// Cap on queued messages while the link is congested; a bulk sync on the
+/// next reconnect covers anything dropped.
+const MAX_OUTBOX: usize = 4096;
+
+// ── RFC 3074 load-balancing hash ────────────────────────────────────────────
+
+const MIX_TABLE: [u8; 256] = [
+    251, 175, 119, 215, 81, 14, 79, 191, 103, 49, 181, 143, 186, 157, 0, 232, 31, 32, 55, 60, 152,
+    58, 17, 237, 174, 70, 160, 144, 220, 90, 57, 223, 59, 3, 18, 140, 111, 166, 203, 196, 134, 243,
+    124, 95, 222, 179, 197, 65, 180, 48, 36, 15, 107, 46, 233, 130, 165, 30, 123, 161, 209, 23, 97,
+    16, 40, 91, 219, 61, 100, 10, 210, 109, 250, 127, 22, 138, 29, 108, 244, 67, 207, 9, 178, 204,
+    74, 98, 126, 249, 167, 116, 34, 77, 193, 200, 121, 5, 20, 113, 71, 35, 128, 13, 182, 94, 25,
+    226, 227, 199, 75, 27, 41, 245, 230, 224, 43, 225, 177, 26, 155, 150, 212, 142, 218, 115, 241,
+    73, 88, 105, 39, 114, 62, 255, 192, 201, 145, 214, 168, 158, 221, 148, 154, 122, 12, 84, 82,
+    163, 44, 139, 228, 236, 205, 242, 217, 11, 187, 146, 159, 64, 86, 239, 195, 42, 106, 198, 118,
+    112, 184, 172, 87, 2, 173, 117, 176, 229, 247, 253, 137, 185, 99, 164, 102, 147, 45, 66, 231,
+    52, 141, 211, 194, 206, 246, 238, 56, 110, 78, 248, 63, 240, 189, 93, 92, 51, 53, 183, 19, 171,
+    72, 50, 33, 104, 101, 69, 8, 252, 83, 120, 76, 135, 85, 54, 202, 125, 188, 213, 96, 235, 136,
+    208, 162, 129, 190, 132, 156, 38, 47, 1, 7, 254, 24, 4, 216, 131, 89, 21, 28, 133, 37, 153,
+    149, 80, 170, 68, 6, 169, 234, 151,
+];
+
+/// Pearson hash of a client hardware address (RFC 3074 §6).
+pub fn load_balance_hash(key: &[u8]) -> u8 {
+    let mut hash = key.len() as u8;
+    for b in key {
+        hash = MIX_TABLE[(hash ^ b) as usize];
+    }
+    hash
+}
+
+// ── Public types ─────────────────────────────────────────────────────────────
+
+/// Failover state of one server (subset of the ISC state machine).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverState {
+    /// Booted, no contact with the peer yet.
+    Startup,
+    /// Peer connected and leases synchronised.
+    Normal,
+    /// Peer unreachable: keep serving, but only allocate from our own pool.
+    CommunicationsInterrupted,
+    /// Peer assumed dead: allocate from the whole range.
+    PartnerDown,
+}
+
+/// Snapshot returned by the status endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverStatus {
+    pub role: FailoverRole,
+    pub mode: FailoverMode,
+    pub state: FailoverState,
+    pub peer_state: Option<FailoverState>,
+    pub peer_address: String,
+    pub connected: bool,
+    /// Unix timestamp (ms) of the last authenticated message from the peer.
+    pub last_peer_contact: Option<u64>,
+    /// Unix timestamp (ms) of the last state transition.
+    pub state_since: u64,
+    /// Lease changes not yet acknowledged by the peer.
+    pub pending_updates: usize,
+    /// Age (ms) of the oldest unacknowledged lease change, 0 when in sync.
+    pub sync_lag_ms: u64,
+    /// Unix timestamp (ms) of the last completed bulk sync from the peer.
+    pub last_bulk_sync: Option<u64>,
+    pub updates_sent: u64,
+    pub updates_received: u64,
+}
+
+/// Messages exchanged on the peer channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PeerMessage {
+    Hello {
+        role: FailoverRole,
+        mode: FailoverMode,
+    },
+    Heartbeat {
+        state: FailoverState,
+    },
+    BulkSync {
+        seq: u64,
+        leases: Vec<Lease>,
+    },
+    LeaseUpdate {
+        seq: u64,
+        lease: Lease,
+    },
+    LeaseRemove {
+        seq: u64,
+        ip: Ipv4Addr,
+        mac: String,
+    },
+    /// Cumulative acknowledgement of every change up to `seq`.
+    Ack {
+        seq: u64,
+    },
+}
+
+/// Wire envelope: `body` is the JSON-encoded message, `sig` the hex HMAC of
+/// `"{ts}.{nonce}.{body}"`. `nonce` strictly increases per sender (seeded
+/// from the wall clock in µs so it keeps growing across restarts) and the
+/// receiver drops anything not newer than the last accepted value.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    ts: u64,
+    nonce: u64,
+    body: String,
+    sig: String,
+}
+
+// ── Runtime ──────────────────────────────────────────────────────────────────
+
+struct PeerRuntime {
+    state: FailoverState,
+    state_since: u64,
+    peer_state: Option<FailoverState>,
+    connected: bool,
+    last_peer_contact: Option<u64>,
+    last_bulk_sync: Option<u64>,
+    next_seq: u64,
+    /// seq -> unix ms at which the change was made.
+    unacked: BTreeMap<u64, u64>,
+    outbox: VecDeque<PeerMessage>,
+    updates_sent: u64,
+    updates_received: u64,
+    /// Nonce of the last envelope we sealed.
+    send_nonce: u64,
+    /// Nonce of the last envelope accepted from the peer.
+    peer_nonce: u64,
+}
+
+impl PeerRuntime {
+    fn set_state(&mut self, state: FailoverState, now_ms: u64) {
+        if self.state != state {
+            info!("DHCP failover: {:?} -> {:?}", self.state, state);
+            self.state = state;
+            self.state_since = now_ms;
+        }
+    }
+}
+
+/// Shared handle on the failover link, held in [`crate::DhcpState`].
+pub struct FailoverPeer {
+    config: FailoverConfig,
+    key: hmac::Key,
+    inner: Mutex<PeerRuntime>,
+    outbox_ready: Notify,
+}
+
+impl FailoverPeer {
+    /// Build the failover handle if failover is enabled in the config.
+    pub fn from_config(config: &FailoverConfig) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Arc::new(Self::new(config.clone(), now_ms())))
+    }
+
+    fn new(config: FailoverConfig, now_ms: u64) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, config.shared_secret.as_bytes());
+        Self {
+            config,
+            key,
+            inner: Mutex::new(PeerRuntime {
+                state: FailoverState::Startup,
+                state_since: now_ms,
+                peer_state: None,
+                connected: false,
+                last_peer_contact: None,
+                last_bulk_sync: None,
+                next_seq: 1,
+                unacked: BTreeMap::new(),
+                outbox: VecDeque::new(),
+                updates_sent: 0,
+                updates_received: 0,
+                send_nonce: 0,
+                peer_nonce: 0,
+            }),
+            outbox_ready: Notify::new(),
+        }
+    }
+
+    pub fn config(&self) -> &FailoverConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> FailoverState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Whether this server should answer the given client message.
+    /// Only DISCOVER and REQUEST are filtered; everything else is processed
+    /// by both servers so the lease tables stay consistent.
+    pub fn should_serve(&self, packet: &DhcpPacket) -> bool {
+        let msg_type = packet.msg_type();
+        if msg_type != Some(DHCPDISCOVER) && msg_type != Some(DHCPREQUEST) {
+            return true;
+        }
+        // REQUEST carrying our server id (SELECTING) or a ciaddr (RENEWING,
+        // unicast to the server that bound it) is always ours to answer.
+        let is_renewal = msg_type == Some(DHCPREQUEST) && packet.ciaddr != Ipv4Addr::UNSPECIFIED;
+        let is_selecting = msg_type == Some(DHCPREQUEST) && packet.server_id().is_some();
+
+        let state = self.state();
+        match (self.config.mode, state) {
+            (_, FailoverState::PartnerDown) => true,
+            (FailoverMode::HotStandby, _) if self.config.role == FailoverRole::Primary => true,
+            (FailoverMode::HotStandby, FailoverState::Normal) => false,
+            (FailoverMode::HotStandby, _) => is_renewal || is_selecting,
+            (FailoverMode::LoadBalance, FailoverState::Normal) => {
+                is_renewal || is_selecting || self.owns_client(packet.mac_bytes())
+            }
+            (FailoverMode::LoadBalance, _) => true,
+        }
+    }
+
+    /// Whether the client's hash bucket belongs to this server.
+    fn owns_client(&self, mac: &[u8]) -> bool {
+        let primary = load_balance_hash(mac) < self.config.split;
+        primary == (self.config.role == FailoverRole::Primary)
+    }
+
+    /// Sub-range of the configured range this server may allocate new
+    /// addresses from. `None` if our share of the pool is empty.
+    pub fn allocation_pool(
+        &self,
+        range_start: Ipv4Addr,
+        range_end: Ipv4Addr,
+    ) -> Option<(Ipv4Addr, Ipv4Addr)> {
+        if self.state() == FailoverState::PartnerDown {
+            return Some((range_start, range_end));
+        }
+        let start = u32::from(range_start);
+        let end = u32::from(range_end);
+        if end < start {
+            return None;
+        }
+        let share = match self.config.mode {
+            FailoverMode::LoadBalance => self.config.split as u64,
+            FailoverMode::HotStandby => {
+                256 * (100 - self.config.standby_reserve_percent.min(100) as u64) / 100
+            }
+        };
+        let size = (end - start) as u64 + 1;
+        let primary_count = (size * share / 256) as u32;
+        match self.config.role {
+            FailoverRole::Primary if primary_count == 0 => None,
+            FailoverRole::Primary => Some((range_start, Ipv4Addr::from(start + primary_count - 1))),
+            FailoverRole::Secondary if primary_count as u64 >= size => None,
+            FailoverRole::Secondary => Some((Ipv4Addr::from(start + primary_count), range_end)),
+        }
+    }
+
+    /// Queue a committed lease for replication to the peer.
+    pub fn publish_lease(&self, lease: Lease) {
+        self.publish(|seq| PeerMessage::LeaseUpdate { seq, lease });
+    }
+
+    /// Queue a released/declined lease for replication to the peer.
+    pub fn publish_removal(&self, ip: Ipv4Addr, mac: String) {
+        self.publish(|seq| PeerMessage::LeaseRemove { seq, ip, mac });
+    }
+
+    fn publish(&self, build: impl FnOnce(u64) -> PeerMessage) {
+        let mut rt = self.inner.lock().unwrap();
+        let seq = rt.next_seq;
+        rt.next_seq += 1;
+        rt.unacked.insert(seq, now_ms());
+        // While disconnected only the lag is tracked: the bulk sync sent on
+        // reconnect carries the full table.
+        if rt.connected {
+            if rt.outbox.len() >= MAX_OUTBOX {
+                rt.outbox.pop_front();
+            }
+            rt.outbox.push_back(build(seq));
+            drop(rt);
+            self.outbox_ready.notify_one();
+        }
+    }
+
+    pub fn status(&self) -> FailoverStatus {
+        let rt = self.inner.lock().unwrap();
+        let now = now_ms();
+        FailoverStatus {
+            role: self.config.role,
+            mode: self.config.mode,
+            state: rt.state,
+            peer_state: rt.peer_state,
+            peer_address: self.config.peer_address.clone(),
+            connected: rt.connected,
+            last_peer_contact: rt.last_peer_contact,
+            state_since: rt.state_since,
+            pending_updates: rt.unacked.len(),
+            sync_lag_ms: rt
+                .unacked
+                .values()
+                .next()
+                .map(|t| now.saturating_sub(*t))
+                .unwrap_or(0),
+            last_bulk_sync: rt.last_bulk_sync,
+            updates_sent: rt.updates_sent,
+            updates_received: rt.updates_received,
+        }
+    }
+
+    /// Periodic state evaluation (peer timeouts, automatic partner-down).
+    fn tick(&self, now_ms: u64) {
+        let mut rt = self.inner.lock().unwrap();
+        let timeout_ms = self.config.peer_timeout_secs * 1000;
+        let peer_alive = rt.connected
+            && rt
+                .last_peer_contact
+                .is_some_and(|t| now_ms.saturating_sub(t) < timeout_ms);
+
+        match rt.state {
+            FailoverState::Startup | FailoverState::Normal
+                if !peer_alive
+                    && now_ms.saturating_sub(rt.last_peer_contact.unwrap_or(rt.state_since))
+                        >= timeout_ms =>
+            {
+                rt.set_state(FailoverState::CommunicationsInterrupted, now_ms);
+            }
+            FailoverState::CommunicationsInterrupted if !peer_alive => {
+                let auto = self.config.auto_partner_down_secs * 1000;
+                if auto > 0 && now_ms.saturating_sub(rt.state_since) >= auto {
+                    rt.set_state(FailoverState::PartnerDown, now_ms);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connected(&self) {
+        let mut rt = self.inner.lock().unwrap();
+        rt.connected = true;
+        rt.outbox.clear();
+    }
+
+    fn on_disconnected(&self) {
+        let mut rt = self.inner.lock().unwrap();
+        rt.connected = false;
+        rt.peer_state = None;
+        rt.outbox.clear();
+    }
+
+    fn on_message(&self, now_ms: u64) {
+        self.inner.lock().unwrap().last_peer_contact = Some(now_ms);
+    }
+
+    fn on_heartbeat(&self, peer_state: FailoverState) {
+        self.inner.lock().unwrap().peer_state = Some(peer_state);
+    }
+
+    /// The peer's full table has been merged: we are in sync.
+    fn on_bulk_sync(&self, now_ms: u64) {
+        let mut rt = self.inner.lock().unwrap();
+        rt.last_bulk_sync = Some(now_ms);
+        rt.set_state(FailoverState::Normal, now_ms);
+    }
+
+    fn on_ack(&self, seq: u64) {
+        let mut rt = self.inner.lock().unwrap();
+        rt.unacked.retain(|s, _| *s > seq);
+    }
+
+    /// Reserve a sequence number covering every change made so far
+    /// (used for the bulk sync sent on connect).
+    fn snapshot_seq(&self) -> u64 {
+        let mut rt = self.inner.lock().unwrap();
+        let seq = rt.next_seq;
+        rt.next_seq += 1;
+        seq
+    }
+
+    fn drain_outbox(&self) -> Vec<PeerMessage> {
+        let mut rt = self.inner.lock().unwrap();
+        let msgs: Vec<PeerMessage> = rt.outbox.drain(..).collect();
+        rt.updates_sent += msgs.len() as u64;
+        msgs
+    }
+
+    fn count_received(&self) {
+        self.inner.lock().unwrap().updates_received += 1;
+    }
+
+    // ── Message signing ──────────────────────────────────────────────────
+
+    fn seal(&self, msg: &PeerMessage, ts: u64) -> Result<String> {
+        let nonce = {
+            let mut rt = self.inner.lock().unwrap();
+            rt.send_nonce = (rt.send_nonce + 1).max(now_micros());
+            rt.send_nonce
+        };
+        let body = serde_json::to_string(msg)?;
+        let tag = hmac::sign(&self.key, format!("{}.{}.{}", ts, nonce, body).as_bytes());
+        let mut line = serde_json::to_string(&Envelope {
+            ts,
+            nonce,
+            body,
+            sig: hex::encode(tag.as_ref()),
+        })?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    fn open(&self, line: &str, now_secs: u64) -> Result<PeerMessage> {
+        let env: Envelope = serde_json::from_str(line).context("malformed envelope")?;
+        let sig = hex::decode(&env.sig).context("malformed signature")?;
+        hmac::verify(
+            &self.key,
+            format!("{}.{}.{}", env.ts, env.nonce, env.body).as_bytes(),
+            &sig,
+        )
+        .map_err(|_| anyhow::anyhow!("bad signature"))?;
+        if env.ts.abs_diff(now_secs) > MAX_CLOCK_SKEW_SECS {
+            bail!("stale message (ts {}, now {})", env.ts, now_secs);
+        }
+        let msg = serde_json::from_str(&env.body)?;
+        let mut rt = self.inner.lock().unwrap();
+        if env.nonce <= rt.peer_nonce {
+            bail!(
+                "replayed message (nonce {}, last {})",
+                env.nonce,
+                rt.peer_nonce
+            );
+        }
+        rt.peer_nonce = env.nonce;
+        Ok(msg)
+    }
+}
+
+// ── Peer link ────────────────────────────────────────────────────────────────
+
+/// Run the failover link: the primary accepts the secondary's connection,
+/// the secondary dials the primary. Reconnects forever.
+pub async fn run_failover(state: SharedDhcpState, peer: Arc<FailoverPeer>) -> Result<()> {
+    let config = peer.config.clone();
+    if config.shared_secret.is_empty() {
+        bail!("DHCP failover enabled without shared_secret");
+    }
+    if config.peer_address.is_empty() {
+        bail!("DHCP failover requires peer_address");
+    }
+
+    let listener = match config.role {
+        FailoverRole::Primary => {
+            let expected = peer_ip(&config.peer_address)?;
+            let listener = TcpListener::bind(&config.listen_address)
+                .await
+                .with_context(|| format!("Failed to bind {}", config.listen_address))?;
+            info!("DHCP failover listening on {}", config.listen_address);
+            Some((listener, expected))
+        }
+        FailoverRole::Secondary => None,
+    };
+
+    let heartbeat = Duration::from_secs(config.heartbeat_interval_secs.max(1));
+    let mut ticker = tokio::time::interval(heartbeat);
+
+    loop {
+        let stream = tokio::select! {
+            _ = ticker.tick() => {
+                peer.tick(now_ms());
+                continue;
+            }
+            s = next_connection(listener.as_ref(), &config, heartbeat) => s,
+        };
+
+        match stream {
+            Ok(stream) => {
+                let addr = stream.peer_addr().ok();
+                info!("DHCP failover peer connected ({:?})", addr);
+                peer.on_connected();
+                if let Err(e) = run_session(stream, &state, &peer, heartbeat).await {
+                    warn!("DHCP failover session ended: {:#}", e);
+                }
+                peer.on_disconnected();
+            }
+            Err(e) => debug!("DHCP failover connect: {:#}", e),
+        }
+    }
+}
+
+/// IP the primary accepts the secondary from: `peer_address` with or
+/// without a port.
+fn peer_ip(peer_address: &str) -> Result<IpAddr> {
+    peer_address
+        .parse::<SocketAddr>()
+        .map(|a| a.ip())
+        .or_else(|_| peer_address.parse::<IpAddr>())
+        .with_context(|| format!("DHCP failover peer_address {} is not an IP", peer_address))
+}
+
+async fn next_connection(
+    listener: Option<&(TcpListener, IpAddr)>,
+    config: &FailoverConfig,
+    retry: Duration,
+) -> Result<TcpStream> {
+    match listener {
+        Some((listener, expected)) => {
+            let (stream, addr) = listener.accept().await?;
+            if addr.ip() != *expected {
+                bail!("rejected connection from unexpected peer {}", addr);
+            }
+            Ok(stream)
+        }
+        None => match TcpStream::connect(&config.peer_address).await {
+            Ok(stream) => Ok(stream),
+            Err(e) => {
+                tokio::time::sleep(retry).await;
+                Err(e).with_context(|| format!("Failed to reach {}", config.peer_address))
+            }
+        },
+    }
+}
+
+async fn run_session(
+    stream: TcpStream,
+    state: &SharedDhcpState,
+    peer: &FailoverPeer,
+    heartbeat: Duration,
+) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let hello = PeerMessage::Hello {
+        role: peer.config.role,
+        mode: peer.config.mode,
+    };
+    writer
+        .write_all(peer.seal(&hello, now_secs())?.as_bytes())
+        .await?;
+
+    let seq = peer.snapshot_seq();
+    let leases: Vec<Lease> = state
+        .read()
+        .await
+        .lease_store
+        .all_leases()
+        .into_iter()
+        .cloned()
+        .collect();
+    let bulk = PeerMessage::BulkSync { seq, leases };
+    writer
+        .write_all(peer.seal(&bulk, now_secs())?.as_bytes())
+        .await?;
+
+    let mut ticker = tokio::time::interval(heartbeat);
+    let timeout_ms = peer.config.peer_timeout_secs * 1000;
+    // The link is only held by a peer that proves the shared secret: drop the
+    // connection if no signed Hello arrives in time.
+    let handshake = tokio::time::sleep(Duration::from_millis(timeout_ms));
+    tokio::pin!(handshake);
+    let mut greeted = false;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    bail!("peer closed the connection");
+                };
+                let msg = peer.open(line.trim(), now_secs())?;
+                greeted |= matches!(msg, PeerMessage::Hello { .. });
+                peer.on_message(now_ms());
+                if let Some(ack) = handle_message(msg, state, peer).await? {
+                    writer.write_all(peer.seal(&ack, now_secs())?.as_bytes()).await?;
+                }
+            }
+            _ = &mut handshake, if !greeted => {
+                bail!("no Hello from peer within {} ms", timeout_ms);
+            }
+            _ = ticker.tick() => {
+                let now = now_ms();
+                peer.tick(now);
+                let silent_for = peer
+                    .inner
+                    .lock()
+                    .unwrap()
+                    .last_peer_contact
+                    .map(|t| now.saturating_sub(t));
+                if silent_for.is_some_and(|s| s >= timeout_ms) {
+                    bail!("peer silent for {} ms", silent_for.unwrap_or_default());
+                }
+                let hb = PeerMessage::Heartbeat { state: peer.state() };
+                writer.write_all(peer.seal(&hb, now_secs())?.as_bytes()).await?;
+            }
+            _ = peer.outbox_ready.notified() => {
+                for msg in peer.drain_outbox() {
+                    writer.write_all(peer.seal(&msg, now_secs())?.as_bytes()).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Apply one peer message. Returns the acknowledgement to send, if any.
+async fn handle_message(
+    msg: PeerMessage,
+    state: &SharedDhcpState,
+    peer: &FailoverPeer,
+) -> Result<Option<PeerMessage>> {
+    match msg {
+        PeerMessage::Hello { role, mode } => {
+            if role == peer.config.role {
+                bail!("peer announced the same role ({:?})", role);
+            }
+            if mode != peer.config.mode {
+                bail!(
+                    "failover mode mismatch (peer {:?}, local {:?})",
+                    mode,
+                    peer.config.mode
+                );
+            }
+            Ok(None)
+        }
+        PeerMessage::Heartbeat { state } => {
+            peer.on_heartbeat(state);
+            Ok(None)
+        }
+        PeerMessage::BulkSync { seq, leases } => {
+            let total = leases.len();
+            let mut s = state.write().await;
+            let applied = leases
+                .into_iter()
+                .filter(|l| s.lease_store.merge_peer_lease(l.clone()))
+                .count();
+            drop(s);
+            info!(
+                "DHCP failover bulk sync: {}/{} peer leases applied",
+                applied, total
+            );
+            peer.on_bulk_sync(now_ms());
+            Ok(Some(PeerMessage::Ack { seq }))
+        }
+        PeerMessage::LeaseUpdate { seq, lease } => {
+            debug!("DHCP failover: peer bound {} to {}", lease.ip, lease.mac);
+            state.write().await.lease_store.merge_peer_lease(lease);
+            peer.count_received();
+            Ok(Some(PeerMessage::Ack { seq }))
+        }
+        PeerMessage::LeaseRemove { seq, ip, mac } => {
+            debug!("DHCP failover: peer released {} ({})", ip, mac);
+            state.write().await.lease_store.remove_peer_lease(ip, &mac);
+            peer.count_received();
+            Ok(Some(PeerMessage::Ack { seq }))
+        }
+        PeerMessage::Ack { seq } => {
+            peer.on_ack(seq);
+            Ok(None)
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(role: FailoverRole, mode: FailoverMode) -> FailoverConfig {
+        FailoverConfig {
+            enabled: true,
+            role,
+            mode,
+            shared_secret: "s3cret".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mix_table_is_permutation() {
+        let mut seen = [false; 256];
+        for b in MIX_TABLE {
+            assert!(!seen[b as usize], "duplicate {}", b);
+            seen[b as usize] = true;
+        }
+    }
+
+    #[test]
+    fn test_pool_split() {
+        let start = Ipv4Addr::new(10, 0, 0, 10);
+        let end = Ipv4Addr::new(10, 0, 0, 209); // 200 addresses
+
+        let primary =
+            FailoverPeer::new(config(FailoverRole::Primary, FailoverMode::LoadBalance), 0);
+        let secondary = FailoverPeer::new(
+            config(FailoverRole::Secondary, FailoverMode::LoadBalance),
+            0,
+        );
+        assert_eq!(
+            primary.allocation_pool(start, end),
+            Some((start, Ipv4Addr::new(10, 0, 0, 109)))
+        );
+        assert_eq!(
+            secondary.allocation_pool(start, end),
+            Some((Ipv4Addr::new(10, 0, 0, 110), end))
+        );
+
+        let standby =
+            FailoverPeer::new(config(FailoverRole::Secondary, FailoverMode::HotStandby), 0);
+        let (s, e) = standby.allocation_pool(start, end).unwrap();
+        assert_eq!(e, end);
+        assert!(u32::from(e) - u32::from(s) < 25);
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let mut cfg = config(FailoverRole::Primary, FailoverMode::LoadBalance);
+        cfg.peer_timeout_secs = 15;
+        cfg.auto_partner_down_secs = 600;
+        let peer = FailoverPeer::new(cfg, 0);
+
+        peer.tick(10_000);
+        assert_eq!(peer.state(), FailoverState::Startup);
+        peer.tick(15_000);
+        assert_eq!(peer.state(), FailoverState::CommunicationsInterrupted);
+        peer.tick(15_000 + 599_000);
+        assert_eq!(peer.state(), FailoverState::CommunicationsInterrupted);
+        peer.tick(15_000 + 600_000);
+        assert_eq!(peer.state(), FailoverState::PartnerDown);
+
+        peer.on_connected();
+        peer.on_message(700_000);
+        peer.on_bulk_sync(700_000);
+        assert_eq!(peer.state(), FailoverState::Normal);
+        peer.tick(710_000);
+        assert_eq!(peer.state(), FailoverState::Normal);
+    }
+
+    #[test]
+    fn test_sync_lag_and_ack() {
+        let peer = FailoverPeer::new(config(FailoverRole::Primary, FailoverMode::LoadBalance), 0);
+        peer.on_connected();
+        peer.publish_removal(Ipv4Addr::new(10, 0, 0, 5), "aa:bb:cc:dd:ee:ff".into());
+        peer.publish_removal(Ipv4Addr::new(10, 0, 0, 6), "aa:bb:cc:dd:ee:00".into());
+        assert_eq!(peer.status().pending_updates, 2);
+        assert_eq!(peer.drain_outbox().len(), 2);
+
+        peer.on_ack(1);
+        assert_eq!(peer.status().pending_updates, 1);
+        peer.on_ack(2);
+        let status = peer.status();
+        assert_eq!(status.pending_updates, 0);
+        assert_eq!(status.sync_lag_ms, 0);
+    }
+
+    #[test]
+    fn test_signed_envelope() {
+        let peer = FailoverPeer::new(config(FailoverRole::Primary, FailoverMode::LoadBalance), 0);
+        let line = peer.seal(&PeerMessage::Ack { seq: 7 }, 1000).unwrap();
+        assert!(matches!(
+            peer.open(line.trim(), 1010).unwrap(),
+            PeerMessage::Ack { seq: 7 }
+        ));
+        assert!(peer.open(line.trim(), 2000).is_err());
+
+        let tampered = line.replace("\\\"seq\\\":7", "\\\"seq\\\":8");
+        assert_ne!(tampered, line);
+        assert!(peer.open(tampered.trim(), 1010).is_err());
+
+        let mut other_cfg = config(FailoverRole::Secondary, FailoverMode::LoadBalance);
+        other_cfg.shared_secret = "other".to_string();
+        let other = FailoverPeer::new(other_cfg, 0);
+        assert!(other.open(line.trim(), 1010).is_err());
+    }
+
+    #[test]
+    fn test_replay_rejected() {
+        let sender = FailoverPeer::new(config(FailoverRole::Primary, FailoverMode::LoadBalance), 0);
+        let receiver = FailoverPeer::new(
+            config(FailoverRole::Secondary, FailoverMode::LoadBalance),
+            0,
+        );
+        let first = sender.seal(&PeerMessage::Ack { seq: 1 }, 1000).unwrap();
+        let second = sender.seal(&PeerMessage::Ack { seq: 2 }, 1000).unwrap();
+
+        assert!(receiver.open(first.trim(), 1000).is_ok());
+        assert!(receiver.open(first.trim(), 1000).is_err());
+        assert!(receiver.open(second.trim(), 1000).is_ok());
+        // An older message is rejected even if it was never seen.
+        let third = sender.seal(&PeerMessage::Ack { seq: 3 }, 1000).unwrap();
+        let fourth = sender.seal(&PeerMessage::Ack { seq: 4 }, 1000).unwrap();
+        assert!(receiver.open(fourth.trim(), 1000).is_ok());
+        assert!(receiver.open(third.trim(), 1000).is_err());
+    }
+
+    #[test]
+    fn test_peer_ip() {
+        assert_eq!(
+            peer_ip("10.0.0.2:8647").unwrap(),
+            Ipv4Addr::new(10, 0, 0, 2)
+        );
+        assert_eq!(peer_ip("10.0.0.2").unwrap(), Ipv4Addr::new(10, 0, 0, 2));
+        assert!(peer_ip("peer.lan:8647").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_silent_peer_dropped() {
+        let mut cfg = config(FailoverRole::Primary, FailoverMode::LoadBalance);
+        cfg.peer_timeout_secs = 1;
+        let peer = FailoverPeer::new(cfg, now_ms());
+        let state: SharedDhcpState = Arc::new(tokio::sync::RwLock::new(crate::DhcpState {
+            config: Default::default(),
+            lease_store: crate::lease_store::LeaseStore::new("/nonexistent/leases"),
+            server_ip: Ipv4Addr::new(10, 0, 0, 1),
+            failover: None,
+            metrics: Default::default(),
+        }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _silent = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let ended = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_session(stream, &state, &peer, Duration::from_millis(200)),
+        )
+        .await
+        .expect("session still held by a silent peer");
+        assert!(ended.unwrap_err().to_string().contains("no Hello"));
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use std::path::PathBuf;
@@ -6,7 +7,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{info, warn};
 
 /// A DHCP lease
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lease {
     pub expiry: u64,
     pub mac: String,
@@ -168,6 +169,32 @@ impl LeaseStore {
         }
     }
 
+    /// Merge a lease received from the failover peer.
+    /// The binding with the later expiry wins; returns `true` if applied.
+    pub fn merge_peer_lease(&mut self, lease: Lease) -> bool {
+        let newer_here = self
+            .leases
+            .get(&lease.ip)
+            .is_some_and(|existing| existing.expiry > lease.expiry);
+        if newer_here {
+            return false;
+        }
+        self.add_lease(lease);
+        true
+    }
+
+    /// Remove a lease released on the failover peer, only if it is still
+    /// bound to the same MAC here.
+    pub fn remove_peer_lease(&mut self, ip: Ipv4Addr, mac: &str) -> bool {
+        match self.leases.get(&ip) {
+            Some(lease) if lease.mac == mac.to_lowercase() => {
+                self.remove_lease(ip);
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Find IP by MAC address
     pub fn find_ip_by_mac(&self, mac: &str) -> Option<Ipv4Addr> {
         self.by_mac.get(&mac.to_lowercase()).copied()
@@ -361,6 +388,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_merge_peer_lease_keeps_latest_expiry() {
+        let mut store = LeaseStore::new("/tmp/test-leases");
+        let ip = Ipv4Addr::new(10, 0, 0, 60);
+        let lease = |mac: &str, expiry| Lease {
+            expiry,
+            mac: mac.to_string(),
+            ip,
+            hostname: None,
+            client_id: None,
+        };
+
+        assert!(store.merge_peer_lease(lease("aa:aa:aa:aa:aa:aa", 2000)));
+        assert!(!store.merge_peer_lease(lease("bb:bb:bb:bb:bb:bb", 1000)));
+        assert_eq!(store.get_lease(ip).unwrap().mac, "aa:aa:aa:aa:aa:aa");
+
+        assert!(!store.remove_peer_lease(ip, "bb:bb:bb:bb:bb:bb"));
+        assert!(store.remove_peer_lease(ip, "AA:AA:AA:AA:AA:AA"));
+        assert!(store.get_lease(ip).is_none());
+    }
+
     #[test]
     fn test_allocate_ip() {
         let store = LeaseStore::new("/tmp/test-leases");
@@ -1,7 +1,10 @@
 pub mod config;
+pub mod failover;
 pub mod lease_store;
+pub mod metrics;
 pub mod options;
 pub mod packet;
+pub mod reservations;
 pub mod server;
 pub mod state_machine;
 
@@ -16,6 +19,10 @@ pub struct DhcpState {
     pub config: config::DhcpConfig,
     pub lease_store: lease_store::LeaseStore,
     pub server_ip: Ipv4Addr,
+    /// Failover link to the peer server, when failover is enabled.
+    pub failover: Option<Arc<failover::FailoverPeer>>,
+    /// Message counters, kept across config reloads.
+    pub metrics: metrics::DhcpMetrics,
 }
 
 pub type SharedDhcpState = Arc<RwLock<DhcpState>>;
@@ -0,0 +1,64 @@
+//! DHCP message counters.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::options::{
+    DHCPACK, DHCPDECLINE, DHCPDISCOVER, DHCPINFORM, DHCPNAK, DHCPOFFER, DHCPRELEASE, DHCPREQUEST,
+};
+
+/// Message type names, indexed by type code - 1.
+const MESSAGE_TYPES: [(u8, &str); 8] = [
+    (DHCPDISCOVER, "discover"),
+    (DHCPOFFER, "offer"),
+    (DHCPREQUEST, "request"),
+    (DHCPDECLINE, "decline"),
+    (DHCPACK, "ack"),
+    (DHCPNAK, "nak"),
+    (DHCPRELEASE, "release"),
+    (DHCPINFORM, "inform"),
+];
+
+/// Messages received and sent by the DHCP server, by message type.
+#[derive(Default)]
+pub struct DhcpMetrics {
+    received: [AtomicU64; 8],
+    sent: [AtomicU64; 8],
+}
+
+fn slot(counters: &[AtomicU64; 8], msg_type: Option<u8>) -> Option<&AtomicU64> {
+    msg_type
+        .filter(|t| (1..=8).contains(t))
+        .map(|t| &counters[t as usize - 1])
+}
+
+fn snapshot(counters: &[AtomicU64; 8]) -> Vec<(String, u64)> {
+    MESSAGE_TYPES
+        .iter()
+        .zip(counters)
+        .map(|((_, name), c)| (name.to_string(), c.load(Ordering::Relaxed)))
+        .collect()
+}
+
+impl DhcpMetrics {
+    pub fn record_received(&self, msg_type: Option<u8>) {
+        if let Some(c) = slot(&self.received, msg_type) {
+            c.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_sent(&self, msg_type: Option<u8>) {
+        if let Some(c) = slot(&self.sent, msg_type) {
+            c.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Received counters as (message type, count).
+    pub fn received(&self) -> Vec<(String, u64)> {
+        snapshot(&self.received)
+    }
+
+    /// Sent counters as (message type, count).
+    pub fn sent(&self) -> Vec<(String, u64)> {
+        snapshot(&self.sent)
+    }
+}
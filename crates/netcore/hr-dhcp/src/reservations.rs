@@ -0,0 +1,637 @@
+//! Import/export of static reservations in foreign DHCP server formats.
+//!
+//! Supports dnsmasq `dhcp-host=` lines, ISC dhcpd `host {}` blocks and Kea
+//! JSON reservations. Imports are analysed against the current config and
+//! lease table first, so callers can show a dry-run report before applying.
+
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DhcpConfig, StaticLease};
+use crate::lease_store::LeaseStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationFormat {
+    Dnsmasq,
+    Isc,
+    Kea,
+}
+
+/// A line (or block) that could not be turned into a reservation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseIssue {
+    /// 1-based line number, 0 when not applicable (Kea JSON).
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    /// No reservation exists for this MAC yet.
+    New,
+    /// Same MAC already reserved with a different IP or hostname.
+    Update,
+    /// Identical reservation already present.
+    Unchanged,
+    /// Cannot be applied without breaking an existing binding.
+    Conflict,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportEntry {
+    pub mac: String,
+    pub ip: String,
+    pub hostname: String,
+    pub status: ImportStatus,
+    /// Conflicts and warnings explaining the status.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// Dry-run result of an import.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub format: ReservationFormat,
+    pub entries: Vec<ImportEntry>,
+    pub errors: Vec<ParseIssue>,
+    pub new: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+}
+
+impl ImportReport {
+    /// Merge the applicable entries (new + updated) into `existing`.
+    pub fn merge_into(&self, existing: &[StaticLease]) -> Vec<StaticLease> {
+        let mut merged: Vec<StaticLease> = existing.to_vec();
+        for entry in &self.entries {
+            let lease = StaticLease {
+                mac: entry.mac.clone(),
+                ip: entry.ip.clone(),
+                hostname: entry.hostname.clone(),
+            };
+            match entry.status {
+                ImportStatus::New => merged.push(lease),
+                ImportStatus::Update => {
+                    if let Some(slot) = merged
+                        .iter_mut()
+                        .find(|s| normalize_mac(&s.mac).as_deref() == Some(entry.mac.as_str()))
+                    {
+                        *slot = lease;
+                    }
+                }
+                ImportStatus::Unchanged | ImportStatus::Conflict => {}
+            }
+        }
+        merged
+    }
+}
+
+// ── Parsing ──────────────────────────────────────────────────────────────────
+
+/// Parse reservations from `content`. Unparseable entries are reported in
+/// the returned issues instead of failing the whole import.
+pub fn parse(format: ReservationFormat, content: &str) -> (Vec<StaticLease>, Vec<ParseIssue>) {
+    match format {
+        ReservationFormat::Dnsmasq => parse_dnsmasq(content),
+        ReservationFormat::Isc => parse_isc(content),
+        ReservationFormat::Kea => parse_kea(content),
+    }
+}
+
+fn parse_dnsmasq(content: &str) -> (Vec<StaticLease>, Vec<ParseIssue>) {
+    let mut leases = Vec::new();
+    let mut issues = Vec::new();
+
+    for (idx, raw) in content.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        let Some(value) = line.strip_prefix("dhcp-host=") else {
+            continue;
+        };
+
+        let mut mac = None;
+        let mut ip = None;
+        let mut hostname = String::new();
+        let mut ignored = false;
+        for token in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if let Some(m) = normalize_mac(token) {
+                // Several MACs may share a reservation; keep the first one.
+                mac.get_or_insert(m);
+            } else if let Ok(addr) = token.parse::<Ipv4Addr>() {
+                ip = Some(addr);
+            } else if token == "ignore" {
+                ignored = true;
+            } else if token.contains(':') || token.starts_with('[') || is_lease_time(token) {
+                // set:/tag:/id: prefixes, IPv6 addresses, lease times.
+            } else {
+                hostname = token.to_string();
+            }
+        }
+
+        if ignored {
+            continue;
+        }
+        match (mac, ip) {
+            (Some(mac), Some(ip)) => leases.push(StaticLease {
+                mac,
+                ip: ip.to_string(),
+                hostname,
+            }),
+            _ => issues.push(ParseIssue {
+                line: idx + 1,
+                message: "dhcp-host needs a MAC address and an IPv4 address".into(),
+            }),
+        }
+    }
+
+    (leases, issues)
+}
+
+fn is_lease_time(token: &str) -> bool {
+    let digits = token
+        .strip_suffix(['s', 'm', 'h', 'd', 'w'])
+        .unwrap_or(token);
+    token == "infinite" || (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn parse_isc(content: &str) -> (Vec<StaticLease>, Vec<ParseIssue>) {
+    let mut leases = Vec::new();
+    let mut issues = Vec::new();
+
+    // Strip comments but keep line structure for error reporting.
+    let text: String = content
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut rest = text.as_str();
+    let mut offset = 0;
+    while let Some(pos) = find_keyword(rest, "host") {
+        let start = offset + pos;
+        let line = text[..start].matches('\n').count() + 1;
+        let after = &rest[pos + 4..];
+        let (Some(open), Some(close)) = (after.find('{'), after.find('}')) else {
+            issues.push(ParseIssue {
+                line,
+                message: "unterminated host block".into(),
+            });
+            break;
+        };
+        if close < open {
+            issues.push(ParseIssue {
+                line,
+                message: "malformed host block".into(),
+            });
+            break;
+        }
+        let name = after[..open].trim().trim_matches('"').to_string();
+        let body = &after[open + 1..close];
+
+        let mut mac = None;
+        let mut ip = None;
+        let mut hostname = None;
+        for stmt in body.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let words: Vec<&str> = stmt.split_whitespace().collect();
+            match words.as_slice() {
+                ["hardware", "ethernet", m] => mac = normalize_mac(m),
+                ["fixed-address", a] => ip = a.parse::<Ipv4Addr>().ok(),
+                ["option", "host-name", h] => hostname = Some(h.trim_matches('"').to_string()),
+                _ => {}
+            }
+        }
+
+        match (mac, ip) {
+            (Some(mac), Some(ip)) => leases.push(StaticLease {
+                mac,
+                ip: ip.to_string(),
+                hostname: hostname.unwrap_or(name),
+            }),
+            _ => issues.push(ParseIssue {
+                line,
+                message: format!(
+                    "host {} needs `hardware ethernet` and an IPv4 `fixed-address`",
+                    name
+                ),
+            }),
+        }
+
+        let consumed = pos + 4 + close + 1;
+        rest = &rest[consumed..];
+        offset += consumed;
+    }
+
+    (leases, issues)
+}
+
+/// Find `keyword` as a standalone word (not part of `host-name` etc.).
+fn find_keyword(text: &str, keyword: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(pos) = text[from..].find(keyword) {
+        let abs = from + pos;
+        let before_ok = abs == 0 || text[..abs].ends_with(|c: char| c.is_whitespace() || c == '}');
+        let after_ok = text[abs + keyword.len()..].starts_with(char::is_whitespace);
+        if before_ok && after_ok {
+            return Some(abs);
+        }
+        from = abs + keyword.len();
+    }
+    None
+}
+
+fn parse_kea(content: &str) -> (Vec<StaticLease>, Vec<ParseIssue>) {
+    let value: serde_json::Value = match serde_json::from_str(content) {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                vec![],
+                vec![ParseIssue {
+                    line: e.line(),
+                    message: format!("invalid JSON: {}", e),
+                }],
+            );
+        }
+    };
+
+    // Accept a full config, the Dhcp4 object, `{"reservations": [...]}` or a bare array.
+    let root = value.get("Dhcp4").unwrap_or(&value);
+    let mut raw = Vec::new();
+    collect_kea_reservations(root, &mut raw);
+
+    let mut leases = Vec::new();
+    let mut issues = Vec::new();
+    for (idx, r) in raw.into_iter().enumerate() {
+        let mac = r
+            .get("hw-address")
+            .and_then(|v| v.as_str())
+            .and_then(normalize_mac);
+        let ip = r
+            .get("ip-address")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse::<Ipv4Addr>().ok());
+        let hostname = r
+            .get("hostname")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        match (mac, ip) {
+            (Some(mac), Some(ip)) => leases.push(StaticLease {
+                mac,
+                ip: ip.to_string(),
+                hostname,
+            }),
+            _ => issues.push(ParseIssue {
+                line: 0,
+                message: format!(
+                    "reservation #{} needs `hw-address` and an IPv4 `ip-address`",
+                    idx + 1
+                ),
+            }),
+        }
+    }
+
+    (leases, issues)
+}
+
+fn collect_kea_reservations<'a>(node: &'a serde_json::Value, out: &mut Vec<&'a serde_json::Value>) {
+    if let Some(arr) = node.as_array() {
+        out.extend(arr.iter());
+        return;
+    }
+    if let Some(arr) = node.get("reservations").and_then(|v| v.as_array()) {
+        out.extend(arr.iter());
+    }
+    for key in ["subnet4", "shared-networks"] {
+        if let Some(children) = node.get(key).and_then(|v| v.as_array()) {
+            for child in children {
+                collect_kea_reservations(child, out);
+            }
+        }
+    }
+}
+
+// ── Export ───────────────────────────────────────────────────────────────────
+
+/// Render reservations in the given format.
+pub fn export(format: ReservationFormat, leases: &[StaticLease]) -> String {
+    match format {
+        ReservationFormat::Dnsmasq => leases
+            .iter()
+            .map(|l| {
+                if l.hostname.is_empty() {
+                    format!("dhcp-host={},{}\n", l.mac, l.ip)
+                } else {
+                    format!("dhcp-host={},{},{}\n", l.mac, l.ip, l.hostname)
+                }
+            })
+            .collect(),
+        ReservationFormat::Isc => leases
+            .iter()
+            .map(|l| {
+                let name = if l.hostname.is_empty() {
+                    format!("host-{}", l.mac.replace(':', ""))
+                } else {
+                    l.hostname.clone()
+                };
+                let mut block = format!(
+                    "host {} {{\n  hardware ethernet {};\n  fixed-address {};\n",
+                    name, l.mac, l.ip
+                );
+                if !l.hostname.is_empty() {
+                    block.push_str(&format!("  option host-name \"{}\";\n", l.hostname));
+                }
+                block.push_str("}\n");
+                block
+            })
+            .collect(),
+        ReservationFormat::Kea => {
+            let reservations: Vec<serde_json::Value> = leases
+                .iter()
+                .map(|l| {
+                    let mut r = serde_json::json!({
+                        "hw-address": l.mac,
+                        "ip-address": l.ip,
+                    });
+                    if !l.hostname.is_empty() {
+                        r["hostname"] = serde_json::json!(l.hostname);
+                    }
+                    r
+                })
+                .collect();
+            serde_json::to_string_pretty(&serde_json::json!({ "reservations": reservations }))
+                .unwrap_or_default()
+                + "\n"
+        }
+    }
+}
+
+// ── Conflict analysis ────────────────────────────────────────────────────────
+
+/// Compare imported reservations with the current config and lease table.
+pub fn analyze(
+    format: ReservationFormat,
+    imported: Vec<StaticLease>,
+    errors: Vec<ParseIssue>,
+    config: &DhcpConfig,
+    lease_store: &LeaseStore,
+) -> ImportReport {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let range = match (
+        config.range_start.parse::<Ipv4Addr>(),
+        config.range_end.parse::<Ipv4Addr>(),
+    ) {
+        (Ok(s), Ok(e)) => Some((u32::from(s), u32::from(e))),
+        _ => None,
+    };
+    let subnet = match (
+        config.gateway.parse::<Ipv4Addr>(),
+        config.netmask.parse::<Ipv4Addr>(),
+    ) {
+        (Ok(gw), Ok(mask)) => Some((u32::from(gw) & u32::from(mask), u32::from(mask))),
+        _ => None,
+    };
+
+    let existing_by_mac: HashMap<String, &StaticLease> = config
+        .static_leases
+        .iter()
+        .filter_map(|s| Some((normalize_mac(&s.mac)?, s)))
+        .collect();
+
+    let mut seen_macs = HashSet::new();
+    let mut seen_ips = HashSet::new();
+    let mut entries = Vec::with_capacity(imported.len());
+
+    for lease in imported {
+        let mut notes = Vec::new();
+        let mut conflict = false;
+        let ip: Ipv4Addr = lease.ip.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let ip_u32 = u32::from(ip);
+
+        if !seen_macs.insert(lease.mac.clone()) {
+            conflict = true;
+            notes.push("MAC appears more than once in the import".into());
+        }
+        if !seen_ips.insert(ip) {
+            conflict = true;
+            notes.push("IP appears more than once in the import".into());
+        }
+        if subnet.is_some_and(|(network, mask)| ip_u32 & mask != network) {
+            conflict = true;
+            notes.push("IP is outside the DHCP subnet".into());
+        }
+        if let Some(other) = config.static_leases.iter().find(|s| {
+            s.ip == lease.ip && normalize_mac(&s.mac).as_deref() != Some(lease.mac.as_str())
+        }) {
+            conflict = true;
+            notes.push(format!("IP already reserved for {}", other.mac));
+        }
+        let active = lease_store
+            .get_lease(ip)
+            .filter(|l| l.mac != lease.mac && l.expiry > now);
+        if let Some(active) = active {
+            conflict = true;
+            notes.push(format!(
+                "IP currently leased to {} for {}s",
+                active.mac,
+                active.expiry - now
+            ));
+        }
+        if range.is_some_and(|(start, end)| ip_u32 >= start && ip_u32 <= end) {
+            notes.push("IP is inside the dynamic range (excluded from the pool)".into());
+        }
+
+        let status = if conflict {
+            ImportStatus::Conflict
+        } else {
+            match existing_by_mac.get(&lease.mac) {
+                None => ImportStatus::New,
+                Some(e) if e.ip == lease.ip && e.hostname == lease.hostname => {
+                    ImportStatus::Unchanged
+                }
+                Some(e) => {
+                    notes.push(
+                        format!("replaces {} {}", e.ip, e.hostname)
+                            .trim_end()
+                            .into(),
+                    );
+                    ImportStatus::Update
+                }
+            }
+        };
+
+        entries.push(ImportEntry {
+            mac: lease.mac,
+            ip: lease.ip,
+            hostname: lease.hostname,
+            status,
+            notes,
+        });
+    }
+
+    let count = |s: ImportStatus| entries.iter().filter(|e| e.status == s).count();
+    ImportReport {
+        format,
+        new: count(ImportStatus::New),
+        updated: count(ImportStatus::Update),
+        unchanged: count(ImportStatus::Unchanged),
+        conflicts: count(ImportStatus::Conflict),
+        entries,
+        errors,
+    }
+}
+
+/// Normalize a MAC address to lowercase colon-separated form.
+/// Returns `None` if `s` is not a 48-bit MAC.
+pub fn normalize_mac(s: &str) -> Option<String> {
+    let parts: Vec<&str> = s.trim().split([':', '-']).collect();
+    if parts.len() != 6
+        || !parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+    Some(parts.join(":").to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dnsmasq() {
+        let content = "\
+# comment
+dhcp-host=AA:BB:CC:DD:EE:01,192.168.1.10,nas,infinite
+dhcp-host=set:iot,aa-bb-cc-dd-ee-02,printer,192.168.1.11,12h
+dhcp-host=aa:bb:cc:dd:ee:03,ignore
+dhcp-host=laptop,192.168.1.12
+domain=lan
+";
+        let (leases, issues) = parse(ReservationFormat::Dnsmasq, content);
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].mac, "aa:bb:cc:dd:ee:01");
+        assert_eq!(leases[0].hostname, "nas");
+        assert_eq!(leases[1].mac, "aa:bb:cc:dd:ee:02");
+        assert_eq!(leases[1].ip, "192.168.1.11");
+        assert_eq!(leases[1].hostname, "printer");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, 5);
+    }
+
+    #[test]
+    fn test_parse_isc() {
+        let content = r#"
+subnet 192.168.1.0 netmask 255.255.255.0 {
+  range 192.168.1.100 192.168.1.200;
+}
+host nas {
+  hardware ethernet AA:BB:CC:DD:EE:01;
+  fixed-address 192.168.1.10;
+}
+host printer { hardware ethernet aa:bb:cc:dd:ee:02; fixed-address 192.168.1.11; option host-name "hp"; }
+host broken { fixed-address 192.168.1.12; }
+"#;
+        let (leases, issues) = parse(ReservationFormat::Isc, content);
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].hostname, "nas");
+        assert_eq!(leases[1].hostname, "hp");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, 10);
+    }
+
+    #[test]
+    fn test_parse_kea_and_roundtrip() {
+        let content = r#"{"Dhcp4": {"subnet4": [{"subnet": "192.168.1.0/24", "reservations": [
+            {"hw-address": "aa:bb:cc:dd:ee:01", "ip-address": "192.168.1.10", "hostname": "nas"},
+            {"hw-address": "aa:bb:cc:dd:ee:02", "ip-address": "192.168.1.11"}
+        ]}]}}"#;
+        let (leases, issues) = parse(ReservationFormat::Kea, content);
+        assert!(issues.is_empty());
+        assert_eq!(leases.len(), 2);
+
+        for format in [
+            ReservationFormat::Dnsmasq,
+            ReservationFormat::Isc,
+            ReservationFormat::Kea,
+        ] {
+            let (again, issues) = parse(format, &export(format, &leases));
+            assert!(issues.is_empty(), "{:?}", format);
+            assert_eq!(again.len(), 2, "{:?}", format);
+            assert_eq!(again[1].ip, "192.168.1.11");
+        }
+    }
+
+    #[test]
+    fn test_analyze_conflicts() {
+        let config: DhcpConfig = serde_json::from_value(serde_json::json!({
+            "range_start": "192.168.1.100",
+            "range_end": "192.168.1.200",
+            "gateway": "192.168.1.1",
+            "static_leases": [
+                {"mac": "aa:bb:cc:dd:ee:01", "ip": "192.168.1.10", "hostname": "nas"},
+                {"mac": "aa:bb:cc:dd:ee:09", "ip": "192.168.1.20", "hostname": "tv"}
+            ]
+        }))
+        .unwrap();
+        let mut store = LeaseStore::new("/tmp/test-leases");
+        store.add_lease(crate::lease_store::Lease {
+            expiry: u64::MAX / 2,
+            mac: "ff:ff:ff:ff:ff:01".into(),
+            ip: Ipv4Addr::new(192, 168, 1, 150),
+            hostname: None,
+            client_id: None,
+        });
+
+        let lease = |mac: &str, ip: &str| StaticLease {
+            mac: mac.into(),
+            ip: ip.into(),
+            hostname: String::new(),
+        };
+        let imported = vec![
+            StaticLease {
+                hostname: "nas".into(),
+                ..lease("aa:bb:cc:dd:ee:01", "192.168.1.10")
+            },
+            lease("aa:bb:cc:dd:ee:02", "192.168.1.20"),
+            lease("aa:bb:cc:dd:ee:03", "192.168.1.150"),
+            lease("aa:bb:cc:dd:ee:04", "10.0.0.5"),
+            lease("aa:bb:cc:dd:ee:05", "192.168.1.160"),
+            lease("aa:bb:cc:dd:ee:09", "192.168.1.21"),
+        ];
+        let report = analyze(
+            ReservationFormat::Dnsmasq,
+            imported,
+            vec![],
+            &config,
+            &store,
+        );
+        let statuses: Vec<ImportStatus> = report.entries.iter().map(|e| e.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ImportStatus::Unchanged,
+                ImportStatus::Conflict,
+                ImportStatus::Conflict,
+                ImportStatus::Conflict,
+                ImportStatus::New,
+                ImportStatus::Update,
+            ]
+        );
+        assert_eq!(report.conflicts, 3);
+        assert!(!report.entries[4].notes.is_empty());
+
+        let merged = report.merge_into(&config.static_leases);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[1].ip, "192.168.1.21");
+    }
+}
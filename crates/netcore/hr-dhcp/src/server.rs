@@ -4,7 +4,7 @@ use std::net::{Ipv4Addr, SocketAddr};
 use tracing::{debug, info, warn};
 
 use crate::SharedDhcpState;
-use crate::options::DHCPNAK;
+use crate::options::{DHCPACK, DHCPDECLINE, DHCPDISCOVER, DHCPNAK, DHCPRELEASE};
 use crate::packet::DhcpPacket;
 use crate::state_machine;
 
@@ -66,16 +66,71 @@ pub async fn run_dhcp_server(state: SharedDhcpState) -> Result<()> {
         }
 
         let mut state_write = state.write().await;
+        state_write.metrics.record_received(packet.msg_type());
         let config = state_write.config.clone();
         let server_ip = state_write.server_ip;
+        let failover = state_write.failover.clone();
+
+        let mut pool = None;
+        if let Some(ref peer) = failover {
+            if !peer.should_serve(&packet) {
+                debug!(
+                    "DHCP failover: leaving {} to the peer ({:?})",
+                    packet.mac_str(),
+                    peer.state()
+                );
+                continue;
+            }
+            if let (Ok(start), Ok(end)) = (
+                config.range_start.parse::<Ipv4Addr>(),
+                config.range_end.parse::<Ipv4Addr>(),
+            ) {
+                // An empty share means we have nothing to offer: stay silent.
+                match peer.allocation_pool(start, end) {
+                    Some(p) => pool = Some(p),
+                    None if packet.msg_type() == Some(DHCPDISCOVER) => continue,
+                    None => {}
+                }
+            }
+        }
+
+        // Lease released or declined by this packet (for replication).
+        let released_ip = match packet.msg_type() {
+            Some(DHCPRELEASE) => Some(packet.ciaddr),
+            Some(DHCPDECLINE) => packet.requested_ip(),
+            _ => None,
+        };
+        let released = released_ip
+            .and_then(|ip| state_write.lease_store.get_lease(ip))
+            .map(|l| (l.ip, l.mac.clone()));
 
-        let response = state_machine::handle_dhcp_packet(
+        let response = state_machine::handle_dhcp_packet_in_pool(
             &packet,
             &config,
             &mut state_write.lease_store,
             server_ip,
+            pool,
         );
 
+        if let Some(ref peer) = failover {
+            match released {
+                Some((ip, mac)) if state_write.lease_store.get_lease(ip).is_none() => {
+                    peer.publish_removal(ip, mac);
+                }
+                _ => {}
+            }
+            let acked = response
+                .as_ref()
+                .filter(|r| r.msg_type() == Some(DHCPACK) && r.yiaddr != Ipv4Addr::UNSPECIFIED)
+                .and_then(|r| state_write.lease_store.get_lease(r.yiaddr));
+            if let Some(lease) = acked {
+                peer.publish_lease(lease.clone());
+            }
+        }
+
+        if let Some(ref response) = response {
+            state_write.metrics.record_sent(response.msg_type());
+        }
         drop(state_write);
 
         if let Some(response) = response {
@@ -13,11 +13,24 @@ pub fn handle_dhcp_packet(
     config: &DhcpConfig,
     lease_store: &mut LeaseStore,
     server_ip: Ipv4Addr,
+) -> Option<DhcpPacket> {
+    handle_dhcp_packet_in_pool(packet, config, lease_store, server_ip, None)
+}
+
+/// Same as [`handle_dhcp_packet`], but new addresses are only allocated from
+/// `pool` (a sub-range of the configured range, used by failover). Requests
+/// for any address in the full range are still honoured.
+pub fn handle_dhcp_packet_in_pool(
+    packet: &DhcpPacket,
+    config: &DhcpConfig,
+    lease_store: &mut LeaseStore,
+    server_ip: Ipv4Addr,
+    pool: Option<(Ipv4Addr, Ipv4Addr)>,
 ) -> Option<DhcpPacket> {
     let msg_type = packet.msg_type()?;
 
     match msg_type {
-        DHCPDISCOVER => handle_discover(packet, config, lease_store, server_ip),
+        DHCPDISCOVER => handle_discover(packet, config, lease_store, server_ip, pool),
         DHCPREQUEST => handle_request(packet, config, lease_store, server_ip),
         DHCPRELEASE => {
             handle_release(packet, lease_store);
@@ -40,12 +53,18 @@ fn handle_discover(
     config: &DhcpConfig,
     lease_store: &mut LeaseStore,
     server_ip: Ipv4Addr,
+    pool: Option<(Ipv4Addr, Ipv4Addr)>,
 ) -> Option<DhcpPacket> {
     let mac = packet.mac_str();
     info!("DHCPDISCOVER from {}", mac);
 
-    let range_start: Ipv4Addr = config.range_start.parse().ok()?;
-    let range_end: Ipv4Addr = config.range_end.parse().ok()?;
+    let (range_start, range_end) = match pool {
+        Some(pool) => pool,
+        None => (
+            config.range_start.parse().ok()?,
+            config.range_end.parse().ok()?,
+        ),
+    };
 
     let static_leases: Vec<(String, Ipv4Addr, String)> = config
         .static_leases
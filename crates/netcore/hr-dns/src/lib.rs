@@ -1,6 +1,7 @@
 pub mod cache;
 pub mod config;
 pub mod logging;
+pub mod metrics;
 pub mod packet;
 pub mod records;
 pub mod resolver;
@@ -21,6 +22,8 @@ pub struct DnsState {
     pub lease_store: Arc<RwLock<hr_dhcp::LeaseStore>>,
     pub adblock_enabled: bool,
     pub adblock_block_response: String,
+    /// Query counters, kept across config reloads.
+    pub metrics: metrics::DnsMetrics,
 }
 
 impl DnsState {
@@ -0,0 +1,58 @@
+//! DNS query counters.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::packet::{RCODE_NOERROR, RCODE_NXDOMAIN, RCODE_SERVFAIL};
+use crate::resolver::ResolveResult;
+
+/// Queries answered by the DNS server, by outcome.
+#[derive(Default)]
+pub struct DnsMetrics {
+    blocked: AtomicU64,
+    cached: AtomicU64,
+    answered: AtomicU64,
+    nxdomain: AtomicU64,
+    servfail: AtomicU64,
+    other: AtomicU64,
+    malformed: AtomicU64,
+}
+
+impl DnsMetrics {
+    /// Count a resolved query.
+    pub fn record(&self, result: &ResolveResult) {
+        let counter = if result.blocked {
+            &self.blocked
+        } else if result.cached {
+            &self.cached
+        } else {
+            match result.rcode {
+                RCODE_NOERROR => &self.answered,
+                RCODE_NXDOMAIN => &self.nxdomain,
+                RCODE_SERVFAIL => &self.servfail,
+                _ => &self.other,
+            }
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a query that could not be parsed.
+    pub fn record_malformed(&self) {
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counters as (outcome, count).
+    pub fn snapshot(&self) -> Vec<(String, u64)> {
+        [
+            ("blocked", &self.blocked),
+            ("cached", &self.cached),
+            ("answered", &self.answered),
+            ("nxdomain", &self.nxdomain),
+            ("servfail", &self.servfail),
+            ("other", &self.other),
+            ("malformed", &self.malformed),
+        ]
+        .into_iter()
+        .map(|(outcome, c)| (outcome.to_string(), c.load(Ordering::Relaxed)))
+        .collect()
+    }
+}
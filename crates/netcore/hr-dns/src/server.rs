@@ -141,6 +141,7 @@ async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: Socke
         Ok(q) => q,
         Err(e) => {
             debug!("Failed to parse DNS query from {}: {}", src, e);
+            state.read().await.metrics.record_malformed();
             // Return FORMERR if we can parse at least the header
             if query_bytes.len() >= 12 {
                 let mut err_resp = query_bytes[..12].to_vec();
@@ -166,6 +167,7 @@ async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: Socke
     if !query.questions.is_empty() {
         let q = &query.questions[0];
         let state_read = state.read().await;
+        state_read.metrics.record(&result);
         if let Some(ref logger) = state_read.query_logger {
             logger.log(
                 &q.name,
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirewallConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// nftables table owned by homeroute (family ip6).
+    #[serde(default = "default_table")]
+    pub table: String,
+    /// Upstream interface; everything arriving on it is untrusted.
+    /// Falls back to the IPv6 PD WAN interface when empty.
+    #[serde(default)]
+    pub wan_interface: String,
+    /// TCP ports on the router itself reachable from the WAN (reverse proxy).
+    #[serde(default = "default_router_tcp_ports")]
+    pub router_tcp_ports: Vec<u16>,
+    /// UDP ports on the router itself reachable from the WAN.
+    #[serde(default)]
+    pub router_udp_ports: Vec<u16>,
+    /// Accept inbound ICMPv6 echo requests (RFC 4890 recommends allowing them).
+    #[serde(default = "default_true")]
+    pub allow_echo_request: bool,
+    #[serde(default)]
+    pub pinholes: Vec<Pinhole>,
+}
+
+/// An inbound opening from the WAN to a LAN device or a local app.
+///
+/// Exactly one target is used, in order of precedence: `address`,
+/// `interface_id` (combined with the current delegated prefix), `device`
+/// (hostname or MAC from the DHCP inventory) or `app` (slug; opens the port
+/// on the router itself).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pinhole {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub interface_id: Option<String>,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub app: Option<String>,
+    #[serde(default)]
+    pub protocol: PinholeProtocol,
+    /// Ports or ranges ("22", "8000-8100"). App pinholes default to the app port.
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// Allowed source prefixes; empty means any.
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinholeProtocol {
+    #[default]
+    Tcp,
+    Udp,
+    Both,
+}
+
+fn default_table() -> String {
+    "homeroute".to_string()
+}
+fn default_router_tcp_ports() -> Vec<u16> {
+    vec![80, 443]
+}
+fn default_true() -> bool {
+    true
+}
+
+impl Default for FirewallConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
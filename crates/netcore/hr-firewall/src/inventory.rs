@@ -0,0 +1,169 @@
+//! Devices and apps that pinholes can refer to by name.
+//!
+//! Devices come from the DHCP reservations and leases (keyed by MAC); their
+//! IPv6 addresses are the DHCPv6 IA_NA bindings whose DUID embeds the same
+//! MAC, plus the EUI-64 address under the current prefix. Apps come from the
+//! orchestrator's app registry.
+
+use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
+
+use hr_dhcp::SharedDhcpState;
+use hr_dhcp::reservations::normalize_mac;
+use hr_ipv6::dhcpv6_server::{BindingKind, BindingStore};
+use serde::Deserialize;
+
+const APPS_REGISTRY_PATH: &str = "/opt/homeroute/data/apps.json";
+
+#[derive(Debug, Clone, Default)]
+pub struct Device {
+    pub hostname: String,
+    pub mac: String,
+    /// Addresses learned from DHCPv6 bindings.
+    pub addresses: Vec<Ipv6Addr>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    /// Sorted by MAC.
+    pub devices: Vec<Device>,
+    /// App slug → port.
+    pub apps: BTreeMap<String, u16>,
+}
+
+/// Subset of `hr_apps::Application` needed here.
+#[derive(Deserialize)]
+struct AppEntry {
+    slug: String,
+    port: u16,
+}
+
+impl Inventory {
+    /// Snapshot the DHCP state, DHCPv6 bindings and app registry.
+    pub async fn collect(dhcp: &SharedDhcpState) -> Self {
+        let mut devices: BTreeMap<String, Device> = BTreeMap::new();
+        {
+            let state = dhcp.read().await;
+            for lease in state.lease_store.all_leases() {
+                let Some(mac) = normalize_mac(&lease.mac) else {
+                    continue;
+                };
+                let entry = devices.entry(mac.clone()).or_insert_with(|| Device {
+                    mac,
+                    ..Default::default()
+                });
+                if let Some(h) = lease.hostname.as_ref().filter(|h| !h.is_empty()) {
+                    entry.hostname = h.clone();
+                }
+            }
+            // Reservation hostnames win over whatever the client sent
+            for sl in &state.config.static_leases {
+                let Some(mac) = normalize_mac(&sl.mac) else {
+                    continue;
+                };
+                let entry = devices.entry(mac.clone()).or_insert_with(|| Device {
+                    mac,
+                    ..Default::default()
+                });
+                if !sl.hostname.is_empty() {
+                    entry.hostname = sl.hostname.clone();
+                }
+            }
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        for binding in BindingStore::load().bindings {
+            if binding.kind != BindingKind::Address || binding.expires_at <= now {
+                continue;
+            }
+            if let Some(device) = duid_mac(&binding.duid).and_then(|m| devices.get_mut(&m)) {
+                device.addresses.push(binding.addr);
+            }
+        }
+
+        let apps = std::fs::read_to_string(APPS_REGISTRY_PATH)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Vec<AppEntry>>(&data).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| (a.slug, a.port))
+            .collect();
+
+        Self {
+            devices: devices.into_values().collect(),
+            apps,
+        }
+    }
+
+    /// Look up a device by hostname (case-insensitive) or MAC.
+    pub fn find_device(&self, key: &str) -> Option<&Device> {
+        match normalize_mac(key) {
+            Some(mac) => self.devices.iter().find(|d| d.mac == mac),
+            None => self
+                .devices
+                .iter()
+                .find(|d| d.hostname.eq_ignore_ascii_case(key)),
+        }
+    }
+}
+
+/// Modified EUI-64 address (RFC 4291 App. A) for `mac` under a /64 `prefix`.
+pub fn eui64(prefix: Ipv6Addr, mac: &str) -> Option<Ipv6Addr> {
+    let mac = normalize_mac(mac)?;
+    let b: Vec<u8> = mac
+        .split(':')
+        .map(|p| u8::from_str_radix(p, 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let mut octets = prefix.octets();
+    octets[8..16].copy_from_slice(&[b[0] ^ 0x02, b[1], b[2], 0xff, 0xfe, b[3], b[4], b[5]]);
+    Some(Ipv6Addr::from(octets))
+}
+
+/// Extract the MAC from a hex-encoded DUID-LLT (type 1) or DUID-LL (type 3)
+/// with Ethernet hardware type.
+fn duid_mac(duid_hex: &str) -> Option<String> {
+    let bytes: Vec<u8> = (0..duid_hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(duid_hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<_>>()?;
+    let mac = match bytes.get(0..4)? {
+        [0, 1, 0, 1] if bytes.len() == 14 => &bytes[8..14],
+        [0, 3, 0, 1] if bytes.len() == 10 => &bytes[4..10],
+        _ => return None,
+    };
+    Some(
+        mac.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eui64_and_duid_mac() {
+        let prefix: Ipv6Addr = "2001:db8:0:1::".parse().unwrap();
+        assert_eq!(
+            eui64(prefix, "00:11:22:33:44:55").unwrap(),
+            "2001:db8:0:1:211:22ff:fe33:4455"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+        assert_eq!(
+            duid_mac("00030001aabbccddeeff").as_deref(),
+            Some("aa:bb:cc:dd:ee:ff")
+        );
+        assert_eq!(
+            duid_mac("000100012a2b2c2daabbccddeeff").as_deref(),
+            Some("aa:bb:cc:dd:ee:ff")
+        );
+        assert_eq!(duid_mac("0002000000090102"), None);
+    }
+}
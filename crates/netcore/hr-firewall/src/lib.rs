@@ -0,0 +1,8 @@
+pub mod config;
+pub mod inventory;
+pub mod nft;
+pub mod ruleset;
+
+pub use config::{FirewallConfig, Pinhole, PinholeProtocol};
+pub use inventory::Inventory;
+pub use ruleset::{Ruleset, render};
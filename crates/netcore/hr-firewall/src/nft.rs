@@ -0,0 +1,95 @@
+//! Applies the rendered ruleset with `nft -f` and keeps it in sync with the
+//! delegated prefix and the device inventory.
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hr_dhcp::SharedDhcpState;
+use hr_ipv6::PrefixWatch;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::config::FirewallConfig;
+use crate::inventory::Inventory;
+use crate::ruleset::render;
+
+/// How often the inventory is re-read to pick up new leases and bindings.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Load a ruleset atomically: `nft -f` applies the whole file as one
+/// transaction, so a failure leaves the previous ruleset in place.
+pub async fn apply_ruleset(text: &str) -> Result<()> {
+    let mut child = tokio::process::Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run nft")?;
+
+    let mut stdin = child.stdin.take().context("nft stdin unavailable")?;
+    stdin.write_all(text.as_bytes()).await?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "nft rejected ruleset: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Render and apply the ruleset whenever the prefix or inventory changes.
+pub async fn run_firewall(
+    config: FirewallConfig,
+    mut prefix_rx: PrefixWatch,
+    dhcp: SharedDhcpState,
+) -> Result<()> {
+    if !config.enabled {
+        info!("IPv6 firewall disabled");
+        std::future::pending::<()>().await;
+        return Ok(());
+    }
+    if config.wan_interface.is_empty() {
+        // Without it every interface would count as trusted LAN
+        anyhow::bail!("IPv6 firewall enabled but no WAN interface configured");
+    }
+
+    info!(
+        "Starting IPv6 firewall (table ip6 {}, WAN {}, {} pinholes)",
+        config.table,
+        config.wan_interface,
+        config.pinholes.len()
+    );
+
+    let mut applied: Option<String> = None;
+
+    loop {
+        let prefix = prefix_rx
+            .borrow()
+            .as_ref()
+            .map(|p| (p.prefix, p.prefix_len));
+        let inventory = Inventory::collect(&dhcp).await;
+        let ruleset = render(&config, &inventory, prefix);
+
+        if applied.as_deref() != Some(ruleset.text.as_str()) {
+            for w in &ruleset.warnings {
+                warn!("Firewall {}", w);
+            }
+            apply_ruleset(&ruleset.text).await?;
+            info!(
+                "Applied IPv6 firewall ruleset ({} lines)",
+                ruleset.text.lines().count()
+            );
+            applied = Some(ruleset.text);
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(REFRESH_INTERVAL) => {}
+            _ = prefix_rx.changed() => {}
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! nftables ruleset rendering.
+//!
+//! The ruleset is produced as plain text so it can be unit-tested and loaded
+//! in a single `nft -f` transaction. Output depends only on the inputs, so an
+//! unchanged config renders byte-identical text and re-applying is skipped.
+//!
+//! Policy: everything arriving on the WAN interface is dropped unless it
+//! belongs to an established flow, is ICMPv6 that RFC 4890 says must not be
+//! filtered, targets a router port, or matches a pinhole.
+
+use std::fmt::Write;
+use std::net::Ipv6Addr;
+
+use crate::config::{FirewallConfig, Pinhole, PinholeProtocol};
+use crate::inventory::{Inventory, eui64};
+
+/// ICMPv6 errors that must never be dropped (RFC 4890 §4.3.1 / §4.4.1).
+const ICMPV6_ERRORS: &str =
+    "destination-unreachable, packet-too-big, time-exceeded, parameter-problem";
+
+/// Neighbor Discovery, only valid with hop limit 255 (RFC 4890 §4.4.1).
+const ICMPV6_ND: &str = "nd-router-solicit, nd-router-advert, nd-neighbor-solicit, nd-neighbor-advert, ind-neighbor-solicit, ind-neighbor-advert";
+
+/// Multicast Listener Discovery, only valid from link-local sources.
+const ICMPV6_MLD: &str =
+    "mld-listener-query, mld-listener-report, mld-listener-done, mld2-listener-report";
+
+/// A rendered ruleset plus anything that could not be turned into rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ruleset {
+    pub text: String,
+    pub warnings: Vec<String>,
+}
+
+/// Render the full ruleset for `prefix` (the current LAN /64, if any).
+pub fn render(
+    config: &FirewallConfig,
+    inventory: &Inventory,
+    prefix: Option<(Ipv6Addr, u8)>,
+) -> Ruleset {
+    let wan = quote(&config.wan_interface);
+    let mut warnings = Vec::new();
+    let mut input_rules = Vec::new();
+    let mut forward_rules = Vec::new();
+
+    for pinhole in config.pinholes.iter().filter(|p| p.enabled) {
+        match pinhole_rule(pinhole, &wan, inventory, prefix) {
+            Ok((Chain::Input, rule)) => input_rules.push(rule),
+            Ok((Chain::Forward, rule)) => forward_rules.push(rule),
+            Err(e) => warnings.push(format!("pinhole '{}': {}", pinhole.name, e)),
+        }
+    }
+
+    let table = &config.table;
+    let mut out = String::new();
+
+    // Create-then-delete makes the reload atomic and idempotent
+    let _ = writeln!(out, "# Generated by homeroute. Do not edit.");
+    let _ = writeln!(out, "table ip6 {table}");
+    let _ = writeln!(out, "delete table ip6 {table}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "table ip6 {table} {{");
+
+    // ── Traffic to the router itself ──
+    let _ = writeln!(out, "\tchain input {{");
+    let _ = writeln!(
+        out,
+        "\t\ttype filter hook input priority filter; policy drop;"
+    );
+    let _ = writeln!(out, "\t\tct state established,related accept");
+    let _ = writeln!(out, "\t\tct state invalid drop");
+    let _ = writeln!(out, "\t\tiif \"lo\" accept");
+    let _ = writeln!(out, "\t\tiifname != {wan} accept");
+    let _ = writeln!(out, "\t\ticmpv6 type {{ {ICMPV6_ERRORS} }} accept");
+    if config.allow_echo_request {
+        let _ = writeln!(
+            out,
+            "\t\ticmpv6 type echo-request limit rate 10/second accept"
+        );
+    }
+    let _ = writeln!(out, "\t\ticmpv6 type echo-reply accept");
+    let _ = writeln!(
+        out,
+        "\t\ticmpv6 type {{ {ICMPV6_ND} }} ip6 hoplimit 255 accept"
+    );
+    let _ = writeln!(
+        out,
+        "\t\tip6 saddr fe80::/10 icmpv6 type {{ {ICMPV6_MLD} }} accept"
+    );
+    // DHCPv6 replies to the prefix delegation client
+    let _ = writeln!(out, "\t\tip6 saddr fe80::/10 udp dport 546 accept");
+    if !config.router_tcp_ports.is_empty() {
+        let _ = writeln!(
+            out,
+            "\t\ttcp dport {} accept",
+            port_set(&config.router_tcp_ports)
+        );
+    }
+    if !config.router_udp_ports.is_empty() {
+        let _ = writeln!(
+            out,
+            "\t\tudp dport {} accept",
+            port_set(&config.router_udp_ports)
+        );
+    }
+    for rule in &input_rules {
+        let _ = writeln!(out, "\t\t{rule}");
+    }
+    let _ = writeln!(out, "\t}}");
+    let _ = writeln!(out);
+
+    // ── Traffic routed through the router ──
+    let _ = writeln!(out, "\tchain forward {{");
+    let _ = writeln!(
+        out,
+        "\t\ttype filter hook forward priority filter; policy drop;"
+    );
+    let _ = writeln!(out, "\t\tct state established,related accept");
+    let _ = writeln!(out, "\t\tct state invalid drop");
+    let _ = writeln!(out, "\t\tiifname != {wan} accept");
+    let _ = writeln!(out, "\t\ticmpv6 type {{ {ICMPV6_ERRORS} }} accept");
+    if config.allow_echo_request {
+        let _ = writeln!(out, "\t\ticmpv6 type echo-request accept");
+    }
+    for rule in &forward_rules {
+        let _ = writeln!(out, "\t\t{rule}");
+    }
+    let _ = writeln!(out, "\t}}");
+    let _ = writeln!(out, "}}");
+
+    Ruleset {
+        text: out,
+        warnings,
+    }
+}
+
+enum Chain {
+    Input,
+    Forward,
+}
+
+/// Build the single rule line for a pinhole.
+fn pinhole_rule(
+    pinhole: &Pinhole,
+    wan: &str,
+    inventory: &Inventory,
+    prefix: Option<(Ipv6Addr, u8)>,
+) -> Result<(Chain, String), String> {
+    let mut ports = pinhole
+        .ports
+        .iter()
+        .map(|p| parse_port_spec(p).ok_or_else(|| format!("invalid port '{}'", p)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (chain, targets) = if let Some(addr) = &pinhole.address {
+        (Chain::Forward, vec![parse_target(addr)?])
+    } else if let Some(iid) = &pinhole.interface_id {
+        let (p, len) = prefix.ok_or("no delegated prefix yet")?;
+        let iid: Ipv6Addr = iid
+            .parse()
+            .map_err(|_| format!("invalid interface id '{}'", iid))?;
+        let host_mask = if len >= 128 { 0 } else { u128::MAX >> len };
+        let addr = (u128::from(p) & !host_mask) | (u128::from(iid) & host_mask);
+        (Chain::Forward, vec![Ipv6Addr::from(addr).to_string()])
+    } else if let Some(key) = &pinhole.device {
+        let device = inventory
+            .find_device(key)
+            .ok_or_else(|| format!("unknown device '{}'", key))?;
+        let mut addrs: Vec<Ipv6Addr> = device
+            .addresses
+            .iter()
+            .copied()
+            .filter(|a| prefix.is_none_or(|(p, len)| in_prefix(*a, p, len)))
+            .collect();
+        if let Some((p, 64)) = prefix {
+            addrs.extend(eui64(p, &device.mac));
+        }
+        addrs.sort();
+        addrs.dedup();
+        if addrs.is_empty() {
+            return Err(format!("no IPv6 address known for device '{}'", key));
+        }
+        (
+            Chain::Forward,
+            addrs.iter().map(|a| a.to_string()).collect(),
+        )
+    } else if let Some(slug) = &pinhole.app {
+        let port = inventory
+            .apps
+            .get(slug)
+            .ok_or_else(|| format!("unknown app '{}'", slug))?;
+        if ports.is_empty() {
+            ports.push(port.to_string());
+        }
+        (Chain::Input, Vec::new())
+    } else {
+        return Err("no target (address, interface_id, device or app)".to_string());
+    };
+
+    if ports.is_empty() {
+        return Err("no ports".to_string());
+    }
+
+    let mut rule = format!("iifname {} ", wan);
+    if !pinhole.sources.is_empty() {
+        let sources = pinhole
+            .sources
+            .iter()
+            .map(|s| parse_target(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let _ = write!(rule, "ip6 saddr {} ", set(&sources));
+    }
+    if !targets.is_empty() {
+        let _ = write!(rule, "ip6 daddr {} ", set(&targets));
+    }
+    let port_expr = set(&ports);
+    match pinhole.protocol {
+        PinholeProtocol::Tcp => {
+            let _ = write!(rule, "tcp dport {port_expr} ");
+        }
+        PinholeProtocol::Udp => {
+            let _ = write!(rule, "udp dport {port_expr} ");
+        }
+        PinholeProtocol::Both => {
+            let _ = write!(rule, "meta l4proto {{ tcp, udp }} th dport {port_expr} ");
+        }
+    }
+    let _ = write!(
+        rule,
+        "accept comment {}",
+        quote(&format!("pinhole: {}", pinhole.name))
+    );
+
+    Ok((chain, rule))
+}
+
+/// Validate an address or prefix and return its canonical form.
+fn parse_target(s: &str) -> Result<String, String> {
+    let invalid = || format!("invalid address '{}'", s);
+    match s.split_once('/') {
+        Some((addr, len)) => {
+            let addr: Ipv6Addr = addr.parse().map_err(|_| invalid())?;
+            let len: u8 = len.parse().ok().filter(|l| *l <= 128).ok_or_else(invalid)?;
+            Ok(format!("{}/{}", addr, len))
+        }
+        None => s
+            .parse::<Ipv6Addr>()
+            .map(|a| a.to_string())
+            .map_err(|_| invalid()),
+    }
+}
+
+/// Validate "N" or "N-M" and return it normalized.
+fn parse_port_spec(s: &str) -> Option<String> {
+    let s = s.trim();
+    match s.split_once('-') {
+        Some((a, b)) => {
+            let (a, b): (u16, u16) = (a.trim().parse().ok()?, b.trim().parse().ok()?);
+            (a > 0 && a <= b).then(|| format!("{}-{}", a, b))
+        }
+        None => s
+            .parse::<u16>()
+            .ok()
+            .filter(|p| *p > 0)
+            .map(|p| p.to_string()),
+    }
+}
+
+fn in_prefix(addr: Ipv6Addr, prefix: Ipv6Addr, len: u8) -> bool {
+    let mask = if len == 0 {
+        0
+    } else {
+        u128::MAX << (128 - len.min(128) as u32)
+    };
+    u128::from(addr) & mask == u128::from(prefix) & mask
+}
+
+fn set(items: &[String]) -> String {
+    match items {
+        [one] => one.clone(),
+        _ => format!("{{ {} }}", items.join(", ")),
+    }
+}
+
+fn port_set(ports: &[u16]) -> String {
+    set(&ports.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+}
+
+/// Quote a string for nft, dropping characters that would end the literal.
+fn quote(s: &str) -> String {
+    let clean: String = s
+        .chars()
+        .filter(|c| !matches!(c, '"' | '\\' | '\n' | '\r'))
+        .take(120)
+        .collect();
+    format!("\"{}\"", clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::Device;
+
+    fn config(pinholes: Vec<Pinhole>) -> FirewallConfig {
+        FirewallConfig {
+            enabled: true,
+            wan_interface: "eth1".to_string(),
+            pinholes,
+            ..Default::default()
+        }
+    }
+
+    fn pinhole(name: &str) -> Pinhole {
+        serde_json::from_value(serde_json::json!({ "name": name })).unwrap()
+    }
+
+    fn inventory() -> Inventory {
+        Inventory {
+            devices: vec![Device {
+                hostname: "nas".to_string(),
+                mac: "00:11:22:33:44:55".to_string(),
+                addresses: vec![
+                    "2001:db8:0:1::1234".parse().unwrap(),
+                    "2001:db8:9:1::1234".parse().unwrap(),
+                ],
+            }],
+            apps: [("game".to_string(), 3005)].into_iter().collect(),
+        }
+    }
+
+    const PREFIX: Option<(Ipv6Addr, u8)> =
+        Some((Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 0), 64));
+
+    #[test]
+    fn baseline_is_default_deny_and_deterministic() {
+        let cfg = config(Vec::new());
+        let a = render(&cfg, &Inventory::default(), None);
+        let b = render(&cfg, &Inventory::default(), None);
+        assert_eq!(a, b);
+        assert!(a.warnings.is_empty());
+        assert!(a.text.starts_with("# Generated by homeroute"));
+        assert!(
+            a.text
+                .contains("table ip6 homeroute\ndelete table ip6 homeroute\n")
+        );
+        assert_eq!(a.text.matches("policy drop;").count(), 2);
+        assert_eq!(
+            a.text
+                .matches("ct state established,related accept")
+                .count(),
+            2
+        );
+        assert!(a.text.contains("iifname != \"eth1\" accept"));
+        assert!(a.text.contains("tcp dport { 80, 443 } accept"));
+        assert!(a.text.contains("ip6 hoplimit 255 accept"));
+        assert!(a.text.contains("packet-too-big"));
+    }
+
+    #[test]
+    fn device_pinhole_resolves_current_addresses() {
+        let mut p = pinhole("nas ssh");
+        p.device = Some("NAS".to_string());
+        p.ports = vec!["22".to_string(), "8000-8100".to_string()];
+        p.sources = vec!["2001:db8:aaaa::/48".to_string()];
+        let rs = render(&config(vec![p]), &inventory(), PREFIX);
+        assert!(rs.warnings.is_empty(), "{:?}", rs.warnings);
+        // DHCPv6 address from the old prefix is dropped, EUI-64 is added
+        assert!(rs.text.contains(
+            "iifname \"eth1\" ip6 saddr 2001:db8:aaaa::/48 \
+             ip6 daddr { 2001:db8:0:1::1234, 2001:db8:0:1:211:22ff:fe33:4455 } \
+             tcp dport { 22, 8000-8100 } accept comment \"pinhole: nas ssh\""
+        ));
+    }
+
+    #[test]
+    fn app_pinhole_opens_router_port_and_bad_pinholes_warn() {
+        let mut app = pinhole("game");
+        app.app = Some("game".to_string());
+        app.protocol = PinholeProtocol::Both;
+        let mut unknown = pinhole("ghost");
+        unknown.device = Some("ghost".to_string());
+        let mut iid = pinhole("cam");
+        iid.interface_id = Some("::10".to_string());
+        iid.ports = vec!["0".to_string()];
+        let mut disabled = pinhole("off");
+        disabled.address = Some("2001:db8::1".to_string());
+        disabled.enabled = false;
+
+        let rs = render(
+            &config(vec![app, unknown, iid, disabled]),
+            &inventory(),
+            PREFIX,
+        );
+        let input = rs.text.split("chain forward").next().unwrap();
+        assert!(input.contains(
+            "iifname \"eth1\" meta l4proto { tcp, udp } th dport 3005 accept comment \"pinhole: game\""
+        ));
+        assert!(!rs.text.contains("pinhole: off"));
+        assert_eq!(
+            rs.warnings,
+            vec![
+                "pinhole 'ghost': unknown device 'ghost'".to_string(),
+                "pinhole 'cam': invalid port '0'".to_string(),
+            ]
+        );
+    }
+}
@@ -12,6 +12,12 @@ pub struct Ipv6Config {
     pub dns_servers: Vec<String>,
     #[serde(default)]
     pub interface: String,
+    /// ULA prefix advertised alongside the GUA (e.g. "fd12:3456:789a::/64").
+    #[serde(default)]
+    pub ula_prefix: String,
+    /// Extra routes announced via Route Information options (RFC 4191).
+    #[serde(default)]
+    pub ra_routes: Vec<String>,
 
     // DHCPv6 Prefix Delegation client (WAN side)
     #[serde(default)]
@@ -22,6 +28,25 @@ pub struct Ipv6Config {
     pub pd_prefix_hint_len: u8,
     #[serde(default)]
     pub pd_subnet_id: u16,
+
+    // Stateful DHCPv6 server (LAN side)
+    #[serde(default)]
+    pub dhcpv6_enabled: bool,
+    /// First interface identifier (lower 64 bits) handed out via IA_NA.
+    #[serde(default = "default_dhcpv6_range_start")]
+    pub dhcpv6_range_start: String,
+    /// Last interface identifier (lower 64 bits) handed out via IA_NA.
+    #[serde(default = "default_dhcpv6_range_end")]
+    pub dhcpv6_range_end: String,
+    #[serde(default = "default_dhcpv6_preferred_lifetime")]
+    pub dhcpv6_preferred_lifetime_secs: u32,
+    #[serde(default = "default_dhcpv6_valid_lifetime")]
+    pub dhcpv6_valid_lifetime_secs: u32,
+    /// Sub-delegate prefixes of this length to downstream routers via IA_PD (0 = disabled).
+    #[serde(default)]
+    pub dhcpv6_pd_length: u8,
+    #[serde(default)]
+    pub dns_search_domains: Vec<String>,
 }
 
 fn default_ra_lifetime() -> u32 {
@@ -30,6 +55,18 @@ fn default_ra_lifetime() -> u32 {
 fn default_pd_prefix_hint_len() -> u8 {
     56
 }
+fn default_dhcpv6_range_start() -> String {
+    "::1000".to_string()
+}
+fn default_dhcpv6_range_end() -> String {
+    "::1fff".to_string()
+}
+fn default_dhcpv6_preferred_lifetime() -> u32 {
+    3600
+}
+fn default_dhcpv6_valid_lifetime() -> u32 {
+    7200
+}
 
 impl Default for Ipv6Config {
     fn default() -> Self {
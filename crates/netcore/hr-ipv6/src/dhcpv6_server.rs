@@ -0,0 +1,1106 @@
+//! Stateful DHCPv6 server (RFC 8415) for the LAN.
+//!
+//! Hands out IA_NA addresses from the /64 published by the PD client,
+//! optionally sub-delegates IA_PD prefixes carved from the upstream
+//! delegation to downstream routers, and answers Information-Request with
+//! DNS options. Bindings are persisted so clients keep their addresses
+//! across restarts.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddrV6};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::config::Ipv6Config;
+use crate::pd_client::{PrefixInfo, PrefixWatch, append_option, generate_client_duid, now_secs};
+
+// ── DHCPv6 message types ────────────────────────────────────────────────────
+
+const MSG_SOLICIT: u8 = 1;
+const MSG_ADVERTISE: u8 = 2;
+const MSG_REQUEST: u8 = 3;
+const MSG_CONFIRM: u8 = 4;
+const MSG_RENEW: u8 = 5;
+const MSG_REBIND: u8 = 6;
+const MSG_REPLY: u8 = 7;
+const MSG_RELEASE: u8 = 8;
+const MSG_DECLINE: u8 = 9;
+const MSG_INFORMATION_REQUEST: u8 = 11;
+
+// ── DHCPv6 option codes ─────────────────────────────────────────────────────
+
+const OPT_CLIENTID: u16 = 1;
+const OPT_SERVERID: u16 = 2;
+const OPT_IA_NA: u16 = 3;
+const OPT_IAADDR: u16 = 5;
+const OPT_STATUS_CODE: u16 = 13;
+const OPT_RAPID_COMMIT: u16 = 14;
+const OPT_DNS_SERVERS: u16 = 23;
+const OPT_DOMAIN_LIST: u16 = 24;
+const OPT_IA_PD: u16 = 25;
+const OPT_IAPREFIX: u16 = 26;
+
+// ── Status codes ────────────────────────────────────────────────────────────
+
+const STATUS_SUCCESS: u16 = 0;
+const STATUS_NO_ADDRS_AVAIL: u16 = 2;
+const STATUS_NO_BINDING: u16 = 3;
+const STATUS_NOT_ON_LINK: u16 = 4;
+const STATUS_NO_PREFIX_AVAIL: u16 = 6;
+
+/// Upper bound on candidates scanned when looking for a free address/prefix.
+const MAX_SCAN: u128 = 65536;
+
+// ── Bindings ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingKind {
+    Address,
+    Prefix,
+}
+
+/// A single IA_NA address or IA_PD prefix bound to a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    /// Client DUID, hex-encoded. Empty for addresses withdrawn after a DECLINE.
+    pub duid: String,
+    pub iaid: u32,
+    pub kind: BindingKind,
+    pub addr: Ipv6Addr,
+    pub prefix_len: u8,
+    pub expires_at: u64,
+    /// Router the delegated prefix is routed through (the requesting
+    /// client's source address). IA_PD bindings only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_hop: Option<Ipv6Addr>,
+}
+
+/// Persisted set of DHCPv6 bindings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BindingStore {
+    pub bindings: Vec<Binding>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl BindingStore {
+    pub fn state_file_path() -> &'static str {
+        "/var/lib/server-dashboard/dhcpv6-bindings.json"
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::state_file_path())
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        let tmp = format!("{}.tmp", Self::state_file_path());
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, Self::state_file_path())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn find(&self, duid: &str, iaid: u32, kind: BindingKind) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .find(|b| b.duid == duid && b.iaid == iaid && b.kind == kind)
+    }
+
+    fn upsert(&mut self, binding: Binding) {
+        self.bindings.retain(|b| {
+            !(b.duid == binding.duid && b.iaid == binding.iaid && b.kind == binding.kind)
+        });
+        self.bindings.push(binding);
+        self.dirty = true;
+    }
+
+    /// Withdraw a declined address for `expires_at`. Parked addresses have
+    /// no owner, so they are keyed by the address itself.
+    fn park(&mut self, addr: Ipv6Addr, expires_at: u64) {
+        self.bindings
+            .retain(|b| !(b.duid.is_empty() && b.kind == BindingKind::Address && b.addr == addr));
+        self.bindings.push(Binding {
+            duid: String::new(),
+            iaid: 0,
+            kind: BindingKind::Address,
+            addr,
+            prefix_len: 128,
+            expires_at,
+            next_hop: None,
+        });
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, duid: &str, iaid: u32, kind: BindingKind) -> bool {
+        let before = self.bindings.len();
+        self.bindings
+            .retain(|b| !(b.duid == duid && b.iaid == iaid && b.kind == kind));
+        let removed = self.bindings.len() != before;
+        self.dirty |= removed;
+        removed
+    }
+
+    /// Whether `addr` is bound to anyone other than (duid, iaid).
+    fn is_taken(&self, addr: Ipv6Addr, kind: BindingKind, duid: &str, iaid: u32, now: u64) -> bool {
+        self.bindings.iter().any(|b| {
+            b.kind == kind
+                && b.addr == addr
+                && b.expires_at > now
+                && !(b.duid == duid && b.iaid == iaid)
+        })
+    }
+
+    /// Routes the live IA_PD bindings need: (prefix, length) -> next hop.
+    fn prefix_routes(&self, now: u64) -> HashMap<(Ipv6Addr, u8), Ipv6Addr> {
+        self.bindings
+            .iter()
+            .filter(|b| b.kind == BindingKind::Prefix && b.expires_at > now)
+            .filter_map(|b| Some(((b.addr, b.prefix_len), b.next_hop?)))
+            .collect()
+    }
+
+    /// Drop expired bindings. Returns the number removed.
+    pub fn purge_expired(&mut self, now: u64) -> usize {
+        let before = self.bindings.len();
+        self.bindings.retain(|b| b.expires_at > now);
+        let removed = before - self.bindings.len();
+        self.dirty |= removed > 0;
+        removed
+    }
+}
+
+// ── Message handling ────────────────────────────────────────────────────────
+
+/// Everything the message handler needs besides the binding store.
+pub struct ServerContext<'a> {
+    pub config: &'a Ipv6Config,
+    pub server_duid: &'a [u8],
+    pub prefix: Option<&'a PrefixInfo>,
+    /// Source address of the message, used as next hop for IA_PD.
+    pub client_addr: Option<Ipv6Addr>,
+}
+
+/// An IA_NA or IA_PD as sent by the client.
+struct ClientIa {
+    kind: BindingKind,
+    iaid: u32,
+    /// Addresses/prefixes the client listed inside the IA.
+    requested: Vec<(Ipv6Addr, u8)>,
+}
+
+/// A lease to put in a reply IA.
+struct IaLease {
+    addr: Ipv6Addr,
+    len: u8,
+    preferred: u32,
+    valid: u32,
+}
+
+/// Process one client message and build the reply, if any.
+pub fn handle_message(
+    ctx: &ServerContext,
+    store: &mut BindingStore,
+    msg: &[u8],
+    now: u64,
+) -> Option<Vec<u8>> {
+    if msg.len() < 4 {
+        return None;
+    }
+    let msg_type = msg[0];
+    let opts = parse_options(&msg[4..]);
+    let client_id = find_option(&opts, OPT_CLIENTID);
+    let server_id = find_option(&opts, OPT_SERVERID);
+
+    // RFC 8415 §16: validate Client/Server Identifier presence per message type
+    let server_id_ok = server_id == Some(ctx.server_duid);
+    let valid = match msg_type {
+        MSG_SOLICIT | MSG_REBIND | MSG_CONFIRM => client_id.is_some() && server_id.is_none(),
+        MSG_REQUEST | MSG_RENEW | MSG_RELEASE | MSG_DECLINE => client_id.is_some() && server_id_ok,
+        MSG_INFORMATION_REQUEST => server_id.is_none() || server_id_ok,
+        _ => false,
+    };
+    if !valid {
+        debug!("Ignoring DHCPv6 message type={}", msg_type);
+        return None;
+    }
+
+    let duid = client_id.map(hex_encode).unwrap_or_default();
+    let ias = parse_client_ias(&opts);
+    let rapid_commit = msg_type == MSG_SOLICIT && find_option(&opts, OPT_RAPID_COMMIT).is_some();
+
+    let reply_type = if msg_type == MSG_SOLICIT && !rapid_commit {
+        MSG_ADVERTISE
+    } else {
+        MSG_REPLY
+    };
+
+    let mut reply = Vec::with_capacity(256);
+    reply.push(reply_type);
+    reply.extend_from_slice(&msg[1..4]);
+    append_option(&mut reply, OPT_SERVERID, ctx.server_duid);
+    if let Some(cid) = client_id {
+        append_option(&mut reply, OPT_CLIENTID, cid);
+    }
+    if rapid_commit {
+        append_option(&mut reply, OPT_RAPID_COMMIT, &[]);
+    }
+
+    match msg_type {
+        MSG_SOLICIT | MSG_REQUEST | MSG_RENEW | MSG_REBIND => {
+            let commit = msg_type != MSG_SOLICIT || rapid_commit;
+            let extend = matches!(msg_type, MSG_RENEW | MSG_REBIND);
+            for ia in &ias {
+                let data = assign_ia(ctx, store, &duid, ia, now, commit, extend);
+                append_option(&mut reply, ia_option_code(ia.kind), &data);
+            }
+            if commit && !ias.is_empty() {
+                info!(
+                    "DHCPv6 {} from {}: {} IA(s) bound",
+                    message_name(msg_type),
+                    duid,
+                    ias.len()
+                );
+            }
+        }
+        MSG_RELEASE => {
+            for ia in &ias {
+                if store.remove(&duid, ia.iaid, ia.kind) {
+                    info!("DHCPv6 release from {} (iaid {})", duid, ia.iaid);
+                } else {
+                    let mut data = ia_header(ia.iaid, 0, 0);
+                    append_status(&mut data, STATUS_NO_BINDING, "No binding for IA");
+                    append_option(&mut reply, ia_option_code(ia.kind), &data);
+                }
+            }
+            append_status(&mut reply, STATUS_SUCCESS, "Release received");
+        }
+        MSG_DECLINE => {
+            for ia in ias.iter().filter(|ia| ia.kind == BindingKind::Address) {
+                for (addr, _) in &ia.requested {
+                    warn!("DHCPv6 decline from {} for {}", duid, addr);
+                    store.remove(&duid, ia.iaid, ia.kind);
+                    // Park the address so it is not handed out again right away
+                    store.park(*addr, now + ctx.config.dhcpv6_valid_lifetime_secs as u64);
+                }
+            }
+            append_status(&mut reply, STATUS_SUCCESS, "Decline received");
+        }
+        MSG_CONFIRM => {
+            // RFC 8415 §18.3.3: without a prefix we cannot tell, so stay silent
+            let prefix = ctx.prefix?;
+            let on_link = ias
+                .iter()
+                .filter(|ia| ia.kind == BindingKind::Address)
+                .flat_map(|ia| ia.requested.iter())
+                .all(|(addr, _)| in_prefix(*addr, prefix.prefix, prefix.prefix_len));
+            if on_link {
+                append_status(&mut reply, STATUS_SUCCESS, "All addresses on link");
+            } else {
+                append_status(&mut reply, STATUS_NOT_ON_LINK, "Address not on link");
+            }
+        }
+        _ => {}
+    }
+
+    append_dns_options(&mut reply, ctx.config);
+    Some(reply)
+}
+
+/// Build the contents of a reply IA_NA/IA_PD for a single client IA.
+fn assign_ia(
+    ctx: &ServerContext,
+    store: &mut BindingStore,
+    duid: &str,
+    ia: &ClientIa,
+    now: u64,
+    commit: bool,
+    extend: bool,
+) -> Vec<u8> {
+    let (preferred, valid) = lifetimes(ctx);
+    let assigned = match ia.kind {
+        BindingKind::Address => {
+            allocate_address(ctx, store, duid, ia.iaid, now).map(|addr| (addr, 128))
+        }
+        BindingKind::Prefix => allocate_prefix(ctx, store, duid, ia.iaid, now),
+    };
+
+    let Some((addr, len)) = assigned.filter(|_| valid > 0) else {
+        let mut data = ia_header(ia.iaid, 0, 0);
+        let (code, text) = match ia.kind {
+            BindingKind::Address => (STATUS_NO_ADDRS_AVAIL, "No addresses available"),
+            BindingKind::Prefix => (STATUS_NO_PREFIX_AVAIL, "No prefixes available"),
+        };
+        append_status(&mut data, code, text);
+        return data;
+    };
+
+    if commit {
+        let next_hop = match ia.kind {
+            BindingKind::Address => None,
+            BindingKind::Prefix => ctx
+                .client_addr
+                .or_else(|| store.find(duid, ia.iaid, ia.kind).and_then(|b| b.next_hop)),
+        };
+        store.upsert(Binding {
+            duid: duid.to_string(),
+            iaid: ia.iaid,
+            kind: ia.kind,
+            addr,
+            prefix_len: len,
+            expires_at: now + valid as u64,
+            next_hop,
+        });
+    }
+
+    let mut leases = vec![IaLease {
+        addr,
+        len,
+        preferred,
+        valid,
+    }];
+    // On Renew/Rebind, anything else the client still holds (e.g. from a
+    // previous delegated prefix) is withdrawn with zero lifetimes.
+    if extend {
+        for (old, old_len) in ia.requested.iter().filter(|(a, _)| *a != addr) {
+            leases.push(IaLease {
+                addr: *old,
+                len: *old_len,
+                preferred: 0,
+                valid: 0,
+            });
+        }
+    }
+
+    let mut data = ia_header(ia.iaid, preferred / 2, preferred / 5 * 4);
+    for lease in &leases {
+        append_lease(&mut data, ia.kind, lease);
+    }
+    data
+}
+
+/// Preferred/valid lifetimes for new leases, capped by the upstream prefix.
+fn lifetimes(ctx: &ServerContext) -> (u32, u32) {
+    let Some(prefix) = ctx.prefix else {
+        return (0, 0);
+    };
+    let valid = ctx
+        .config
+        .dhcpv6_valid_lifetime_secs
+        .min(prefix.valid_lifetime);
+    let preferred = ctx
+        .config
+        .dhcpv6_preferred_lifetime_secs
+        .min(prefix.preferred_lifetime)
+        .min(valid);
+    (preferred, valid)
+}
+
+// ── Allocation ──────────────────────────────────────────────────────────────
+
+/// Pick an IA_NA address for the client: its existing binding if still on
+/// the current prefix, otherwise a free address from the configured range.
+fn allocate_address(
+    ctx: &ServerContext,
+    store: &BindingStore,
+    duid: &str,
+    iaid: u32,
+    now: u64,
+) -> Option<Ipv6Addr> {
+    let prefix = ctx.prefix?;
+
+    if let Some(b) = store
+        .find(duid, iaid, BindingKind::Address)
+        .filter(|b| in_prefix(b.addr, prefix.prefix, prefix.prefix_len))
+    {
+        return Some(b.addr);
+    }
+
+    let start = parse_interface_id(&ctx.config.dhcpv6_range_start)?.max(2);
+    let end = parse_interface_id(&ctx.config.dhcpv6_range_end)?;
+    if start > end {
+        return None;
+    }
+    let size = (end - start) as u128 + 1;
+    // Start the scan at a DUID-derived offset so clients tend to get the same
+    // address back even after their binding expired.
+    let offset = fnv1a(duid.as_bytes(), iaid) as u128 % size;
+    let base = u128::from(prefix.prefix) & prefix_mask(prefix.prefix_len);
+
+    (0..size.min(MAX_SCAN))
+        .map(|i| start as u128 + (offset + i) % size)
+        .map(|id| Ipv6Addr::from(base | (id & !prefix_mask(prefix.prefix_len))))
+        .find(|addr| !store.is_taken(*addr, BindingKind::Address, duid, iaid, now))
+}
+
+/// Pick an IA_PD prefix of `dhcpv6_pd_length` out of the upstream delegation,
+/// skipping the one that contains the LAN /64.
+fn allocate_prefix(
+    ctx: &ServerContext,
+    store: &BindingStore,
+    duid: &str,
+    iaid: u32,
+    now: u64,
+) -> Option<(Ipv6Addr, u8)> {
+    let prefix = ctx.prefix?;
+    let plen = ctx.config.dhcpv6_pd_length;
+    let dlen = prefix.delegated_prefix_len;
+    if plen == 0 || plen <= dlen || plen > 64 {
+        return None;
+    }
+
+    if let Some(b) = store
+        .find(duid, iaid, BindingKind::Prefix)
+        .filter(|b| b.prefix_len == plen && in_prefix(b.addr, prefix.delegated_prefix, dlen))
+    {
+        return Some((b.addr, b.prefix_len));
+    }
+
+    let base = u128::from(prefix.delegated_prefix) & prefix_mask(dlen);
+    let count = 1u128 << (plen - dlen);
+    (0..count.min(MAX_SCAN))
+        .map(|i| Ipv6Addr::from(base | (i << (128 - plen as u32))))
+        .filter(|cand| !in_prefix(prefix.prefix, *cand, plen))
+        .find(|cand| !store.is_taken(*cand, BindingKind::Prefix, duid, iaid, now))
+        .map(|cand| (cand, plen))
+}
+
+fn prefix_mask(len: u8) -> u128 {
+    match len {
+        0 => 0,
+        l if l >= 128 => u128::MAX,
+        l => u128::MAX << (128 - l as u32),
+    }
+}
+
+fn in_prefix(addr: Ipv6Addr, prefix: Ipv6Addr, len: u8) -> bool {
+    let mask = prefix_mask(len);
+    u128::from(addr) & mask == u128::from(prefix) & mask
+}
+
+/// Parse the lower 64 bits of an address written as e.g. `::1000`.
+fn parse_interface_id(s: &str) -> Option<u64> {
+    s.parse::<Ipv6Addr>().ok().map(|a| u128::from(a) as u64)
+}
+
+fn fnv1a(data: &[u8], iaid: u32) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data.iter().chain(iaid.to_be_bytes().iter()) {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// ── Packet parsing ──────────────────────────────────────────────────────────
+
+fn parse_options(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut opts = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        offset += 4;
+        if offset + len > data.len() {
+            break;
+        }
+        opts.push((code, &data[offset..offset + len]));
+        offset += len;
+    }
+    opts
+}
+
+fn find_option<'a>(opts: &[(u16, &'a [u8])], code: u16) -> Option<&'a [u8]> {
+    opts.iter().find(|(c, _)| *c == code).map(|(_, d)| *d)
+}
+
+fn parse_client_ias(opts: &[(u16, &[u8])]) -> Vec<ClientIa> {
+    let mut ias = Vec::new();
+    for (code, data) in opts {
+        let kind = match *code {
+            OPT_IA_NA => BindingKind::Address,
+            OPT_IA_PD => BindingKind::Prefix,
+            _ => continue,
+        };
+        if data.len() < 12 {
+            continue;
+        }
+        let iaid = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let requested = parse_options(&data[12..])
+            .into_iter()
+            .filter_map(|(c, d)| match (kind, c) {
+                (BindingKind::Address, OPT_IAADDR) if d.len() >= 24 => {
+                    let octets: [u8; 16] = d[0..16].try_into().ok()?;
+                    Some((Ipv6Addr::from(octets), 128))
+                }
+                (BindingKind::Prefix, OPT_IAPREFIX) if d.len() >= 25 => {
+                    let octets: [u8; 16] = d[9..25].try_into().ok()?;
+                    Some((Ipv6Addr::from(octets), d[8]))
+                }
+                _ => None,
+            })
+            .collect();
+        ias.push(ClientIa {
+            kind,
+            iaid,
+            requested,
+        });
+    }
+    ias
+}
+
+// ── Packet builders ─────────────────────────────────────────────────────────
+
+fn ia_option_code(kind: BindingKind) -> u16 {
+    match kind {
+        BindingKind::Address => OPT_IA_NA,
+        BindingKind::Prefix => OPT_IA_PD,
+    }
+}
+
+fn ia_header(iaid: u32, t1: u32, t2: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(&iaid.to_be_bytes());
+    data.extend_from_slice(&t1.to_be_bytes());
+    data.extend_from_slice(&t2.to_be_bytes());
+    data
+}
+
+fn append_lease(buf: &mut Vec<u8>, kind: BindingKind, lease: &IaLease) {
+    let mut data = Vec::with_capacity(25);
+    match kind {
+        BindingKind::Address => {
+            data.extend_from_slice(&lease.addr.octets());
+            data.extend_from_slice(&lease.preferred.to_be_bytes());
+            data.extend_from_slice(&lease.valid.to_be_bytes());
+            append_option(buf, OPT_IAADDR, &data);
+        }
+        BindingKind::Prefix => {
+            data.extend_from_slice(&lease.preferred.to_be_bytes());
+            data.extend_from_slice(&lease.valid.to_be_bytes());
+            data.push(lease.len);
+            data.extend_from_slice(&lease.addr.octets());
+            append_option(buf, OPT_IAPREFIX, &data);
+        }
+    }
+}
+
+fn append_status(buf: &mut Vec<u8>, code: u16, message: &str) {
+    let mut data = Vec::with_capacity(2 + message.len());
+    data.extend_from_slice(&code.to_be_bytes());
+    data.extend_from_slice(message.as_bytes());
+    append_option(buf, OPT_STATUS_CODE, &data);
+}
+
+/// DNS Recursive Name Server (RFC 3646 §3) and Domain Search List (§4).
+fn append_dns_options(buf: &mut Vec<u8>, config: &Ipv6Config) {
+    let servers: Vec<u8> = config
+        .dns_servers
+        .iter()
+        .filter_map(|s| s.parse::<Ipv6Addr>().ok())
+        .flat_map(|a| a.octets())
+        .collect();
+    if !servers.is_empty() {
+        append_option(buf, OPT_DNS_SERVERS, &servers);
+    }
+
+    let domains: Vec<u8> = config
+        .dns_search_domains
+        .iter()
+        .flat_map(|d| encode_domain_name(d))
+        .collect();
+    if !domains.is_empty() {
+        append_option(buf, OPT_DOMAIN_LIST, &domains);
+    }
+}
+
+/// Encode a domain name in uncompressed DNS wire format.
+pub(crate) fn encode_domain_name(name: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(name.len() + 2);
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+    out
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn message_name(msg_type: u8) -> &'static str {
+    match msg_type {
+        MSG_SOLICIT => "SOLICIT",
+        MSG_REQUEST => "REQUEST",
+        MSG_RENEW => "RENEW",
+        MSG_REBIND => "REBIND",
+        _ => "UNKNOWN",
+    }
+}
+
+// ── Server loop ─────────────────────────────────────────────────────────────
+
+/// Run `ip -6 route <args>`, logging failures.
+async fn ip_route(args: &[&str]) -> bool {
+    let output = tokio::process::Command::new("ip")
+        .arg("-6")
+        .arg("route")
+        .args(args)
+        .output()
+        .await;
+    match output {
+        Ok(o) if o.status.success() => true,
+        Ok(o) => {
+            warn!(
+                "ip -6 route {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&o.stderr).trim()
+            );
+            false
+        }
+        Err(e) => {
+            warn!("Failed to run ip command: {}", e);
+            false
+        }
+    }
+}
+
+/// Bring the kernel routes for delegated prefixes in line with the bindings:
+/// each live IA_PD is routed via the router that requested it, and the route
+/// goes away once the binding is released, replaced or expires.
+async fn sync_prefix_routes(
+    interface: &str,
+    store: &BindingStore,
+    installed: &mut HashMap<(Ipv6Addr, u8), Ipv6Addr>,
+    now: u64,
+) {
+    let wanted = store.prefix_routes(now);
+
+    let stale: Vec<(Ipv6Addr, u8)> = installed
+        .keys()
+        .filter(|k| !wanted.contains_key(k))
+        .copied()
+        .collect();
+    for (prefix, len) in stale {
+        let cidr = format!("{}/{}", prefix, len);
+        if ip_route(&["del", &cidr, "dev", interface]).await {
+            info!("Removed route for delegated prefix {}", cidr);
+        }
+        installed.remove(&(prefix, len));
+    }
+
+    for (&(prefix, len), &via) in &wanted {
+        if installed.get(&(prefix, len)) == Some(&via) {
+            continue;
+        }
+        let cidr = format!("{}/{}", prefix, len);
+        let gw = via.to_string();
+        if ip_route(&["replace", &cidr, "via", &gw, "dev", interface]).await {
+            info!("Routed delegated prefix {} via {}", cidr, gw);
+            installed.insert((prefix, len), via);
+        }
+    }
+}
+
+fn create_server_socket(interface: &str) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_only_v6(true)?;
+
+    #[cfg(target_os = "linux")]
+    socket.bind_device(Some(interface.as_bytes()))?;
+
+    let bind_addr: SocketAddrV6 = "[::]:547".parse().unwrap();
+    socket.bind(&bind_addr.into())?;
+
+    // All_DHCP_Relay_Agents_and_Servers
+    let ifindex = std::fs::read_to_string(format!("/sys/class/net/{}/ifindex", interface))
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+    socket.join_multicast_v6(&"ff02::1:2".parse().unwrap(), ifindex)?;
+    socket.set_nonblocking(true)?;
+
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Run the stateful DHCPv6 server on the LAN interface.
+pub async fn run_dhcpv6_server(config: Ipv6Config, prefix_rx: PrefixWatch) -> Result<()> {
+    if !config.dhcpv6_enabled {
+        info!("DHCPv6 server disabled");
+        std::future::pending::<()>().await;
+        return Ok(());
+    }
+
+    let socket = create_server_socket(&config.interface)?;
+    let server_duid = generate_client_duid(&config.interface);
+    let mut store = BindingStore::load();
+    store.purge_expired(now_secs());
+
+    info!(
+        "Starting DHCPv6 server on {} (range {}-{}, pd /{}, {} bindings)",
+        config.interface,
+        config.dhcpv6_range_start,
+        config.dhcpv6_range_end,
+        config.dhcpv6_pd_length,
+        store.bindings.len()
+    );
+
+    let mut buf = [0u8; 1500];
+    let mut purge = tokio::time::interval(Duration::from_secs(60));
+    let mut routes = HashMap::new();
+
+    loop {
+        tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (len, src) = match res {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("DHCPv6 server recv error: {}", e);
+                        continue;
+                    }
+                };
+                let prefix = prefix_rx.borrow().clone();
+                let ctx = ServerContext {
+                    config: &config,
+                    server_duid: &server_duid,
+                    prefix: prefix.as_ref(),
+                    client_addr: match src.ip() {
+                        IpAddr::V6(addr) => Some(addr),
+                        IpAddr::V4(_) => None,
+                    },
+                };
+                if let Some(reply) = handle_message(&ctx, &mut store, &buf[..len], now_secs())
+                    && let Err(e) = socket.send_to(&reply, src).await
+                {
+                    warn!("Failed to send DHCPv6 reply to {}: {}", src, e);
+                }
+            }
+            _ = purge.tick() => {
+                let removed = store.purge_expired(now_secs());
+                if removed > 0 {
+                    debug!("Purged {} expired DHCPv6 bindings", removed);
+                }
+            }
+        }
+
+        sync_prefix_routes(&config.interface, &store, &mut routes, now_secs()).await;
+
+        if store.dirty
+            && let Err(e) = store.save()
+        {
+            warn!("Failed to persist DHCPv6 bindings: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_DUID: &[u8] = &[0, 3, 0, 1, 1, 2, 3, 4, 5, 6];
+    const CLIENT_DUID: &[u8] = &[0, 3, 0, 1, 0xa, 0xb, 0xc, 0xd, 0xe, 0xf];
+
+    fn prefix(p: &str, delegated: &str) -> PrefixInfo {
+        PrefixInfo {
+            prefix: p.parse().unwrap(),
+            prefix_len: 64,
+            valid_lifetime: 86400,
+            preferred_lifetime: 14400,
+            delegated_prefix: delegated.parse().unwrap(),
+            delegated_prefix_len: 56,
+        }
+    }
+
+    fn test_config() -> Ipv6Config {
+        Ipv6Config {
+            dhcpv6_enabled: true,
+            dhcpv6_pd_length: 60,
+            dns_servers: vec!["2001:db8::53".to_string()],
+            dns_search_domains: vec!["home.lan".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn client_msg(msg_type: u8, with_server_id: bool, ias: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut buf = vec![msg_type, 0x12, 0x34, 0x56];
+        append_option(&mut buf, OPT_CLIENTID, CLIENT_DUID);
+        if with_server_id {
+            append_option(&mut buf, OPT_SERVERID, SERVER_DUID);
+        }
+        for (code, data) in ias {
+            append_option(&mut buf, *code, data);
+        }
+        buf
+    }
+
+    fn ia_na(iaid: u32, addrs: &[Ipv6Addr]) -> (u16, Vec<u8>) {
+        let mut data = ia_header(iaid, 0, 0);
+        for a in addrs {
+            let lease = IaLease {
+                addr: *a,
+                len: 128,
+                preferred: 0,
+                valid: 0,
+            };
+            append_lease(&mut data, BindingKind::Address, &lease);
+        }
+        (OPT_IA_NA, data)
+    }
+
+    /// Leases (addr, len, valid) from the IAs of a reply.
+    fn reply_leases(reply: &[u8], code: u16) -> Vec<(Ipv6Addr, u8, u32)> {
+        let opts = parse_options(&reply[4..]);
+        let mut out = Vec::new();
+        for (_, data) in opts.iter().filter(|(c, _)| *c == code) {
+            for (sub, d) in parse_options(&data[12..]) {
+                match sub {
+                    OPT_IAADDR => {
+                        let octets: [u8; 16] = d[0..16].try_into().unwrap();
+                        let valid = u32::from_be_bytes(d[20..24].try_into().unwrap());
+                        out.push((Ipv6Addr::from(octets), 128, valid));
+                    }
+                    OPT_IAPREFIX => {
+                        let octets: [u8; 16] = d[9..25].try_into().unwrap();
+                        let valid = u32::from_be_bytes(d[4..8].try_into().unwrap());
+                        out.push((Ipv6Addr::from(octets), d[8], valid));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn solicit_advertises_without_binding_and_request_commits() {
+        let config = test_config();
+        let pfx = prefix("2001:db8:0:1::", "2001:db8::");
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: Some(&pfx),
+            client_addr: None,
+        };
+        let mut store = BindingStore::default();
+
+        let adv = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_SOLICIT, false, &[ia_na(1, &[])]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(adv[0], MSG_ADVERTISE);
+        let offered = reply_leases(&adv, OPT_IA_NA);
+        assert_eq!(offered.len(), 1);
+        assert!(in_prefix(offered[0].0, pfx.prefix, 64));
+        assert!(store.bindings.is_empty());
+
+        let reply = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_REQUEST, true, &[ia_na(1, &[])]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(reply[0], MSG_REPLY);
+        assert_eq!(reply_leases(&reply, OPT_IA_NA)[0].0, offered[0].0);
+        assert_eq!(store.bindings.len(), 1);
+        assert_eq!(store.bindings[0].expires_at, 7200);
+
+        // Request addressed to another server is ignored
+        let mut foreign = client_msg(MSG_REQUEST, false, &[ia_na(1, &[])]);
+        append_option(&mut foreign, OPT_SERVERID, &[0, 3, 0, 1, 9, 9, 9, 9, 9, 9]);
+        assert!(handle_message(&ctx, &mut store, &foreign, 0).is_none());
+    }
+
+    #[test]
+    fn renew_after_renumbering_withdraws_old_address() {
+        let config = test_config();
+        let old_pfx = prefix("2001:db8:0:1::", "2001:db8::");
+        let new_pfx = prefix("2001:db8:ff:1::", "2001:db8:ff::");
+        let mut store = BindingStore::default();
+
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: Some(&old_pfx),
+            client_addr: None,
+        };
+        let reply = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_REQUEST, true, &[ia_na(7, &[])]),
+            0,
+        )
+        .unwrap();
+        let old_addr = reply_leases(&reply, OPT_IA_NA)[0].0;
+
+        let ctx = ServerContext {
+            prefix: Some(&new_pfx),
+            ..ctx
+        };
+        let reply = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_RENEW, true, &[ia_na(7, &[old_addr])]),
+            100,
+        )
+        .unwrap();
+        let leases = reply_leases(&reply, OPT_IA_NA);
+        assert_eq!(leases.len(), 2);
+        assert!(in_prefix(leases[0].0, new_pfx.prefix, 64));
+        assert!(leases[0].2 > 0);
+        assert_eq!((leases[1].0, leases[1].2), (old_addr, 0));
+        assert_eq!(store.bindings.len(), 1);
+    }
+
+    #[test]
+    fn release_removes_binding_and_address_is_reusable() {
+        let config = test_config();
+        let pfx = prefix("2001:db8:0:1::", "2001:db8::");
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: Some(&pfx),
+            client_addr: None,
+        };
+        let mut store = BindingStore::default();
+        let reply = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_REQUEST, true, &[ia_na(1, &[])]),
+            0,
+        )
+        .unwrap();
+        let addr = reply_leases(&reply, OPT_IA_NA)[0].0;
+        assert!(store.is_taken(addr, BindingKind::Address, "other", 1, 0));
+
+        handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_RELEASE, true, &[ia_na(1, &[addr])]),
+            1,
+        )
+        .unwrap();
+        assert!(store.bindings.is_empty());
+        assert!(!store.is_taken(addr, BindingKind::Address, "other", 1, 1));
+    }
+
+    #[test]
+    fn ia_pd_sub_delegation_skips_lan_subnet() {
+        let config = test_config();
+        // LAN /64 sits in the first /60 of the /56
+        let pfx = prefix("2001:db8:0:1::", "2001:db8::");
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: Some(&pfx),
+            client_addr: Some("fe80::2".parse().unwrap()),
+        };
+        let mut store = BindingStore::default();
+        let ia_pd = (OPT_IA_PD, ia_header(2, 0, 0));
+        let reply = handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_REQUEST, true, &[ia_pd]),
+            0,
+        )
+        .unwrap();
+        let leases = reply_leases(&reply, OPT_IA_PD);
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].0, "2001:db8:0:10::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(leases[0].1, 60);
+
+        // The prefix is routed via the requesting router until released
+        let routes = store.prefix_routes(0);
+        assert_eq!(
+            routes.get(&(leases[0].0, 60)),
+            Some(&"fe80::2".parse::<Ipv6Addr>().unwrap())
+        );
+        let mut release = ia_header(2, 0, 0);
+        append_lease(
+            &mut release,
+            BindingKind::Prefix,
+            &IaLease {
+                addr: leases[0].0,
+                len: 60,
+                preferred: 0,
+                valid: 0,
+            },
+        );
+        handle_message(
+            &ctx,
+            &mut store,
+            &client_msg(MSG_RELEASE, true, &[(OPT_IA_PD, release)]),
+            1,
+        )
+        .unwrap();
+        assert!(store.prefix_routes(1).is_empty());
+    }
+
+    #[test]
+    fn declined_addresses_are_all_parked() {
+        let config = test_config();
+        let pfx = prefix("2001:db8:0:1::", "2001:db8::");
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: Some(&pfx),
+            client_addr: None,
+        };
+        let mut store = BindingStore::default();
+        let first: Ipv6Addr = "2001:db8:0:1::100".parse().unwrap();
+        let second: Ipv6Addr = "2001:db8:0:1::101".parse().unwrap();
+        for addr in [first, second] {
+            handle_message(
+                &ctx,
+                &mut store,
+                &client_msg(MSG_DECLINE, true, &[ia_na(1, &[addr])]),
+                0,
+            )
+            .unwrap();
+        }
+        assert!(store.is_taken(first, BindingKind::Address, "other", 1, 0));
+        assert!(store.is_taken(second, BindingKind::Address, "other", 1, 0));
+    }
+
+    #[test]
+    fn information_request_returns_dns_options() {
+        let config = test_config();
+        let ctx = ServerContext {
+            config: &config,
+            server_duid: SERVER_DUID,
+            prefix: None,
+            client_addr: None,
+        };
+        let mut store = BindingStore::default();
+        let mut msg = vec![MSG_INFORMATION_REQUEST, 1, 2, 3];
+        append_option(&mut msg, OPT_CLIENTID, CLIENT_DUID);
+        let reply = handle_message(&ctx, &mut store, &msg, 0).unwrap();
+        assert_eq!(reply[0], MSG_REPLY);
+        let opts = parse_options(&reply[4..]);
+        let dns = find_option(&opts, OPT_DNS_SERVERS).unwrap();
+        assert_eq!(dns, "2001:db8::53".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(
+            find_option(&opts, OPT_DOMAIN_LIST).unwrap(),
+            b"\x04home\x03lan\x00"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod config;
+pub mod dhcpv6_server;
 pub mod pd_client;
 pub mod ra;
 
@@ -43,6 +43,9 @@ pub struct PrefixInfo {
     pub prefix_len: u8,
     pub valid_lifetime: u32,
     pub preferred_lifetime: u32,
+    /// Whole prefix delegated by upstream (e.g. the /56 the LAN /64 was carved from).
+    pub delegated_prefix: Ipv6Addr,
+    pub delegated_prefix_len: u8,
 }
 
 pub type PrefixSender = watch::Sender<Option<PrefixInfo>>;
@@ -105,15 +108,7 @@ pub async fn run_pd_client(config: Ipv6Config, prefix_tx: PrefixSender) -> Resul
                             remaining_secs(&saved)
                         );
                         // Publish the saved prefix
-                        let subnet_prefix = parse_prefix_str(&saved.selected_subnet);
-                        if let Some((addr, len)) = subnet_prefix {
-                            let _ = prefix_tx.send(Some(PrefixInfo {
-                                prefix: addr,
-                                prefix_len: len,
-                                valid_lifetime: saved.valid_lifetime,
-                                preferred_lifetime: saved.preferred_lifetime,
-                            }));
-                        }
+                        publish_prefix(&prefix_tx, &saved);
                         fsm = PdFsmState::Renewing { state: saved };
                         continue;
                     }
@@ -336,7 +331,7 @@ fn create_dhcpv6_socket(wan_interface: &str) -> Result<UdpSocket> {
 
 // ── DUID generation ──────────────────────────────────────────────────────────
 
-fn generate_client_duid(interface: &str) -> Vec<u8> {
+pub(crate) fn generate_client_duid(interface: &str) -> Vec<u8> {
     // DUID-LL (type 3): link-layer address
     // Type(2) + HW type(2) + MAC(6) = 10 bytes
     let mac = read_interface_mac(interface).unwrap_or([0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01]);
@@ -474,7 +469,7 @@ fn build_ia_pd(iaid: u32, t1: u32, t2: u32, hint_prefix_len: Option<u8>) -> Vec<
     data
 }
 
-fn append_option(buf: &mut Vec<u8>, code: u16, data: &[u8]) {
+pub(crate) fn append_option(buf: &mut Vec<u8>, code: u16, data: &[u8]) {
     buf.extend_from_slice(&code.to_be_bytes());
     buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
     buf.extend_from_slice(data);
@@ -780,14 +775,19 @@ fn select_subnet(delegated: Ipv6Addr, delegated_len: u8, subnet_id: u16) -> (Ipv
 // ── Helpers ──────────────────────────────────────────────────────────────────
 
 fn publish_prefix(tx: &PrefixSender, state: &PdState) {
-    if let Some((addr, len)) = parse_prefix_str(&state.selected_subnet) {
-        let _ = tx.send(Some(PrefixInfo {
-            prefix: addr,
-            prefix_len: len,
-            valid_lifetime: state.valid_lifetime,
-            preferred_lifetime: state.preferred_lifetime,
-        }));
-    }
+    let Some((addr, len)) = parse_prefix_str(&state.selected_subnet) else {
+        return;
+    };
+    let (delegated, delegated_len) =
+        parse_prefix_str(&state.delegated_prefix).unwrap_or((addr, len));
+    let _ = tx.send(Some(PrefixInfo {
+        prefix: addr,
+        prefix_len: len,
+        valid_lifetime: state.valid_lifetime,
+        preferred_lifetime: state.preferred_lifetime,
+        delegated_prefix: delegated,
+        delegated_prefix_len: delegated_len,
+    }));
 }
 
 fn parse_prefix_str(s: &str) -> Option<(Ipv6Addr, u8)> {
@@ -800,7 +800,7 @@ fn parse_prefix_str(s: &str) -> Option<(Ipv6Addr, u8)> {
     Some((addr, len))
 }
 
-fn now_secs() -> u64 {
+pub(crate) fn now_secs() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_secs())
@@ -1,10 +1,17 @@
 //! Router Advertisement sender via raw ICMPv6 socket.
 //!
-//! Sends the GUA prefix from DHCPv6-PD with SLAAC (A=1, M=0, O=1).
-//! All clients use SLAAC for address configuration; DNS via RDNSS option.
+//! Sends the GUA prefix from DHCPv6-PD (plus an optional ULA) with SLAAC
+//! (A=1, M=0, O=1), RDNSS/DNSSL for DNS and Route Information options for
+//! the delegated prefix and any configured routes. When the stateful DHCPv6
+//! server is enabled, M=1 is set as well so clients also request an IA_NA
+//! address.
+//!
+//! On renumbering the previous GUA keeps being advertised with a zero
+//! preferred lifetime and a shrinking valid lifetime (RFC 8978 / RFC 9096)
+//! so hosts stop using it immediately instead of waiting for it to expire.
 
 use std::net::{Ipv6Addr, SocketAddrV6};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use socket2::{Domain, Protocol, Socket, Type};
@@ -12,14 +19,22 @@ use tokio::sync::watch;
 use tracing::{error, info, warn};
 
 use crate::config::Ipv6Config;
+use crate::dhcpv6_server::encode_domain_name;
 use crate::pd_client::PrefixInfo;
 
-/// Assign a GUA address (<prefix>::1) to the LAN interface.
-async fn assign_lan_gua(interface: &str, prefix: &PrefixInfo) {
-    let mut octets = prefix.prefix.octets();
+/// Upper bound on the valid lifetime of a deprecated prefix (RFC 9096 §3.5).
+const DEPRECATED_VALID_LIFETIME_MAX: u32 = 7200;
+
+/// Lifetimes for the statically configured ULA prefix.
+const ULA_VALID_LIFETIME: u32 = 86400;
+const ULA_PREFERRED_LIFETIME: u32 = 14400;
+
+/// Assign the router address (<prefix>::1) to the LAN interface.
+async fn assign_lan_addr(interface: &str, prefix: Ipv6Addr, prefix_len: u8) {
+    let mut octets = prefix.octets();
     octets[15] = 1;
     let addr = Ipv6Addr::from(octets);
-    let cidr = format!("{}/{}", addr, prefix.prefix_len);
+    let cidr = format!("{}/{}", addr, prefix_len);
 
     let output = tokio::process::Command::new("ip")
         .args(["-6", "addr", "add", &cidr, "dev", interface])
@@ -27,23 +42,23 @@ async fn assign_lan_gua(interface: &str, prefix: &PrefixInfo) {
         .await;
 
     match output {
-        Ok(o) if o.status.success() => info!("Assigned GUA {} to {}", cidr, interface),
+        Ok(o) if o.status.success() => info!("Assigned {} to {}", cidr, interface),
         Ok(o) => {
             let stderr = String::from_utf8_lossy(&o.stderr);
             if !stderr.contains("File exists") {
-                error!("Failed to assign GUA {} to {}: {}", cidr, interface, stderr);
+                error!("Failed to assign {} to {}: {}", cidr, interface, stderr);
             }
         }
         Err(e) => error!("Failed to run ip command: {}", e),
     }
 }
 
-/// Remove a GUA address from the LAN interface.
-async fn remove_lan_gua(interface: &str, prefix: &PrefixInfo) {
-    let mut octets = prefix.prefix.octets();
+/// Remove the router address (<prefix>::1) from the LAN interface.
+async fn remove_lan_addr(interface: &str, prefix: Ipv6Addr, prefix_len: u8) {
+    let mut octets = prefix.octets();
     octets[15] = 1;
     let addr = Ipv6Addr::from(octets);
-    let cidr = format!("{}/{}", addr, prefix.prefix_len);
+    let cidr = format!("{}/{}", addr, prefix_len);
 
     let output = tokio::process::Command::new("ip")
         .args(["-6", "addr", "del", &cidr, "dev", interface])
@@ -51,11 +66,11 @@ async fn remove_lan_gua(interface: &str, prefix: &PrefixInfo) {
         .await;
 
     match output {
-        Ok(o) if o.status.success() => info!("Removed GUA {} from {}", cidr, interface),
+        Ok(o) if o.status.success() => info!("Removed {} from {}", cidr, interface),
         Ok(o) => {
             let stderr = String::from_utf8_lossy(&o.stderr);
             if !stderr.contains("Cannot assign") {
-                warn!("Failed to remove GUA from {}: {}", interface, stderr);
+                warn!("Failed to remove {} from {}: {}", cidr, interface, stderr);
             }
         }
         Err(e) => warn!("Failed to run ip command: {}", e),
@@ -63,6 +78,7 @@ async fn remove_lan_gua(interface: &str, prefix: &PrefixInfo) {
 }
 
 /// A prefix to include in the Router Advertisement.
+#[derive(Debug)]
 struct PrefixOption {
     addr: Ipv6Addr,
     len: u8,
@@ -70,10 +86,46 @@ struct PrefixOption {
     preferred_lifetime: u32,
 }
 
-/// Build an ICMPv6 Router Advertisement packet with multiple prefixes.
+/// A route to include as a Route Information option.
+#[derive(Debug)]
+struct RouteOption {
+    addr: Ipv6Addr,
+    len: u8,
+    lifetime: u32,
+}
+
+/// A previous GUA still being advertised after renumbering.
+#[derive(Debug)]
+struct DeprecatedPrefix {
+    addr: Ipv6Addr,
+    len: u8,
+    expires: Instant,
+}
+
+impl DeprecatedPrefix {
+    fn new(old: &PrefixInfo, now: Instant) -> Self {
+        let valid = old.valid_lifetime.min(DEPRECATED_VALID_LIFETIME_MAX);
+        Self {
+            addr: old.prefix,
+            len: old.prefix_len,
+            expires: now + Duration::from_secs(valid as u64),
+        }
+    }
+
+    fn remaining_secs(&self, now: Instant) -> u32 {
+        self.expires.saturating_duration_since(now).as_secs() as u32
+    }
+}
+
+/// Build an ICMPv6 Router Advertisement packet.
 /// SLAAC mode: A=1, M=0 (no DHCPv6 stateful), O=1 (DHCPv6 for DNS options).
-fn build_ra_packet(config: &Ipv6Config, prefixes: &[PrefixOption]) -> Vec<u8> {
-    let mut buf = Vec::with_capacity(128);
+/// M=1 when the stateful DHCPv6 server is enabled.
+fn build_ra_packet(
+    config: &Ipv6Config,
+    prefixes: &[PrefixOption],
+    routes: &[RouteOption],
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(256);
 
     // ICMPv6 header
     buf.push(134); // Type: Router Advertisement
@@ -83,7 +135,7 @@ fn build_ra_packet(config: &Ipv6Config, prefixes: &[PrefixOption]) -> Vec<u8> {
     // RA fields
     buf.push(64); // Cur Hop Limit
     // M=0 (no DHCPv6 for addresses, use SLAAC), O=1 (use DHCPv6 for DNS options)
-    let flags: u8 = 0x40; // M=0, O=1
+    let flags: u8 = if config.dhcpv6_enabled { 0xC0 } else { 0x40 }; // M, O
     buf.push(flags);
     buf.extend_from_slice(&config.ra_lifetime_secs.to_be_bytes()[2..4]); // Router Lifetime (16-bit)
     buf.extend_from_slice(&0u32.to_be_bytes()); // Reachable Time
@@ -101,6 +153,22 @@ fn build_ra_packet(config: &Ipv6Config, prefixes: &[PrefixOption]) -> Vec<u8> {
         buf.extend_from_slice(&pfx.addr.octets());
     }
 
+    // Route Information Options (type=24, RFC 4191)
+    for route in routes {
+        // Only as many prefix bytes as the length requires: 0, 8 or 16
+        let prefix_bytes = match route.len {
+            0 => 0,
+            1..=64 => 8,
+            _ => 16,
+        };
+        buf.push(24); // Type: Route Information
+        buf.push(1 + prefix_bytes as u8 / 8); // Length in units of 8 bytes
+        buf.push(route.len);
+        buf.push(0); // Flags: Prf=00 (medium)
+        buf.extend_from_slice(&route.lifetime.to_be_bytes());
+        buf.extend_from_slice(&route.addr.octets()[..prefix_bytes]);
+    }
+
     // RDNSS Option (type=25) — Recursive DNS Server
     for dns_str in &config.dns_servers {
         if let Ok(dns_ip) = dns_str.parse::<Ipv6Addr>() {
@@ -112,15 +180,35 @@ fn build_ra_packet(config: &Ipv6Config, prefixes: &[PrefixOption]) -> Vec<u8> {
         }
     }
 
+    // DNSSL Option (type=31, RFC 8106) — DNS Search List
+    let mut domains: Vec<u8> = config
+        .dns_search_domains
+        .iter()
+        .flat_map(|d| encode_domain_name(d))
+        .collect();
+    if !domains.is_empty() {
+        // Pad the names with zeros to a multiple of 8 bytes
+        domains.resize(domains.len().div_ceil(8) * 8, 0);
+        buf.push(31); // Type: DNSSL
+        buf.push((1 + domains.len() / 8) as u8); // Length in units of 8 bytes
+        buf.extend_from_slice(&[0, 0]); // Reserved
+        buf.extend_from_slice(&config.ra_lifetime_secs.to_be_bytes()); // Lifetime
+        buf.extend_from_slice(&domains);
+    }
+
     buf
 }
 
-/// Collect the list of prefixes to advertise.
-/// Only the GUA prefix from Starlink PD is advertised (no ULA).
-fn collect_prefixes(_config: &Ipv6Config, gua: &Option<PrefixInfo>) -> Vec<PrefixOption> {
-    let mut prefixes = Vec::with_capacity(1);
+/// Collect the list of prefixes to advertise: the current GUA from PD, the
+/// configured ULA, and any deprecated GUAs with a zero preferred lifetime.
+fn collect_prefixes(
+    config: &Ipv6Config,
+    gua: &Option<PrefixInfo>,
+    deprecated: &[DeprecatedPrefix],
+    now: Instant,
+) -> Vec<PrefixOption> {
+    let mut prefixes = Vec::with_capacity(2 + deprecated.len());
 
-    // Only GUA prefix from PD - no ULA, SLAAC handles addressing
     if let Some(pd) = gua {
         prefixes.push(PrefixOption {
             addr: pd.prefix,
@@ -130,20 +218,61 @@ fn collect_prefixes(_config: &Ipv6Config, gua: &Option<PrefixInfo>) -> Vec<Prefi
         });
     }
 
+    if let Some((addr, len)) = parse_prefix(&config.ula_prefix) {
+        prefixes.push(PrefixOption {
+            addr,
+            len,
+            valid_lifetime: ULA_VALID_LIFETIME,
+            preferred_lifetime: ULA_PREFERRED_LIFETIME,
+        });
+    }
+
+    for old in deprecated {
+        prefixes.push(PrefixOption {
+            addr: old.addr,
+            len: old.len,
+            valid_lifetime: old.remaining_secs(now),
+            preferred_lifetime: 0,
+        });
+    }
+
     prefixes
 }
 
-/// Build a deprecation packet: announces the old GUA prefix with lifetime=0.
-fn build_deprecation_packet(config: &Ipv6Config, old_prefix: &PrefixInfo) -> Vec<u8> {
-    // Deprecate old GUA prefix
-    let prefixes = vec![PrefixOption {
-        addr: old_prefix.prefix,
-        len: old_prefix.prefix_len,
-        valid_lifetime: 0,
-        preferred_lifetime: 0,
-    }];
-
-    build_ra_packet(config, &prefixes)
+/// Collect the routes to advertise: the whole delegated prefix (so hosts
+/// reach sub-delegated downstream routers through us) plus configured routes.
+fn collect_routes(config: &Ipv6Config, gua: &Option<PrefixInfo>) -> Vec<RouteOption> {
+    let mut routes = Vec::new();
+
+    if let Some(pd) = gua
+        .as_ref()
+        .filter(|pd| pd.delegated_prefix_len < pd.prefix_len)
+    {
+        routes.push(RouteOption {
+            addr: pd.delegated_prefix,
+            len: pd.delegated_prefix_len,
+            lifetime: config.ra_lifetime_secs.min(pd.valid_lifetime),
+        });
+    }
+
+    for route in &config.ra_routes {
+        match parse_prefix(route) {
+            Some((addr, len)) => routes.push(RouteOption {
+                addr,
+                len,
+                lifetime: config.ra_lifetime_secs,
+            }),
+            None => warn!("Ignoring invalid RA route '{}'", route),
+        }
+    }
+
+    routes
+}
+
+fn parse_prefix(s: &str) -> Option<(Ipv6Addr, u8)> {
+    let (addr, len) = s.split_once('/')?;
+    let len: u8 = len.parse().ok().filter(|l| *l <= 128)?;
+    Some((addr.parse().ok()?, len))
 }
 
 /// Send periodic Router Advertisements with dynamic prefix support.
@@ -157,7 +286,10 @@ pub async fn run_ra_sender(
         return Ok(());
     }
 
-    info!("Starting Router Advertisement sender (SLAAC mode, M=0, O=1, A=1)");
+    info!(
+        "Starting Router Advertisement sender (SLAAC mode, M={}, O=1, A=1)",
+        config.dhcpv6_enabled as u8
+    );
 
     let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?;
     socket.set_multicast_hops_v6(255)?;
@@ -176,21 +308,28 @@ pub async fn run_ra_sender(
     info!("RA sender: sending every {}s to ff02::1", interval_secs);
 
     let mut last_gua: Option<PrefixInfo>;
+    let mut deprecated: Vec<DeprecatedPrefix> = Vec::new();
     let lan_iface = config.interface.clone();
 
-    // Assign GUA to LAN if prefix already available at startup
+    // Assign GUA/ULA to LAN if already available at startup
     {
         let initial = prefix_rx.borrow().clone();
         if let Some(ref info) = initial {
-            assign_lan_gua(&lan_iface, info).await;
+            assign_lan_addr(&lan_iface, info.prefix, info.prefix_len).await;
+        }
+        if let Some((addr, len)) = parse_prefix(&config.ula_prefix) {
+            assign_lan_addr(&lan_iface, addr, len).await;
         }
     }
 
     loop {
         // Build and send RA with current prefixes
+        let now = Instant::now();
+        deprecated.retain(|d| d.expires > now);
         let current_gua = prefix_rx.borrow().clone();
-        let prefixes = collect_prefixes(&config, &current_gua);
-        let ra_packet = build_ra_packet(&config, &prefixes);
+        let prefixes = collect_prefixes(&config, &current_gua, &deprecated, now);
+        let routes = collect_routes(&config, &current_gua);
+        let ra_packet = build_ra_packet(&config, &prefixes, &routes);
 
         match socket
             .send_to(&ra_packet, std::net::SocketAddr::V6(dest))
@@ -220,40 +359,43 @@ pub async fn run_ra_sender(
             _ = prefix_rx.changed() => {
                 let new_gua = prefix_rx.borrow().clone();
 
-                // Manage GUA address on LAN interface
+                // Manage GUA address on LAN interface and deprecate the old prefix
                 match (&new_gua, &last_gua) {
                     (Some(new), None) => {
                         // New prefix: assign address
-                        assign_lan_gua(&lan_iface, new).await;
+                        assign_lan_addr(&lan_iface, new.prefix, new.prefix_len).await;
                     }
                     (Some(new), Some(old)) if new.prefix != old.prefix => {
                         // Prefix changed: remove old, assign new
-                        remove_lan_gua(&lan_iface, old).await;
-                        assign_lan_gua(&lan_iface, new).await;
+                        info!(
+                            "GUA renumbered {}/{} -> {}/{}, deprecating old prefix",
+                            old.prefix, old.prefix_len, new.prefix, new.prefix_len
+                        );
+                        remove_lan_addr(&lan_iface, old.prefix, old.prefix_len).await;
+                        assign_lan_addr(&lan_iface, new.prefix, new.prefix_len).await;
+                        deprecated.push(DeprecatedPrefix::new(old, Instant::now()));
                     }
                     (None, Some(old)) => {
                         // Prefix withdrawn: remove address
-                        remove_lan_gua(&lan_iface, old).await;
+                        info!("GUA prefix withdrawn, deprecating {}/{}", old.prefix, old.prefix_len);
+                        remove_lan_addr(&lan_iface, old.prefix, old.prefix_len).await;
+                        deprecated.push(DeprecatedPrefix::new(old, Instant::now()));
                     }
                     _ => {}
                 }
 
-                // If prefix was withdrawn, send deprecation for old prefix
-                if new_gua.is_none() {
-                    if let Some(ref old) = last_gua {
-                        info!("GUA prefix withdrawn, sending deprecation RA");
-                        let deprecation = build_deprecation_packet(&config, old);
-                        let _ = socket.send_to(&deprecation, std::net::SocketAddr::V6(dest)).await;
-                    }
-                } else {
-                    info!("GUA prefix changed, sending rapid RAs");
+                // A prefix that comes back is no longer deprecated
+                if let Some(ref new) = new_gua {
+                    deprecated.retain(|d| d.addr != new.prefix);
                 }
 
                 // RFC 4861 §6.2.4: send 3 rapid RAs on prefix change
                 for i in 0..3 {
+                    let now = Instant::now();
                     let gua = prefix_rx.borrow().clone();
-                    let pfx = collect_prefixes(&config, &gua);
-                    let pkt = build_ra_packet(&config, &pfx);
+                    let pfx = collect_prefixes(&config, &gua, &deprecated, now);
+                    let routes = collect_routes(&config, &gua);
+                    let pkt = build_ra_packet(&config, &pfx, &routes);
                     let _ = socket.send_to(&pkt, std::net::SocketAddr::V6(dest)).await;
                     if i < 2 {
                         tokio::time::sleep(Duration::from_secs(1)).await;
@@ -263,3 +405,87 @@ pub async fn run_ra_sender(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gua(prefix: &str) -> PrefixInfo {
+        PrefixInfo {
+            prefix: prefix.parse().unwrap(),
+            prefix_len: 64,
+            valid_lifetime: 86400,
+            preferred_lifetime: 14400,
+            delegated_prefix: prefix.parse().unwrap(),
+            delegated_prefix_len: 56,
+        }
+    }
+
+    /// Walk the RA options, returning (type, body) pairs.
+    fn options(pkt: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut out = Vec::new();
+        let mut offset = 16;
+        while offset + 2 <= pkt.len() {
+            let len = pkt[offset + 1] as usize * 8;
+            assert!(len > 0 && offset + len <= pkt.len(), "malformed option");
+            out.push((pkt[offset], &pkt[offset + 2..offset + len]));
+            offset += len;
+        }
+        out
+    }
+
+    #[test]
+    fn renumbering_advertises_old_prefix_with_zero_preferred_lifetime() {
+        let config = Ipv6Config {
+            ula_prefix: "fd00:1::/64".to_string(),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let old = gua("2001:db8:0:1::");
+        let deprecated = vec![DeprecatedPrefix::new(&old, now)];
+
+        let prefixes = collect_prefixes(&config, &Some(gua("2001:db8:ff:1::")), &deprecated, now);
+        assert_eq!(prefixes.len(), 3);
+        assert_eq!(
+            prefixes[0].addr,
+            "2001:db8:ff:1::".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(prefixes[1].addr, "fd00:1::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(prefixes[2].addr, old.prefix);
+        assert_eq!(prefixes[2].preferred_lifetime, 0);
+        assert_eq!(prefixes[2].valid_lifetime, DEPRECATED_VALID_LIFETIME_MAX);
+
+        let later = now + Duration::from_secs(7000);
+        let prefixes = collect_prefixes(&config, &None, &deprecated, later);
+        assert_eq!(prefixes[1].valid_lifetime, 200);
+    }
+
+    #[test]
+    fn ra_packet_carries_route_rdnss_and_dnssl_options() {
+        let config = Ipv6Config {
+            dns_servers: vec!["2001:db8::53".to_string()],
+            dns_search_domains: vec!["home.lan".to_string()],
+            ra_routes: vec!["2001:db8:100::/48".to_string(), "bogus".to_string()],
+            ..Default::default()
+        };
+        let current = Some(gua("2001:db8:0:1::"));
+        let prefixes = collect_prefixes(&config, &current, &[], Instant::now());
+        let routes = collect_routes(&config, &current);
+        assert_eq!(routes.len(), 2);
+
+        let pkt = build_ra_packet(&config, &prefixes, &routes);
+        let opts = options(&pkt);
+        let types: Vec<u8> = opts.iter().map(|(t, _)| *t).collect();
+        assert_eq!(types, vec![3, 24, 24, 25, 31]);
+
+        // RIO for the delegated /56: 8 prefix bytes, lifetime capped by RA lifetime
+        let (_, rio) = opts[1];
+        assert_eq!(rio[0], 56);
+        assert_eq!(u32::from_be_bytes(rio[2..6].try_into().unwrap()), 1800);
+        assert_eq!(rio.len(), 14);
+
+        let (_, dnssl) = opts[4];
+        assert_eq!(&dnssl[6..16], b"\x04home\x03lan\x00");
+        assert_eq!(dnssl.len() % 8, 6);
+    }
+}
@@ -39,6 +39,19 @@ impl IpcHandler<IpcRequest, IpcResponse> for NetcoreHandler {
                 self.handle_dns_set_managed_records(owner, records).await
             }
             IpcRequest::DhcpLeases => self.handle_dhcp_leases().await,
+            IpcRequest::DhcpFailoverStatus => self.handle_dhcp_failover_status().await,
+            IpcRequest::DhcpImportReservations {
+                format,
+                content,
+                apply,
+                skip_conflicts,
+            } => {
+                self.handle_dhcp_import_reservations(format, content, apply, skip_conflicts)
+                    .await
+            }
+            IpcRequest::DhcpExportReservations { format } => {
+                self.handle_dhcp_export_reservations(format).await
+            }
             IpcRequest::AdblockStats => self.handle_adblock_stats().await,
             IpcRequest::AdblockWhitelistList => self.handle_adblock_whitelist_list().await,
             IpcRequest::AdblockWhitelistAdd { domain } => {
@@ -52,6 +65,7 @@ impl IpcHandler<IpcRequest, IpcResponse> for NetcoreHandler {
                 self.handle_adblock_search(query, limit).await
             }
             IpcRequest::ServiceStatus => self.handle_service_status().await,
+            IpcRequest::Metrics => self.handle_metrics().await,
         }
     }
 }
@@ -99,6 +113,28 @@ impl NetcoreHandler {
         })
     }
 
+    // ── Metrics ─────────────────────────────────────────────────────────
+
+    async fn handle_metrics(&self) -> IpcResponse {
+        let dns = self.dns_state.read().await;
+        let dhcp = self.dhcp_state.read().await;
+        let ab = self.adblock.read().await;
+        IpcResponse::ok_data(NetcoreMetricsData {
+            dns_queries: dns.metrics.snapshot(),
+            dns_cache_size: dns.dns_cache.len().await,
+            dhcp_received: dhcp.metrics.received(),
+            dhcp_sent: dhcp.metrics.sent(),
+            dhcp_active_leases: dhcp
+                .lease_store
+                .all_leases()
+                .into_iter()
+                .filter(|l| dhcp.lease_store.is_ip_in_use(l.ip))
+                .count(),
+            adblock_enabled: dns.adblock_enabled,
+            adblock_domains: ab.domain_count(),
+        })
+    }
+
     // ── DnsStatus ───────────────────────────────────────────────────────
 
     async fn handle_dns_status(&self) -> IpcResponse {
@@ -207,6 +243,78 @@ impl NetcoreHandler {
         IpcResponse::ok_data(leases)
     }
 
+    // ── DhcpFailoverStatus ──────────────────────────────────────────────
+
+    async fn handle_dhcp_failover_status(&self) -> IpcResponse {
+        let s = self.dhcp_state.read().await;
+        match s.failover {
+            Some(ref peer) => IpcResponse::ok_data(peer.status()),
+            None => IpcResponse::ok_data(serde_json::json!({ "enabled": false })),
+        }
+    }
+
+    // ── DhcpImportReservations ──────────────────────────────────────────
+
+    async fn handle_dhcp_import_reservations(
+        &self,
+        format: String,
+        content: String,
+        apply: bool,
+        skip_conflicts: bool,
+    ) -> IpcResponse {
+        use hr_dhcp::reservations;
+
+        let format = match parse_reservation_format(&format) {
+            Ok(f) => f,
+            Err(e) => return IpcResponse::err(e),
+        };
+        let (imported, errors) = reservations::parse(format, &content);
+
+        // Hold the write lock across analysis and apply so the report matches
+        // what gets written.
+        let mut s = self.dhcp_state.write().await;
+        let report = reservations::analyze(format, imported, errors, &s.config, &s.lease_store);
+
+        if !apply {
+            return IpcResponse::ok_data(serde_json::json!({ "applied": false, "report": report }));
+        }
+        if report.conflicts > 0 && !skip_conflicts {
+            return IpcResponse::err(format!(
+                "{} conflicting reservation(s); fix them or set skip_conflicts",
+                report.conflicts
+            ));
+        }
+
+        let merged = report.merge_into(&s.config.static_leases);
+        if let Err(e) = self.write_static_leases(&merged).await {
+            error!("Failed to save imported reservations: {}", e);
+            return IpcResponse::err(format!("Config write error: {}", e));
+        }
+        s.config.static_leases = merged;
+        info!(
+            new = report.new,
+            updated = report.updated,
+            "DHCP reservations imported"
+        );
+
+        IpcResponse::ok_data(serde_json::json!({ "applied": true, "report": report }))
+    }
+
+    // ── DhcpExportReservations ──────────────────────────────────────────
+
+    async fn handle_dhcp_export_reservations(&self, format: String) -> IpcResponse {
+        let parsed = match parse_reservation_format(&format) {
+            Ok(f) => f,
+            Err(e) => return IpcResponse::err(e),
+        };
+        let s = self.dhcp_state.read().await;
+        IpcResponse::ok_data(ReservationExportData {
+            format,
+            count: s.config.static_leases.len(),
+            content: hr_dhcp::reservations::export(parsed, &s.config.static_leases),
+        })
+    }
+
     // ── AdblockStats ────────────────────────────────────────────────────
 
     async fn handle_adblock_stats(&self) -> IpcResponse {
@@ -420,13 +528,13 @@ impl NetcoreHandler {
 
         let reg = self.service_registry.read().await;
 
-        // Aggregate services: dns-udp:*/dns-tcp:* → "dns", ipv6-pd/ipv6-ra → "ipv6", rest as-is
+        // Aggregate services: dns-udp:*/dns-tcp:* → "dns", ipv6-* → "ipv6", rest as-is
         let mut groups: HashMap<String, Vec<&hr_common::service_registry::ServiceStatus>> =
             HashMap::new();
         for s in reg.values() {
             let key = if s.name.starts_with("dns-udp:") || s.name.starts_with("dns-tcp:") {
                 "dns".to_string()
-            } else if s.name == "ipv6-pd" || s.name == "ipv6-ra" {
+            } else if s.name.starts_with("ipv6-") {
                 "ipv6".to_string()
             } else {
                 s.name.clone()
@@ -482,6 +590,34 @@ impl NetcoreHandler {
         }
     }
 
+    /// Persist `dhcp.static_leases` in dns-dhcp-config.json (atomic write).
+    async fn write_static_leases(
+        &self,
+        leases: &[hr_dhcp::config::StaticLease],
+    ) -> anyhow::Result<()> {
+        let config_path = &self.dns_dhcp_config_path;
+        let mut config: serde_json::Value = match tokio::fs::read_to_string(config_path).await {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::json!({}),
+            Err(e) => return Err(e.into()),
+        };
+
+        let root = config
+            .as_object_mut()
+            .ok_or_else(|| anyhow::anyhow!("config root is not an object"))?;
+        let dhcp = root
+            .entry("dhcp")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or_else(|| anyhow::anyhow!("`dhcp` is not an object"))?;
+        dhcp.insert("static_leases".into(), serde_json::to_value(leases)?);
+
+        let tmp = config_path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_string_pretty(&config)?).await?;
+        tokio::fs::rename(&tmp, config_path).await?;
+        Ok(())
+    }
+
     async fn read_adblock_sources(&self) -> Vec<AdblockSourceInfo> {
         let config_path = &self.dns_dhcp_config_path;
         let content = match tokio::fs::read_to_string(config_path).await {
@@ -517,6 +653,17 @@ impl NetcoreHandler {
     }
 }
 
+fn parse_reservation_format(
+    format: &str,
+) -> Result<hr_dhcp::reservations::ReservationFormat, String> {
+    serde_json::from_value(serde_json::json!(format.to_lowercase())).map_err(|_| {
+        format!(
+            "Unknown reservation format '{}' (dnsmasq, isc, kea)",
+            format
+        )
+    })
+}
+
 /// Combined config from dns-dhcp-config.json (matches the original file layout).
 /// This is a local copy used only for config reload in the handler.
 /// All fields must be present for correct deserialization even if not all are read.
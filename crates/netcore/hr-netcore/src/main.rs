@@ -29,6 +29,8 @@ struct DnsDhcpConfig {
     #[serde(default)]
     ipv6: hr_ipv6::Ipv6Config,
     #[serde(default)]
+    firewall: hr_firewall::FirewallConfig,
+    #[serde(default)]
     adblock: hr_adblock::config::AdblockConfig,
 }
 
@@ -157,10 +159,15 @@ async fn main() -> anyhow::Result<()> {
         warn!("Failed to load lease file: {}", e);
     }
 
+    let dhcp_failover =
+        hr_dhcp::failover::FailoverPeer::from_config(&dns_dhcp_config.dhcp.failover);
+
     let dhcp_state: hr_dhcp::SharedDhcpState = Arc::new(RwLock::new(hr_dhcp::DhcpState {
         config: dns_dhcp_config.dhcp.clone(),
         lease_store,
         server_ip,
+        failover: dhcp_failover.clone(),
+        metrics: Default::default(),
     }));
 
     // Separate LeaseStore for DNS resolver (synced from DHCP state every 10s).
@@ -200,6 +207,7 @@ async fn main() -> anyhow::Result<()> {
         lease_store: lease_store_for_dns.clone(),
         adblock_enabled: dns_dhcp_config.adblock.enabled,
         adblock_block_response: dns_dhcp_config.adblock.block_response.clone(),
+        metrics: Default::default(),
     }));
 
     // ── Spawn supervised services ──────────────────────────────────────
@@ -265,6 +273,22 @@ async fn main() -> anyhow::Result<()> {
             let state = dhcp_state_c.clone();
             async move { hr_dhcp::server::run_dhcp_server(state).await }
         });
+
+        // DHCP failover link (Important: losing it degrades to local-only serving)
+        if let Some(peer) = dhcp_failover.clone() {
+            let dhcp_state_c = dhcp_state.clone();
+            let reg = service_registry.clone();
+            spawn_supervised(
+                "dhcp-failover",
+                ServicePriority::Important,
+                reg,
+                move || {
+                    let state = dhcp_state_c.clone();
+                    let peer = peer.clone();
+                    async move { hr_dhcp::failover::run_failover(state, peer).await }
+                },
+            );
+        }
     } else {
         let mut reg = service_registry.write().await;
         reg.insert(
@@ -338,6 +362,68 @@ async fn main() -> anyhow::Result<()> {
         drop(reg);
     }
 
+    // 3) Stateful DHCPv6 server (IA_NA from the /64, IA_PD from the delegation)
+    if dns_dhcp_config.ipv6.enabled && dns_dhcp_config.ipv6.dhcpv6_enabled {
+        let ipv6_config = dns_dhcp_config.ipv6.clone();
+        let rx = prefix_rx.clone();
+        let reg = service_registry.clone();
+        spawn_supervised("ipv6-dhcpv6", ServicePriority::Important, reg, move || {
+            let config = ipv6_config.clone();
+            let rx = rx.clone();
+            async move { hr_ipv6::dhcpv6_server::run_dhcpv6_server(config, rx).await }
+        });
+    } else {
+        let mut reg = service_registry.write().await;
+        reg.insert(
+            "ipv6-dhcpv6".into(),
+            ServiceStatus {
+                name: "ipv6-dhcpv6".into(),
+                state: ServiceState::Disabled,
+                priority: ServicePriorityLevel::Important,
+                restart_count: 0,
+                last_state_change: now_millis(),
+                error: None,
+            },
+        );
+        drop(reg);
+    }
+
+    // 4) IPv6 firewall (nftables, pinholes follow the delegated prefix)
+    if dns_dhcp_config.firewall.enabled {
+        let mut fw_config = dns_dhcp_config.firewall.clone();
+        if fw_config.wan_interface.is_empty() {
+            fw_config.wan_interface = dns_dhcp_config.ipv6.pd_wan_interface.clone();
+        }
+        let rx = prefix_rx.clone();
+        let dhcp_state_c = dhcp_state.clone();
+        let reg = service_registry.clone();
+        spawn_supervised(
+            "ipv6-firewall",
+            ServicePriority::Important,
+            reg,
+            move || {
+                let config = fw_config.clone();
+                let rx = rx.clone();
+                let dhcp = dhcp_state_c.clone();
+                async move { hr_firewall::nft::run_firewall(config, rx, dhcp).await }
+            },
+        );
+    } else {
+        let mut reg = service_registry.write().await;
+        reg.insert(
+            "ipv6-firewall".into(),
+            ServiceStatus {
+                name: "ipv6-firewall".into(),
+                state: ServiceState::Disabled,
+                priority: ServicePriorityLevel::Important,
+                restart_count: 0,
+                last_state_change: now_millis(),
+                error: None,
+            },
+        );
+        drop(reg);
+    }
+
     // ── Background tasks ───────────────────────────────────────────────
 
     // Lease persistence + expired lease purge (every 60s)
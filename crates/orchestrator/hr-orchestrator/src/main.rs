@@ -337,6 +337,49 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    // Forward app process states to hr-edge, which serves an auto-refreshing
+    // "starting" page while an app comes up.
+    {
+        let registry = app_registry.clone();
+        let supervisor = supervisor.clone();
+        let edge = edge.clone();
+        let mut state_rx = events.app_state.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match state_rx.recv().await {
+                    Ok(event) => event,
+                    // Missed events (e.g. the burst from start_all_running):
+                    // push every app's current state instead.
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "App state events lagged, resyncing edge");
+                        for app in registry.list().await {
+                            let state = match supervisor.status(&app.slug).await {
+                                Some(status) => status.state,
+                                None => app.state,
+                            };
+                            let state = format!("{:?}", state).to_lowercase();
+                            if let Err(e) = edge.set_app_state(&app.domain, &state).await {
+                                warn!(
+                                    slug = %app.slug,
+                                    error = %e,
+                                    "Failed to forward app state to edge"
+                                );
+                            }
+                        }
+                        continue;
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                };
+                let Some(app) = registry.get(&event.slug).await else {
+                    continue;
+                };
+                if let Err(e) = edge.set_app_state(&app.domain, &event.state).await {
+                    warn!(slug = %event.slug, error = %e, "Failed to forward app state to edge");
+                }
+            }
+        });
+    }
+
     if let Err(e) = supervisor.start_all_running().await {
         warn!(error = %e, "start_all_running failed at boot");
     }
@@ -524,6 +524,25 @@ fn tool_definitions_extended() -> Value {
                 "required": ["id"]
             }
         },
+        {
+            "name": "reverseproxy.maintenance_list",
+            "description": "List domains in maintenance mode with their message and allowlist.",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "reverseproxy.maintenance",
+            "description": "Turn maintenance mode on or off for a domain (route or app). Visitors get a maintenance page (503) except allowlisted clients.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "domain": { "type": "string", "description": "Full domain (e.g. app.example.com)" },
+                    "enabled": { "type": "boolean", "description": "true to enter maintenance, false to leave it" },
+                    "message": { "type": "string", "description": "Message shown on the maintenance page" },
+                    "allow": { "type": "array", "items": { "type": "string" }, "description": "CIDRs, IPs or address list names (e.g. 'lan') that still reach the site" }
+                },
+                "required": ["domain", "enabled"]
+            }
+        },
     ])
 }
 
@@ -684,6 +703,8 @@ async fn handle_tools_call(id: Value, params: Value, state: &McpState, project_s
         "reverseproxy.add" => tool_reverseproxy_add(id, &arguments).await,
         "reverseproxy.delete" => tool_reverseproxy_delete(id, &arguments).await,
         "reverseproxy.toggle" => tool_reverseproxy_toggle(id, &arguments).await,
+        "reverseproxy.maintenance_list" => tool_reverseproxy_maintenance_list(id).await,
+        "reverseproxy.maintenance" => tool_reverseproxy_maintenance(id, &arguments).await,
         // ── Docs (v2) ──
         "docs.overview" => tool_docs_overview(id, &arguments).await,
         "docs.list_entries" => tool_docs_list_entries(id, &arguments).await,
@@ -703,6 +724,7 @@ async fn handle_tools_call(id: Value, params: Value, state: &McpState, project_s
         "app.status" => tool_app_status(id, &arguments, state).await,
         "app.exec" => tool_app_exec(id, &arguments, state).await,
         "app.build" => tool_app_build(id, &arguments, state).await,
+        "app.canary" => tool_app_canary(id, &arguments, state).await,
         "app.logs" => tool_app_logs(id, &arguments, state).await,
         "app.create" => tool_app_create(id, &arguments, state).await,
         "app.delete" => tool_app_delete(id, &arguments, state).await,
@@ -1589,6 +1611,39 @@ async fn tool_reverseproxy_toggle(id: Value, args: &Value) -> Value {
     }
 }
 
+async fn tool_reverseproxy_maintenance_list(id: Value) -> Value {
+    match internal_api_get("/rust-proxy/maintenance").await {
+        Ok(data) => tool_success(id, data),
+        Err(e) => tool_error(id, &e),
+    }
+}
+
+async fn tool_reverseproxy_maintenance(id: Value, args: &Value) -> Value {
+    let Some(domain) = args.get("domain").and_then(|v| v.as_str()) else {
+        return error_response(id, INVALID_PARAMS, "Missing domain".into());
+    };
+    let Some(enabled) = args.get("enabled").and_then(|v| v.as_bool()) else {
+        return error_response(id, INVALID_PARAMS, "Missing enabled".into());
+    };
+    let path = format!("/rust-proxy/maintenance/{domain}");
+    let result = if enabled {
+        let mut body = json!({});
+        if let Some(v) = args.get("message").and_then(|v| v.as_str()) {
+            body["message"] = json!(v);
+        }
+        if let Some(v) = args.get("allow").and_then(|v| v.as_array()) {
+            body["allow"] = json!(v);
+        }
+        internal_api_post(&path, body).await
+    } else {
+        internal_api_delete(&path).await
+    };
+    match result {
+        Ok(data) => tool_success(id, data),
+        Err(e) => tool_error(id, &e),
+    }
+}
+
 // ── Docs tools (v2: structured by overview/screens/features/components + mermaid) ──
 
 fn docs_store() -> Store {
@@ -2101,6 +2156,21 @@ fn tool_definitions_apps() -> Value {
                 "required": ["slug"]
             }
         },
+        {
+            "name": "app.canary",
+            "description": "Canary release of an app route on hr-edge. action=split sets weighted targets ({targets:[{name,target_ip,target_port,weight}], sticky}), weights shifts traffic ({canary: 25}), promote makes one target the only backend, clear ends the split. Testers can pin a target with the X-HR-Variant header.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "domain": { "type": "string" },
+                    "action": { "type": "string", "enum": ["split", "weights", "promote", "clear"] },
+                    "split": { "type": "object" },
+                    "weights": { "type": "object", "additionalProperties": { "type": "integer" } },
+                    "target": { "type": "string" }
+                },
+                "required": ["domain", "action"]
+            }
+        },
         {
             "name": "app.logs",
             "description": "Get recent logs for an application.",
@@ -2452,6 +2522,37 @@ async fn tool_app_build(id: Value, args: &Value, state: &McpState) -> Value {
     ipc_resp_to_mcp(id, ctx.build(slug.to_string(), timeout_secs).await)
 }
 
+async fn tool_app_canary(id: Value, args: &Value, state: &McpState) -> Value {
+    use hr_ipc::edge::EdgeRequest;
+    let Some(domain) = args.get("domain").and_then(|v| v.as_str()).map(String::from) else {
+        return error_response(id, INVALID_PARAMS, "Missing domain".into());
+    };
+    let request = match args.get("action").and_then(|v| v.as_str()) {
+        Some("split") => match args.get("split") {
+            Some(split) => EdgeRequest::SetAppSplit { domain, split: Some(split.clone()) },
+            None => return error_response(id, INVALID_PARAMS, "Missing split".into()),
+        },
+        Some("clear") => EdgeRequest::SetAppSplit { domain, split: None },
+        Some("weights") => {
+            let weights = match args.get("weights").cloned().map(serde_json::from_value) {
+                Some(Ok(w)) => w,
+                Some(Err(e)) => return error_response(id, INVALID_PARAMS, format!("Invalid weights: {e}")),
+                None => return error_response(id, INVALID_PARAMS, "Missing weights".into()),
+            };
+            EdgeRequest::SetAppWeights { domain, weights }
+        }
+        Some("promote") => match args.get("target").and_then(|v| v.as_str()) {
+            Some(target) => EdgeRequest::PromoteAppTarget { domain, target: target.to_string() },
+            None => return error_response(id, INVALID_PARAMS, "Missing target".into()),
+        },
+        _ => return error_response(id, INVALID_PARAMS, "action must be split, weights, promote or clear".into()),
+    };
+    match state.edge.request(&request).await {
+        Ok(resp) => ipc_resp_to_mcp(id, resp),
+        Err(e) => tool_error(id, &format!("Failed to reach hr-edge: {e}")),
+    }
+}
+
 async fn tool_app_control(id: Value, args: &Value, state: &McpState) -> Value {
     let ctx = match require_apps_ctx(&id, state) {
         Ok(c) => c,
@@ -112,6 +112,37 @@ impl NetcoreClient {
         extract_data(resp)
     }
 
+    pub async fn dhcp_failover_status(&self) -> Result<DhcpFailoverStatusData> {
+        let resp = self.request(&IpcRequest::DhcpFailoverStatus).await?;
+        extract_data(resp)
+    }
+
+    /// Returns the import report as raw JSON (see `hr_dhcp::reservations::ImportReport`).
+    pub async fn dhcp_import_reservations(
+        &self,
+        format: &str,
+        content: String,
+        apply: bool,
+        skip_conflicts: bool,
+    ) -> Result<IpcResponse> {
+        self.request(&IpcRequest::DhcpImportReservations {
+            format: format.to_string(),
+            content,
+            apply,
+            skip_conflicts,
+        })
+        .await
+    }
+
+    pub async fn dhcp_export_reservations(&self, format: &str) -> Result<ReservationExportData> {
+        let resp = self
+            .request(&IpcRequest::DhcpExportReservations {
+                format: format.to_string(),
+            })
+            .await?;
+        extract_data(resp)
+    }
+
     pub async fn adblock_stats(&self) -> Result<AdblockStatsData> {
         let resp = self.request(&IpcRequest::AdblockStats).await?;
         extract_data(resp)
@@ -162,6 +193,11 @@ impl NetcoreClient {
         let resp = self.request(&IpcRequest::ServiceStatus).await?;
         extract_data(resp)
     }
+
+    pub async fn metrics(&self) -> Result<NetcoreMetricsData> {
+        let resp = self.request(&IpcRequest::Metrics).await?;
+        extract_data(resp)
+    }
 }
 
 /// Extract typed data from IpcResponse, returning an error if the response indicates failure.
@@ -6,27 +6,83 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "cmd", rename_all = "snake_case")]
 pub enum EdgeRequest {
     // Route management
-    SetAppRoute {
-        domain: String,
-        app_id: String,
-        host_id: String,
-        target_ip: String,
-        target_port: u16,
-        auth_required: bool,
-        allowed_groups: Vec<String>,
-        local_only: bool,
-    },
+    SetAppRoute(Box<AppRouteSpec>),
     RemoveAppRoute {
         domain: String,
     },
     ListAppRoutes,
+    /// App process state from the app supervisor ("starting", "running", ...).
+    SetAppState {
+        domain: String,
+        state: String,
+    },
+    /// Split an app route between weighted targets; `None` ends the split.
+    /// `hr_proxy::TrafficSplit` as JSON.
+    SetAppSplit {
+        domain: String,
+        #[serde(default)]
+        split: Option<serde_json::Value>,
+    },
+    /// New weights for targets of an app route's split, by target name.
+    SetAppWeights {
+        domain: String,
+        weights: std::collections::HashMap<String, u32>,
+    },
+    /// Make a split target the app route's only backend.
+    PromoteAppTarget {
+        domain: String,
+        target: String,
+    },
+
+    // Maintenance mode
+    MaintenanceList,
+    MaintenanceSet {
+        domain: String,
+        #[serde(default)]
+        message: Option<String>,
+        /// Clients bypassing maintenance (CIDRs, IPs or address list names).
+        #[serde(default)]
+        allow: Vec<String>,
+    },
+    MaintenanceClear {
+        domain: String,
+    },
+
+    // Redirect rules
+    /// Which domain alias or redirect rule a URL would hit.
+    RedirectTest {
+        url: String,
+    },
+
+    // Stream routes
+    /// Connection counters of the TCP/UDP and TLS passthrough routes.
+    StreamStatus,
+
+    // Access log index
+    /// Filters as `hr_proxy::AccessLogQuery`.
+    AccessLogQuery {
+        filter: serde_json::Value,
+    },
+    AccessLogStats,
 
     // Proxy config
     ReloadConfig,
     GetProxyConfig,
+    /// Validate, write atomically and apply a full config, recording it
+    /// in the revision history. Rejected when validation finds errors.
     SaveProxyConfig {
         config: serde_json::Value,
     },
+    /// Validation report and diff against the running config, nothing applied.
+    ValidateProxyConfig {
+        config: serde_json::Value,
+    },
+    ListConfigRevisions,
+    /// Re-apply a recorded config (default: the one before the current).
+    RollbackProxyConfig {
+        #[serde(default)]
+        revision: Option<u64>,
+    },
 
     // ACME
     AcmeStatus,
@@ -39,6 +95,16 @@ pub enum EdgeRequest {
     },
     AcmeRenewAll,
 
+    // Device certificates (mTLS)
+    DeviceCertList,
+    DeviceCertIssue {
+        name: String,
+        validity_days: u32,
+    },
+    DeviceCertRevoke {
+        serial: String,
+    },
+
     // Auth
     AuthLogin {
         username: String,
@@ -69,6 +135,47 @@ pub enum EdgeRequest {
 
     // Stats / metrics
     GetStats,
+    GetPoolStatus,
+    /// OCSP stapling state and embedded SCTs of the ACME certificates.
+    GetOcspStatus,
+}
+
+/// An app route to add or replace. Settings left unset keep the ones the
+/// route already has, so re-registering a target doesn't reset them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppRouteSpec {
+    pub domain: String,
+    pub app_id: String,
+    pub host_id: String,
+    pub target_ip: String,
+    pub target_port: u16,
+    pub auth_required: bool,
+    pub allowed_groups: Vec<String>,
+    pub local_only: bool,
+    /// Backend pool from the proxy config, replacing `target_ip:target_port`.
+    #[serde(default)]
+    pub pool: Option<String>,
+    /// `hr_proxy::RateLimitConfig` as JSON.
+    #[serde(default)]
+    pub rate_limit: Option<serde_json::Value>,
+    /// `hr_proxy::RouteHeaders` as JSON.
+    #[serde(default)]
+    pub headers: Option<serde_json::Value>,
+    /// `hr_proxy::AccessList` as JSON.
+    #[serde(default)]
+    pub acl: Option<serde_json::Value>,
+    /// `hr_proxy::ClientCertMode`: "off", "optional" or "required".
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// `hr_proxy::BackendTlsConfig` as JSON.
+    #[serde(default)]
+    pub backend_tls: Option<serde_json::Value>,
+    /// `hr_proxy::MachineAuthConfig` as JSON.
+    #[serde(default)]
+    pub machine_auth: Option<serde_json::Value>,
+    /// `hr_proxy::UpstreamConfig` as JSON.
+    #[serde(default)]
+    pub upstream: Option<serde_json::Value>,
 }
 
 // ── EdgeClient ───────────────────────────────────────────
@@ -118,7 +225,7 @@ impl EdgeClient {
         allowed_groups: Vec<String>,
         local_only: bool,
     ) -> Result<IpcResponse> {
-        self.request(&EdgeRequest::SetAppRoute {
+        self.request(&EdgeRequest::SetAppRoute(Box::new(AppRouteSpec {
             domain,
             app_id,
             host_id,
@@ -127,7 +234,15 @@ impl EdgeClient {
             auth_required,
             allowed_groups,
             local_only,
-        })
+            pool: None,
+            rate_limit: None,
+            headers: None,
+            acl: None,
+            client_cert: None,
+            backend_tls: None,
+            machine_auth: None,
+            upstream: None,
+        })))
         .await
     }
 
@@ -138,6 +253,14 @@ impl EdgeClient {
         .await
     }
 
+    pub async fn set_app_state(&self, domain: &str, state: &str) -> Result<IpcResponse> {
+        self.request(&EdgeRequest::SetAppState {
+            domain: domain.to_string(),
+            state: state.to_string(),
+        })
+        .await
+    }
+
     /// Request a wildcard TLS certificate for an environment (e.g., *.dev.mynetwk.biz).
     /// Uses ACME DNS-01 challenge via Cloudflare. This is a long-running operation
     /// (may take 30-60s) so it uses a longer timeout.
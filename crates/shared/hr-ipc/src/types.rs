@@ -25,6 +25,21 @@ pub enum IpcRequest {
         records: Vec<StaticRecordDto>,
     },
     DhcpLeases,
+    DhcpFailoverStatus,
+    /// Parse foreign reservations (`dnsmasq`, `isc`, `kea`) and report
+    /// conflicts. Nothing is written unless `apply` is set.
+    DhcpImportReservations {
+        format: String,
+        content: String,
+        #[serde(default)]
+        apply: bool,
+        /// Apply the non-conflicting entries even if some entries conflict.
+        #[serde(default)]
+        skip_conflicts: bool,
+    },
+    DhcpExportReservations {
+        format: String,
+    },
     AdblockStats,
     AdblockWhitelistList,
     AdblockWhitelistAdd {
@@ -39,6 +54,8 @@ pub enum IpcRequest {
         limit: Option<usize>,
     },
     ServiceStatus,
+    /// DNS, DHCP and adblock counters for the metrics endpoint.
+    Metrics,
 }
 
 // ── IPC Response (hr-netcore → client) ──────────────────────
@@ -89,6 +106,50 @@ pub struct LeaseInfo {
     pub client_id: Option<String>,
 }
 
+/// DHCP failover status. Only `enabled` is set when failover is off.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DhcpFailoverStatusData {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub peer_state: Option<String>,
+    #[serde(default)]
+    pub peer_address: Option<String>,
+    #[serde(default)]
+    pub connected: bool,
+    #[serde(default)]
+    pub last_peer_contact: Option<u64>,
+    #[serde(default)]
+    pub state_since: Option<u64>,
+    #[serde(default)]
+    pub pending_updates: usize,
+    #[serde(default)]
+    pub sync_lag_ms: u64,
+    #[serde(default)]
+    pub last_bulk_sync: Option<u64>,
+    #[serde(default)]
+    pub updates_sent: u64,
+    #[serde(default)]
+    pub updates_received: u64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReservationExportData {
+    pub format: String,
+    pub count: usize,
+    pub content: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DnsCacheStatsData {
     pub cache_size: usize,
@@ -168,6 +229,20 @@ pub struct ServiceStatusEntry {
     pub error: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetcoreMetricsData {
+    /// DNS queries by outcome (`blocked`, `cached`, `nxdomain`, ...).
+    pub dns_queries: Vec<(String, u64)>,
+    pub dns_cache_size: usize,
+    /// DHCP messages received, by message type.
+    pub dhcp_received: Vec<(String, u64)>,
+    /// DHCP messages sent, by message type.
+    pub dhcp_sent: Vec<(String, u64)>,
+    pub dhcp_active_leases: usize,
+    pub adblock_enabled: bool,
+    pub adblock_domains: usize,
+}
+
 // ── App* DTOs (parallel to hr-apps types, no crate dep) ────────
 
 /// Application summary returned by app_list / app_get IPC calls.
@@ -294,4 +369,3 @@ pub struct AppDbQueryResult {
     pub rows: Vec<serde_json::Value>,
     pub total: u64,
 }
-
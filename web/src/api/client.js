@@ -78,6 +78,8 @@ export const getSystemRoutes = () => api.get('/reverseproxy/system-routes');
 
 // Rust Proxy
 export const getRustProxyStatus = () => api.get('/rust-proxy/status');
+export const getRustProxyStreams = () => api.get('/rust-proxy/streams');
+export const testRedirect = (url) => api.get('/rust-proxy/redirects/test', { params: { url } });
 
 // Auth - Session (login page)
 export const login = (code, remember_me = false) => api.post('/auth/login', { code, remember_me });
@@ -85,6 +87,11 @@ export const logout = () => api.post('/auth/logout');
 export const getMe = () => api.get('/auth/me');
 export const changeCode = (new_code) => api.post('/auth/change-code', { new_code });
 
+// Auth - Machine credentials (API keys, basic auth)
+export const getCredentials = () => api.get('/auth/credentials');
+export const createCredential = (credential) => api.post('/auth/credentials', credential);
+export const revokeCredential = (id) => api.delete(`/auth/credentials/${id}`);
+
 // System Updates
 export const getUpdatesStatus = () => api.get('/updates/status');
 export const getLastUpdatesCheck = () => api.get('/updates/last');
@@ -225,6 +232,8 @@ export const introspectAppDbSdl = (slug) => api.get(`/apps/${slug}/db/introspect
 // ========== Logs ==========
 export const getLogs = (params = {}) => api.get('/logs', { params });
 export const getLogStats = () => api.get('/logs/stats');
+export const getAccessLogs = (params = {}) => api.get('/logs/access', { params });
+export const getAccessLogStats = () => api.get('/logs/access/stats');
 
 // ========== Docs (v2: structured overview/screens/features/components + mermaid) ==========
 // Read-only — mutations go through MCP from the agent.